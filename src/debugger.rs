@@ -0,0 +1,103 @@
+use crate::Breakpoint;
+use std::io::{BufRead, Write};
+
+const HELP: &str = "\
+Commands:
+  c, continue     Continue until the next breakpoint
+  s, step         Continue until the next expansion of any definition
+  a, arg          Show what the definition is applied to, in HVM syntax
+  p, print        Show the partially evaluated program
+  b, break <def>  Pause at the expansions of a definition, or list the breakpoints if none is given
+  d, delete <def> Remove the breakpoint of a definition
+  q, quit         Stop the evaluation and show the partially evaluated result
+  h, help         Show this message";
+
+/// An interactive debugging session for [`crate::debug_book`],
+/// reading commands from `input` and writing to `output`.
+pub struct Debugger<R, W> {
+  input: R,
+  output: W,
+  breaks: Vec<String>,
+  /// Pauses at the next expansion, of any definition.
+  stepping: bool,
+  pretty: bool,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+  /// Pauses at the expansions of the definitions in `breaks`.
+  /// Without any breakpoints, pauses at the first expansion, of the entrypoint.
+  pub fn new(input: R, output: W, breaks: Vec<String>, pretty: bool) -> Self {
+    let stepping = breaks.is_empty();
+    Self { input, output, breaks, stepping, pretty }
+  }
+
+  /// If the evaluation should pause at this expansion, shows where it is and handles commands until one
+  /// of them resumes the evaluation.
+  ///
+  /// Returns whether to keep evaluating. When the input ends, the evaluation continues without pausing again.
+  pub fn on_break(&mut self, bp: &Breakpoint) -> bool {
+    if !self.stepping && !self.breaks.iter().any(|def| def == bp.def()) {
+      return true;
+    }
+    self.stepping = false;
+
+    let (def, n, itrs) = (bp.def(), bp.calls() + 1, bp.itrs());
+    self.say(format!("Paused at expansion {n} of '{def}', after {itrs} rewrites."));
+    loop {
+      let _ = write!(self.output, "(debug) ");
+      let _ = self.output.flush();
+      let mut line = String::new();
+      if !matches!(self.input.read_line(&mut line), Ok(1..)) {
+        self.say("");
+        self.breaks.clear();
+        return true;
+      }
+
+      let mut words = line.split_whitespace();
+      match (words.next(), words.next()) {
+        (None, _) => (),
+        (Some("c" | "continue"), None) => return true,
+        (Some("s" | "step"), None) => {
+          self.stepping = true;
+          return true;
+        }
+        (Some("q" | "quit"), None) => return false,
+        (Some("a" | "arg"), None) => self.say(bp.arg()),
+        (Some("p" | "print"), None) => {
+          let (term, diags) = bp.readback();
+          let _ = write!(self.output, "{diags}");
+          let term = if self.pretty { term.display_pretty(0).to_string() } else { term.to_string() };
+          self.say(term);
+        }
+        (Some("b" | "break"), None) => {
+          let breaks =
+            if self.breaks.is_empty() { "No breakpoints.".to_string() } else { self.breaks.join("\n") };
+          self.say(breaks);
+        }
+        (Some("b" | "break"), Some(def)) => {
+          if !self.breaks.iter().any(|b| b == def) {
+            self.breaks.push(def.to_string());
+          }
+          self.say(format!("Breakpoint set at '{def}'."));
+        }
+        (Some("d" | "delete"), Some(def)) => {
+          let len = self.breaks.len();
+          self.breaks.retain(|b| b != def);
+          if self.breaks.len() != len {
+            self.say(format!("Breakpoint at '{def}' removed."));
+          } else {
+            self.say(format!("There's no breakpoint at '{def}'."));
+          }
+        }
+        (Some("h" | "help"), None) => self.say(HELP),
+        (Some(_), _) => {
+          self.say(format!("Unknown command '{}'. Type 'help' to see the commands.", line.trim()))
+        }
+      }
+    }
+  }
+
+  fn say(&mut self, msg: impl std::fmt::Display) {
+    let _ = writeln!(self.output, "{msg}");
+  }
+}
@@ -11,6 +11,8 @@ pub struct Diagnostics {
   err_counter: usize,
   pub diagnostics: BTreeMap<DiagnosticOrigin, Vec<Diagnostic>>,
   pub config: DiagnosticsConfig,
+  /// The warnings silenced in each definition with an `#[allow(..)]` attribute.
+  pub allowed: BTreeMap<Name, Vec<WarningType>>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -22,6 +24,10 @@ pub struct DiagnosticsConfig {
   pub unused_definition: Severity,
   pub repeated_bind: Severity,
   pub recursion_cycle: Severity,
+  pub partial_application: Severity,
+  pub shadowed_bind: Severity,
+  pub unused_variable: Severity,
+  pub implicit_dup: Severity,
 }
 
 #[derive(Debug, Clone)]
@@ -49,7 +55,7 @@ pub enum Severity {
   Error,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WarningType {
   IrrefutableMatch,
   RedundantMatch,
@@ -57,11 +63,15 @@ pub enum WarningType {
   UnusedDefinition,
   RepeatedBind,
   RecursionCycle,
+  PartialApplication,
+  ShadowedBind,
+  UnusedVariable,
+  ImplicitDup,
 }
 
 impl Diagnostics {
   pub fn new(config: DiagnosticsConfig) -> Self {
-    Self { err_counter: 0, diagnostics: Default::default(), config }
+    Self { err_counter: 0, diagnostics: Default::default(), config, allowed: Default::default() }
   }
 
   pub fn add_book_error(&mut self, err: impl std::fmt::Display) {
@@ -80,11 +90,13 @@ impl Diagnostics {
   }
 
   pub fn add_rule_warning(&mut self, warn: impl std::fmt::Display, warn_type: WarningType, def_name: Name) {
-    let severity = self.config.warning_severity(warn_type);
+    let def_name = def_name.def_name_from_generated();
+    let allowed = self.allowed.get(&def_name).is_some_and(|allowed| allowed.contains(&warn_type));
+    let severity = if allowed { Severity::Allow } else { self.config.warning_severity(warn_type) };
     if severity == Severity::Error {
       self.err_counter += 1;
     }
-    self.add_diagnostic(warn, severity, DiagnosticOrigin::Rule(def_name.def_name_from_generated()));
+    self.add_diagnostic(warn, severity, DiagnosticOrigin::Rule(def_name));
   }
 
   pub fn add_book_warning(&mut self, warn: impl std::fmt::Display, warn_type: WarningType) {
@@ -152,6 +164,14 @@ impl Diagnostics {
     }
   }
 
+  /// Removes the warnings, keeping only the errors.
+  pub fn remove_warnings(&mut self) {
+    for diags in self.diagnostics.values_mut() {
+      diags.retain(|diag| diag.severity != Severity::Warning);
+    }
+    self.diagnostics.retain(|_, diags| !diags.is_empty());
+  }
+
   /// Returns a Display that prints the diagnostics with one of the given severities.
   pub fn display_with_severity(&self, severity: Severity) -> impl std::fmt::Display + '_ {
     DisplayFn(move |f| {
@@ -179,7 +199,8 @@ impl Diagnostics {
               }
             }
             DiagnosticOrigin::Inet(nam) => {
-              writeln!(f, "\x1b[1mIn compiled inet '\x1b[4m{}\x1b[0m\x1b[1m':\x1b[0m", nam)?;
+              let nam = Name::new(nam);
+              writeln!(f, "\x1b[1mIn compiled inet '\x1b[4m{}\x1b[0m\x1b[1m':\x1b[0m", nam.display_origin())?;
               for err in errs {
                 writeln!(f, "{:ERR_INDENT_SIZE$}{err}", "")?;
               }
@@ -235,10 +256,35 @@ impl DiagnosticsConfig {
       unused_definition: severity,
       repeated_bind: severity,
       recursion_cycle: severity,
+      partial_application: severity,
+      shadowed_bind: severity,
+      unused_variable: severity,
+      implicit_dup: severity,
       verbose,
     }
   }
 
+  /// Turns the warnings into allowed diagnostics, keeping the ones that are errors.
+  pub fn without_warnings(mut self) -> Self {
+    for severity in [
+      &mut self.irrefutable_match,
+      &mut self.redundant_match,
+      &mut self.unreachable_match,
+      &mut self.unused_definition,
+      &mut self.repeated_bind,
+      &mut self.recursion_cycle,
+      &mut self.partial_application,
+      &mut self.shadowed_bind,
+      &mut self.unused_variable,
+      &mut self.implicit_dup,
+    ] {
+      if *severity == Severity::Warning {
+        *severity = Severity::Allow;
+      }
+    }
+    self
+  }
+
   pub fn warning_severity(&self, warn: WarningType) -> Severity {
     match warn {
       WarningType::UnusedDefinition => self.unused_definition,
@@ -247,6 +293,10 @@ impl DiagnosticsConfig {
       WarningType::IrrefutableMatch => self.irrefutable_match,
       WarningType::RedundantMatch => self.redundant_match,
       WarningType::UnreachableMatch => self.unreachable_match,
+      WarningType::PartialApplication => self.partial_application,
+      WarningType::ShadowedBind => self.shadowed_bind,
+      WarningType::UnusedVariable => self.unused_variable,
+      WarningType::ImplicitDup => self.implicit_dup,
     }
   }
 }
@@ -255,10 +305,32 @@ impl Default for DiagnosticsConfig {
   fn default() -> Self {
     let mut cfg = Self::new(Severity::Warning, false);
     cfg.recursion_cycle = Severity::Error;
+    cfg.shadowed_bind = Severity::Allow;
+    cfg.implicit_dup = Severity::Allow;
     cfg
   }
 }
 
+impl WarningType {
+  /// Returns the warning with the given name, as used by the `-W`, `-A` and `-D` options.
+  /// Underscores are accepted in place of dashes.
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name.replace('_', "-").as_str() {
+      "irrefutable-match" => Some(WarningType::IrrefutableMatch),
+      "redundant-match" => Some(WarningType::RedundantMatch),
+      "unreachable-match" => Some(WarningType::UnreachableMatch),
+      "unused-definition" => Some(WarningType::UnusedDefinition),
+      "repeated-bind" => Some(WarningType::RepeatedBind),
+      "recursion-cycle" => Some(WarningType::RecursionCycle),
+      "partial-application" => Some(WarningType::PartialApplication),
+      "shadowed-bind" => Some(WarningType::ShadowedBind),
+      "unused-variable" => Some(WarningType::UnusedVariable),
+      "implicit-dup" => Some(WarningType::ImplicitDup),
+      _ => None,
+    }
+  }
+}
+
 impl Display for Diagnostic {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
     write!(f, "{}", self.message)
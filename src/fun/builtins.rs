@@ -37,10 +37,41 @@ pub const IO: &str = "IO";
 pub const IO_DONE: &str = "IO/Done";
 pub const IO_CALL: &str = "IO/Call";
 
-pub const BUILTIN_CTRS: &[&str] =
-  &[LCONS, LNIL, SCONS, SNIL, NAT_SUCC, NAT_ZERO, TREE_NODE, TREE_LEAF, MAP_NODE, MAP_LEAF, IO_DONE, IO_CALL];
-
-pub const BUILTIN_TYPES: &[&str] = &[LIST, STRING, NAT, TREE, MAP, IO];
+pub const JSON: &str = "Json";
+
+pub const JSON_NULL: &str = "Json/Null";
+pub const JSON_BOOL: &str = "Json/Bool";
+pub const JSON_NUMBER: &str = "Json/Number";
+pub const JSON_STRING: &str = "Json/String";
+pub const JSON_ARRAY: &str = "Json/Array";
+pub const JSON_OBJECT: &str = "Json/Object";
+
+pub const BUILTIN_CTRS: &[&str] = &[
+  LCONS,
+  LNIL,
+  SCONS,
+  SNIL,
+  NAT_SUCC,
+  NAT_ZERO,
+  TREE_NODE,
+  TREE_LEAF,
+  MAP_NODE,
+  MAP_LEAF,
+  IO_DONE,
+  IO_CALL,
+  JSON_NULL,
+  JSON_BOOL,
+  JSON_NUMBER,
+  JSON_STRING,
+  JSON_ARRAY,
+  JSON_OBJECT,
+];
+
+pub const BUILTIN_TYPES: &[&str] = &[LIST, STRING, NAT, TREE, MAP, IO, JSON];
+
+/// Builtin types that aren't used by the compiler, so a program can define its own version of them.
+/// Defining the type or anything in its module replaces the builtin type and all of its module.
+pub const REPLACEABLE_TYPES: &[&str] = &[JSON];
 
 impl Book {
   pub fn builtins() -> Book {
@@ -49,6 +80,20 @@ impl Book {
       .expect("Error parsing builtin file, this should not happen")
   }
 
+  /// Removes a builtin type with its constructors and the builtin definitions of its module,
+  /// if `name` is part of a replaceable one, like `Json` or `Json/parse`.
+  pub fn remove_replaceable_builtin(&mut self, name: &Name) {
+    let typ = Name::new(name.split('/').next().unwrap());
+    if !REPLACEABLE_TYPES.contains(&typ.as_ref()) || !self.adts.get(&typ).is_some_and(|adt| adt.builtin) {
+      return;
+    }
+    let prefix = format!("{typ}/");
+    self.adts.shift_remove(&typ);
+    self.ctrs.retain(|_, ctr_typ| *ctr_typ != typ);
+    self.defs.retain(|name, def| !(def.builtin && name.starts_with(&prefix)));
+    self.hvm_defs.retain(|name, def| !(def.builtin && name.starts_with(&prefix)));
+  }
+
   pub fn encode_builtins(&mut self) {
     for def in self.defs.values_mut() {
       for rule in def.rules.iter_mut() {
@@ -66,6 +111,9 @@ impl Term {
       Term::Str { val } => *self = Term::encode_str(val),
       Term::Nat { val } => *self = Term::encode_nat(*val),
       _ => {
+        if let Term::Lam { pat, .. } = self {
+          pat.encode_builtins();
+        }
         for child in self.children_mut() {
           child.encode_builtins();
         }
@@ -0,0 +1,129 @@
+use crate::{
+  diagnostics::{Diagnostics, WarningType},
+  fun::{Ctx, Name, Pattern, Term},
+  maybe_grow,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImplicitDupErr {
+  var: Name,
+  uses: u64,
+}
+
+/// How many times a variable is used, counting only one arm of each match, and whether it's applied.
+#[derive(Debug, Clone, Copy, Default)]
+struct Uses {
+  count: u64,
+  applied: bool,
+}
+
+impl Ctx<'_> {
+  /// Finds the variables bound by lambdas and rule patterns that are applied as functions
+  /// and used more than once in the same branch.
+  ///
+  /// These variables are duplicated automatically, and duplicating a function doesn't share the work
+  /// done inside its body, so each copy redoes it. If the value is a superposition or a function that
+  /// duplicates its own arguments, the copies can also interact in unexpected ways.
+  /// Writing the duplication explicitly with `let {a b} = x` makes this visible.
+  ///
+  /// Precondition: `open` terms have been desugared.
+  pub fn check_implicit_dups(&mut self) -> Result<(), Diagnostics> {
+    self.info.start_pass();
+
+    for def in self.book.defs.values() {
+      // Builtins and the encoded constructors are not written by the user.
+      if def.builtin || self.book.ctrs.contains_key(&def.name) {
+        continue;
+      }
+      let mut errs = vec![];
+      for rule in def.rules.iter() {
+        for nam in rule.pats.iter().flat_map(|pat| pat.binds().flatten()) {
+          check_var(nam, &rule.body, &mut errs);
+        }
+        rule.body.check_implicit_dups(&mut errs);
+      }
+      for err in errs {
+        self.info.add_rule_warning(err, WarningType::ImplicitDup, def.name.clone());
+      }
+    }
+
+    self.info.fatal(())
+  }
+}
+
+impl Term {
+  fn check_implicit_dups(&self, errs: &mut Vec<ImplicitDupErr>) {
+    maybe_grow(|| {
+      if let Term::Lam { pat, bod, .. } = self {
+        if let Pattern::Var(Some(nam)) = pat.as_ref() {
+          check_var(nam, bod, errs);
+        }
+      }
+      for child in self.children() {
+        child.check_implicit_dups(errs);
+      }
+    })
+  }
+
+  fn count_uses(&self, nam: &Name) -> Uses {
+    maybe_grow(|| {
+      let mut uses = Uses::default();
+      match self {
+        Term::Var { nam: var } if var == nam => uses.count = 1,
+        Term::App { fun, .. } if matches!(fun.as_ref(), Term::Var { nam: var } if var == nam) => {
+          uses.applied = true;
+        }
+        _ => (),
+      }
+
+      // Only one of the arms of a match is taken, so they don't duplicate the variable between them.
+      let n_args = match self {
+        Term::Mat { with_arg, .. } | Term::Fold { with_arg, .. } | Term::Swt { with_arg, .. } => {
+          1 + with_arg.len()
+        }
+        Term::Bend { arg, .. } => arg.len() + 1,
+        _ => usize::MAX,
+      };
+      let mut arms = Uses::default();
+      for (i, (child, mut binds)) in self.children_with_binds().enumerate() {
+        if binds.any(|bind| bind.as_ref() == Some(nam)) {
+          continue;
+        }
+        let child = child.count_uses(nam);
+        uses.applied |= child.applied;
+        if i < n_args {
+          uses.count += child.count;
+        } else {
+          arms.count = arms.count.max(child.count);
+        }
+      }
+      uses.count += arms.count;
+      uses
+    })
+  }
+}
+
+fn check_var(nam: &Name, body: &Term, errs: &mut Vec<ImplicitDupErr>) {
+  // Generated names can't be written by the user.
+  if nam.starts_with('%') {
+    return;
+  }
+  let uses = body.count_uses(nam);
+  if uses.applied && uses.count > 1 {
+    let err = ImplicitDupErr { var: nam.clone(), uses: uses.count };
+    if !errs.contains(&err) {
+      errs.push(err);
+    }
+  }
+}
+
+impl std::fmt::Display for ImplicitDupErr {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let var = &self.var;
+    write!(
+      f,
+      "Function '{var}' is used {} times and implicitly duplicated. Use 'let {{{var}1 {var}2}} = {var}' to duplicate it explicitly.",
+      self.uses
+    )
+  }
+}
@@ -1,4 +1,10 @@
+pub mod implicit_dups;
 pub mod set_entrypoint;
+pub mod shadowed_binds;
 pub mod shared_names;
+pub mod target_support;
+pub mod type_params;
 pub mod unbound_refs;
 pub mod unbound_vars;
+pub mod unscoped_vars;
+pub mod unused_vars;
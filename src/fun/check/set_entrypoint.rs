@@ -1,6 +1,6 @@
 use crate::{
-  fun::{Book, Ctx, Definition, Name},
-  ENTRY_POINT, HVM1_ENTRY_POINT,
+  fun::{Book, Ctx, Definition, Name, Rule, Term},
+  maybe_grow, ENTRY_POINT, HVM1_ENTRY_POINT,
 };
 
 #[derive(Debug, Clone)]
@@ -12,6 +12,12 @@ pub enum EntryErr {
 
 impl Ctx<'_> {
   pub fn set_entrypoint(&mut self) {
+    if let Some(custom) = self.book.entrypoint.clone() {
+      if !matches!(custom.as_ref(), ENTRY_POINT | HVM1_ENTRY_POINT) {
+        return self.set_custom_entrypoint(custom);
+      }
+    }
+
     let mut entrypoint = None;
 
     let (custom, main, hvm1_main) = self.book.get_possible_entry_points();
@@ -49,6 +55,70 @@ impl Ctx<'_> {
 
     self.book.entrypoint = entrypoint;
   }
+
+  /// Sets a definition chosen by the user as the entrypoint, taking precedence over `main`.
+  ///
+  /// So that the definition can still be referenced by the rest of the program
+  /// and have any number of rules, the entrypoint is a new definition that calls it.
+  fn set_custom_entrypoint(&mut self, custom: Name) {
+    if !self.book.defs.contains_key(&custom) {
+      self.info.add_book_error(EntryErr::NotFound(custom));
+      self.book.entrypoint = None;
+      return;
+    }
+    let entry = Name::new(format!("{custom}{CUSTOM_ENTRY_SUFFIX}"));
+    let body = Term::Var { nam: custom };
+    self
+      .book
+      .defs
+      .insert(entry.clone(), Definition::new(entry.clone(), vec![Rule { pats: vec![], body }], false));
+    self.book.entrypoint = Some(entry);
+  }
+}
+
+/// Suffix of the definition generated to call a custom entrypoint.
+pub const CUSTOM_ENTRY_SUFFIX: &str = "__entry";
+
+/// Name given to a user's `main` definition when it's not the entrypoint,
+/// since the entrypoint is always compiled to HVM's `main`.
+pub const RENAMED_MAIN: &str = "main__user";
+
+impl Book {
+  /// Renames a `main` definition that's not the entrypoint, so that it doesn't clash with the
+  /// entrypoint when compiled to HVM.
+  ///
+  /// Precondition: References have been resolved.
+  pub fn rename_main(&mut self) {
+    let main = Name::new(ENTRY_POINT);
+    if self.entrypoint.is_none() || self.entrypoint.as_ref() == Some(&main) {
+      return;
+    }
+    let Some(mut def) = self.defs.shift_remove(&main) else { return };
+    let renamed = Name::new(RENAMED_MAIN);
+    def.name = renamed.clone();
+    self.defs.insert(renamed.clone(), def);
+
+    for def in self.defs.values_mut() {
+      for rule in def.rules.iter_mut() {
+        rule.body.rename_ref(&main, &renamed);
+      }
+    }
+  }
+}
+
+impl Term {
+  fn rename_ref(&mut self, from: &Name, to: &Name) {
+    maybe_grow(|| {
+      if let Term::Ref { nam } = self {
+        if nam == from {
+          *nam = to.clone();
+        }
+      }
+      for child in self.children_mut() {
+        child.rename_ref(from, to);
+      }
+    })
+  }
 }
 
 fn validate_entry_point(entry: &Definition) -> Result<Name, EntryErr> {
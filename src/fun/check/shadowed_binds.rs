@@ -0,0 +1,125 @@
+use crate::{
+  diagnostics::{Diagnostics, WarningType},
+  fun::{Book, Ctx, Name, Term},
+  maybe_grow,
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShadowedBindErr {
+  Local(Name),
+  Def(Name),
+}
+
+impl Ctx<'_> {
+  /// Finds the variables that are bound with the same name as
+  /// a variable in an outer scope or as a top level definition.
+  ///
+  /// Variables that are explicitly rebound to the same value,
+  /// like the argument of `match x` and the variables of a `with` clause, are not reported.
+  ///
+  /// Precondition: `open` terms have been desugared.
+  pub fn check_shadowed_binds(&mut self) -> Result<(), Diagnostics> {
+    self.info.start_pass();
+
+    for def in self.book.defs.values() {
+      // Builtins and the encoded constructors are not written by the user.
+      if def.builtin || self.book.ctrs.contains_key(&def.name) {
+        continue;
+      }
+      let mut errs = vec![];
+      for rule in def.rules.iter() {
+        let mut scope = HashMap::new();
+        for nam in rule.pats.iter().flat_map(|pat| pat.binds().flatten()) {
+          if self.book.is_top_level_name(nam) {
+            push_err(&mut errs, ShadowedBindErr::Def(nam.clone()));
+          }
+          *scope.entry(nam.as_ref()).or_default() += 1;
+        }
+        rule.body.check_shadowed_binds(self.book, &mut scope, &mut errs);
+      }
+      for err in errs {
+        self.info.add_rule_warning(err, WarningType::ShadowedBind, def.name.clone());
+      }
+    }
+
+    self.info.fatal(())
+  }
+}
+
+impl Term {
+  fn check_shadowed_binds<'a>(
+    &'a self,
+    book: &Book,
+    scope: &mut HashMap<&'a str, u64>,
+    errs: &mut Vec<ShadowedBindErr>,
+  ) {
+    maybe_grow(|| {
+      let rebound = self.rebound_names();
+      for (child, binds) in self.children_with_binds() {
+        let binds = binds.flatten().collect::<Vec<_>>();
+        // Generated names can't be written by the user.
+        for nam in binds.iter().filter(|nam| !rebound.contains(nam) && !nam.starts_with('%')) {
+          if scope.contains_key(nam.as_ref()) {
+            push_err(errs, ShadowedBindErr::Local((*nam).clone()));
+          } else if book.is_top_level_name(nam) {
+            push_err(errs, ShadowedBindErr::Def((*nam).clone()));
+          }
+        }
+
+        for &nam in &binds {
+          *scope.entry(nam.as_ref()).or_default() += 1;
+        }
+        child.check_shadowed_binds(book, scope, errs);
+        for &nam in &binds {
+          let count = scope.get_mut(nam.as_ref()).unwrap();
+          *count -= 1;
+          if *count == 0 {
+            scope.remove(nam.as_ref());
+          }
+        }
+      }
+    })
+  }
+
+  /// The names that a match term binds again to the value they already had.
+  fn rebound_names(&self) -> Vec<&Name> {
+    match self {
+      Term::Mat { arg, bnd, with_bnd, .. }
+      | Term::Swt { arg, bnd, with_bnd, .. }
+      | Term::Fold { arg, bnd, with_bnd, .. } => {
+        let mut names = with_bnd.iter().flatten().collect::<Vec<_>>();
+        if let (Term::Var { nam }, Some(bnd)) = (arg.as_ref(), bnd) {
+          if nam == bnd {
+            names.push(bnd);
+          }
+        }
+        names
+      }
+      _ => vec![],
+    }
+  }
+}
+
+impl Book {
+  fn is_top_level_name(&self, nam: &Name) -> bool {
+    self.defs.contains_key(nam) || self.hvm_defs.contains_key(nam)
+  }
+}
+
+fn push_err(errs: &mut Vec<ShadowedBindErr>, err: ShadowedBindErr) {
+  if !errs.contains(&err) {
+    errs.push(err);
+  }
+}
+
+impl std::fmt::Display for ShadowedBindErr {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ShadowedBindErr::Local(nam) => write!(f, "Variable '{nam}' shadows a variable bound before it."),
+      ShadowedBindErr::Def(nam) => {
+        write!(f, "Variable '{nam}' shadows the top level definition with the same name.")
+      }
+    }
+  }
+}
@@ -10,8 +10,8 @@ pub struct RepeatedTopLevelNameErr {
 }
 
 impl Ctx<'_> {
-  /// Checks if there are any repeated top level names. Constructors
-  /// and functions can't share names and adts can't share names.
+  /// Checks if there are any repeated top level names. Constructors,
+  /// functions and macros can't share names and adts can't share names.
   pub fn check_shared_names(&mut self) {
     let mut names = NameInfo::default();
 
@@ -27,6 +27,10 @@ impl Ctx<'_> {
       names.add_name(def_name, NameKind::Def);
     }
 
+    for macro_name in self.book.macros.keys() {
+      names.add_name(macro_name, NameKind::Macro);
+    }
+
     for err in names.into_errs() {
       self.info.add_book_error(err);
     }
@@ -38,6 +42,7 @@ enum NameKind {
   Adt,
   Def,
   Ctr,
+  Macro,
 }
 
 #[derive(Debug, Default)]
@@ -80,6 +85,7 @@ impl Display for NameKind {
       NameKind::Adt => write!(f, "data type"),
       NameKind::Def => write!(f, "function"),
       NameKind::Ctr => write!(f, "constructor"),
+      NameKind::Macro => write!(f, "macro"),
     }
   }
 }
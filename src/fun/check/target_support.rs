@@ -0,0 +1,119 @@
+use crate::{
+  diagnostics::Diagnostics,
+  fun::{Book, Name, Term},
+  hvm::prune::prune_hvm_book,
+  maybe_grow,
+};
+
+/// The IO functions implemented by the C and CUDA runtimes.
+pub const NATIVE_IO_FUNCS: &[&str] =
+  &["READ_CHAR", "READ_LINE", "OPEN_FILE", "CLOSE_FILE", "WRITE", "GET_TIME", "SLEEP"];
+
+/// The IO functions called by each definition, by name.
+pub type IoCalls = Vec<(Name, Vec<String>)>;
+
+impl Book {
+  /// The IO functions called directly by each definition,
+  /// like `"WRITE"` in `(IO/Call IO/MAGIC "WRITE" args λx x)` or in `(call "WRITE" args)`.
+  ///
+  /// Must be called before the strings are encoded.
+  pub fn io_calls(&self) -> IoCalls {
+    let mut calls = IoCalls::new();
+    for def in self.defs.values() {
+      let mut funcs = vec![];
+      for rule in &def.rules {
+        rule.body.io_calls(&mut funcs);
+      }
+      if !funcs.is_empty() {
+        calls.push((def.name.clone(), funcs));
+      }
+    }
+    calls
+  }
+}
+
+impl Term {
+  fn io_calls(&self, funcs: &mut Vec<String>) {
+    maybe_grow(|| {
+      if let Some(func) = self.io_call_func() {
+        if !funcs.iter().any(|f| f == func) {
+          funcs.push(func.to_string());
+        }
+      }
+      for child in self.children() {
+        child.io_calls(funcs);
+      }
+    })
+  }
+
+  /// The name of the IO function called by this application, if it's a call with a literal function name.
+  fn io_call_func(&self) -> Option<&str> {
+    let mut args = vec![];
+    let mut head = self;
+    while let Term::App { fun, arg, .. } = head {
+      args.push(arg.as_ref());
+      head = fun;
+    }
+    args.reverse();
+    let (Term::Var { nam } | Term::Ref { nam }) = head else { return None };
+    let func = match (nam.as_ref(), args.as_slice()) {
+      ("IO/Call", [_, func, ..]) => func,
+      ("call", [func, ..]) => func,
+      _ => return None,
+    };
+    match func {
+      Term::Str { val } => Some(val.as_ref()),
+      _ => None,
+    }
+  }
+}
+
+/// Reports the IO functions that the given backend can't run, used by the definitions reachable from the entrypoint.
+///
+/// The C and CUDA runtimes only implement [`NATIVE_IO_FUNCS`],
+/// while the Rust and JavaScript backends return the IO actions as the result, without performing them.
+pub fn check_target_io(
+  io_calls: &IoCalls,
+  hvm_book: &::hvm::ast::Book,
+  entrypoint: &str,
+  target: &str,
+  diagnostics: &mut Diagnostics,
+) -> Result<(), Diagnostics> {
+  diagnostics.start_pass();
+
+  // Only the definitions that can be called by the program, since every program includes the IO builtins.
+  let mut used = ::hvm::ast::Book { defs: hvm_book.defs.clone() };
+  prune_hvm_book(&mut used, &[entrypoint.to_string()]);
+  let mut used = used.defs.keys().map(|name| Name::new(name).def_name_from_generated()).collect::<Vec<_>>();
+  used.sort();
+  used.dedup();
+
+  for (def_name, funcs) in io_calls {
+    if used.binary_search(def_name).is_err() {
+      continue;
+    }
+    match target {
+      "c" | "cuda" => {
+        let runtime = if target == "c" { "C" } else { "CUDA" };
+        for func in funcs.iter().filter(|func| !NATIVE_IO_FUNCS.contains(&func.as_str())) {
+          diagnostics.add_rule_error(
+            format!("Calls the IO function '{func}', which is not implemented by the {runtime} runtime."),
+            def_name.clone(),
+          );
+        }
+      }
+      _ => {
+        let backend = if target == "js" { "JavaScript" } else { "Rust" };
+        diagnostics.add_rule_error(
+          format!(
+            "Uses IO ({}), which is not performed by the {backend} backend. Its IO actions are returned as the result instead.",
+            funcs.iter().map(|func| format!("'{func}'")).collect::<Vec<_>>().join(", ")
+          ),
+          def_name.clone(),
+        );
+      }
+    }
+  }
+
+  diagnostics.fatal(())
+}
@@ -0,0 +1,52 @@
+use crate::fun::{Adt, Adts, Ctx, FieldType};
+
+/// The number types that can be given to constructor fields.
+const NUM_TYPES: &[&str] = &["u24", "i24", "f24"];
+
+impl Ctx<'_> {
+  /// Checks the types given to the fields of the constructors.
+  /// They must be parameters of the type being defined, numbers, or
+  /// other types applied to as many types as they have parameters.
+  pub fn check_type_params(&mut self) {
+    for adt in self.book.adts.values() {
+      for (ctr_name, fields) in adt.ctrs.iter() {
+        for field in fields {
+          let Some(typ) = &field.typ else { continue };
+          if let Err(err) = check_field_type(typ, adt, &self.book.adts) {
+            self.info.add_book_error(format!("{err} In field '{}' of constructor '{ctr_name}'.", field.nam));
+          }
+        }
+      }
+    }
+  }
+}
+
+fn check_field_type(typ: &FieldType, adt: &Adt, adts: &Adts) -> Result<(), String> {
+  let n_params = if adt.params.contains(&typ.nam) || NUM_TYPES.contains(&typ.nam.as_ref()) {
+    0
+  } else if let Some(typ_adt) = adts.get(&typ.nam) {
+    typ_adt.params.len()
+  } else {
+    return Err(format!("Unknown type '{}'.", typ.nam));
+  };
+  if typ.args.len() != n_params {
+    return Err(format!(
+      "Type '{}' expects {n_params} type {}, but got {}.",
+      typ.nam,
+      plural(n_params),
+      typ.args.len()
+    ));
+  }
+  for arg in &typ.args {
+    check_field_type(arg, adt, adts)?;
+  }
+  Ok(())
+}
+
+fn plural(n: usize) -> &'static str {
+  if n == 1 {
+    "argument"
+  } else {
+    "arguments"
+  }
+}
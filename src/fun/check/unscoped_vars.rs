@@ -0,0 +1,144 @@
+use crate::{
+  diagnostics::Diagnostics,
+  fun::{Ctx, Name, Pattern, Term},
+  maybe_grow,
+};
+use indexmap::IndexMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnscopedVarErr {
+  /// The variable is used in a definition, but only bound in another one.
+  OtherDef { var: Name, bound_in: Name },
+  /// The variable is bound and used on different sides of the body of a `fold` or `bend`.
+  CrossesBody { var: Name, kind: &'static str },
+}
+
+/// Where an unscoped variable is bound or used.
+/// The body of each `fold` and `bend` is numbered, since it will become a separate definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Occurrence {
+  is_bind: bool,
+  body: Option<(usize, &'static str)>,
+}
+
+type Occurrences = IndexMap<Name, Vec<Occurrence>>;
+
+impl Ctx<'_> {
+  /// Checks that each unscoped variable is bound and used in places that end up in the same net.
+  ///
+  /// Each definition is compiled to a separate net, and the bodies of `fold` and `bend`
+  /// are extracted to new definitions, so an unscoped variable can't cross these boundaries.
+  /// Otherwise, the variable would be reported as unbound in one net and unused in the other,
+  /// often in a generated definition that the user didn't write.
+  ///
+  /// The number of binds and uses of each variable is checked afterwards by [`Ctx::check_unbound_vars`].
+  ///
+  /// Precondition: `open` terms have been desugared.
+  pub fn check_unscoped_vars(&mut self) -> Result<(), Diagnostics> {
+    self.info.start_pass();
+
+    let mut occurrences = IndexMap::new();
+    for (def_name, def) in self.book.defs.iter() {
+      let mut occs = Occurrences::new();
+      let mut n_bodies = 0;
+      for rule in def.rules.iter() {
+        for pat in rule.pats.iter() {
+          collect_pat_binds(pat, None, &mut occs);
+        }
+        rule.body.collect_unscoped_occs(None, &mut n_bodies, &mut occs);
+      }
+      occurrences.insert(def_name.clone(), occs);
+    }
+
+    for (def_name, occs) in occurrences.iter() {
+      let mut errs = vec![];
+      for (var, var_occs) in occs.iter() {
+        let has_bind = var_occs.iter().any(|occ| occ.is_bind);
+        let has_use = var_occs.iter().any(|occ| !occ.is_bind);
+
+        if has_bind && has_use {
+          let crossing = var_occs.iter().find(|occ| occ.body != var_occs[0].body).map(|occ| occ.body);
+          if let Some(body) = crossing {
+            let (_, kind) = body.or(var_occs[0].body).unwrap();
+            errs.push(UnscopedVarErr::CrossesBody { var: var.clone(), kind });
+          }
+        } else if has_use {
+          // Only report it if some other definition binds the variable without using it.
+          let bound_in = occurrences.iter().find(|(other, other_occs)| {
+            *other != def_name && other_occs.get(var).is_some_and(|occs| occs.iter().all(|occ| occ.is_bind))
+          });
+          if let Some((bound_in, _)) = bound_in {
+            errs.push(UnscopedVarErr::OtherDef { var: var.clone(), bound_in: bound_in.clone() });
+          }
+        }
+      }
+      for err in errs {
+        self.info.add_rule_error(err, def_name.clone());
+      }
+    }
+
+    self.info.fatal(())
+  }
+}
+
+impl Term {
+  fn collect_unscoped_occs(
+    &self,
+    body: Option<(usize, &'static str)>,
+    n_bodies: &mut usize,
+    occs: &mut Occurrences,
+  ) {
+    maybe_grow(|| {
+      if let Term::Link { nam } = self {
+        occs.entry(nam.clone()).or_default().push(Occurrence { is_bind: false, body });
+      }
+      if let Some(pat) = self.pattern() {
+        collect_pat_binds(pat, body, occs);
+      }
+
+      // The first children of a `fold` or `bend` are the arguments, that stay where they are.
+      let (n_args, kind) = match self {
+        Term::Fold { with_arg, .. } => (1 + with_arg.len(), "fold"),
+        Term::Bend { arg, .. } => (arg.len(), "bend"),
+        _ => (usize::MAX, ""),
+      };
+      let inner = if n_args != usize::MAX {
+        *n_bodies += 1;
+        Some((*n_bodies, kind))
+      } else {
+        None
+      };
+      for (i, child) in self.children().enumerate() {
+        let child_body = if i < n_args { body } else { inner };
+        child.collect_unscoped_occs(child_body, n_bodies, occs);
+      }
+    })
+  }
+}
+
+fn collect_pat_binds(pat: &Pattern, body: Option<(usize, &'static str)>, occs: &mut Occurrences) {
+  maybe_grow(|| {
+    if let Pattern::Chn(nam) = pat {
+      occs.entry(nam.clone()).or_default().push(Occurrence { is_bind: true, body });
+    }
+    for child in pat.children() {
+      collect_pat_binds(child, body, occs);
+    }
+  })
+}
+
+impl std::fmt::Display for UnscopedVarErr {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      UnscopedVarErr::OtherDef { var, bound_in } => write!(
+        f,
+        "Unscoped variable '${var}' is bound by 'λ${var}' in definition '{}', but used here. Unscoped variables can't be shared between definitions.",
+        bound_in.display_origin()
+      ),
+      UnscopedVarErr::CrossesBody { var, kind } => write!(
+        f,
+        "Unscoped variable '${var}' is bound and used on different sides of the body of a '{kind}'. The body becomes a separate function, so it can't share unscoped variables with the code around it."
+      ),
+    }
+  }
+}
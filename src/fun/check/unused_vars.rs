@@ -0,0 +1,128 @@
+use crate::{
+  diagnostics::{Diagnostics, WarningType},
+  fun::{Ctx, Name, Term},
+  maybe_grow,
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnusedVarErr {
+  var: Name,
+  /// Whether the variable was bound again by a `let`, replacing a variable with the same name.
+  rebound: bool,
+}
+
+impl Ctx<'_> {
+  /// Finds the variables bound by rule patterns, lambdas, `let`s and `use`s that are never used.
+  /// These variables are silently erased, so not using them is often a mistake.
+  ///
+  /// Variables starting with `_` are not reported,
+  /// like variables that are replaced by the `*` eraser pattern.
+  ///
+  /// The variables bound by `match`, `switch`, `fold` and `bend` terms are not checked,
+  /// since not all arms are expected to use them.
+  ///
+  /// Rebinding a variable, like `x = x * 2` or `x += 1` in the imperative syntax,
+  /// and then never reading it is reported with its own message.
+  ///
+  /// Precondition: `open` terms have been desugared.
+  pub fn check_unused_vars(&mut self) -> Result<(), Diagnostics> {
+    self.info.start_pass();
+
+    for def in self.book.defs.values() {
+      // Builtins and the encoded constructors are not written by the user.
+      if def.builtin || self.book.ctrs.contains_key(&def.name) {
+        continue;
+      }
+      let mut errs = vec![];
+      for rule in def.rules.iter() {
+        let binds = unique_binds(rule.pats.iter().flat_map(|pat| pat.binds().flatten()));
+        let mut scope = HashMap::new();
+        push_scope(&binds, &mut scope);
+        rule.body.check_unused_vars(&mut scope, &mut errs);
+        pop_scope(&binds, &mut scope, Some(&mut errs), false);
+      }
+      for err in errs {
+        self.info.add_rule_warning(err, WarningType::UnusedVariable, def.name.clone());
+      }
+    }
+
+    self.info.fatal(())
+  }
+}
+
+impl Term {
+  fn check_unused_vars<'a>(&'a self, scope: &mut HashMap<&'a str, Vec<u64>>, errs: &mut Vec<UnusedVarErr>) {
+    maybe_grow(|| {
+      if let Term::Var { nam } = self {
+        if let Some(uses) = scope.get_mut(nam.as_ref()).and_then(|uses| uses.last_mut()) {
+          *uses += 1;
+        }
+      }
+
+      let checked = matches!(self, Term::Lam { .. } | Term::Let { .. } | Term::Ask { .. } | Term::Use { .. });
+      let assigns = matches!(self, Term::Let { .. } | Term::Ask { .. });
+      for (child, binds) in self.children_with_binds() {
+        let binds = unique_binds(binds.flatten());
+        push_scope(&binds, scope);
+        child.check_unused_vars(scope, errs);
+        pop_scope(&binds, scope, if checked { Some(errs) } else { None }, assigns);
+      }
+    })
+  }
+}
+
+/// Repeated binds are erased and reported by another pass, so only the last one is checked.
+fn unique_binds<'a>(binds: impl DoubleEndedIterator<Item = &'a Name>) -> Vec<&'a Name> {
+  let mut unique = vec![];
+  for nam in binds.rev() {
+    if !unique.contains(&nam) {
+      unique.push(nam);
+    }
+  }
+  unique.reverse();
+  unique
+}
+
+fn push_scope<'a>(binds: &[&'a Name], scope: &mut HashMap<&'a str, Vec<u64>>) {
+  for &nam in binds {
+    scope.entry(nam.as_ref()).or_default().push(0);
+  }
+}
+
+/// Removes the binds from the scope, reporting the ones that were not used if `errs` is given.
+/// If the binds were assigned, the ones that replace an outer variable are reported as rebound.
+fn pop_scope(
+  binds: &[&Name],
+  scope: &mut HashMap<&str, Vec<u64>>,
+  mut errs: Option<&mut Vec<UnusedVarErr>>,
+  assigns: bool,
+) {
+  for &nam in binds.iter().rev() {
+    let uses = scope.get_mut(nam.as_ref()).unwrap();
+    let n_uses = uses.pop().unwrap();
+    let rebound = assigns && !uses.is_empty();
+    if uses.is_empty() {
+      scope.remove(nam.as_ref());
+    }
+    // Generated names can't be written by the user.
+    if n_uses == 0 && !nam.starts_with('_') && !nam.starts_with('%') {
+      if let Some(errs) = errs.as_deref_mut() {
+        let err = UnusedVarErr { var: nam.clone(), rebound };
+        if !errs.contains(&err) {
+          errs.push(err);
+        }
+      }
+    }
+  }
+}
+
+impl std::fmt::Display for UnusedVarErr {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if self.rebound {
+      write!(f, "Variable '{}' is rebound, but the new value is never read.", self.var)
+    } else {
+      write!(f, "Variable '{}' is unused.", self.var)
+    }
+  }
+}
@@ -1,4 +1,6 @@
-use super::{Book, Definition, FanKind, Name, Num, Op, Pattern, Rule, Tag, Term};
+use super::{
+  Book, Definition, ExportSig, ExportType, FanKind, GeneratedKind, Name, Num, Op, Pattern, Rule, Tag, Term,
+};
 use crate::maybe_grow;
 use std::{fmt, ops::Deref, sync::atomic::AtomicU64};
 
@@ -235,6 +237,58 @@ impl fmt::Display for Name {
   }
 }
 
+impl Name {
+  /// Shows where a generated definition comes from, like `fib (combinator #2)`.
+  /// Other names are shown as they are.
+  pub fn display_origin(&self) -> impl fmt::Display + '_ {
+    DisplayFn(move |f| {
+      let Some(origins) = self.generated_origins() else { return write!(f, "{self}") };
+      for (i, (def, steps)) in origins.iter().enumerate() {
+        if i != 0 {
+          write!(f, ", ")?;
+        }
+        write!(f, "{def}")?;
+        if !steps.is_empty() {
+          write!(f, " ({})", DisplayJoin(|| steps, ", "))?;
+        }
+      }
+      Ok(())
+    })
+  }
+}
+
+impl fmt::Display for GeneratedKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      GeneratedKind::Combinator(n) => write!(f, "combinator #{n}"),
+      GeneratedKind::Fold(n) => write!(f, "fold #{n}"),
+      GeneratedKind::Bend(n) => write!(f, "bend #{n}"),
+      GeneratedKind::Local(nam) => write!(f, "local def {nam}"),
+      GeneratedKind::Where(nam) => write!(f, "where {nam}"),
+      GeneratedKind::Entry => write!(f, "entrypoint"),
+      GeneratedKind::Specialized(n) => write!(f, "specialization #{n}"),
+    }
+  }
+}
+
+impl fmt::Display for ExportSig {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "({}) -> {}", DisplayJoin(|| self.args.iter(), ", "), self.ret)
+  }
+}
+
+impl fmt::Display for ExportType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ExportType::U24 => write!(f, "u24"),
+      ExportType::I24 => write!(f, "i24"),
+      ExportType::F24 => write!(f, "f24"),
+      ExportType::String => write!(f, "String"),
+      ExportType::List(item) => write!(f, "List({item})"),
+    }
+  }
+}
+
 impl Term {
   fn display_app<'a>(&'a self, tag: &'a Tag) -> impl fmt::Display + 'a {
     maybe_grow(|| {
@@ -500,3 +554,62 @@ impl Term {
     })
   }
 }
+
+impl Term {
+  /// Shows a normalized term as JSON, for tools that consume the result of a program.
+  ///
+  /// Numbers, strings and lists become their JSON counterparts, tuples become `{"tuple": [..]}`
+  /// and constructors become `{"ctr": name, "fields": [..]}`.
+  /// Anything else, like lambdas and superpositions, becomes `{"term": ".."}` with the usual textual form.
+  pub fn display_json(&self) -> impl fmt::Display + '_ {
+    maybe_grow(|| {
+      DisplayFn(move |f| match self {
+        Term::Num { val: Num::U24(val) } => write!(f, "{val}"),
+        Term::Num { val: Num::I24(val) } => write!(f, "{val}"),
+        Term::Num { val: Num::F24(val) } if val.is_finite() => write!(f, "{val:?}"),
+        Term::Nat { val } => write!(f, "{val}"),
+        Term::Str { val } => write!(f, "{}", json_string(val)),
+        Term::List { els } => write!(f, "[{}]", DisplayJoin(|| els.iter().map(|e| e.display_json()), ", ")),
+        Term::Fan { fan: FanKind::Tup, tag: Tag::Static, els } => {
+          write!(f, "{{\"tuple\": [{}]}}", DisplayJoin(|| els.iter().map(|e| e.display_json()), ", "))
+        }
+        Term::Era => write!(f, "null"),
+        Term::Ref { nam } => write!(f, "{{\"ctr\": {}, \"fields\": []}}", json_string(nam)),
+        Term::App { tag: Tag::Static, .. } => {
+          let mut args = vec![];
+          let mut head = self;
+          while let Term::App { tag: Tag::Static, fun, arg } = head {
+            args.push(arg.as_ref());
+            head = fun;
+          }
+          args.reverse();
+          if let Term::Ref { nam } = head {
+            let fields = DisplayJoin(|| args.iter().map(|e| e.display_json()), ", ");
+            write!(f, "{{\"ctr\": {}, \"fields\": [{}]}}", json_string(nam), fields)
+          } else {
+            write!(f, "{{\"term\": {}}}", json_string(&self.to_string()))
+          }
+        }
+        _ => write!(f, "{{\"term\": {}}}", json_string(&self.to_string())),
+      })
+    })
+  }
+}
+
+fn json_string(s: &str) -> String {
+  let mut out = String::with_capacity(s.len() + 2);
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+  out
+}
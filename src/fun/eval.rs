@@ -0,0 +1,693 @@
+use crate::{
+  fun::{num_to_name, Book, FanKind, Name, Num, Op, Pattern, Tag, Term},
+  maybe_grow,
+};
+use std::{fmt::Display, rc::Rc};
+
+/// Evaluates the entrypoint of a desugared book to its normal form by walking its terms.
+///
+/// This is an implementation of the semantics of the terms that doesn't depend on HVM:
+/// it doesn't build interaction nets, and it computes the numeric operations itself, following
+/// `docs/native-numbers.md`. `bend selftest` uses it as the reference the runtimes are compared against.
+///
+/// Arguments are evaluated before the functions are applied, and the bodies of the lambdas in the
+/// result are normalized by applying them to fresh variables.
+/// Duplicated values are copied instead of being shared lazily, so a duplication only interacts with
+/// the superpositions that it reaches directly, and a superposition inside a lambda is copied
+/// together with it.
+/// Programs that depend on other interactions of superpositions can give different results than HVM.
+///
+/// With `eta`, a lambda that only applies a function to its variable is the function itself,
+/// like in the eta-reduced nets of [`crate::hvm::eta_reduce`], so that the definitions that
+/// the reduction leaves unexpanded in the result are also unexpanded here.
+pub fn eval_book(book: &Book, eta: bool) -> Result<Term, EvalError> {
+  let Some(main) = book.entrypoint.as_ref().and_then(|main| book.defs.get(main)) else {
+    return Err(EvalError::MissingMain);
+  };
+  let mut evaluator = Evaluator { book, eta, next_var: 0 };
+  let val = evaluator.eval(&main.rule().body, None)?;
+  evaluator.readback(val)
+}
+
+#[derive(Debug, Clone)]
+pub enum EvalError {
+  MissingMain,
+  /// Divided an integer by zero.
+  DivisionByZero,
+  /// Applied a numeric operation to a type it isn't defined for, like shifting a float.
+  UndefinedOperation {
+    op: Op,
+    typ: &'static str,
+  },
+  /// Applied a numeric operation to numbers of different types.
+  MixedTypes {
+    op: Op,
+    fst: &'static str,
+    snd: &'static str,
+  },
+  /// Used something that this evaluator doesn't implement, like unscoped variables.
+  Unsupported(String),
+}
+
+/// The variables bound in a scope, with the innermost first.
+type Env<'t> = Option<Rc<Bind<'t>>>;
+
+struct Bind<'t> {
+  nam: &'t Name,
+  val: Val<'t>,
+  next: Env<'t>,
+}
+
+#[derive(Clone)]
+enum Val<'t> {
+  Era,
+  Num(Num),
+  /// A reference that wasn't expanded, since it wasn't applied or matched yet.
+  Ref(&'t Name),
+  Lam(Rc<Closure<'t>>),
+  /// A tuple or a superposition, with the label of its tag.
+  Fan(FanKind, Option<u16>, Rc<[Val<'t>; 2]>),
+  /// A native HVM definition, with the arguments it was applied to so far.
+  Prim(Prim, Rc<Vec<Val<'t>>>),
+  /// A computation stuck on a variable of a lambda being read back.
+  Neu(Rc<Neutral<'t>>),
+}
+
+struct Closure<'t> {
+  tag: &'t Tag,
+  pat: &'t Pattern,
+  bod: &'t Term,
+  env: Env<'t>,
+}
+
+#[derive(Clone)]
+enum Neutral<'t> {
+  Var(Name),
+  App(Tag, Val<'t>, Val<'t>),
+  Oper(Op, Val<'t>, Val<'t>),
+  /// A switch, with its zero arm and its function of the predecessor.
+  Swt(Val<'t>, Val<'t>, Val<'t>),
+  /// One of the two parts of a variable taken apart by a tuple pattern or a duplication,
+  /// kept apart so that putting them together again gives back the variable.
+  Part(FanKind, Option<u16>, usize, Rc<Neutral<'t>>),
+}
+
+/// The native HVM definitions of the builtins.
+/// Other HVM definitions can't be evaluated, since they're written as nets.
+#[derive(Clone, Copy)]
+enum Prim {
+  Log,
+  Atan2,
+  ToU24,
+  ToI24,
+  ToF24,
+}
+
+impl Prim {
+  fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "log" => Some(Prim::Log),
+      "atan2" => Some(Prim::Atan2),
+      "to_u24" => Some(Prim::ToU24),
+      "to_i24" => Some(Prim::ToI24),
+      "to_f24" => Some(Prim::ToF24),
+      _ => None,
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      Prim::Log => "log",
+      Prim::Atan2 => "atan2",
+      Prim::ToU24 => "to_u24",
+      Prim::ToI24 => "to_i24",
+      Prim::ToF24 => "to_f24",
+    }
+  }
+
+  fn arity(self) -> usize {
+    match self {
+      Prim::Log | Prim::Atan2 => 2,
+      Prim::ToU24 | Prim::ToI24 | Prim::ToF24 => 1,
+    }
+  }
+}
+
+struct Evaluator<'t> {
+  book: &'t Book,
+  eta: bool,
+  /// Counter for the names of the variables of the lambdas being read back.
+  next_var: u64,
+}
+
+impl<'t> Evaluator<'t> {
+  fn eval(&mut self, term: &'t Term, env: Env<'t>) -> Result<Val<'t>, EvalError> {
+    maybe_grow(|| {
+      let (mut term, mut env) = (term, env);
+      // Applications and switches continue in the same loop, so that tail calls don't grow the stack.
+      loop {
+        match term {
+          Term::Var { nam } => return lookup(&env, nam),
+          Term::Lam { tag, pat, bod } => match (pat.as_ref(), bod.as_ref()) {
+            (Pattern::Var(Some(var)), Term::App { tag: app_tag, fun, arg })
+              if self.eta && tag == app_tag && is_eta(var, fun, arg) =>
+            {
+              term = fun
+            }
+            _ => return Ok(Val::Lam(Rc::new(Closure { tag, pat, bod, env }))),
+          },
+          Term::App { tag, fun, arg } => {
+            let fun = self.eval(fun, env.clone())?;
+            let arg = self.eval(arg, env)?;
+            match self.force(fun)? {
+              Val::Lam(lam) => (term, env) = self.enter(&lam, tag, arg)?,
+              fun => return self.apply(fun, arg, tag),
+            }
+          }
+          Term::Let { pat, val, nxt } => {
+            let val = self.eval(val, env.clone())?;
+            env = self.bind(pat, val, env)?;
+            term = nxt;
+          }
+          Term::Fan { fan, tag, els } => {
+            // Like in the compiled nets, a fan of more than two elements is made of nested pairs.
+            let lab = label(tag)?;
+            let els = els.iter().map(|el| self.eval(el, env.clone())).collect::<Result<Vec<_>, _>>()?;
+            let mut els = els.into_iter().rev();
+            let last = els.next().unwrap();
+            return Ok(els.fold(last, |snd, fst| fan_val(*fan, lab, fst, snd)));
+          }
+          Term::Num { val } => return Ok(Val::Num(num(*val))),
+          Term::Oper { opr, fst, snd } => {
+            let fst = self.eval(fst, env.clone())?;
+            let snd = self.eval(snd, env)?;
+            return self.operate(*opr, fst, snd);
+          }
+          Term::Swt { arg, arms, .. } => {
+            let arg = self.eval(arg, env.clone())?;
+            match self.force(arg)? {
+              Val::Num(Num::U24(0)) => term = &arms[0],
+              Val::Num(Num::U24(n)) => {
+                let succ = self.eval(&arms[1], env)?;
+                let pred = Val::Num(Num::U24(n - 1));
+                match self.force(succ)? {
+                  Val::Lam(lam) => (term, env) = self.enter(&lam, &Tag::Static, pred)?,
+                  succ => return self.apply(succ, pred, &Tag::Static),
+                }
+              }
+              arg => return self.switch(arg, arms, env),
+            }
+          }
+          Term::Ref { nam } => return Ok(Val::Ref(nam)),
+          Term::Era => return Ok(Val::Era),
+          Term::Link { .. } => return Err(EvalError::Unsupported("unscoped variables".to_string())),
+          _ => {
+            return Err(EvalError::Unsupported(format!("the term '{term}', which should've been desugared")))
+          }
+        }
+      }
+    })
+  }
+
+  /// Returns the body of a lambda applied to `arg`, with the scope it's evaluated in.
+  fn enter(&mut self, lam: &Closure<'t>, tag: &Tag, arg: Val<'t>) -> Result<(&'t Term, Env<'t>), EvalError> {
+    if label(lam.tag)? != label(tag)? {
+      return Err(EvalError::Unsupported("applying a lambda with an application of another tag".to_string()));
+    }
+    Ok((lam.bod, self.bind(lam.pat, arg, lam.env.clone())?))
+  }
+
+  /// Expands the references to definitions until reaching a value.
+  fn force(&mut self, mut val: Val<'t>) -> Result<Val<'t>, EvalError> {
+    while let Val::Ref(nam) = val {
+      val = if let Some(def) = self.book.defs.get(nam) {
+        self.eval(&def.rule().body, None)?
+      } else if let Some(prim) = Prim::from_name(nam) {
+        Val::Prim(prim, Rc::new(vec![]))
+      } else {
+        return Err(EvalError::Unsupported(format!("the HVM definition '{nam}'")));
+      };
+    }
+    Ok(val)
+  }
+
+  fn apply(&mut self, fun: Val<'t>, arg: Val<'t>, tag: &'t Tag) -> Result<Val<'t>, EvalError> {
+    maybe_grow(|| match self.force(fun)? {
+      Val::Lam(lam) => {
+        let (bod, env) = self.enter(&lam, tag, arg)?;
+        self.eval(bod, env)
+      }
+      Val::Fan(FanKind::Dup, lab, funs) => {
+        let [fst, snd] = unwrap_or_clone(funs);
+        let [fst_arg, snd_arg] = self.dup(arg, lab)?;
+        Ok(sup(lab, self.apply(fst, fst_arg, tag)?, self.apply(snd, snd_arg, tag)?))
+      }
+      Val::Prim(prim, args) => {
+        let mut args = unwrap_or_clone(args);
+        args.push(arg);
+        if args.len() < prim.arity() {
+          return Ok(Val::Prim(prim, Rc::new(args)));
+        }
+        match (prim, args.as_slice()) {
+          (Prim::Log, [x, base]) => self.operate(Op::LOG, base.clone(), x.clone()),
+          (Prim::Atan2, [x, y]) => self.operate(Op::ATN, x.clone(), y.clone()),
+          (_, [x]) => self.cast(prim, x.clone()),
+          _ => unreachable!(),
+        }
+      }
+      fun @ Val::Neu(_) => Ok(Val::Neu(Rc::new(Neutral::App(tag.clone(), fun, arg)))),
+      // Erasers and numbers have no ports to receive the argument, so they're the result.
+      val @ (Val::Era | Val::Num(_)) => Ok(val),
+      Val::Fan(FanKind::Tup, ..) => Err(EvalError::Unsupported("applying a tuple".to_string())),
+      Val::Ref(_) => unreachable!(),
+    })
+  }
+
+  /// Matches on a value of a switch that isn't a number.
+  fn switch(&mut self, arg: Val<'t>, arms: &'t [Term], env: Env<'t>) -> Result<Val<'t>, EvalError> {
+    maybe_grow(|| match self.force(arg)? {
+      Val::Num(Num::U24(0)) => self.eval(&arms[0], env),
+      Val::Num(Num::U24(n)) => {
+        let succ = self.eval(&arms[1], env)?;
+        self.apply(succ, Val::Num(Num::U24(n - 1)), &Tag::Static)
+      }
+      Val::Era => Ok(Val::Era),
+      Val::Fan(FanKind::Dup, lab, args) => {
+        let [fst, snd] = unwrap_or_clone(args);
+        Ok(sup(lab, self.switch(fst, arms, env.clone())?, self.switch(snd, arms, env)?))
+      }
+      arg @ Val::Neu(_) => {
+        let zero = self.eval(&arms[0], env.clone())?;
+        let succ = self.eval(&arms[1], env)?;
+        Ok(Val::Neu(Rc::new(Neutral::Swt(arg, zero, succ))))
+      }
+      Val::Num(_) => Err(EvalError::Unsupported("a switch on a number that isn't a u24".to_string())),
+      _ => Err(EvalError::Unsupported("a switch on a value that isn't a number".to_string())),
+    })
+  }
+
+  /// Binds the variables of a pattern to the parts of a value.
+  fn bind(&mut self, pat: &'t Pattern, val: Val<'t>, env: Env<'t>) -> Result<Env<'t>, EvalError> {
+    match pat {
+      Pattern::Var(Some(nam)) => Ok(Some(Rc::new(Bind { nam, val, next: env }))),
+      Pattern::Var(None) => Ok(env),
+      Pattern::Fan(fan, tag, pats) => self.bind_fan(*fan, label(tag)?, pats, val, env),
+      Pattern::Chn(_) => Err(EvalError::Unsupported("unscoped variables".to_string())),
+      _ => Err(EvalError::Unsupported(format!("the pattern '{pat}', which should've been desugared"))),
+    }
+  }
+
+  /// Binds the patterns of the elements of a tuple or duplication.
+  /// Like in the compiled nets, a fan of more than two elements is made of nested pairs.
+  fn bind_fan(
+    &mut self,
+    fan: FanKind,
+    lab: Option<u16>,
+    pats: &'t [Pattern],
+    val: Val<'t>,
+    env: Env<'t>,
+  ) -> Result<Env<'t>, EvalError> {
+    let [fst, snd] = match fan {
+      FanKind::Dup => self.dup(val, lab)?,
+      FanKind::Tup => self.untuple(val, lab)?,
+    };
+    let env = self.bind(&pats[0], fst, env)?;
+    match &pats[1..] {
+      [last] => self.bind(last, snd, env),
+      rest => self.bind_fan(fan, lab, rest, snd, env),
+    }
+  }
+
+  /// Splits a pair with the label `lab` into its elements.
+  fn untuple(&mut self, val: Val<'t>, lab: Option<u16>) -> Result<[Val<'t>; 2], EvalError> {
+    maybe_grow(|| match self.force(val)? {
+      Val::Fan(FanKind::Tup, tup_lab, els) if tup_lab == lab => Ok(unwrap_or_clone(els)),
+      Val::Era => Ok([Val::Era, Val::Era]),
+      // Each element of the tuple is a superposition of the elements of the superposed tuples.
+      Val::Fan(FanKind::Dup, sup_lab, sups) => {
+        let [fst, snd] = unwrap_or_clone(sups);
+        let [fst_a, fst_b] = self.untuple(fst, lab)?;
+        let [snd_a, snd_b] = self.untuple(snd, lab)?;
+        Ok([sup(sup_lab, fst_a, snd_a), sup(sup_lab, fst_b, snd_b)])
+      }
+      Val::Neu(neu) => Ok(parts(FanKind::Tup, lab, neu)),
+      _ => Err(EvalError::Unsupported("destructuring a value that isn't a tuple with that tag".to_string())),
+    })
+  }
+
+  /// Makes the two copies of a value for a duplication with the label `lab`.
+  ///
+  /// A superposition with the same label is split into its elements instead,
+  /// and one with another label has each of its elements duplicated.
+  fn dup(&mut self, val: Val<'t>, lab: Option<u16>) -> Result<[Val<'t>; 2], EvalError> {
+    maybe_grow(|| match val {
+      Val::Fan(FanKind::Dup, sup_lab, els) if sup_lab == lab => Ok(unwrap_or_clone(els)),
+      Val::Fan(FanKind::Dup, sup_lab, els) => {
+        let [fst, snd] = unwrap_or_clone(els);
+        let [fst_a, fst_b] = self.dup(fst, lab)?;
+        let [snd_a, snd_b] = self.dup(snd, lab)?;
+        Ok([sup(sup_lab, fst_a, snd_a), sup(sup_lab, fst_b, snd_b)])
+      }
+      Val::Neu(neu) => Ok(parts(FanKind::Dup, lab, neu)),
+      val => Ok([val.clone(), val]),
+    })
+  }
+
+  fn operate(&mut self, op: Op, fst: Val<'t>, snd: Val<'t>) -> Result<Val<'t>, EvalError> {
+    maybe_grow(|| match (self.force(fst)?, self.force(snd)?) {
+      (Val::Num(fst), Val::Num(snd)) => operate(op, fst, snd).map(Val::Num),
+      (Val::Era, _) | (_, Val::Era) => Ok(Val::Era),
+      (Val::Fan(FanKind::Dup, lab, fsts), snd) => {
+        let [fst_a, fst_b] = unwrap_or_clone(fsts);
+        let [snd_a, snd_b] = self.dup(snd, lab)?;
+        Ok(sup(lab, self.operate(op, fst_a, snd_a)?, self.operate(op, fst_b, snd_b)?))
+      }
+      (fst, Val::Fan(FanKind::Dup, lab, snds)) => {
+        let [fst_a, fst_b] = self.dup(fst, lab)?;
+        let [snd_a, snd_b] = unwrap_or_clone(snds);
+        Ok(sup(lab, self.operate(op, fst_a, snd_a)?, self.operate(op, fst_b, snd_b)?))
+      }
+      (fst @ (Val::Num(_) | Val::Neu(_)), snd @ (Val::Num(_) | Val::Neu(_))) => {
+        Ok(Val::Neu(Rc::new(Neutral::Oper(op, fst, snd))))
+      }
+      _ => {
+        Err(EvalError::Unsupported(format!("the '{}' operation on a value that isn't a number", op_name(op))))
+      }
+    })
+  }
+
+  fn cast(&mut self, prim: Prim, val: Val<'t>) -> Result<Val<'t>, EvalError> {
+    maybe_grow(|| match self.force(val)? {
+      Val::Num(val) => Ok(Val::Num(cast(prim, val))),
+      Val::Era => Ok(Val::Era),
+      Val::Fan(FanKind::Dup, lab, els) => {
+        let [fst, snd] = unwrap_or_clone(els);
+        Ok(sup(lab, self.cast(prim, fst)?, self.cast(prim, snd)?))
+      }
+      _ => Err(EvalError::Unsupported(format!("'{}' on a value that isn't a number", prim.name()))),
+    })
+  }
+
+  /// Converts a value back into a term, normalizing the bodies of its lambdas.
+  fn readback(&mut self, val: Val<'t>) -> Result<Term, EvalError> {
+    maybe_grow(|| match val {
+      Val::Era => Ok(Term::Era),
+      Val::Num(val) => Ok(Term::Num { val }),
+      Val::Ref(nam) => Ok(Term::Ref { nam: nam.clone() }),
+      Val::Lam(lam) => {
+        let (pat, env) = self.fresh_pattern(lam.pat, lam.env.clone())?;
+        let bod = self.eval(lam.bod, env)?;
+        let mut bod = self.readback(bod)?;
+        // The lambdas whose bodies become an application to their variable once normalized
+        // are also reduced in the nets.
+        if let (Pattern::Var(Some(var)), Term::App { tag, fun, arg }) = (&pat, &mut bod) {
+          if self.eta && tag == lam.tag && is_eta(var, fun, arg) {
+            return Ok(std::mem::take(fun.as_mut()));
+          }
+        }
+        Ok(Term::Lam { tag: lam.tag.clone(), pat: Box::new(pat), bod: Box::new(bod) })
+      }
+      Val::Fan(fan, lab, els) => {
+        let [fst, snd] = unwrap_or_clone(els);
+        Ok(Term::Fan { fan, tag: tag(lab), els: vec![self.readback(fst)?, self.readback(snd)?] })
+      }
+      Val::Prim(prim, args) => {
+        let mut term = Term::Ref { nam: Name::new(prim.name()) };
+        for arg in unwrap_or_clone(args) {
+          term = Term::App { tag: Tag::Static, fun: Box::new(term), arg: Box::new(self.readback(arg)?) };
+        }
+        Ok(term)
+      }
+      Val::Neu(neu) => match unwrap_or_clone(neu) {
+        Neutral::Var(nam) => Ok(Term::Var { nam }),
+        Neutral::App(tag, fun, arg) => {
+          Ok(Term::App { tag, fun: Box::new(self.readback(fun)?), arg: Box::new(self.readback(arg)?) })
+        }
+        Neutral::Oper(opr, fst, snd) => {
+          Ok(Term::Oper { opr, fst: Box::new(self.readback(fst)?), snd: Box::new(self.readback(snd)?) })
+        }
+        // Written like the readback of the nets, with the predecessor as the variable `bnd-1`.
+        Neutral::Swt(arg, zero, succ) => {
+          let arg = self.readback(arg)?;
+          let bnd = match &arg {
+            Term::Var { nam } => nam.clone(),
+            _ => self.fresh_name(),
+          };
+          let pred = Val::Neu(Rc::new(Neutral::Var(Name::new(format!("{bnd}-1")))));
+          let zero = self.readback(zero)?;
+          let succ = self.apply(succ, pred, &Tag::Static)?;
+          let succ = self.readback(succ)?;
+          Ok(Term::Swt {
+            arg: Box::new(arg),
+            bnd: Some(bnd),
+            with_bnd: vec![],
+            with_arg: vec![],
+            pred: None,
+            arms: vec![zero, succ],
+          })
+        }
+        Neutral::Part(FanKind::Dup, _, _, neu) => self.readback(Val::Neu(neu)),
+        Neutral::Part(FanKind::Tup, ..) => {
+          Err(EvalError::Unsupported("reading back a tuple taken apart from a variable".to_string()))
+        }
+      },
+    })
+  }
+
+  fn fresh_name(&mut self) -> Name {
+    let nam = Name::new(num_to_name(self.next_var));
+    self.next_var += 1;
+    nam
+  }
+
+  /// Gives fresh names to the variables of the pattern of a lambda being read back,
+  /// binding them to themselves.
+  fn fresh_pattern(&mut self, pat: &'t Pattern, env: Env<'t>) -> Result<(Pattern, Env<'t>), EvalError> {
+    match pat {
+      Pattern::Var(None) => Ok((Pattern::Var(None), env)),
+      Pattern::Var(Some(nam)) => {
+        let var = self.fresh_name();
+        let val = Val::Neu(Rc::new(Neutral::Var(var.clone())));
+        Ok((Pattern::Var(Some(var)), Some(Rc::new(Bind { nam, val, next: env }))))
+      }
+      Pattern::Fan(fan, tag, pats) => {
+        let mut env = env;
+        let mut els = vec![];
+        for pat in pats {
+          let (el, new_env) = self.fresh_pattern(pat, env)?;
+          els.push(el);
+          env = new_env;
+        }
+        Ok((Pattern::Fan(*fan, tag.clone(), els), env))
+      }
+      Pattern::Chn(_) => Err(EvalError::Unsupported("unscoped variables".to_string())),
+      _ => Err(EvalError::Unsupported(format!("the pattern '{pat}', which should've been desugared"))),
+    }
+  }
+}
+
+/// Makes a pair of a tuple or superposition.
+/// Putting back together the two parts of a variable gives back the variable.
+fn fan_val<'t>(fan: FanKind, lab: Option<u16>, fst: Val<'t>, snd: Val<'t>) -> Val<'t> {
+  if let (Val::Neu(a), Val::Neu(b)) = (&fst, &snd) {
+    if let (Neutral::Part(a_fan, a_lab, 0, a), Neutral::Part(b_fan, b_lab, 1, b)) = (a.as_ref(), b.as_ref()) {
+      if (*a_fan, *a_lab) == (fan, lab) && (*b_fan, *b_lab) == (fan, lab) && Rc::ptr_eq(a, b) {
+        return Val::Neu(a.clone());
+      }
+    }
+  }
+  Val::Fan(fan, lab, Rc::new([fst, snd]))
+}
+
+/// Takes the value out of an `Rc`, cloning it if it's shared.
+fn unwrap_or_clone<T: Clone>(rc: Rc<T>) -> T {
+  Rc::try_unwrap(rc).unwrap_or_else(|rc| (*rc).clone())
+}
+
+fn sup<'t>(lab: Option<u16>, fst: Val<'t>, snd: Val<'t>) -> Val<'t> {
+  fan_val(FanKind::Dup, lab, fst, snd)
+}
+
+/// The two parts of a variable taken apart by a tuple pattern or a duplication.
+fn parts<'t>(fan: FanKind, lab: Option<u16>, neu: Rc<Neutral<'t>>) -> [Val<'t>; 2] {
+  [0, 1].map(|idx| Val::Neu(Rc::new(Neutral::Part(fan, lab, idx, neu.clone()))))
+}
+
+/// Whether `λvar (fun arg)` is an eta-reducible lambda.
+fn is_eta(var: &Name, fun: &Term, arg: &Term) -> bool {
+  matches!(arg, Term::Var { nam } if nam == var) && !fun.free_vars().contains_key(var)
+}
+
+fn lookup<'t>(env: &Env<'t>, nam: &Name) -> Result<Val<'t>, EvalError> {
+  let mut env = env;
+  while let Some(bind) = env {
+    if bind.nam == nam {
+      return Ok(bind.val.clone());
+    }
+    env = &bind.next;
+  }
+  Err(EvalError::Unsupported(format!("the unbound variable '{nam}'")))
+}
+
+/// The label of the nodes of a tag in the compiled net, used to know which fans interact.
+fn label(tag: &Tag) -> Result<Option<u16>, EvalError> {
+  match tag {
+    Tag::Static => Ok(None),
+    Tag::Auto => Ok(Some(0)),
+    Tag::Numeric(lab) => Ok(Some(*lab)),
+    Tag::Named(nam) => Err(EvalError::Unsupported(format!("the named tag '{nam}'"))),
+  }
+}
+
+fn tag(lab: Option<u16>) -> Tag {
+  match lab {
+    None => Tag::Static,
+    Some(0) => Tag::Auto,
+    Some(lab) => Tag::Numeric(lab),
+  }
+}
+
+fn u24(val: u32) -> Num {
+  Num::U24(val & 0xFFFFFF)
+}
+
+fn i24(val: i32) -> Num {
+  Num::I24(val << 8 >> 8)
+}
+
+/// Rounds a float to the 16 bits of mantissa of an f24, to the nearest and to the even one on ties.
+fn f24(val: f32) -> Num {
+  if val.is_nan() {
+    return Num::F24(f32::NAN);
+  }
+  let bits = val.to_bits();
+  let (mut high, low) = (bits >> 8, bits & 0xFF);
+  if low > 0x80 || (low == 0x80 && high & 1 == 1) {
+    high += 1;
+  }
+  Num::F24(f32::from_bits(high << 8))
+}
+
+fn num(val: Num) -> Num {
+  match val {
+    Num::U24(val) => u24(val),
+    Num::I24(val) => i24(val),
+    Num::F24(val) => f24(val),
+  }
+}
+
+fn typ_name(val: Num) -> &'static str {
+  match val {
+    Num::U24(_) => "u24",
+    Num::I24(_) => "i24",
+    Num::F24(_) => "f24",
+  }
+}
+
+fn op_name(op: Op) -> String {
+  match op {
+    Op::LOG => "log".to_string(),
+    Op::ATN => "atan2".to_string(),
+    op => op.to_string(),
+  }
+}
+
+/// Computes a numeric operation, with the semantics documented in `docs/native-numbers.md`.
+fn operate(op: Op, fst: Num, snd: Num) -> Result<Num, EvalError> {
+  let undefined = |typ| Err(EvalError::UndefinedOperation { op, typ });
+  let res = match (fst, snd) {
+    (Num::U24(a), Num::U24(b)) => match op {
+      Op::ADD => u24(a.wrapping_add(b)),
+      Op::SUB => u24(a.wrapping_sub(b)),
+      Op::MUL => u24(a.wrapping_mul(b)),
+      Op::DIV | Op::REM if b == 0 => return Err(EvalError::DivisionByZero),
+      Op::DIV => u24(a / b),
+      Op::REM => u24(a % b),
+      Op::AND => u24(a & b),
+      Op::OR => u24(a | b),
+      Op::XOR => u24(a ^ b),
+      Op::SHL => u24(a << (b % 32)),
+      Op::SHR => u24(a >> (b % 32)),
+      Op::POW | Op::LOG | Op::ATN => return undefined("u24"),
+      _ => compare(op, a, b),
+    },
+    (Num::I24(a), Num::I24(b)) => match op {
+      Op::ADD => i24(a.wrapping_add(b)),
+      Op::SUB => i24(a.wrapping_sub(b)),
+      Op::MUL => i24(a.wrapping_mul(b)),
+      Op::DIV | Op::REM if b == 0 => return Err(EvalError::DivisionByZero),
+      Op::DIV => i24(a.wrapping_div(b)),
+      Op::REM => i24(a.wrapping_rem(b)),
+      Op::AND => i24(a & b),
+      Op::OR => i24(a | b),
+      Op::XOR => i24(a ^ b),
+      Op::SHL | Op::SHR | Op::POW | Op::LOG | Op::ATN => return undefined("i24"),
+      _ => compare(op, a, b),
+    },
+    (Num::F24(a), Num::F24(b)) => match op {
+      Op::ADD => f24(a + b),
+      Op::SUB => f24(a - b),
+      Op::MUL => f24(a * b),
+      Op::DIV => f24(a / b),
+      Op::REM => f24(a % b),
+      Op::POW => f24(a.powf(b)),
+      Op::ATN => f24(a.atan2(b)),
+      // The logarithm of `b` in base `a`.
+      Op::LOG => f24(b.log(a)),
+      Op::AND | Op::OR | Op::XOR | Op::SHL | Op::SHR => return undefined("f24"),
+      _ => compare(op, a, b),
+    },
+    _ => return Err(EvalError::MixedTypes { op, fst: typ_name(fst), snd: typ_name(snd) }),
+  };
+  Ok(res)
+}
+
+fn compare<T: PartialOrd>(op: Op, a: T, b: T) -> Num {
+  let res = match op {
+    Op::EQ => a == b,
+    Op::NEQ => a != b,
+    Op::LT => a < b,
+    Op::GT => a > b,
+    Op::LE => a <= b,
+    Op::GE => a >= b,
+    _ => unreachable!(),
+  };
+  Num::U24(res as u32)
+}
+
+/// Converts a number to another type.
+/// Integers are reinterpreted, and floats are saturated to the integer range, with NaN becoming 0.
+fn cast(prim: Prim, val: Num) -> Num {
+  match (prim, val) {
+    (Prim::ToU24, Num::U24(val)) => u24(val),
+    (Prim::ToU24, Num::I24(val)) => u24(val as u32),
+    (Prim::ToU24, Num::F24(val)) => u24((val as u32).min(0xFFFFFF)),
+    (Prim::ToI24, Num::U24(val)) => i24(val as i32),
+    (Prim::ToI24, Num::I24(val)) => i24(val),
+    (Prim::ToI24, Num::F24(val)) => i24((val as i32).clamp(-0x800000, 0x7FFFFF)),
+    (Prim::ToF24, Num::U24(val)) => f24(val as f32),
+    (Prim::ToF24, Num::I24(val)) => f24(val as f32),
+    (Prim::ToF24, Num::F24(val)) => f24(val),
+    (Prim::Log | Prim::Atan2, _) => unreachable!(),
+  }
+}
+
+impl Display for EvalError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      EvalError::MissingMain => write!(f, "The program has no entrypoint."),
+      EvalError::DivisionByZero => write!(f, "Division by zero."),
+      EvalError::UndefinedOperation { op, typ } => {
+        write!(f, "The '{}' operation is not defined for {typ} numbers.", op_name(*op))
+      }
+      EvalError::MixedTypes { op, fst, snd } => {
+        write!(
+          f,
+          "The '{}' operation was applied to numbers of different types, {fst} and {snd}.",
+          op_name(*op)
+        )
+      }
+      EvalError::Unsupported(what) => write!(f, "The reference evaluator doesn't support {what}."),
+    }
+  }
+}
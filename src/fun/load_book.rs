@@ -1,15 +1,135 @@
-use crate::fun::{self, parser::TermParser};
+use crate::{
+  diagnostics::Diagnostics,
+  fun::{self, manifest::Manifest, parser::TermParser, Cfg, Name},
+  ENTRY_POINT, HVM1_ENTRY_POINT,
+};
+use indexmap::IndexMap;
 use std::path::Path;
 
 // TODO: Refactor so that we don't mix the two syntaxes here.
 
-/// Reads a file and parses to a definition book.
-pub fn load_file_to_book(path: &Path) -> Result<fun::Book, String> {
+/// Reads a file and parses to a definition book, leaving out the definitions whose `#[cfg(..)]` doesn't hold.
+pub fn load_file_to_book(path: &Path, cfg: &Cfg) -> Result<fun::Book, Diagnostics> {
   let builtins = fun::Book::builtins();
   let code = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
-  do_parse_book(&code, path, builtins)
+  parse_book_with(&code, path, builtins, false, cfg)
 }
 
-pub fn do_parse_book(code: &str, path: &Path, builtins: fun::Book) -> Result<fun::Book, String> {
-  TermParser::new(code).parse_book(builtins, false).map_err(|e| format!("In {} :\n{}", path.display(), e))
+/// Reads a file of a project and the main files of the project's dependencies, and of their dependencies,
+/// to a single definition book. When `vendored`, the dependencies are read from the `vendor` directory.
+///
+/// The definitions of the dependencies are loaded like the builtin ones, so the unused ones are
+/// pruned and they aren't reported as unused. The entrypoint of each dependency is left out, and
+/// two dependencies can't define the same name.
+pub fn load_project_to_book(
+  path: &Path,
+  manifest: &Manifest,
+  vendored: bool,
+  cfg: &Cfg,
+) -> Result<fun::Book, Diagnostics> {
+  let builtins = fun::Book::builtins();
+  let mut book = builtins.clone();
+  let mut owners = DependencyOwners::default();
+  for (_, dep) in manifest.load_dependencies(vendored)? {
+    let path = dep.main_path();
+    let code =
+      std::fs::read_to_string(&path).map_err(|e| format!("Couldn't read '{}'. {e}", path.display()))?;
+    let dep_book = parse_book_with(&code, &path, builtins.clone(), true, cfg)?;
+    owners.add_dependency(&mut book, dep_book, &builtins, &dep)?;
+  }
+  let code = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+  parse_book_with(&code, path, book, false, cfg)
+}
+
+/// The package that defined each name loaded from a dependency.
+/// Types are kept apart, since a type can have a constructor of the same name.
+#[derive(Default)]
+struct DependencyOwners {
+  names: IndexMap<Name, String>,
+  types: IndexMap<Name, String>,
+}
+
+impl DependencyOwners {
+  /// Adds what a dependency defines, besides the builtins, to the book, except for its entrypoint
+  /// and the definitions generated from it.
+  fn add_dependency(
+    &mut self,
+    book: &mut fun::Book,
+    dep_book: fun::Book,
+    builtins: &fun::Book,
+    dep: &Manifest,
+  ) -> Result<(), String> {
+    let entrypoint = dep.entrypoint.as_deref().unwrap_or(ENTRY_POINT);
+    let is_entrypoint = |name: &Name| {
+      let name = name.def_name_from_generated();
+      matches!(name.as_ref(), ENTRY_POINT | HVM1_ENTRY_POINT) || name == entrypoint
+    };
+
+    for (name, def) in dep_book.defs {
+      if !builtins.defs.contains_key(&name) && !is_entrypoint(&name) {
+        Self::claim(&mut self.names, &name, &dep.name)?;
+        book.defs.insert(name, def);
+      }
+    }
+    for (name, def) in dep_book.hvm_defs {
+      if !builtins.hvm_defs.contains_key(&name) && !is_entrypoint(&name) {
+        Self::claim(&mut self.names, &name, &dep.name)?;
+        book.hvm_defs.insert(name, def);
+      }
+    }
+    for (name, mac) in dep_book.macros {
+      if !builtins.macros.contains_key(&name) {
+        Self::claim(&mut self.names, &name, &dep.name)?;
+        book.macros.insert(name, mac);
+      }
+    }
+    for (name, adt) in dep_book.adts {
+      if !builtins.adts.contains_key(&name) {
+        Self::claim(&mut self.types, &name, &dep.name)?;
+        book.adts.insert(name, adt);
+      }
+    }
+    for (name, adt) in dep_book.ctrs {
+      if !builtins.ctrs.contains_key(&name) {
+        Self::claim(&mut self.names, &name, &dep.name)?;
+        book.ctrs.insert(name, adt);
+      }
+    }
+    Ok(())
+  }
+
+  fn claim(owners: &mut IndexMap<Name, String>, name: &Name, package: &str) -> Result<(), String> {
+    if let Some(owner) = owners.get(name) {
+      return Err(format!(
+        "Dependency conflict: both '{owner}' and '{package}' define '{name}'. Each name can only be defined by one dependency."
+      ));
+    }
+    owners.insert(name.clone(), package.to_string());
+    Ok(())
+  }
+}
+
+/// Parses a book, reporting each syntax error found in it as a separate diagnostic.
+pub fn do_parse_book(code: &str, path: &Path, builtins: fun::Book) -> Result<fun::Book, Diagnostics> {
+  parse_book_with(code, path, builtins, false, &Cfg::default())
+}
+
+fn parse_book_with(
+  code: &str,
+  path: &Path,
+  builtins: fun::Book,
+  builtin: bool,
+  cfg: &Cfg,
+) -> Result<fun::Book, Diagnostics> {
+  let mut parser = TermParser::new(code).with_cfg(cfg.clone());
+  if let Some(dir) = path.parent() {
+    parser = parser.with_dir(dir);
+  }
+  parser.parse_book(builtins, builtin).map_err(|errs| {
+    let mut diags = Diagnostics::default();
+    for err in errs {
+      diags.add_book_error(format!("In {} :\n{}", path.display(), err));
+    }
+    diags
+  })
 }
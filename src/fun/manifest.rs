@@ -0,0 +1,344 @@
+use std::path::{Path, PathBuf};
+
+/// The manifest of a Bend project, read from the `bend.toml` file at the root of the project:
+///
+/// ```toml
+/// [package]
+/// name = "app"
+/// version = "0.1.0"
+/// main = "src/main.bend"
+/// entrypoint = "start"
+///
+/// [dependencies]
+/// utils = { path = "../utils" }
+///
+/// [compile]
+/// opts = ["linearize-matches-alt", "no-eta"]
+/// ```
+///
+/// Only the part of TOML used by the manifest is accepted: tables of `key = value` lines, where a value
+/// is a string, a list of strings or an inline table of strings, and `#` comments.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+  /// The directory of the project, where the manifest is.
+  pub dir: PathBuf,
+  pub name: String,
+  pub version: String,
+  /// The file of the program, relative to the project directory. Defaults to `main.bend`.
+  pub main: PathBuf,
+  /// The definition used as the entrypoint instead of `main`.
+  pub entrypoint: Option<String>,
+  /// The name and directory of each dependency, which is another project with its own manifest.
+  pub dependencies: Vec<(String, PathBuf)>,
+  /// The compile options used by default, as given to `-O`.
+  pub opts: Vec<String>,
+}
+
+#[derive(Debug)]
+enum Value {
+  Str(String),
+  List(Vec<String>),
+  Table(Vec<(String, String)>),
+}
+
+impl Manifest {
+  pub const FILE: &'static str = "bend.toml";
+  /// The directory of a project where its dependencies are vendored.
+  pub const VENDOR_DIR: &'static str = "vendor";
+
+  /// Looks for the manifest of the project that `dir` is in, in it and in its parents.
+  pub fn find(dir: &Path) -> Result<Option<Self>, String> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let Ok(dir) = dir.canonicalize() else {
+      return Ok(None);
+    };
+    match dir.ancestors().map(|dir| dir.join(Self::FILE)).find(|path| path.is_file()) {
+      Some(path) => Self::load(&path).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let code =
+      std::fs::read_to_string(path).map_err(|e| format!("Couldn't read '{}'. {e}", path.display()))?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut manifest = Manifest {
+      dir,
+      name: String::new(),
+      version: String::new(),
+      main: PathBuf::from("main.bend"),
+      entrypoint: None,
+      dependencies: vec![],
+      opts: vec![],
+    };
+
+    let mut table = String::new();
+    for (i, line) in code.lines().enumerate() {
+      let err = |msg: String| format!("{msg} In line {} of '{}'.", i + 1, path.display());
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      if let Some(name) = line.strip_prefix('[') {
+        let Some((name, rest)) = name.split_once(']') else {
+          return Err(err("Expected ']' after the name of the table.".to_string()));
+        };
+        if !rest.trim().is_empty() && !rest.trim().starts_with('#') {
+          return Err(err("Unexpected characters after the name of the table.".to_string()));
+        }
+        table = name.trim().to_string();
+        if !matches!(table.as_str(), "package" | "dependencies" | "compile") {
+          return Err(err(format!(
+            "Unknown table '{table}'. The tables are 'package', 'dependencies' and 'compile'."
+          )));
+        }
+        continue;
+      }
+
+      let Some((key, val)) = line.split_once('=') else {
+        return Err(err("Expected 'key = value'.".to_string()));
+      };
+      let key = key.trim();
+      let val = parse_value(val).map_err(err)?;
+      match (table.as_str(), key, val) {
+        ("package", "name", Value::Str(name)) => manifest.name = name,
+        ("package", "version", Value::Str(version)) => manifest.version = version,
+        ("package", "main", Value::Str(main)) => manifest.main = PathBuf::from(main),
+        ("package", "entrypoint", Value::Str(entrypoint)) => manifest.entrypoint = Some(entrypoint),
+        ("dependencies", name, Value::Table(fields)) => {
+          let Some((_, dep_path)) = fields.iter().find(|(key, _)| key == "path") else {
+            return Err(err(format!("Expected a 'path' for the dependency '{name}'.")));
+          };
+          manifest.dependencies.push((name.to_string(), manifest.dir.join(dep_path)));
+        }
+        ("compile", "opts", Value::List(opts)) => manifest.opts = opts,
+        ("package", "name" | "version" | "main" | "entrypoint", _) => {
+          return Err(err(format!("Expected a string for '{key}'.")))
+        }
+        ("dependencies", name, _) => {
+          return Err(err(format!(
+            "Expected a table like '{{ path = \"..\" }}' for the dependency '{name}'."
+          )))
+        }
+        ("compile", "opts", _) => return Err(err("Expected a list of strings for 'opts'.".to_string())),
+        ("package", ..) => {
+          return Err(err(format!(
+            "Unknown key '{key}'. The keys of 'package' are 'name', 'version', 'main' and 'entrypoint'."
+          )))
+        }
+        ("compile", ..) => return Err(err(format!("Unknown key '{key}'. The key of 'compile' is 'opts'."))),
+        _ => return Err(err(format!("Expected a table before '{key}'."))),
+      }
+    }
+
+    if manifest.name.is_empty() {
+      return Err(format!("Expected a 'name' in the 'package' table of '{}'.", path.display()));
+    }
+    Ok(manifest)
+  }
+
+  /// The path of the file of the program.
+  pub fn main_path(&self) -> PathBuf {
+    self.dir.join(&self.main)
+  }
+
+  /// The manifests of the dependencies of the project, and of their dependencies, each one after its
+  /// own dependencies, with the name it was declared with.
+  ///
+  /// When `vendored`, the dependencies are read only from the `vendor` directory of this project, where
+  /// [`Manifest::vendor`] copies them, by their names instead of from their paths.
+  pub fn load_dependencies(&self, vendored: bool) -> Result<Vec<(String, Manifest)>, String> {
+    let vendor = vendored.then(|| self.dir.join(Self::VENDOR_DIR));
+    let dir = self.dir.canonicalize().unwrap_or_else(|_| self.dir.clone());
+    let mut loading = vec![(self.name.clone(), dir)];
+    let mut deps = vec![];
+    self.load_dependencies_go(vendor.as_deref(), &mut loading, &mut deps)?;
+    Ok(deps)
+  }
+
+  /// Loads the dependencies of this project, which is the last one of `loading`, the chain of
+  /// dependents being loaded, that a dependency can't be in.
+  fn load_dependencies_go(
+    &self,
+    vendor: Option<&Path>,
+    loading: &mut Vec<(String, PathBuf)>,
+    deps: &mut Vec<(String, Manifest)>,
+  ) -> Result<(), String> {
+    for (name, dir) in self.dependencies.iter() {
+      let dir = match vendor {
+        Some(vendor) => vendor.join(name),
+        None => dir.clone(),
+      };
+      let dir = dir.canonicalize().map_err(|e| match vendor {
+        Some(_) => format!(
+          "The dependency '{name}' of '{}' isn't in '{}'. Run 'bend vendor' to copy the dependencies there.",
+          self.name,
+          dir.display()
+        ),
+        None => {
+          format!("Couldn't find the dependency '{name}' of '{}' in '{}'. {e}", self.name, dir.display())
+        }
+      })?;
+      if let Some(i) = loading.iter().position(|(_, loading)| *loading == dir) {
+        let cycle = loading[i..].iter().map(|(name, _)| format!("'{name}'")).collect::<Vec<_>>();
+        return Err(format!(
+          "Dependency cycle: {} -> '{}'. A project can't depend on itself, directly or through other dependencies.",
+          cycle.join(" -> "),
+          loading[i].0
+        ));
+      }
+      if deps.iter().any(|(_, dep)| dep.dir == dir) {
+        continue;
+      }
+
+      let dep = Manifest::load(&dir.join(Self::FILE))?;
+      loading.push((dep.name.clone(), dir));
+      dep.load_dependencies_go(vendor, loading, deps)?;
+      loading.pop();
+      deps.push((name.clone(), dep));
+    }
+    Ok(())
+  }
+
+  /// Copies the dependencies of the project, and their dependencies, to its `vendor` directory,
+  /// replacing what was there before, so that they can be loaded without the original directories.
+  ///
+  /// Each dependency is copied, with its manifest and its main file, to a directory with the name it
+  /// was declared with, so different dependencies can't be declared with the same name.
+  pub fn vendor(&self) -> Result<Vec<(String, Manifest)>, String> {
+    let deps = self.load_dependencies(false)?;
+    for (i, (name, dep)) in deps.iter().enumerate() {
+      if deps[..i].iter().any(|(other, _)| other == name) {
+        return Err(format!("There are different dependencies named '{name}', so they can't be vendored."));
+      }
+      if dep.main.components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+        return Err(format!(
+          "The main file of the dependency '{name}' isn't inside of its directory, so it can't be vendored."
+        ));
+      }
+    }
+
+    let vendor = self.dir.join(Self::VENDOR_DIR);
+    if vendor.exists() {
+      std::fs::remove_dir_all(&vendor).map_err(|e| format!("Couldn't remove '{}'. {e}", vendor.display()))?;
+    }
+    for (name, dep) in deps.iter() {
+      let dir = vendor.join(name);
+      for file in [PathBuf::from(Self::FILE), dep.main.clone()] {
+        let (from, to) = (dep.dir.join(&file), dir.join(&file));
+        if let Some(parent) = to.parent() {
+          std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Couldn't create '{}'. {e}", parent.display()))?;
+        }
+        std::fs::copy(&from, &to).map_err(|e| format!("Couldn't copy '{}'. {e}", from.display()))?;
+      }
+    }
+    Ok(deps)
+  }
+
+  /// Creates a project in `dir`, creating the directory if needed: a manifest, a `src/main.bend`
+  /// program and a `tests` directory with an example test. The files that already exist are kept.
+  pub fn create_project(dir: &Path, name: &str) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+      return Err(format!(
+        "Invalid project name '{name}'. It can only have letters, digits, '_' and '-'. Use '--name' to choose another one."
+      ));
+    }
+    let manifest = dir.join(Self::FILE);
+    if manifest.exists() {
+      return Err(format!("There's already a project in '{}'.", dir.display()));
+    }
+
+    let files = [
+      (manifest, format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nmain = \"src/main.bend\"\n\n[dependencies]\n\n[compile]\nopts = []\n")),
+      (dir.join("src").join("main.bend"), "def main():\n  return \"Hello, world!\"\n".to_string()),
+      (
+        dir.join("tests").join("main.bend"),
+        "# The programs in this directory can be tested with `bend selftest tests`, which checks that\n\
+         # they give the same result with HVM and with the sequential evaluator.\n\
+         def main():\n  return 1 + 2\n"
+          .to_string(),
+      ),
+    ];
+    for (path, code) in files {
+      if path.exists() {
+        continue;
+      }
+      if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Couldn't create '{}'. {e}", dir.display()))?;
+      }
+      std::fs::write(&path, code).map_err(|e| format!("Couldn't write '{}'. {e}", path.display()))?;
+    }
+    Ok(())
+  }
+}
+
+/// Parses the value of a `key = value` line, with an optional comment after it.
+fn parse_value(val: &str) -> Result<Value, String> {
+  let mut rest = val.trim_start();
+  let val = match rest.chars().next() {
+    Some('"') => Value::Str(parse_str(&mut rest)?),
+    Some('[') => Value::List(parse_seq(&mut rest, ']', parse_str)?),
+    Some('{') => Value::Table(parse_seq(&mut rest, '}', |rest| {
+      let (key, val) = rest.split_once('=').ok_or("Expected 'key = value' in the table.")?;
+      *rest = val.trim_start();
+      Ok((key.trim().to_string(), parse_str(rest)?))
+    })?),
+    _ => return Err("Expected a string, a list or a table.".to_string()),
+  };
+  let rest = rest.trim();
+  if rest.is_empty() || rest.starts_with('#') {
+    Ok(val)
+  } else {
+    Err(format!("Unexpected '{rest}' after the value."))
+  }
+}
+
+/// Parses a quoted string at the start of `rest`, advancing past it.
+fn parse_str(rest: &mut &str) -> Result<String, String> {
+  let Some(body) = rest.strip_prefix('"') else {
+    return Err("Expected a string.".to_string());
+  };
+  let mut str = String::new();
+  let mut chars = body.char_indices();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '"' => {
+        *rest = body[i + 1..].trim_start();
+        return Ok(str);
+      }
+      '\\' => match chars.next() {
+        Some((_, '"')) => str.push('"'),
+        Some((_, '\\')) => str.push('\\'),
+        Some((_, 'n')) => str.push('\n'),
+        Some((_, 't')) => str.push('\t'),
+        _ => return Err("Unknown escape sequence in the string.".to_string()),
+      },
+      c => str.push(c),
+    }
+  }
+  Err("Expected '\"' at the end of the string.".to_string())
+}
+
+/// Parses a comma separated sequence of items, between the opening character at the start of `rest` and `end`.
+fn parse_seq<T>(
+  rest: &mut &str,
+  end: char,
+  mut item: impl FnMut(&mut &str) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+  *rest = rest[1..].trim_start();
+  let mut items = vec![];
+  loop {
+    if let Some(after) = rest.strip_prefix(end) {
+      *rest = after;
+      return Ok(items);
+    }
+    items.push(item(rest)?);
+    *rest = rest.trim_start();
+    if let Some(after) = rest.strip_prefix(',') {
+      *rest = after.trim_start();
+    } else if !rest.starts_with(end) {
+      return Err(format!("Expected ',' or '{end}'."));
+    }
+  }
+}
@@ -0,0 +1,89 @@
+use crate::fun::parser::is_name_char;
+
+/// The builtin constructor names of older versions of Bend, with their current names.
+const OLD_CTR_NAMES: &[(&str, &str)] = &[
+  ("List.cons", "List/Cons"),
+  ("List.nil", "List/Nil"),
+  ("List/cons", "List/Cons"),
+  ("List/nil", "List/Nil"),
+  ("String.cons", "String/Cons"),
+  ("String.nil", "String/Nil"),
+  ("String/cons", "String/Cons"),
+  ("String/nil", "String/Nil"),
+  ("Nat.succ", "Nat/Succ"),
+  ("Nat.zero", "Nat/Zero"),
+  ("Nat/succ", "Nat/Succ"),
+  ("Nat/zero", "Nat/Zero"),
+];
+
+/// The keywords that used to start a type definition.
+const OLD_TYPE_KEYWORDS: &[&str] = &["data", "enum"];
+
+/// Rewrites a program written with an older version of the syntax to the current syntax.
+///
+/// Only the tokens of the program are looked at, so this works for both the functional
+/// and the imperative syntax, and for programs that don't parse with the current grammar.
+/// The following changes are done:
+/// - `//` comments become `#` comments.
+/// - Type definitions with `data` or `enum` become type definitions with `type`.
+/// - The old names of the builtin constructors, like `List.cons`, are renamed.
+///
+/// Everything else, including the formatting of the program, is kept as it is.
+pub fn migrate_syntax(code: &str) -> String {
+  let mut out = String::with_capacity(code.len());
+  let mut rest = code;
+  let mut line_start = true;
+  let mut prev = None;
+  while let Some(c) = rest.chars().next() {
+    let len = match c {
+      '"' | '\'' => quoted_len(rest, c),
+      '#' => rest.find('\n').unwrap_or(rest.len()),
+      '/' if rest.starts_with("//") && !prev.is_some_and(is_name_char) => {
+        let len = rest.find('\n').unwrap_or(rest.len());
+        out.push('#');
+        out.push_str(&rest[2..len]);
+        prev = Some('/');
+        line_start = false;
+        rest = &rest[len..];
+        continue;
+      }
+      c if is_name_char(c) => {
+        let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        let name = &rest[..len];
+        let after = &rest[len..];
+        if line_start && OLD_TYPE_KEYWORDS.contains(&name) && after.starts_with([' ', '\t']) {
+          out.push_str("type");
+        } else if let Some((_, new)) = OLD_CTR_NAMES.iter().find(|(old, _)| *old == name) {
+          out.push_str(new);
+        } else {
+          out.push_str(name);
+        }
+        prev = name.chars().last();
+        line_start = false;
+        rest = after;
+        continue;
+      }
+      c => c.len_utf8(),
+    };
+    out.push_str(&rest[..len]);
+    prev = rest[..len].chars().last();
+    line_start = c == '\n';
+    rest = &rest[len..];
+  }
+  out
+}
+
+/// The length of the string or character literal at the start of the input, including its quotes.
+fn quoted_len(input: &str, quote: char) -> usize {
+  let mut escaped = false;
+  for (i, c) in input.char_indices().skip(1) {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' => escaped = true,
+      c if c == quote => return i + 1,
+      '\n' => return i,
+      _ => (),
+    }
+  }
+  input.len()
+}
@@ -1,6 +1,6 @@
 use crate::{
-  diagnostics::{Diagnostics, DiagnosticsConfig},
-  maybe_grow, multi_iterator, ENTRY_POINT,
+  diagnostics::{Diagnostics, DiagnosticsConfig, WarningType},
+  maybe_grow, multi_iterator, AdtEncoding, ENTRY_POINT,
 };
 use indexmap::{IndexMap, IndexSet};
 use interner::global::{GlobalPool, GlobalString};
@@ -10,7 +10,10 @@ use std::{borrow::Cow, collections::HashMap, hash::Hash, ops::Deref};
 pub mod builtins;
 pub mod check;
 pub mod display;
+pub mod eval;
 pub mod load_book;
+pub mod manifest;
+pub mod migrate;
 pub mod net_to_term;
 pub mod parser;
 pub mod term_to_net;
@@ -28,7 +31,13 @@ pub struct Ctx<'book> {
 
 impl Ctx<'_> {
   pub fn new(book: &mut Book, diagnostics_cfg: DiagnosticsConfig) -> Ctx {
-    Ctx { book, info: Diagnostics::new(diagnostics_cfg) }
+    let mut info = Diagnostics::new(diagnostics_cfg);
+    for def in book.defs.values() {
+      if !def.allowed_warnings.is_empty() {
+        info.allowed.insert(def.name.clone(), def.allowed_warnings.clone());
+      }
+    }
+    Ctx { book, info }
   }
 }
 
@@ -47,6 +56,9 @@ pub struct Book {
   /// Map of constructor name to type name.
   pub ctrs: Constructors,
 
+  /// Macro definitions, expanded before the program is desugared.
+  pub macros: Macros,
+
   /// A custom or default "main" entrypoint.
   pub entrypoint: Option<Name>,
 }
@@ -55,6 +67,7 @@ pub type Definitions = IndexMap<Name, Definition>;
 pub type HvmDefinitions = IndexMap<Name, HvmDefinition>;
 pub type Adts = IndexMap<Name, Adt>;
 pub type Constructors = IndexMap<Name, Name>;
+pub type Macros = IndexMap<Name, Macro>;
 
 /// A pattern matching function definition.
 #[derive(Debug, Clone)]
@@ -62,6 +75,78 @@ pub struct Definition {
   pub name: Name,
   pub rules: Vec<Rule>,
   pub builtin: bool,
+  /// The warnings silenced for this definition with `#[allow(..)]`.
+  pub allowed_warnings: Vec<WarningType>,
+  /// The passes disabled for this definition with attributes like `#[no_float]`.
+  pub disabled_passes: Vec<DefPass>,
+  /// The signature given with `#[export(..)]`, to call the definition from C when compiled to a library.
+  pub export: Option<ExportSig>,
+  /// Whether the definition is marked with `#[associative]`, as a binary function whose applications can be regrouped.
+  pub associative: bool,
+}
+
+/// A compiler pass that can be disabled for a single definition with an attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefPass {
+  /// `#[no_float]`, don't extract combinators from the definition.
+  FloatCombinators,
+  /// `#[no_prune]`, keep the definition and what it uses even when unused.
+  Prune,
+  /// `#[no_merge]`, don't merge the definition with identical ones.
+  Merge,
+}
+
+/// The types of the arguments and result of a definition exported with `#[export(..)]`,
+/// used to convert them from and to C values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportSig {
+  pub args: Vec<ExportType>,
+  pub ret: ExportType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportType {
+  U24,
+  I24,
+  F24,
+  String,
+  /// A list of numbers or strings.
+  List(Box<ExportType>),
+}
+
+/// What the `#[cfg(..)]` attributes of the definitions of a program are checked against,
+/// to keep only the definitions for the backend a program is compiled to and the enabled features.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+  /// The backend the program is compiled to, one of [`Cfg::TARGETS`].
+  pub target: String,
+  pub features: Vec<String>,
+}
+
+impl Cfg {
+  pub const TARGETS: &'static [&'static str] = &["rust", "c", "cuda", "js"];
+
+  pub fn new(target: &str, features: Vec<String>) -> Self {
+    Self { target: target.to_string(), features }
+  }
+}
+
+impl Default for Cfg {
+  fn default() -> Self {
+    Self::new("rust", vec![])
+  }
+}
+
+/// A macro, like `macro twice(f, x) = (f (f x))`.
+///
+/// Its uses are replaced by its body with the arguments in place of the parameters.
+#[derive(Debug, Clone)]
+pub struct Macro {
+  pub name: Name,
+  pub params: Vec<Name>,
+  /// The parameter written as `..rest`, that receives the list of the remaining arguments.
+  pub rest: Option<Name>,
+  pub body: Term,
 }
 
 /// An HVM native definition.
@@ -252,13 +337,27 @@ pub enum Tag {
 #[derive(Debug, Clone, Default)]
 pub struct Adt {
   pub ctrs: IndexMap<Name, Vec<CtrField>>,
+  /// The type parameters, like `a` in `type Tree(a)`.
+  pub params: Vec<Name>,
   pub builtin: bool,
+  /// The encoding chosen for this type with an `#[encoding(..)]` attribute,
+  /// instead of the one in the compilation options.
+  pub encoding: Option<AdtEncoding>,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct CtrField {
   pub nam: Name,
   pub rec: bool,
+  /// The type written for the field, like `a` in `Node { val: a }`.
+  pub typ: Option<FieldType>,
+}
+
+/// The type of a constructor field, like `u24`, a type parameter or `List(a)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldType {
+  pub nam: Name,
+  pub args: Vec<FieldType>,
 }
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Name(GlobalString);
@@ -935,6 +1034,11 @@ impl Pattern {
     matches!(self, Pattern::Var(_) | Pattern::Chn(_))
   }
 
+  /// Whether the pattern only destructures its value, without having to match on it.
+  pub fn is_simple(&self) -> bool {
+    self.iter().all(|pat| matches!(pat, Pattern::Var(_) | Pattern::Chn(_) | Pattern::Fan(..)))
+  }
+
   pub fn to_term(&self) -> Term {
     match self {
       Pattern::Var(nam) => Term::var_or_era(nam.clone()),
@@ -946,7 +1050,8 @@ impl Pattern {
       Pattern::Fan(fan, tag, args) => {
         Term::Fan { fan: *fan, tag: tag.clone(), els: args.iter().map(|p| p.to_term()).collect() }
       }
-      Pattern::Lst(_) | Pattern::Str(_) => todo!(),
+      Pattern::Lst(els) => Term::List { els: els.iter().map(|p| p.to_term()).collect() },
+      Pattern::Str(val) => Term::Str { val: val.clone() },
     }
   }
 
@@ -966,6 +1071,18 @@ impl Rule {
 }
 
 impl Definition {
+  pub fn new(name: Name, rules: Vec<Rule>, builtin: bool) -> Self {
+    Self {
+      name,
+      rules,
+      builtin,
+      allowed_warnings: vec![],
+      disabled_passes: vec![],
+      export: None,
+      associative: false,
+    }
+  }
+
   pub fn arity(&self) -> usize {
     self.rules[0].arity()
   }
@@ -1008,6 +1125,67 @@ impl Name {
   }
 }
 
+/// A step in the generation of a definition from another one, as encoded in the generated name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeneratedKind {
+  /// A combinator extracted by [`transform::float_combinators`], `def__C{n}`.
+  Combinator(u64),
+  /// A function extracted from a `fold`, `def__fold{n}`.
+  Fold(u64),
+  /// A function extracted from a `bend`, `def__bend{n}`.
+  Bend(u64),
+  /// A local definition of the imperative syntax, `def__local_{n}_{name}`.
+  Local(Name),
+  /// A helper of a `where` block, `def__where_{n}_{name}`.
+  Where(Name),
+  /// The wrapper that runs a custom entrypoint, `def__entry`.
+  Entry,
+  /// A copy of a definition specialized by [`transform::specialize_calls`], `def__spec{n}`.
+  Specialized(u64),
+}
+
+impl Name {
+  /// Returns the user definitions a generated definition comes from,
+  /// with the steps that generated it from each of them.
+  ///
+  /// A definition generated by merging others comes from all of the merged ones.
+  /// Returns `None` if the name isn't of a generated definition.
+  pub fn generated_origins(&self) -> Option<Vec<(Name, Vec<GeneratedKind>)>> {
+    fn parse_step(seg: &str) -> Option<GeneratedKind> {
+      let named = |rest: &str| rest.split_once('_').map(|(_, nam)| Name::new(nam));
+      if seg == "entry" {
+        Some(GeneratedKind::Entry)
+      } else if let Some(rest) = seg.strip_prefix("local_") {
+        named(rest).map(GeneratedKind::Local)
+      } else if let Some(rest) = seg.strip_prefix("where_") {
+        named(rest).map(GeneratedKind::Where)
+      } else if let Some(n) = seg.strip_prefix("fold") {
+        n.parse().ok().map(GeneratedKind::Fold)
+      } else if let Some(n) = seg.strip_prefix("bend") {
+        n.parse().ok().map(GeneratedKind::Bend)
+      } else if let Some(n) = seg.strip_prefix("spec") {
+        n.parse().ok().map(GeneratedKind::Specialized)
+      } else if let Some(n) = seg.strip_prefix('C') {
+        n.parse().ok().map(GeneratedKind::Combinator)
+      } else {
+        None
+      }
+    }
+
+    if !self.contains("__") {
+      return None;
+    }
+    let mut origins = vec![];
+    for merged in self.split(transform::definition_merge::MERGE_SEPARATOR) {
+      let mut segs = merged.split("__");
+      let def = Name::new(segs.next()?);
+      let steps = segs.map(parse_step).collect::<Option<Vec<_>>>()?;
+      origins.push((def, steps));
+    }
+    Some(origins)
+  }
+}
+
 impl Default for Name {
   fn default() -> Self {
     Self::new("")
@@ -1041,11 +1219,24 @@ impl AsRef<str> for Name {
 }
 
 impl Book {
+  /// The name of the entrypoint in the compiled HVM book.
+  /// HVM always starts from `main`, whatever the entrypoint is called in Bend.
   pub fn hvm_entrypoint(&self) -> &str {
-    match self.entrypoint.as_ref().map(|e| e.as_ref()) {
-      Some("main" | "Main") | None => ENTRY_POINT,
-      Some(nam) => nam,
-    }
+    ENTRY_POINT
+  }
+
+  /// The encoding of the type of a constructor, which is `default` unless the type chose another one.
+  pub fn ctr_encoding(&self, ctr: &Name, default: AdtEncoding) -> AdtEncoding {
+    let adt = self.ctrs.get(ctr).and_then(|typ| self.adts.get(typ));
+    adt.and_then(|adt| adt.encoding).unwrap_or(default)
+  }
+
+  /// Whether `pass` was disabled with an attribute for a definition
+  /// or for the definition it was generated from.
+  pub fn pass_disabled(&self, def_name: &Name, pass: DefPass) -> bool {
+    [def_name.clone(), def_name.def_name_from_generated()]
+      .iter()
+      .any(|nam| self.defs.get(nam).is_some_and(|def| def.disabled_passes.contains(&pass)))
   }
 }
 
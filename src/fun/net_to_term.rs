@@ -8,11 +8,15 @@ use hvm::hvm::Numb;
 use std::collections::{BTreeSet, HashMap, HashSet};
 
 /// Converts an Interaction-INet to a Lambda Calculus term
+///
+/// With `mark_unreduced`, the redexes left by an evaluation that was stopped early are shown as
+/// `<unreduced: N redexes>`, instead of being read back as terms.
 pub fn net_to_term(
   net: &INet,
   book: &Book,
   labels: &Labels,
   linear: bool,
+  mark_unreduced: bool,
   diagnostics: &mut Diagnostics,
 ) -> Term {
   let mut reader = Reader {
@@ -25,6 +29,8 @@ pub fn net_to_term(
     seen_fans: Default::default(),
     namegen: Default::default(),
     seen: Default::default(),
+    mark_unreduced,
+    redexes: Default::default(),
     errors: Default::default(),
   };
 
@@ -72,6 +78,9 @@ pub struct Reader<'a> {
   // To avoid reinserting things in the scope.
   seen_fans: Scope,
   seen: HashSet<Port>,
+  mark_unreduced: bool,
+  /// The unreduced redexes found, by the nodes of each pair.
+  redexes: HashSet<(NodeId, NodeId)>,
   errors: Vec<ReadbackError>,
   recursive_defs: &'a BTreeSet<Name>,
 }
@@ -83,10 +92,15 @@ impl Reader<'_> {
     maybe_grow(|| {
       if !self.seen.insert(next) && self.dup_paths.is_none() {
         self.error(ReadbackError::Cyclic);
-        return Term::Var { nam: Name::new("...") };
+        return self.unreadable(next);
       }
 
       let node = next.node_id();
+      // An eliminator whose main port points to the main port of another node wasn't reduced,
+      // so the evaluation was stopped before the end and its result can't be read.
+      if self.mark_unreduced && next.slot() != 0 && self.is_redex(node) {
+        return self.unreduced(next);
+      }
       match &self.net.node(node).kind {
         NodeKind::Era => Term::Era,
         NodeKind::Ctr(CtrKind::Con(lab)) => self.read_con(next, *lab),
@@ -542,11 +556,20 @@ impl Reader<'_> {
   }
 
   pub fn report_errors(&mut self, diagnostics: &mut Diagnostics) {
-    let mut err_counts = std::collections::HashMap::new();
+    // Keeps the order in which the errors were found, so that the report is always the same.
+    let mut err_counts = indexmap::IndexMap::new();
     for err in &self.errors {
       *err_counts.entry(*err).or_insert(0) += 1;
     }
 
+    if !self.redexes.is_empty() {
+      let msg = format!(
+        "The result has {} unreduced redexes, shown as '<unreduced: N redexes>'.",
+        self.redexes.len()
+      );
+      diagnostics.add_diagnostic(msg.as_str(), Severity::Warning, DiagnosticOrigin::Readback);
+    }
+
     for (err, count) in err_counts {
       let count_msg = if count > 1 { format!(" ({count} occurrences)") } else { "".to_string() };
       let msg = format!("{}{}", err, count_msg);
@@ -554,17 +577,93 @@ impl Reader<'_> {
     }
   }
 
+  /// Whether the main port of `node` is connected to the main port of another node.
+  fn is_redex(&self, node: NodeId) -> bool {
+    let main = self.net.enter_port(Port(node, 0));
+    main.slot() == 0 && main.node_id() != ROOT.node_id() && main.node_id() != node
+  }
+
+  /// A placeholder for the unreduced part of the net entered by `port`,
+  /// showing how many redexes it has.
+  ///
+  /// The part is made of the trees of the redexes, and of the redexes whose results are used inside of them.
+  fn unreduced(&mut self, port: Port) -> Term {
+    let mut seen = HashSet::new();
+    let mut redexes = HashSet::new();
+    let mut to_visit = vec![port.node_id()];
+    while let Some(node) = to_visit.pop() {
+      if !seen.insert(node) {
+        continue;
+      }
+      if self.is_redex(node) {
+        let other = self.net.enter_port(Port(node, 0)).node_id();
+        redexes.insert((node.min(other), node.max(other)));
+        to_visit.push(other);
+      }
+      if matches!(self.net.node(node).kind, NodeKind::Era | NodeKind::Ref { .. } | NodeKind::Num { .. }) {
+        continue;
+      }
+      for slot in [1, 2] {
+        // Don't go back to where the part was entered from.
+        if Port(node, slot) == port {
+          continue;
+        }
+        let next = self.net.enter_port(Port(node, slot));
+        if next.slot() == 0 || self.is_redex(next.node_id()) {
+          to_visit.push(next.node_id());
+        }
+      }
+    }
+    let count = redexes.len();
+    self.redexes.extend(redexes);
+    let redexes = if count == 1 { "redex" } else { "redexes" };
+    Term::Ref { nam: Name::new(format!("<unreduced: {count} {redexes}>")) }
+  }
+
+  /// A placeholder for a part of the net that can't be read back, showing how big it is.
+  fn unreadable(&self, port: Port) -> Term {
+    let size = self.subnet_size(port);
+    let nodes = if size == 1 { "node" } else { "nodes" };
+    Term::Ref { nam: Name::new(format!("<unreadable: {size} {nodes}>")) }
+  }
+
+  /// Counts the nodes connected to the node of `port`, not counting the root.
+  fn subnet_size(&self, port: Port) -> usize {
+    let mut seen = HashSet::new();
+    let mut to_visit = vec![port.node_id()];
+    while let Some(node) = to_visit.pop() {
+      if node == ROOT.node_id() || !seen.insert(node) {
+        continue;
+      }
+      let n_ports = match self.net.node(node).kind {
+        NodeKind::Era | NodeKind::Ref { .. } | NodeKind::Num { .. } => 1,
+        _ => 3,
+      };
+      to_visit.extend((0..n_ports).map(|slot| self.net.enter_port(Port(node, slot)).node_id()));
+    }
+    seen.len()
+  }
+
   /// Returns whether the given port represents a tuple or some other
   /// term (usually a lambda).
   ///
-  /// Used heuristic: a con node is a tuple if port 1 is a closed net and not an ERA.
+  /// Used heuristic: a con node is a tuple if port 1 is a closed net and not an ERA,
+  /// or if its ports 1 and 2 are the two copies of the same dup, like in `let {a b} = x; (a, b)`.
   fn is_tup(&self, node: NodeId) -> bool {
     if !matches!(self.net.node(node).kind, NodeKind::Ctr(CtrKind::Con(_))) {
       return false;
     }
-    if self.net.node(self.net.enter_port(Port(node, 1)).node_id()).kind == NodeKind::Era {
+    let (fst, snd) = (self.net.enter_port(Port(node, 1)), self.net.enter_port(Port(node, 2)));
+    if self.net.node(fst.node_id()).kind == NodeKind::Era {
       return false;
     }
+    if matches!(self.net.node(fst.node_id()).kind, NodeKind::Ctr(CtrKind::Dup(_)))
+      && fst.node_id() == snd.node_id()
+      && fst.slot() != 0
+      && snd.slot() != 0
+    {
+      return true;
+    }
     let mut wires = HashSet::new();
     let mut to_check = vec![self.net.enter_port(Port(node, 1))];
     while let Some(port) = to_check.pop() {
@@ -1,20 +1,31 @@
-use std::ops::Range;
+use std::{
+  ops::Range,
+  path::{Path, PathBuf},
+};
 
 use crate::{
+  diagnostics::WarningType,
   fun::{
-    display::DisplayFn, Adt, Book, CtrField, Definition, FanKind, HvmDefinition, MatchRule, Name, Num, Op,
-    Pattern, Rule, Tag, Term, STRINGS,
+    Adt, Book, Cfg, CtrField, DefPass, Definition, ExportSig, ExportType, FanKind, FieldType, HvmDefinition,
+    Macro, MatchRule, Name, Num, Op, Pattern, Rule, Tag, Term, STRINGS,
   },
   imp::{parser::PyParser, Enum, Variant},
-  maybe_grow,
+  maybe_grow, AdtEncoding,
 };
-use highlight_error::highlight_error;
+use indexmap::IndexMap;
 use TSPL::Parser;
 
 // Bend grammar description:
-// <Book>       ::= (<Data> | <Rule>)*
+// <Book>       ::= (<Attribute>* <Data> | <Attribute>* <Rule>)*
+// <Attribute>  ::= "#[allow(" <Name> ("," <Name>)* ")]" | "#[no_float]" | "#[no_prune]" | "#[no_merge]" | "#[associative]"
+//                | "#[encoding(" ("scott" | "num_scott" | "tagged_tuple") ")]"
+//                | "#[export(" (<ExportType> ("," <ExportType>)*)? ")" "->" <ExportType> "]"
+//                | "#[cfg(" <CfgPred> ")]"
+// <ExportType> ::= "u24" | "i24" | "f24" | "String" | "List(" <ExportType> ")"
+// <CfgPred>    ::= ("target" | "feature") "=" <String> | "not(" <CfgPred> ")"
 // <ADT>        ::= "type" <Name> "=" ( <Name> | "(" <Name> (<Name>)* ")" )+
-// <Rule>       ::= ("(" <Name> <Pattern>* ")" | <Name> <Pattern>*) "=" <Term>
+// <Rule>       ::= ("(" <Name> <Pattern>* ")" | <Name> <Pattern>*) "=" <Term> <Where>?
+// <Where>      ::= "where" ":" (<Newline> <Indent> <Rule>)+
 // <Pattern>    ::= "(" <Name> <Pattern>* ")" | <NameEra> | <Number> | "(" <Pattern> ("," <Pattern>)+ ")"
 // <Term>       ::=
 //   <Number> | <NumOp> | <Tup> | <App> | <Group> | <Nat> | <Lam> | <UnscopedLam> | <Bend> | <Fold> |
@@ -40,7 +51,8 @@ use TSPL::Parser;
 // <WithClause> ::= "with" (<Name> ("=" <Term>)? ","?)+
 // <MatchArm>   ::= "|"? <Pattern> ":" <Term> ";"?
 // <Switch>     ::= "switch" <MatchArg> <WithClause>? "{" <SwitchArm>+ "}"
-// <SwitchArm>  ::= "|"? (<Num>|"_") ":" <Term> ";"?
+// <SwitchArm>  ::= "|"? (<SwitchVal> ("," <SwitchVal>)* | "_") ":" <Term> ";"?
+// <SwitchVal>  ::= <Num> (".." <Num>)?
 // <Bend>       ::= "bend" (<MatchArg> ","?)+ "{" "when" <Term> ":" <Term> "else" ":" <Term> "}"
 // <Var>        ::= <Name>
 // <UnscopedVar>::= "$" <Name>
@@ -50,114 +62,395 @@ use TSPL::Parser;
 // <Name>       ::= [_\-./a-zA-Z0-9]+
 // <Number>     ::= ([0-9]+ | "0x"[0-9a-fA-F]+ | "0b"[01]+)
 // <Operator>   ::= ( "+" | "-" | "*" | "/" | "%" | "==" | "!=" | "<<" | ">>" | "<" | ">" | "&" | "|" | "^" | "**" )
+// <Infix>      ::= ("infixl" | "infixr" | "infix") <Digit> <Symbol> "=" <Name>
+// <InfixApp>   ::= "(" <Term>+ (<Symbol> <Term>+)+ ")"
+// <Symbol>     ::= [!%&*+\-./<=>?^|~:]+
 
 pub type ParseResult<T> = std::result::Result<T, String>;
 
+/// The highest precedence a user defined infix operator can have.
+pub const MAX_INFIX_PREC: usize = 9;
+
+/// The symbols of the built-in operators, which can't be redeclared.
+const BUILTIN_OPS: &[&str] =
+  &["+", "-", "*", "/", "%", "**", "<<", ">>", "<=", ">=", "<", ">", "==", "!=", "&", "|", "^", "=", ":"];
+
+/// A user defined infix operator, declared with `infixl`, `infixr` or `infix`.
+#[derive(Debug, Clone)]
+pub struct InfixOp {
+  /// The function that `a op b` is resolved to, as in `(fun a b)`.
+  pub fun: Name,
+  pub prec: usize,
+  pub assoc: Assoc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+  Left,
+  Right,
+  /// Can't be chained with another non-associative operator of the same precedence.
+  None,
+}
+
+/// The infix operators declared so far, by their symbol.
+pub type InfixOps = IndexMap<String, InfixOp>;
+
+/// The attributes written in the lines before a top level definition.
+#[derive(Default)]
+struct Attributes {
+  allowed_warnings: Vec<WarningType>,
+  disabled_passes: Vec<DefPass>,
+  /// The `#[encoding(..)]` of a type definition.
+  encoding: Option<AdtEncoding>,
+  /// The `#[export(..)]` signature of a function definition.
+  export: Option<ExportSig>,
+  /// Whether all the `#[cfg(..)]` attributes hold, if there are any.
+  cfg: Option<bool>,
+  /// Whether the function has an `#[associative]` attribute.
+  associative: bool,
+}
+
 pub struct TermParser<'i> {
   input: &'i str,
   index: usize,
+  /// The `as` binds of the patterns of the rule being parsed, with the values they rebuild.
+  as_binds: Vec<(Name, Term)>,
+  /// The infix operators declared so far in the file being parsed.
+  infix_ops: InfixOps,
+  /// How many `where` blocks were parsed so far, to give unique names to the lifted helpers.
+  where_gen: usize,
+  /// What the `#[cfg(..)]` attributes are checked against.
+  cfg: Cfg,
+  /// The function whose last rule was left out by a `#[cfg(..)]`, so the rules after it are left out too.
+  disabled_rule: Option<Name>,
+  /// The directory that the paths of `include` expressions are relative to, instead of the current one.
+  dir: Option<PathBuf>,
 }
 
 impl<'a> TermParser<'a> {
   pub fn new(input: &'a str) -> Self {
-    Self { input, index: 0 }
+    Self {
+      input,
+      index: 0,
+      as_binds: vec![],
+      infix_ops: InfixOps::new(),
+      where_gen: 0,
+      cfg: Cfg::default(),
+      disabled_rule: None,
+      dir: None,
+    }
+  }
+
+  /// Reads the files of `include` expressions relative to `dir`, usually the one of the file being parsed.
+  pub fn with_dir(mut self, dir: &Path) -> Self {
+    self.dir = Some(dir.to_path_buf());
+    self
+  }
+
+  /// Checks the `#[cfg(..)]` attributes against `cfg` instead of the default one, of the Rust runtime.
+  pub fn with_cfg(mut self, cfg: Cfg) -> Self {
+    self.cfg = cfg;
+    self
   }
 
   /* AST parsing functions */
 
-  pub fn parse_book(&mut self, default_book: Book, builtin: bool) -> ParseResult<Book> {
+  /// Parses a book, reporting all the syntax errors found in it.
+  ///
+  /// After an error, the parser recovers at the next line that starts
+  /// a `def`, `type`, `object` or `hvm` definition.
+  pub fn parse_book(&mut self, default_book: Book, builtin: bool) -> Result<Book, Vec<String>> {
     let mut book = default_book;
-    let mut indent = self.advance_newlines()?;
+    let mut errs = vec![];
+    let mut indent = match self.advance_newlines() {
+      Ok(indent) => indent,
+      Err(e) => {
+        errs.push(e);
+        self.recover_top_level(0)
+      }
+    };
     let mut last_rule = None;
     while !self.is_eof() {
       let ini_idx = *self.index();
-
-      // Record type definition
-      if self.try_parse_keyword("object") {
-        let mut prs = PyParser { input: self.input, index: *self.index() };
-        let (obj, nxt_indent) = prs.parse_object(indent)?;
-        self.index = prs.index;
-        let end_idx = *self.index();
-        self.add_object(obj, &mut book, ini_idx..end_idx, builtin)?;
-        indent = nxt_indent;
+      if let Err(e) = self.parse_top_level(&mut book, builtin, &mut indent, &mut last_rule) {
+        errs.push(e);
+        indent = self.recover_top_level(ini_idx);
         last_rule = None;
-        continue;
       }
+    }
+
+    if errs.is_empty() {
+      Ok(book)
+    } else {
+      Err(errs)
+    }
+  }
 
-      // Imp function definition
-      if self.try_parse_keyword("def") {
-        let mut prs = PyParser { input: self.input, index: *self.index() };
-        let (def, nxt_indent) = prs.parse_def(indent)?;
+  fn parse_top_level(
+    &mut self,
+    book: &mut Book,
+    builtin: bool,
+    indent: &mut Indent,
+    last_rule: &mut Option<Name>,
+  ) -> ParseResult<()> {
+    let ini_idx = *self.index();
+    let (attrs, attrs_ini) = self.parse_attributes_before(ini_idx)?;
+    let Attributes { allowed_warnings, disabled_passes, encoding, export, cfg, associative } = attrs;
+    // What a `#[cfg(..)]` leaves out is still parsed, but added to a book that is then discarded.
+    let mut disabled_book = Book::default();
+    let enabled = cfg != Some(false);
+    let mut book = if enabled { book } else { &mut disabled_book };
+    let is_def = self.starts_with_keyword("def") || !self.starts_with_top_level_keyword();
+    let is_type = self.starts_with_keyword("type") || self.starts_with_keyword("object");
+    let has_def_attrs = !allowed_warnings.is_empty() || !disabled_passes.is_empty() || export.is_some();
+    if (has_def_attrs || associative) && !is_def {
+      let msg = "Attributes can only be used on function definitions.".to_string();
+      return self.with_ctx(Err(msg), attrs_ini..ini_idx);
+    }
+    if encoding.is_some() && !is_type {
+      let msg = "The 'encoding' attribute can only be used on type definitions.".to_string();
+      return self.with_ctx(Err(msg), attrs_ini..ini_idx);
+    }
+
+    // Record type definition
+    if self.try_parse_keyword("object") {
+      let mut prs = PyParser {
+        input: self.input,
+        index: *self.index(),
+        infix_ops: self.infix_ops.clone(),
+        dir: self.dir.clone(),
+      };
+      let (obj, params, nxt_indent) = prs.parse_object(*indent)?;
+      self.index = prs.index;
+      let end_idx = *self.index();
+      let name = obj.name.clone();
+      self.add_object(obj, params, book, ini_idx..end_idx, builtin)?;
+      book.adts[&name].encoding = encoding;
+      *indent = nxt_indent;
+      *last_rule = None;
+      return Ok(());
+    }
+
+    // Imp function definition
+    if self.try_parse_keyword("def") {
+      let mut prs = PyParser {
+        input: self.input,
+        index: *self.index(),
+        infix_ops: self.infix_ops.clone(),
+        dir: self.dir.clone(),
+      };
+      let (def, nxt_indent) = prs.parse_def(*indent)?;
+      self.index = prs.index;
+      let end_idx = *self.index();
+      let name = def.name.clone();
+      self.add_imp_def(def, book, ini_idx..end_idx, builtin)?;
+      book.defs[&name].allowed_warnings = allowed_warnings;
+      book.defs[&name].disabled_passes = disabled_passes;
+      book.defs[&name].export = export;
+      book.defs[&name].associative = associative;
+      *indent = nxt_indent;
+      *last_rule = None;
+      return Ok(());
+    }
+
+    // Fun/Imp type definition
+    if self.try_parse_keyword("type") {
+      self.skip_trivia();
+      let rewind_index = self.index;
+
+      let _ = self.labelled(|p| p.parse_top_level_name(), "datatype name")?;
+      let _ = self.parse_type_params()?;
+
+      // Imp type definition
+      if self.starts_with(":") {
+        let mut prs = PyParser {
+          input: self.input,
+          index: rewind_index,
+          infix_ops: self.infix_ops.clone(),
+          dir: self.dir.clone(),
+        };
+        let (r#enum, nxt_indent) = prs.parse_type(*indent)?;
         self.index = prs.index;
         let end_idx = *self.index();
-        self.add_imp_def(def, &mut book, ini_idx..end_idx, builtin)?;
-        indent = nxt_indent;
-        last_rule = None;
-        continue;
+        let name = r#enum.name.clone();
+        self.add_imp_type(r#enum, book, ini_idx..end_idx, builtin)?;
+        book.adts[&name].encoding = encoding;
+        *indent = nxt_indent;
+        *last_rule = None;
+        return Ok(());
+      // Fun type definition
+      } else {
+        self.index = rewind_index;
+        let (nam, mut adt) = self.parse_datatype(builtin)?;
+        adt.encoding = encoding;
+        let end_idx = *self.index();
+        self.add_fun_type(book, nam, adt, ini_idx..end_idx)?;
+        *indent = self.advance_newlines()?;
+        *last_rule = None;
+        return Ok(());
       }
+    }
 
-      // Fun/Imp type definition
-      if self.try_parse_keyword("type") {
-        self.skip_trivia();
-        let rewind_index = self.index;
-
-        let _ = self.labelled(|p| p.parse_top_level_name(), "datatype name")?;
-
-        // Imp type definition
-        if self.starts_with(":") {
-          let mut prs = PyParser { input: self.input, index: rewind_index };
-          let (r#enum, nxt_indent) = prs.parse_type(indent)?;
-          self.index = prs.index;
-          let end_idx = *self.index();
-          self.add_imp_type(r#enum, &mut book, ini_idx..end_idx, builtin)?;
-          indent = nxt_indent;
-          last_rule = None;
-          continue;
-        // Fun type definition
-        } else {
-          self.index = rewind_index;
-          let (nam, adt) = self.parse_datatype(builtin)?;
-          let end_idx = *self.index();
-          self.add_fun_type(&mut book, nam, adt, ini_idx..end_idx)?;
-          indent = self.advance_newlines()?;
-          last_rule = None;
-          continue;
-        }
+    // Infix operator declaration
+    if let Some(assoc) = self.try_parse_fixity() {
+      let (sym, op) = self.parse_infix_decl(assoc)?;
+      if enabled {
+        self.infix_ops.insert(sym, op);
       }
+      *indent = self.advance_newlines()?;
+      *last_rule = None;
+      return Ok(());
+    }
 
-      // HVM native function definition
-      if self.try_parse_keyword("hvm") {
-        let def = self.parse_hvm(builtin)?;
-        let end_idx = *self.index();
-        self.add_hvm(def, &mut book, ini_idx..end_idx)?;
-        indent = self.advance_newlines()?;
-        last_rule = None;
-        continue;
-      }
+    // HVM native function definition
+    if self.try_parse_keyword("hvm") {
+      let def = self.parse_hvm(builtin)?;
+      let end_idx = *self.index();
+      self.add_hvm(def, book, ini_idx..end_idx)?;
+      *indent = self.advance_newlines()?;
+      *last_rule = None;
+      return Ok(());
+    }
 
-      // Fun function definition
-      let ini_idx = *self.index();
-      let (name, rule) = self.parse_rule()?;
+    // Macro definition
+    if self.try_parse_keyword("macro") {
+      let mac = self.parse_macro()?;
       let end_idx = *self.index();
-      self.add_fun_def(&name, rule, builtin, &last_rule, &mut book, ini_idx..end_idx)?;
-      indent = self.advance_newlines()?;
-      last_rule = Some(name);
+      self.add_macro(mac, book, ini_idx..end_idx)?;
+      *indent = self.advance_newlines()?;
+      *last_rule = None;
+      return Ok(());
     }
 
-    Ok(book)
+    // Fun function definition
+    let ini_idx = *self.index();
+    let mut helpers = vec![];
+    let (name, rule) = self.parse_rule(Some(&mut helpers))?;
+    let end_idx = *self.index();
+    // The rules without a `#[cfg(..)]` are kept or left out together with the ones before them.
+    let continues_disabled = last_rule.as_ref() == Some(&name) && self.disabled_rule.as_ref() == Some(&name);
+    let disabled = !enabled || (cfg.is_none() && continues_disabled);
+    self.disabled_rule = disabled.then(|| name.clone());
+    if disabled {
+      book = &mut disabled_book;
+    }
+    self.add_fun_def(&name, rule, builtin, last_rule, book, ini_idx..end_idx)?;
+    book.defs[&name].allowed_warnings.extend(allowed_warnings);
+    book.defs[&name].disabled_passes.extend(disabled_passes);
+    if export.is_some() {
+      book.defs[&name].export = export;
+    }
+    book.defs[&name].associative |= associative;
+    for (nam, rules) in helpers {
+      self.check_top_level_redefinition(&nam, book, ini_idx..end_idx)?;
+      book.defs.insert(nam.clone(), Definition::new(nam, rules, builtin));
+    }
+    *indent = self.advance_newlines()?;
+    *last_rule = Some(name);
+    Ok(())
+  }
+
+  /// Parses the attributes in the lines right above `idx`.
+  /// Returns them and where the first attribute starts.
+  ///
+  /// Since attributes start with `#`, they're skipped together with the comments
+  /// before a definition, so they're found by looking back from its start.
+  fn parse_attributes_before(&mut self, idx: usize) -> ParseResult<(Attributes, usize)> {
+    let mut attrs = Attributes::default();
+    let mut attrs_ini = idx;
+    let mut line_end = self.input[..idx].rfind('\n');
+    while let Some(end) = line_end {
+      let ini = self.input[..end].rfind('\n').map_or(0, |i| i + 1);
+      let line = self.input[ini..end].trim_end();
+      if !line.starts_with("#[") {
+        break;
+      }
+      let span = ini..ini + line.len();
+      match line {
+        "#[no_float]" => attrs.disabled_passes.push(DefPass::FloatCombinators),
+        "#[no_prune]" => attrs.disabled_passes.push(DefPass::Prune),
+        "#[no_merge]" => attrs.disabled_passes.push(DefPass::Merge),
+        "#[associative]" => attrs.associative = true,
+        "#[encoding(scott)]" => attrs.encoding = Some(AdtEncoding::Scott),
+        "#[encoding(num_scott)]" => attrs.encoding = Some(AdtEncoding::NumScott),
+        "#[encoding(tagged_tuple)]" => attrs.encoding = Some(AdtEncoding::TaggedTuple),
+        _ if line.starts_with("#[encoding(") => {
+          let msg = format!(
+            "Unknown encoding in attribute '{line}'. Expected 'scott', 'num_scott' or 'tagged_tuple'."
+          );
+          return self.with_ctx(Err(msg), span);
+        }
+        _ if line.starts_with("#[export(") => {
+          let sig = parse_export_sig(line);
+          attrs.export = Some(self.with_ctx(sig, span)?);
+          // Exported definitions are looked up by name in the compiled program.
+          attrs.disabled_passes.extend([DefPass::Prune, DefPass::Merge]);
+        }
+        _ if line.starts_with("#[cfg(") => {
+          let holds = match line.strip_prefix("#[cfg(").and_then(|l| l.strip_suffix(")]")) {
+            Some(pred) => eval_cfg(pred, &self.cfg),
+            None => Err(format!("Invalid attribute '{line}'. Expected '#[cfg(<predicate>)]'.")),
+          };
+          let holds = self.with_ctx(holds, span)?;
+          attrs.cfg = Some(attrs.cfg.unwrap_or(true) && holds);
+        }
+        _ => {
+          let Some(names) = line.strip_prefix("#[allow(").and_then(|l| l.strip_suffix(")]")) else {
+            let msg = format!(
+              "Unknown attribute '{line}'. Expected '#[allow(<warning>, ..)]', '#[no_float]', '#[no_prune]', '#[no_merge]', '#[associative]', '#[encoding(<encoding>)]', '#[export(..)]' or '#[cfg(..)]'."
+            );
+            return self.with_ctx(Err(msg), span);
+          };
+          for name in names.split(',').map(str::trim) {
+            let Some(warn) = WarningType::from_name(name) else {
+              return self.with_ctx(Err(format!("Unknown warning '{name}' in attribute.")), span);
+            };
+            attrs.allowed_warnings.push(warn);
+          }
+        }
+      }
+      attrs_ini = ini;
+      line_end = ini.checked_sub(1);
+    }
+    Ok((attrs, attrs_ini))
+  }
+
+  fn starts_with_top_level_keyword(&mut self) -> bool {
+    const KEYWORDS: &[&str] = &["def", "type", "object", "hvm", "macro", "infixl", "infixr", "infix"];
+    KEYWORDS.iter().any(|kw| self.starts_with_keyword(kw))
+  }
+
+  /// Skips to the next line after `ini_idx` that starts a top level definition with a keyword.
+  fn recover_top_level(&mut self, ini_idx: usize) -> Indent {
+    const RECOVERY_KEYWORDS: &[&str] = &["def", "type", "object", "hvm", "macro"];
+    let mut idx = ini_idx;
+    while let Some(line_len) = self.input[idx..].find('\n') {
+      idx += line_len + 1;
+      let line = &self.input[idx..];
+      let is_recovery_point = RECOVERY_KEYWORDS.iter().any(|kw| {
+        line.strip_prefix(kw).is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_whitespace()))
+      });
+      if is_recovery_point {
+        self.index = idx;
+        return Indent::Val(0);
+      }
+    }
+    self.index = self.input.len();
+    Indent::Eof
   }
 
   fn parse_datatype(&mut self, builtin: bool) -> ParseResult<(Name, Adt)> {
     // type name = ctr (| ctr)*
     self.skip_trivia();
     let name = self.labelled(|p| p.parse_top_level_name(), "datatype name")?;
+    let params = self.parse_type_params()?;
     self.consume("=")?;
     let mut ctrs = vec![self.parse_datatype_ctr(&name)?];
     while self.try_consume("|") {
       ctrs.push(self.parse_datatype_ctr(&name)?);
     }
     let ctrs = ctrs.into_iter().collect();
-    let adt = Adt { ctrs, builtin };
+    let adt = Adt { ctrs, params, builtin, encoding: None };
     Ok((name, adt))
   }
 
@@ -170,10 +463,19 @@ impl<'a> TermParser<'a> {
       let name = Name::new(format!("{typ_name}/{name}"));
 
       fn parse_field(p: &mut TermParser) -> ParseResult<CtrField> {
+        // A field with a type is written as `(name: type)`.
+        let typed = p.try_consume("(");
         let rec = p.try_consume("~");
         p.skip_trivia();
         let nam = p.labelled(|p| p.parse_bend_name(), "datatype constructor field")?;
-        Ok(CtrField { nam, rec })
+        let typ = if typed {
+          let typ = p.parse_field_type(rec)?;
+          p.consume(")")?;
+          Some(typ)
+        } else {
+          None
+        };
+        Ok(CtrField { nam, rec, typ })
       }
 
       let fields = self.list_like(parse_field, "", ")", "", false, 0)?;
@@ -186,6 +488,83 @@ impl<'a> TermParser<'a> {
     }
   }
 
+  /// Parses the fixity keyword of an infix operator declaration.
+  ///
+  /// Only counts as a declaration if followed by a precedence and an operator symbol,
+  /// so that functions can still be called `infix`, `infixl` or `infixr`.
+  fn try_parse_fixity(&mut self) -> Option<Assoc> {
+    let ini_idx = *self.index();
+    for (keyword, assoc) in [("infixl", Assoc::Left), ("infixr", Assoc::Right), ("infix", Assoc::None)] {
+      if self.try_parse_keyword(keyword) {
+        self.skip_trivia();
+        let has_prec = self.peek_one().is_some_and(|c| c.is_ascii_digit());
+        self.take_while(|c| c.is_ascii_digit());
+        self.skip_trivia();
+        let sym = self.peek_op_symbol();
+        if has_prec && !sym.is_empty() && sym != "=" {
+          *self.index() = ini_idx + keyword.len();
+          return Some(assoc);
+        }
+        *self.index() = ini_idx;
+        return None;
+      }
+    }
+    None
+  }
+
+  /// <Digit> <Symbol> "=" <Name>
+  fn parse_infix_decl(&mut self, assoc: Assoc) -> ParseResult<(String, InfixOp)> {
+    self.skip_trivia();
+    let ini_idx = *self.index();
+    let prec = self.parse_u32()? as usize;
+    let end_idx = *self.index();
+    if prec > MAX_INFIX_PREC {
+      let msg = format!("Infix operator precedence must be between 0 and {MAX_INFIX_PREC}, got {prec}.");
+      return self.with_ctx(Err(msg), ini_idx..end_idx);
+    }
+
+    self.skip_trivia();
+    let ini_idx = *self.index();
+    let sym = self.peek_op_symbol().to_string();
+    self.advance_many(sym.len());
+    let end_idx = *self.index();
+    if BUILTIN_OPS.contains(&sym.as_str()) {
+      return self.with_ctx(Err(format!("Redefinition of builtin operator '{sym}'.")), ini_idx..end_idx);
+    }
+    if self.infix_ops.contains_key(&sym) {
+      return self.with_ctx(Err(format!("Redefinition of infix operator '{sym}'.")), ini_idx..end_idx);
+    }
+
+    self.consume("=")?;
+    self.skip_trivia();
+    let fun = self.labelled(|p| p.parse_bend_name(), "operator function name")?;
+    Ok((sym, InfixOp { fun, prec, assoc }))
+  }
+
+  /// Consumes the declared infix operator at the current position, if there is one.
+  fn try_parse_infix_op(&mut self) -> Option<InfixOp> {
+    self.skip_trivia();
+    let sym = self.peek_op_symbol();
+    let op = self.infix_ops.get(sym)?.clone();
+    self.advance_many(sym.len());
+    Some(op)
+  }
+
+  /// Parses the arguments of an application, up to the closing parenthesis or an infix operator.
+  fn parse_app_args(&mut self, head: Term, tag: &Option<Tag>) -> ParseResult<Term> {
+    let mut term = head;
+    loop {
+      self.skip_trivia();
+      let sym = self.peek_op_symbol();
+      if self.starts_with(")") || self.infix_ops.contains_key(sym) {
+        break;
+      }
+      let arg = self.parse_term()?;
+      term = Term::App { tag: tag.clone().unwrap_or(Tag::Static), fun: Box::new(term), arg: Box::new(arg) };
+    }
+    Ok(term)
+  }
+
   fn parse_hvm(&mut self, builtin: bool) -> ParseResult<HvmDefinition> {
     self.skip_trivia_inline()?;
     let name = self.parse_bend_name()?;
@@ -201,9 +580,54 @@ impl<'a> TermParser<'a> {
     Ok(def)
   }
 
-  fn parse_rule(&mut self) -> ParseResult<(Name, Rule)> {
+  /// Parses a macro definition, like `macro twice(f, x) = (f (f x))` or `macro call(f, ..args) = (f args)`.
+  fn parse_macro(&mut self) -> ParseResult<Macro> {
+    self.skip_trivia_inline()?;
+    let name = self.labelled(|p| p.parse_top_level_name(), "macro name")?;
+    let ini_idx = *self.index();
+    let params = self.list_like(
+      |p| {
+        let rest = p.try_consume_exactly("..");
+        Ok((p.labelled(|p| p.parse_bend_name(), "macro parameter")?, rest))
+      },
+      "(",
+      ")",
+      ",",
+      true,
+      0,
+    )?;
+    let end_idx = *self.index();
+    let mut names = vec![];
+    let mut rest = None;
+    for (param, is_rest) in params {
+      if rest.is_some() {
+        let msg = "The '..' parameter of a macro must be the last one.".to_string();
+        return self.with_ctx(Err(msg), ini_idx..end_idx);
+      }
+      if names.contains(&param) || rest.as_ref() == Some(&param) {
+        let msg = format!("Repeated parameter '{param}' in macro '{name}'.");
+        return self.with_ctx(Err(msg), ini_idx..end_idx);
+      }
+      if is_rest {
+        rest = Some(param);
+      } else {
+        names.push(param);
+      }
+    }
+    self.consume("=")?;
+    let body = self.parse_term()?;
+    Ok(Macro { name, params: names, rest, body })
+  }
+
+  /// Parses a rule of a function definition.
+  ///
+  /// If `helpers` is given, the rule can be followed by a `where` block,
+  /// whose functions are lifted to new definitions in `helpers`.
+  fn parse_rule(&mut self, helpers: Option<&mut Vec<(Name, Vec<Rule>)>>) -> ParseResult<(Name, Rule)> {
     // (name pat*) = term
     // name pat* = term
+    // Drops the `as` binds of a rule that failed to parse, when the parser recovered from its error.
+    self.as_binds.clear();
     let (name, pats) = if self.try_consume_exactly("(") {
       self.skip_trivia();
       let name = self.labelled(|p| p.parse_top_level_name(), "function name")?;
@@ -220,12 +644,122 @@ impl<'a> TermParser<'a> {
       (name, pats)
     };
 
-    let body = self.parse_term()?;
+    let as_binds = std::mem::take(&mut self.as_binds);
+    let mut body = self.parse_term()?;
+    if let Some(helpers) = helpers {
+      let mut scope = pats.iter().flat_map(|pat| pat.binds().flatten().cloned()).collect::<Vec<_>>();
+      scope.extend(as_binds.iter().map(|(nam, _)| nam.clone()));
+      body = self.parse_where(&name, scope, body, helpers)?;
+    }
+    for (nam, val) in as_binds.into_iter().rev() {
+      body = Term::Use { nam: Some(nam), val: Box::new(val), nxt: Box::new(body) };
+    }
 
     let rule = Rule { pats, body };
     Ok((name, rule))
   }
 
+  /// Parses the optional `where` block after the body of a rule.
+  ///
+  /// Helpers without arguments become `let` binds around the body.
+  /// Helpers with arguments are lifted to new definitions, taking as extra arguments the
+  /// variables of the rule and earlier helpers that they use, like imperative local definitions.
+  fn parse_where(
+    &mut self,
+    parent: &Name,
+    mut scope: Vec<Name>,
+    body: Term,
+    lifted: &mut Vec<(Name, Vec<Rule>)>,
+  ) -> ParseResult<Term> {
+    let rewind_idx = *self.index();
+    self.skip_trivia_inline()?;
+    // In a later line, the block must be indented, so that a definition named `where` isn't taken for one.
+    let same_line = !matches!(self.peek_one(), Some('\n' | '\r') | None);
+    let indented = same_line || matches!(self.advance_newlines()?, Indent::Val(n) if n > 0);
+    if !indented || !self.try_parse_keyword("where") {
+      *self.index() = rewind_idx;
+      return Ok(body);
+    }
+    self.skip_trivia_inline()?;
+    self.consume_exactly(":")?;
+
+    // Helper rules, one per indented line, grouped by name.
+    let mut helpers: Vec<(Name, Vec<Rule>)> = vec![];
+    let mut nxt_indent = self.advance_newlines()?;
+    if !matches!(nxt_indent, Indent::Val(n) if n > 0) {
+      return self.expected("indented 'where' helper");
+    }
+    while matches!(nxt_indent, Indent::Val(n) if n > 0) {
+      let ini_idx = *self.index();
+      let (nam, rule) = self.parse_rule(None)?;
+      let end_idx = *self.index();
+      // Consecutive rules with the same name and arguments are of the same helper function.
+      let is_fun = !rule.pats.is_empty();
+      let continues =
+        matches!(helpers.last(), Some((last, rules)) if *last == nam && is_fun && !rules[0].pats.is_empty());
+      if continues {
+        helpers.last_mut().unwrap().1.push(rule);
+      } else if helpers.iter().any(|(other, _)| *other == nam) {
+        let msg = format!("Redefinition of 'where' helper '{nam}'.");
+        return self.with_ctx(Err(msg), ini_idx..end_idx);
+      } else {
+        helpers.push((nam, vec![rule]));
+      }
+      let rewind_idx = *self.index();
+      nxt_indent = self.advance_newlines()?;
+      if !matches!(nxt_indent, Indent::Val(n) if n > 0) {
+        *self.index() = rewind_idx;
+      }
+    }
+
+    let mut binds = vec![];
+    for (nam, mut rules) in helpers {
+      if rules[0].pats.is_empty() {
+        let val = std::mem::take(&mut rules[0].body);
+        binds.push((nam.clone(), val, false));
+      } else {
+        let lifted_nam = Name::new(format!("{parent}__where_{}_{nam}", self.where_gen));
+        let mut fvs = vec![];
+        for rule in &rules {
+          let binds = rule.pats.iter().flat_map(|pat| pat.binds().flatten()).collect::<Vec<_>>();
+          let rule_fvs = rule.body.free_vars().into_keys();
+          fvs.extend(rule_fvs.filter(|fv| *fv != nam && scope.contains(fv) && !binds.contains(&fv)));
+        }
+        fvs.sort();
+        fvs.dedup();
+        let call = Term::call(
+          Term::Var { nam: lifted_nam.clone() },
+          fvs.iter().map(|nam| Term::Var { nam: nam.clone() }),
+        );
+        for rule in &mut rules {
+          let shadowed = rule.pats.iter().any(|pat| pat.binds().flatten().any(|bind| *bind == nam));
+          if !shadowed && rule.body.free_vars().contains_key(&nam) {
+            let body = std::mem::take(&mut rule.body);
+            rule.body =
+              Term::Use { nam: Some(nam.clone()), val: Box::new(call.clone()), nxt: Box::new(body) };
+          }
+          let captured = fvs.iter().map(|nam| Pattern::Var(Some(nam.clone())));
+          rule.pats = captured.chain(std::mem::take(&mut rule.pats)).collect();
+        }
+        lifted.push((lifted_nam, rules));
+        binds.push((nam.clone(), call, true));
+      }
+      scope.push(nam);
+    }
+    self.where_gen += 1;
+
+    // Each helper is in scope of the ones after it and of the rule body.
+    let body = binds.into_iter().rev().fold(body, |nxt, (nam, val, is_fun)| {
+      let (val, nxt) = (Box::new(val), Box::new(nxt));
+      if is_fun {
+        Term::Use { nam: Some(nam), val, nxt }
+      } else {
+        Term::Let { pat: Box::new(Pattern::Var(Some(nam))), val, nxt }
+      }
+    });
+    Ok(body)
+  }
+
   fn parse_pattern(&mut self, simple: bool) -> ParseResult<Pattern> {
     maybe_grow(|| {
       let (tag, unexpected_tag) = self.parse_tag()?;
@@ -244,7 +778,7 @@ impl<'a> TermParser<'a> {
           self.consume(",")?;
           let mut els = self.list_like(|p| p.parse_pattern(simple), "", ")", ",", true, 1)?;
           els.insert(0, head);
-          return Ok(Pattern::Fan(FanKind::Tup, tag.unwrap_or(Tag::Static), els));
+          return self.parse_as_bind(Pattern::Fan(FanKind::Tup, tag.unwrap_or(Tag::Static), els), simple);
         }
 
         // Ctr
@@ -253,7 +787,7 @@ impl<'a> TermParser<'a> {
           return self.expected_spanned("constructor name", head_ini_idx..head_end_idx);
         };
         let els = self.list_like(|p| p.parse_pattern(simple), "", ")", "", false, 0)?;
-        return Ok(Pattern::Ctr(name, els));
+        return self.parse_as_bind(Pattern::Ctr(name, els), simple);
       }
 
       // Dup
@@ -266,14 +800,14 @@ impl<'a> TermParser<'a> {
       if self.starts_with("[") && !simple {
         unexpected_tag(self)?;
         let els = self.list_like(|p| p.parse_pattern(simple), "[", "]", ",", false, 0)?;
-        return Ok(Pattern::Lst(els));
+        return self.parse_as_bind(Pattern::Lst(els), simple);
       }
 
       // String
       if self.starts_with("\"") && !simple {
         unexpected_tag(self)?;
         let str = self.parse_quoted_string()?;
-        return Ok(Pattern::Str(STRINGS.get(str)));
+        return self.parse_as_bind(Pattern::Str(STRINGS.get(str)), simple);
       }
 
       // Char
@@ -306,6 +840,57 @@ impl<'a> TermParser<'a> {
     })
   }
 
+  /// Parses the pattern of a lambda.
+  ///
+  /// Besides the simple patterns, it can also be any rule pattern,
+  /// in which case the argument is matched when the lambda is applied.
+  fn parse_lam_pattern(&mut self) -> ParseResult<Pattern> {
+    let ini_idx = *self.index();
+    if let Ok(pat) = self.parse_pattern(true) {
+      return Ok(pat);
+    }
+    *self.index() = ini_idx;
+    let as_binds = self.as_binds.len();
+    let pat = self.parse_pattern(false)?;
+    if self.as_binds.len() != as_binds {
+      let end_idx = *self.index();
+      return self.with_ctx(Err("'as' patterns are only allowed in rules."), ini_idx..end_idx);
+    }
+    Ok(pat)
+  }
+
+  /// Parses an optional `as name` after a rule pattern, binding `name` to the whole matched value.
+  ///
+  /// Instead of keeping the matched value around, it's rebuilt from the fields in the rule body,
+  /// so wildcards inside the pattern are given names.
+  fn parse_as_bind(&mut self, mut pat: Pattern, simple: bool) -> ParseResult<Pattern> {
+    if simple {
+      return Ok(pat);
+    }
+    self.skip_trivia();
+    if !self.try_parse_keyword("as") {
+      return Ok(pat);
+    }
+    self.skip_trivia();
+    let nam = self.labelled(|p| p.parse_bend_name(), "name of the 'as' pattern")?;
+    for (i, bind) in pat.binds_mut().filter(|bind| bind.is_none()).enumerate() {
+      *bind = Some(Name::new(format!("{nam}%{i}")));
+    }
+    self.as_binds.push((nam, pat.to_term()));
+    Ok(pat)
+  }
+
+  /// Parses a term that spans the whole input, like an argument given to a program in the command line.
+  pub fn parse_whole_term(&mut self) -> ParseResult<Term> {
+    let term = self.parse_term()?;
+    self.skip_trivia();
+    if !self.is_eof() {
+      let idx = *self.index();
+      return self.expected_spanned("end of the argument", idx..idx + 1);
+    }
+    Ok(term)
+  }
+
   pub fn parse_term(&mut self) -> ParseResult<Term> {
     maybe_grow(|| {
       let (tag, unexpected_tag) = self.parse_tag()?;
@@ -315,13 +900,14 @@ impl<'a> TermParser<'a> {
       if self.starts_with("λ") || self.starts_with("@") {
         self.advance_one();
         let tag = tag.unwrap_or(Tag::Static);
-        let pat = self.parse_pattern(true)?;
+        let pat = self.parse_lam_pattern()?;
         let bod = self.parse_term()?;
         return Ok(Term::Lam { tag, pat: Box::new(pat), bod: Box::new(bod) });
       }
 
       // App, Tup, Num Op
       if self.starts_with("(") {
+        let ini_idx = *self.index();
         self.advance_one();
 
         // Opr but maybe a tup
@@ -362,13 +948,23 @@ impl<'a> TermParser<'a> {
         }
 
         // App
-        let els = self.list_like(|p| p.parse_term(), "", ")", "", false, 0)?;
-        let term = els.into_iter().fold(head, |fun, arg| Term::App {
-          tag: tag.clone().unwrap_or(Tag::Static),
-          fun: Box::new(fun),
-          arg: Box::new(arg),
-        });
-        return Ok(term);
+        let fst = self.parse_app_args(head, &tag)?;
+
+        // Infix operators, with applications as operands
+        let mut rest = vec![];
+        while let Some(op) = self.try_parse_infix_op() {
+          let op_idx = *self.index();
+          self.skip_trivia();
+          if self.starts_with(")") {
+            return self.expected_spanned("operand", op_idx..op_idx + 1);
+          }
+          let head = self.parse_term()?;
+          rest.push((op, self.parse_app_args(head, &tag)?));
+        }
+        let end_idx = *self.index();
+        self.consume(")")?;
+        let term = resolve_infix(fst, rest, &|fun, lhs, rhs| Term::call(Term::Var { nam: fun }, [lhs, rhs]));
+        return self.with_ctx(term, ini_idx..end_idx + 1);
       }
 
       // List
@@ -435,6 +1031,15 @@ impl<'a> TermParser<'a> {
         return Ok(Term::Str { val: STRINGS.get(str) });
       }
 
+      // Include
+      if self.starts_with_keyword("include") {
+        let dir = self.dir.clone();
+        if let Some(contents) = self.try_parse_include(dir.as_deref())? {
+          unexpected_tag(self)?;
+          return Ok(Term::Str { val: STRINGS.get(contents) });
+        }
+      }
+
       // Char
       if self.starts_with("'") {
         unexpected_tag(self)?;
@@ -540,35 +1145,52 @@ impl<'a> TermParser<'a> {
         let (bnd, arg) = self.parse_match_arg()?;
         let (with_bnd, with_arg) = self.parse_with_clause()?;
 
-        self.try_consume("|");
-        self.consume("0")?;
-        self.consume(":")?;
-        let zero = self.parse_term()?;
-        self.try_consume(";");
-
-        let mut arms = vec![zero];
-        let mut expected_num = 1;
-        loop {
+        let mut cases = vec![];
+        let mut seen = vec![];
+        let default = loop {
           self.try_consume("|");
           // case _
+          let ini_idx = *self.index();
           if self.try_consume("_") {
+            if cases.is_empty() {
+              let end_idx = *self.index();
+              return self.expected_spanned("numbered case", ini_idx..end_idx);
+            }
             self.consume(":")?;
-            arms.push(self.parse_term()?);
+            let default = self.parse_term()?;
             self.try_consume(";");
             self.consume("}")?;
-            break;
+            break default;
           }
-          // case num
-          let val = self.parse_u32()?;
-          if val != expected_num {
-            return self.expected(&format!("'{}'", &expected_num.to_string()));
+          // case num, num..num, ...
+          let mut case = vec![];
+          loop {
+            self.skip_trivia();
+            let range = self.parse_switch_range(&seen)?;
+            seen.push(range.clone());
+            case.push(range);
+            if !self.try_consume(",") {
+              break;
+            }
           }
-          expected_num += 1;
           self.consume(":")?;
-          arms.push(self.parse_term()?);
+          cases.push((case, self.parse_term()?));
           self.try_consume(";");
+        };
+
+        // Switches on the consecutive numbers from 0 are native, any others become chains of `if`s.
+        let is_native = cases
+          .iter()
+          .enumerate()
+          .all(|(i, (case, _))| matches!(case.as_slice(), [r] if *r == (i as u32..i as u32 + 1)));
+        let bnd = bnd.unwrap();
+        if !is_native {
+          return Ok(range_switch_to_ifs(arg, bnd, with_bnd, with_arg, cases, default));
         }
-        let pred = Some(Name::new(format!("{}-{}", bnd.as_ref().unwrap(), arms.len() - 1)));
+        let mut arms = cases.into_iter().map(|(_, arm)| arm).collect::<Vec<_>>();
+        arms.push(default);
+        let pred = Some(Name::new(format!("{}-{}", bnd, arms.len() - 1)));
+        let bnd = Some(bnd);
         return Ok(Term::Swt { arg: Box::new(arg), bnd, with_bnd, with_arg, pred, arms });
       }
 
@@ -747,6 +1369,9 @@ impl<'a> TermParser<'a> {
     book: &mut Book,
     span: Range<usize>,
   ) -> ParseResult<()> {
+    if !builtin {
+      book.remove_replaceable_builtin(name);
+    }
     match (book.defs.get_mut(name), last_rule) {
       // Continuing with a new rule to the current definition
       (Some(def), Some(last_rule)) if last_rule == name => {
@@ -765,7 +1390,7 @@ impl<'a> TermParser<'a> {
       // Adding the first rule of a new definition
       (None, _) => {
         self.check_top_level_redefinition(name, book, span)?;
-        book.defs.insert(name.clone(), Definition { name: name.clone(), rules: vec![rule], builtin });
+        book.defs.insert(name.clone(), Definition::new(name.clone(), vec![rule], builtin));
       }
     }
     Ok(())
@@ -778,7 +1403,12 @@ impl<'a> TermParser<'a> {
     span: Range<usize>,
     builtin: bool,
   ) -> ParseResult<()> {
+    if !builtin {
+      book.remove_replaceable_builtin(&def.name);
+    }
     self.check_top_level_redefinition(&def.name, book, span)?;
+    def.desugar_loops()?;
+    def.desugar_range_switches();
     def.order_kwargs(book)?;
     def.gen_map_get();
     let locals = def.lift_local_defs(&mut 0)?;
@@ -789,11 +1419,24 @@ impl<'a> TermParser<'a> {
   }
 
   fn add_hvm(&mut self, def: HvmDefinition, book: &mut Book, span: Range<usize>) -> ParseResult<()> {
+    if !def.builtin {
+      book.remove_replaceable_builtin(&def.name);
+    }
     self.check_top_level_redefinition(&def.name, book, span)?;
     book.hvm_defs.insert(def.name.clone(), def);
     Ok(())
   }
 
+  fn add_macro(&mut self, mac: Macro, book: &mut Book, span: Range<usize>) -> ParseResult<()> {
+    self.check_top_level_redefinition(&mac.name, book, span.clone())?;
+    if book.macros.contains_key(&mac.name) {
+      let msg = format!("Redefinition of macro '{}'.", mac.name);
+      return self.with_ctx(Err(msg), span);
+    }
+    book.macros.insert(mac.name.clone(), mac);
+    Ok(())
+  }
+
   fn add_imp_type(
     &mut self,
     enum_: Enum,
@@ -801,8 +1444,11 @@ impl<'a> TermParser<'a> {
     span: Range<usize>,
     builtin: bool,
   ) -> ParseResult<()> {
+    if !builtin {
+      book.remove_replaceable_builtin(&enum_.name);
+    }
     self.check_type_redefinition(&enum_.name, book, span.clone())?;
-    let mut adt = Adt { ctrs: Default::default(), builtin };
+    let mut adt = Adt { ctrs: Default::default(), params: enum_.params, builtin, encoding: None };
     for variant in enum_.variants {
       self.check_top_level_redefinition(&enum_.name, book, span.clone())?;
       book.ctrs.insert(variant.name.clone(), enum_.name.clone());
@@ -813,6 +1459,9 @@ impl<'a> TermParser<'a> {
   }
 
   fn add_fun_type(&mut self, book: &mut Book, nam: Name, adt: Adt, span: Range<usize>) -> ParseResult<()> {
+    if !adt.builtin {
+      book.remove_replaceable_builtin(&nam);
+    }
     if book.adts.contains_key(&nam) {
       let msg = TermParser::redefinition_of_type_msg(&nam);
       return self.with_ctx(Err(msg), span);
@@ -834,13 +1483,17 @@ impl<'a> TermParser<'a> {
   fn add_object(
     &mut self,
     obj: Variant,
+    params: Vec<Name>,
     book: &mut Book,
     span: Range<usize>,
     builtin: bool,
   ) -> ParseResult<()> {
+    if !builtin {
+      book.remove_replaceable_builtin(&obj.name);
+    }
     self.check_type_redefinition(&obj.name, book, span.clone())?;
     self.check_top_level_redefinition(&obj.name, book, span)?;
-    let mut adt = Adt { ctrs: Default::default(), builtin };
+    let mut adt = Adt { ctrs: Default::default(), params, builtin, encoding: None };
     book.ctrs.insert(obj.name.clone(), obj.name.clone());
     adt.ctrs.insert(obj.name.clone(), obj.fields);
     book.adts.insert(obj.name, adt);
@@ -930,6 +1583,247 @@ impl<'a> Parser<'a> for TermParser<'a> {
   }
 }
 
+/// Resolves a sequence of operands separated by infix operators into nested applications,
+/// following the precedence and associativity of each operator.
+pub fn resolve_infix<T>(
+  fst: T,
+  rest: Vec<(InfixOp, T)>,
+  apply: &impl Fn(Name, T, T) -> T,
+) -> Result<T, String> {
+  fn climb<T>(
+    mut lhs: T,
+    min_prec: usize,
+    ops: &mut std::iter::Peekable<std::vec::IntoIter<(InfixOp, T)>>,
+    apply: &impl Fn(Name, T, T) -> T,
+  ) -> Result<T, String> {
+    while let Some((op, mut rhs)) = ops.next_if(|(op, _)| op.prec >= min_prec) {
+      while let Some((nxt, _)) = ops.peek() {
+        if nxt.prec == op.prec && (op.assoc == Assoc::None || nxt.assoc == Assoc::None) {
+          return Err(format!(
+            "Can't chain the non-associative infix operators for '{}' and '{}' without parentheses.",
+            op.fun, nxt.fun
+          ));
+        } else if nxt.prec == op.prec && op.assoc != nxt.assoc {
+          return Err(format!(
+            "Can't chain the infix operators for '{}' and '{}' without parentheses, since they have the same precedence but associate to different sides.",
+            op.fun, nxt.fun
+          ));
+        } else if nxt.prec > op.prec {
+          rhs = climb(rhs, op.prec + 1, ops, apply)?;
+        } else if nxt.prec == op.prec && op.assoc == Assoc::Right {
+          rhs = climb(rhs, op.prec, ops, apply)?;
+        } else {
+          break;
+        }
+      }
+      lhs = apply(op.fun, lhs, rhs);
+    }
+    Ok(lhs)
+  }
+  climb(fst, 0, &mut rest.into_iter().peekable(), apply)
+}
+
+/// Converts a `switch` with cases other than the consecutive numbers from 0 into a chain of `if`s,
+/// with one comparison per range of numbers in a case.
+///
+/// ```bend
+/// switch x = (f y) { 1, 5..10: A; _: B }
+/// // Becomes
+/// let x = (f y); if (| (== x 1) (& (>= x 5) (< x 10))) { A } else { B }
+/// ```
+fn range_switch_to_ifs(
+  arg: Term,
+  bnd: Name,
+  with_bnd: Vec<Option<Name>>,
+  with_arg: Vec<Term>,
+  cases: Vec<(Vec<Range<u32>>, Term)>,
+  default: Term,
+) -> Term {
+  let var = || Term::Var { nam: bnd.clone() };
+  let num = |val| Term::Num { val: Num::U24(val) };
+  let opr = |opr, fst, snd| Term::Oper { opr, fst: Box::new(fst), snd: Box::new(snd) };
+  let case_cond = |case: Vec<Range<u32>>| {
+    let conds = case.into_iter().map(|range| {
+      if range.end == range.start + 1 {
+        opr(Op::EQ, var(), num(range.start))
+      } else if range.start == 0 {
+        opr(Op::LT, var(), num(range.end))
+      } else {
+        opr(Op::AND, opr(Op::GE, var(), num(range.start)), opr(Op::LT, var(), num(range.end)))
+      }
+    });
+    conds.reduce(|acc, cond| opr(Op::OR, acc, cond)).unwrap()
+  };
+
+  let mut term = cases.into_iter().rfold(default, |otherwise, (case, then)| Term::Swt {
+    arg: Box::new(case_cond(case)),
+    bnd: Some(Name::new("%cond")),
+    with_bnd: Vec::new(),
+    with_arg: Vec::new(),
+    pred: Some(Name::new("%cond-1")),
+    arms: vec![otherwise, then],
+  });
+
+  // The switch arg and the `with` args are bound before the chain so that every case can use them.
+  let binds = std::iter::once((Some(bnd.clone()), arg)).chain(with_bnd.into_iter().zip(with_arg));
+  for (nam, val) in binds.rev() {
+    match nam {
+      Some(nam) if !matches!(&val, Term::Var { nam: var } if *var == nam) => {
+        term = Term::Let { pat: Box::new(Pattern::Var(Some(nam))), val: Box::new(val), nxt: Box::new(term) };
+      }
+      _ => {}
+    }
+  }
+  term
+}
+
+/// Checks the predicate of a `#[cfg(..)]` attribute, like `target = "cuda"` or `not(feature = "debug")`.
+fn eval_cfg(pred: &str, cfg: &Cfg) -> Result<bool, String> {
+  let pred = pred.trim();
+  if let Some(inner) = pred.strip_prefix("not(").and_then(|p| p.strip_suffix(')')) {
+    return eval_cfg(inner, cfg).map(|holds| !holds);
+  }
+  let err = || {
+    format!(
+      "Invalid predicate '{pred}' in attribute. Expected 'target = \"<target>\"', 'feature = \"<feature>\"' or 'not(<predicate>)'."
+    )
+  };
+  let (key, val) = pred.split_once('=').ok_or_else(err)?;
+  let val = val.trim().strip_prefix('"').and_then(|v| v.strip_suffix('"')).ok_or_else(err)?;
+  match key.trim() {
+    "target" if Cfg::TARGETS.contains(&val) => Ok(cfg.target == val),
+    "target" => Err(format!(
+      "Unknown target '{val}' in attribute. Expected one of {}.",
+      Cfg::TARGETS.iter().map(|t| format!("'{t}'")).collect::<Vec<_>>().join(", ")
+    )),
+    "feature" => Ok(cfg.features.iter().any(|feature| feature == val)),
+    _ => Err(err()),
+  }
+}
+
+/// Parses the signature of an `#[export(<type>, ..) -> <type>]` attribute.
+fn parse_export_sig(attr: &str) -> Result<ExportSig, String> {
+  let err = || {
+    format!(
+      "Invalid signature in attribute '{attr}'. Expected '#[export(<type>, ..) -> <type>]', with the types 'u24', 'i24', 'f24', 'String' or 'List(<type>)' of numbers or strings."
+    )
+  };
+  let mut rest = attr.strip_prefix("#[export(").and_then(|s| s.strip_suffix(']')).ok_or_else(err)?;
+  let mut args = vec![];
+  if let Some(after) = rest.trim_start().strip_prefix(')') {
+    rest = after;
+  } else {
+    loop {
+      let (typ, after) = parse_export_type(rest).ok_or_else(err)?;
+      args.push(typ);
+      let after = after.trim_start();
+      if let Some(after) = after.strip_prefix(',') {
+        rest = after;
+      } else {
+        rest = after.strip_prefix(')').ok_or_else(err)?;
+        break;
+      }
+    }
+  }
+  let rest = rest.trim_start().strip_prefix("->").ok_or_else(err)?;
+  let (ret, rest) = parse_export_type(rest).ok_or_else(err)?;
+  if !rest.trim().is_empty() {
+    return Err(err());
+  }
+  Ok(ExportSig { args, ret })
+}
+
+/// Parses a type at the start of `s`, returning it and the rest of `s`.
+fn parse_export_type(s: &str) -> Option<(ExportType, &str)> {
+  let s = s.trim_start();
+  let (word, rest) = s.split_at(s.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(s.len()));
+  let typ = match word {
+    "u24" => ExportType::U24,
+    "i24" => ExportType::I24,
+    "f24" => ExportType::F24,
+    "String" => ExportType::String,
+    "List" => {
+      let rest = rest.trim_start().strip_prefix('(')?;
+      let (item, rest) = parse_export_type(rest)?;
+      if matches!(item, ExportType::List(..)) {
+        return None;
+      }
+      let rest = rest.trim_start().strip_prefix(')')?;
+      return Some((ExportType::List(Box::new(item)), rest));
+    }
+    _ => return None,
+  };
+  Some((typ, rest))
+}
+
+/// Shows the lines of the input covered by the span, with the span highlighted,
+/// a caret under its first line and the line and column where it starts.
+fn highlight_span(input: &str, span: Range<usize>) -> String {
+  let ini = span.start.min(input.len());
+  // Spans of whole definitions can include the whitespace after them.
+  let end = span.end.clamp(ini, input.len());
+  let end = ini + input[ini..end].trim_end().len();
+  let fst_line_ini = input[..ini].rfind('\n').map_or(0, |i| i + 1);
+  let lst_line_end = input[end..].find('\n').map_or(input.len(), |i| end + i);
+  let fst_line_num = input[..ini].matches('\n').count() + 1;
+  let lines = input[fst_line_ini..lst_line_end].split('\n').collect::<Vec<_>>();
+  let width = (fst_line_num + lines.len() - 1).to_string().len();
+
+  let col = input[fst_line_ini..ini].chars().count() + 1;
+  let mut text = vec![format!("{:width$}\x1b[1m--> line {fst_line_num}, column {col}\x1b[0m", "")];
+  let mut line_ini = fst_line_ini;
+  for (i, line) in lines.iter().enumerate() {
+    let next_line_ini = line_ini + line.len() + 1;
+    let line = line.trim_end_matches('\r');
+    let line_end = line_ini + line.len();
+    let high_ini = ini.clamp(line_ini, line_end);
+    let high_end = end.clamp(line_ini, line_end);
+    text.push(format!(
+      "{:>width$} | {}\x1b[4m\x1b[31m{}\x1b[0m{}",
+      fst_line_num + i,
+      &line[..high_ini - line_ini],
+      &line[high_ini - line_ini..high_end - line_ini],
+      &line[high_end - line_ini..]
+    ));
+    if i == 0 {
+      let n_carets = input[high_ini..high_end].chars().count().max(1);
+      text.push(format!("{:width$} | {}\x1b[31m{}\x1b[0m", "", " ".repeat(col - 1), "^".repeat(n_carets)));
+    }
+    line_ini = next_line_ini;
+  }
+  text.join("\n")
+}
+
+/// The token found at the given position of the input, to be shown in parse errors.
+fn detected_token(input: &str, idx: usize) -> String {
+  let rest = input.get(idx..).unwrap_or("");
+  match rest.chars().next() {
+    None => "end of input".to_string(),
+    Some('\n' | '\r') => "end of line".to_string(),
+    Some(c) if is_name_char(c) => {
+      let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+      format!("'{}'", &rest[..len])
+    }
+    Some(c) => format!("'{c}'"),
+  }
+}
+
+/// A hint for when the line of the error starts with a keyword from an older version of the syntax.
+fn old_syntax_hint(input: &str, idx: usize) -> Option<&'static str> {
+  let idx = idx.min(input.len());
+  let line_ini = input[..idx].rfind('\n').map_or(0, |i| i + 1);
+  let fst_word = input[line_ini..].split(|c: char| c.is_ascii_whitespace()).next()?;
+  match fst_word {
+    "enum" => Some("Types are now defined with 'type' instead of 'enum'."),
+    "data" => Some("Types are now defined with 'type' instead of 'data'."),
+    _ => None,
+  }
+}
+
+pub fn is_op_char(c: char) -> bool {
+  "!%&*+-./<=>?^|~:".contains(c)
+}
+
 pub fn is_name_char(c: char) -> bool {
   c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' || c == '/'
 }
@@ -967,6 +1861,13 @@ impl Book {}
 impl<'a> ParserCommons<'a> for TermParser<'a> {}
 
 pub trait ParserCommons<'a>: Parser<'a> {
+  /// Returns the longest run of operator characters at the current position, without consuming it.
+  fn peek_op_symbol(&mut self) -> &'a str {
+    let input = &self.input()[*self.index()..];
+    let len = input.find(|c| !is_op_char(c)).unwrap_or(input.len());
+    &input[..len]
+  }
+
   fn labelled<T>(&mut self, parser: impl Fn(&mut Self) -> ParseResult<T>, label: &str) -> ParseResult<T> {
     match parser(self) {
       Ok(val) => Ok(val),
@@ -1001,6 +1902,47 @@ pub trait ParserCommons<'a>: Parser<'a> {
     self.parse_restricted_name("Variable")
   }
 
+  /// Parses the parameters of a type definition, like `(a, b)` in `type Pair(a, b)`, if it has any.
+  fn parse_type_params(&mut self) -> ParseResult<Vec<Name>> {
+    if !self.starts_with("(") {
+      return Ok(vec![]);
+    }
+    let ini_idx = *self.index();
+    let params = self.list_like(|p| p.parse_bend_name(), "(", ")", ",", true, 1)?;
+    let end_idx = *self.index();
+    for (i, param) in params.iter().enumerate() {
+      if params[..i].contains(param) {
+        let msg = format!("Repeated type parameter '{param}'.");
+        return self.with_ctx(Err(msg), ini_idx..end_idx);
+      }
+    }
+    Ok(params)
+  }
+
+  /// Parses the type of a constructor field after its name, like `: a` in `Node { val: a }`.
+  fn parse_field_type(&mut self, rec: bool) -> ParseResult<FieldType> {
+    let ini_idx = *self.index();
+    self.consume(":")?;
+    if rec {
+      let msg = "Recursive fields can't have a type, they always hold the type being defined.".to_string();
+      let end_idx = *self.index();
+      return self.with_ctx(Err(msg), ini_idx..end_idx);
+    }
+    self.parse_type_expr()
+  }
+
+  /// Parses a type, like `u24`, `a` or `List(a)`.
+  fn parse_type_expr(&mut self) -> ParseResult<FieldType> {
+    self.skip_trivia();
+    let nam = self.labelled(|p| p.parse_top_level_name(), "type")?;
+    let args = if self.starts_with("(") {
+      self.list_like(|p| p.parse_type_expr(), "(", ")", ",", true, 1)?
+    } else {
+      vec![]
+    };
+    Ok(FieldType { nam, args })
+  }
+
   /// Consumes exactly the text without skipping.
   fn consume_exactly(&mut self, text: &str) -> ParseResult<()> {
     if self.input().get(*self.index()..).unwrap_or_default().starts_with(text) {
@@ -1071,15 +2013,17 @@ pub trait ParserCommons<'a>: Parser<'a> {
   }
 
   fn expected_spanned<T>(&mut self, exp: &str, span: Range<usize>) -> ParseResult<T> {
-    let is_eof = self.is_eof();
-    let detected = DisplayFn(|f| if is_eof { write!(f, " end of input") } else { Ok(()) });
-    let msg = format!("\x1b[1m- expected:\x1b[0m {}\n\x1b[1m- detected:\x1b[0m{}", exp, detected);
+    let detected = detected_token(self.input(), span.start);
+    let mut msg = format!("\x1b[1m- expected:\x1b[0m {}\n\x1b[1m- detected:\x1b[0m {}", exp, detected);
+    if let Some(hint) = old_syntax_hint(self.input(), span.start) {
+      msg.push_str(&format!("\n\x1b[1m- hint:\x1b[0m {hint}"));
+    }
     self.with_ctx(Err(msg), span)
   }
 
   fn with_ctx<T>(&mut self, res: Result<T, impl std::fmt::Display>, span: Range<usize>) -> ParseResult<T> {
     res.map_err(|msg| {
-      let ctx = highlight_error(span.start, span.end, self.input());
+      let ctx = highlight_span(self.input(), span);
       format!("{msg}\n{ctx}")
     })
   }
@@ -1105,6 +2049,33 @@ pub trait ParserCommons<'a>: Parser<'a> {
     }
   }
 
+  fn starts_with_keyword(&mut self, keyword: &str) -> bool {
+    let ini_idx = *self.index();
+    let is_keyword = self.try_parse_keyword(keyword);
+    *self.index() = ini_idx;
+    is_keyword
+  }
+
+  /// Parses an `include "<path>"` expression, returning the contents of the file, with the path relative
+  /// to `dir`. Returns `None` if it's not an include, like a variable named `include`.
+  fn try_parse_include(&mut self, dir: Option<&Path>) -> ParseResult<Option<String>> {
+    let ini_idx = *self.index();
+    if !self.try_parse_keyword("include") {
+      return Ok(None);
+    }
+    self.skip_trivia_inline()?;
+    if !self.starts_with("\"") {
+      *self.index() = ini_idx;
+      return Ok(None);
+    }
+    let file = self.parse_quoted_string()?;
+    let end_idx = *self.index();
+    let path = dir.map_or_else(|| PathBuf::from(&file), |dir| dir.join(&file));
+    let contents =
+      std::fs::read_to_string(&path).map_err(|e| format!("Couldn't include '{}'. {e}", path.display()));
+    self.with_ctx(contents, ini_idx..end_idx).map(Some)
+  }
+
   fn try_parse_keyword(&mut self, keyword: &str) -> bool {
     if !self.starts_with(keyword) {
       return false;
@@ -1340,6 +2311,41 @@ pub trait ParserCommons<'a>: Parser<'a> {
     Ok(Num::U24(num))
   }
 
+  /// Parses a number or a range of numbers, excluding the end, as the values of a switch case.
+  ///
+  /// The values must fit in a u24 and can't be matched by the values before them in the switch, `prev`,
+  /// since the first case with a value is the one taken.
+  fn parse_switch_range(&mut self, prev: &[Range<u32>]) -> ParseResult<Range<u32>> {
+    let ini_idx = *self.index();
+    if !self.peek_one().is_some_and(|c| c.is_ascii_digit()) {
+      return self.expected_spanned("number or '_'", ini_idx..ini_idx + 1);
+    }
+    let start = self.parse_u32()?;
+    if start >= 1 << 24 {
+      return self.num_range_err(ini_idx, "U24");
+    }
+    let end = if self.try_consume_exactly("..") {
+      let end = self.parse_u32()?;
+      if end > 1 << 24 {
+        return self.num_range_err(ini_idx, "U24");
+      }
+      end
+    } else {
+      start + 1
+    };
+    let end_idx = *self.index();
+    if end <= start {
+      return self.expected_spanned("non-empty range", ini_idx..end_idx);
+    }
+    if let Some(old) = prev.iter().find(|old| old.start < end && start < old.end) {
+      let (lo, hi) = (start.max(old.start), end.min(old.end));
+      let vals = if hi == lo + 1 { format!("value {lo} is") } else { format!("values {lo}..{hi} are") };
+      let msg = format!("The {vals} already matched by a previous case of the switch.");
+      return self.with_ctx(Err(msg), ini_idx..end_idx);
+    }
+    Ok(start..end)
+  }
+
   fn num_range_err<T>(&mut self, ini_idx: usize, typ: &str) -> ParseResult<T> {
     let msg = format!("\x1b[1mNumber literal outside of range for {}.\x1b[0m", typ);
     let end_idx = *self.index();
@@ -1,6 +1,6 @@
 use crate::{
   diagnostics::Diagnostics,
-  fun::{Ctx, Pattern, Rule, Term},
+  fun::{Book, Ctx, Name, Pattern, Rule, Term},
 };
 
 impl Ctx<'_> {
@@ -42,13 +42,47 @@ impl Ctx<'_> {
         }
       }
 
+      main_def.rules = vec![Rule { pats: vec![], body: main_body }];
+
       if let Some(args) = args {
-        main_body = Term::call(main_body, args);
+        let n_params = self.book.n_params(&self.book.defs[entrypoint].rules[0].body, &mut vec![]);
+        if args.len() > n_params {
+          self.info.add_rule_error(
+            format!("The entrypoint takes {n_params} arguments, but {} were given.", args.len()),
+            entrypoint.clone(),
+          );
+        }
+        let main_def = &mut self.book.defs[entrypoint];
+        let main_body = std::mem::take(&mut main_def.rules[0].body);
+        main_def.rules[0].body = Term::call(main_body, args);
       }
-
-      main_def.rules = vec![Rule { pats: vec![], body: main_body }];
     }
 
     self.info.fatal(())
   }
 }
+
+impl Book {
+  /// Counts how many arguments a term takes, following the definitions it refers to.
+  ///
+  /// Counts the lambdas the term starts with and, if it then refers to a definition,
+  /// the patterns of the definition and the lambdas its body starts with.
+  /// `seen` has the visited definitions and the variables bound by the lambdas, which aren't followed.
+  fn n_params(&self, term: &Term, seen: &mut Vec<Name>) -> usize {
+    match term {
+      Term::Lam { pat, bod, .. } => {
+        seen.extend(pat.binds().flatten().cloned());
+        1 + self.n_params(bod, seen)
+      }
+      Term::Var { nam } if !seen.contains(nam) => match self.defs.get(nam) {
+        Some(def) => {
+          seen.push(nam.clone());
+          let body = if def.rules.len() == 1 { self.n_params(&def.rules[0].body, seen) } else { 0 };
+          def.arity() + body
+        }
+        None => 0,
+      },
+      _ => 0,
+    }
+  }
+}
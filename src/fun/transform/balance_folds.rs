@@ -0,0 +1,167 @@
+use crate::{
+  fun::{builtins, Ctx, Name, Tag, Term},
+  maybe_grow,
+};
+
+/// The builtin that builds a balanced tree with the elements of a list.
+const PARALLEL_TREE: &str = "Parallel/tree";
+
+impl Ctx<'_> {
+  /// Rewrites the `fold`s over lists that combine each element with the folded rest of the list,
+  /// using a function marked with `#[associative]`, into `fold`s over a balanced tree with the same elements.
+  /// Instead of combining the elements one after the other, they are combined in a balanced tree,
+  /// so that the fold can run in parallel.
+  ///
+  /// Example:
+  /// ```bend
+  /// #[associative]
+  /// (Max a b) = switch (< a b) { 0: a; _: b }
+  ///
+  /// main = fold xs = [3, 1, 2] { List/Cons: (Max (* xs.head 2) xs.tail); List/Nil: 0 }
+  /// ```
+  /// Becomes:
+  /// ```bend
+  /// main = match %list0 = [3, 1, 2] {
+  ///   List/Nil: 0
+  ///   List/Cons %list0.head %list0.tail: (Max
+  ///     fold %tree0 = (Parallel/tree (List/Cons %list0.head %list0.tail)) {
+  ///       Tree/Node %tree0.left %tree0.right: (Max %tree0.left %tree0.right)
+  ///       Tree/Leaf xs.head: (* xs.head 2)
+  ///     }
+  ///     0
+  ///   )
+  /// }
+  /// ```
+  ///
+  /// Precondition: Refs are resolved and the match arms are fixed.
+  pub fn balance_folds(&mut self) {
+    if !self.book.defs.contains_key(&Name::new(PARALLEL_TREE)) {
+      return;
+    }
+    let associative =
+      self.book.defs.values().filter(|def| def.associative).map(|def| def.name.clone()).collect::<Vec<_>>();
+    if associative.is_empty() {
+      return;
+    }
+
+    for def in self.book.defs.values_mut() {
+      let mut fresh = 0;
+      for rule in def.rules.iter_mut() {
+        rule.body.balance_folds(&associative, &mut fresh);
+      }
+    }
+  }
+}
+
+impl Term {
+  fn balance_folds(&mut self, associative: &[Name], fresh: &mut usize) {
+    maybe_grow(|| {
+      for child in self.children_mut() {
+        child.balance_folds(associative, fresh);
+      }
+      if !self.is_balanceable_fold(associative) {
+        return;
+      }
+      let Term::Fold { arg, arms, .. } = self else { unreachable!() };
+      let arg = std::mem::take(arg.as_mut());
+      let nil = arms.iter().position(|(ctr, ..)| ctr.as_deref() == Some(builtins::LNIL)).unwrap();
+      let init = std::mem::take(without_rebuild_mut(&mut arms[nil].2));
+      let (_, fields, body) = &mut arms[1 - nil];
+      let head = fields[0].take();
+      let Term::App { fun, .. } = without_rebuild_mut(body) else { unreachable!() };
+      let Term::App { fun: op, arg: val, .. } = fun.as_mut() else { unreachable!() };
+      let op = std::mem::take(op.as_mut());
+      let val = std::mem::take(val.as_mut());
+
+      let list = Name::new(format!("%list{fresh}"));
+      let tree = Name::new(format!("%tree{fresh}"));
+      *fresh += 1;
+      let field = |nam: &Name, field: &str| Name::new(format!("{nam}.{field}"));
+      let (list_head, list_tail) = (field(&list, builtins::HEAD), field(&list, builtins::TAIL));
+      let (left, right) = (field(&tree, "left"), field(&tree, "right"));
+
+      // The leaves of the tree bind the head of the original `fold`, so its value doesn't change.
+      let list_arg = Term::call(
+        Term::r#ref(builtins::LCONS),
+        [Term::Var { nam: list_head.clone() }, Term::Var { nam: list_tail.clone() }],
+      );
+      let tree_fold = Term::Fold {
+        bnd: Some(tree),
+        arg: Box::new(Term::call(Term::r#ref(PARALLEL_TREE), [list_arg])),
+        with_bnd: vec![],
+        with_arg: vec![],
+        arms: vec![
+          (
+            Some(Name::new(builtins::TREE_NODE)),
+            vec![Some(left.clone()), Some(right.clone())],
+            Term::call(op.clone(), [Term::Var { nam: left }, Term::Var { nam: right }]),
+          ),
+          (Some(Name::new(builtins::TREE_LEAF)), vec![head], val),
+        ],
+      };
+      *self = Term::Mat {
+        bnd: Some(list),
+        arg: Box::new(arg),
+        with_bnd: vec![],
+        with_arg: vec![],
+        arms: vec![
+          (Some(Name::new(builtins::LNIL)), vec![], init.clone()),
+          (
+            Some(Name::new(builtins::LCONS)),
+            vec![Some(list_head), Some(list_tail)],
+            Term::call(op, [tree_fold, init]),
+          ),
+        ],
+      };
+    })
+  }
+
+  /// Whether this is a `fold` over a list, without `with` state, where the `List/Cons` arm applies an
+  /// associative function to a value computed from the head and to the folded tail, like `(Op (f xs.head) xs.tail)`.
+  fn is_balanceable_fold(&self, associative: &[Name]) -> bool {
+    let Term::Fold { bnd, arg: _, with_bnd, with_arg: _, arms } = self else { return false };
+    if !with_bnd.is_empty() || arms.len() != 2 {
+      return false;
+    }
+    let Some((_, _, init)) = arms.iter().find(|(ctr, ..)| ctr.as_deref() == Some(builtins::LNIL)) else {
+      return false;
+    };
+    let Some((_, fields, body)) = arms.iter().find(|(ctr, ..)| ctr.as_deref() == Some(builtins::LCONS))
+    else {
+      return false;
+    };
+    let [Some(_), Some(tail)] = fields.as_slice() else { return false };
+    let init = without_rebuild(init);
+    let Term::App { tag: Tag::Static, fun, arg: rec } = without_rebuild(body) else { return false };
+    let Term::App { tag: Tag::Static, fun: op, arg: val } = fun.as_ref() else { return false };
+    let Term::Ref { nam: op } = op.as_ref() else { return false };
+    if !associative.contains(op) || !matches!(rec.as_ref(), Term::Var { nam } if nam == tail) {
+      return false;
+    }
+    // The value of each element and the initial value are moved out of the fold,
+    // so they can't use the folded tail or the matched list.
+    let uses = |term: &Term, nam: &Name| term.free_vars().contains_key(nam);
+    let uses_bnd = bnd.as_ref().is_some_and(|bnd| uses(val, bnd) || uses(init, bnd));
+    !uses(val, tail) && !uses_bnd && !val.has_unscoped() && !init.has_unscoped()
+  }
+}
+
+/// The body of a match arm without the `use` that rebuilds the matched value, if the value isn't used.
+fn without_rebuild(body: &Term) -> &Term {
+  match body {
+    Term::Use { nxt, .. } if is_unused_rebuild(body) => nxt,
+    _ => body,
+  }
+}
+
+fn without_rebuild_mut(body: &mut Term) -> &mut Term {
+  if is_unused_rebuild(body) {
+    let Term::Use { nxt, .. } = body else { unreachable!() };
+    return nxt;
+  }
+  body
+}
+
+fn is_unused_rebuild(body: &Term) -> bool {
+  matches!(body, Term::Use { nam: Some(nam), val: _, nxt } if !nxt.free_vars().contains_key(nam))
+}
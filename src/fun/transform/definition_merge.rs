@@ -1,90 +1,128 @@
 use crate::{
-  fun::{Book, Definition, Name, Rule, Term},
+  fun::{Book, DefPass, Definition, Name, Rule, Term},
   maybe_grow,
 };
 use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, hash::Hash};
 
 pub const MERGE_SEPARATOR: &str = "__M_";
 
+/// For each definition that was merged or that references a merged one, by its name after the merge,
+/// the definitions it comes from and the ones they referenced before the merge.
+pub type MergedDefs = IndexMap<Name, Vec<(Name, IndexSet<Name>)>>;
+
 impl Book {
   /// Merges definitions that have the same structure into one definition.
   /// Expects variables to be linear.
   ///
+  /// The definitions are compared by their content, with the references to other definitions
+  /// considered equal if they point to definitions that are merged together.
+  /// So groups of identical recursive definitions, like copies of the same function
+  /// from different dependencies, are also merged.
+  ///
+  /// Returns the references of the definitions before the merge, so that the errors about
+  /// the merged ones can be reported with the original names.
+  ///
   /// Ignores origin of the rules when merging,
   /// Should not be preceded by passes that cares about the origins.
-  pub fn merge_definitions(&mut self) {
-    let defs: Vec<_> = self.defs.keys().cloned().collect();
-    self.merge(defs.into_iter());
-  }
-
-  /// Checks and merges identical definitions given by `defs`.
-  /// We never merge the entrypoint function with something else.
-  fn merge(&mut self, defs: impl Iterator<Item = Name>) {
-    let name = self.entrypoint.clone();
-    // Sets of definitions that are identical, indexed by the body term.
-    let equal_terms =
-      self.collect_terms(defs.filter(|def_name| !name.as_ref().is_some_and(|m| m == def_name)));
+  pub fn merge_definitions(&mut self) -> MergedDefs {
+    let groups = self.equal_defs().into_iter().filter(|equal_defs| equal_defs.len() > 1).collect::<Vec<_>>();
 
     // Map of old name to new merged name
     let mut name_map = BTreeMap::new();
-
-    for (term, equal_defs) in equal_terms {
+    for equal_defs in groups.iter() {
       // def1_$_def2_$_def3
       let new_name = Name::new(equal_defs.iter().join(MERGE_SEPARATOR));
+      for name in equal_defs {
+        name_map.insert(name.clone(), new_name.clone());
+      }
+    }
+
+    let mut merged = MergedDefs::new();
+    for def in self.defs.values() {
+      let refs = def.rule().body.refs();
+      if name_map.contains_key(&def.name) || refs.iter().any(|nam| name_map.contains_key(nam)) {
+        let new_name = name_map.get(&def.name).unwrap_or(&def.name);
+        merged.entry(new_name.clone()).or_default().push((def.name.clone(), refs));
+      }
+    }
+
+    for equal_defs in groups {
+      let new_name = name_map[&equal_defs[0]].clone();
 
       // Builtin origin takes precedence
       let builtin = equal_defs.iter().any(|nam| self.defs[nam].builtin);
 
-      if equal_defs.len() > 1 {
-        // Merging some defs
-        // Add the merged def
-        let new_def =
-          Definition { name: new_name.clone(), rules: vec![Rule { pats: vec![], body: term }], builtin };
-        self.defs.insert(new_name.clone(), new_def);
-        // Remove the old ones and write the map of old names to new ones.
-        for name in equal_defs {
-          self.defs.swap_remove(&name);
-          name_map.insert(name, new_name.clone());
-        }
-      } else {
-        // Not merging, just put the body back
-        let def_name = equal_defs.into_iter().next().unwrap();
-        let def = self.defs.get_mut(&def_name).unwrap();
-        def.rule_mut().body = term;
+      // Add the merged def, with the body of any of them since they only differ in the names of the references.
+      let body = std::mem::take(&mut self.defs.get_mut(&equal_defs[0]).unwrap().rule_mut().body);
+      let new_def = Definition::new(new_name.clone(), vec![Rule { pats: vec![], body }], builtin);
+      for name in equal_defs {
+        self.defs.swap_remove(&name);
       }
+      self.defs.insert(new_name, new_def);
     }
-    self.update_refs(&name_map);
-  }
 
-  fn collect_terms(&mut self, def_entries: impl Iterator<Item = Name>) -> IndexMap<Term, IndexSet<Name>> {
-    let mut equal_terms: IndexMap<Term, IndexSet<Name>> = IndexMap::new();
-
-    for def_name in def_entries {
-      let def = self.defs.get_mut(&def_name).unwrap();
-      let term = std::mem::take(&mut def.rule_mut().body);
-      equal_terms.entry(term).or_default().insert(def_name);
+    for def in self.defs.values_mut() {
+      Term::subst_ref_to_ref(&mut def.rule_mut().body, &name_map);
     }
 
-    equal_terms
+    merged
   }
 
-  fn update_refs(&mut self, name_map: &BTreeMap<Name, Name>) {
-    let mut updated_defs = Vec::new();
+  /// The sets of definitions that are identical and can be merged, including the ones that are alone.
+  /// We never merge the entrypoint function or the definitions marked with `#[no_merge]` with something else.
+  ///
+  /// Starts grouping the definitions by their bodies without the names of the references,
+  /// and then splits the groups whose definitions reference definitions of different groups until nothing changes.
+  fn equal_defs(&self) -> Vec<Vec<Name>> {
+    let defs = self
+      .defs
+      .keys()
+      .filter(|def_name| self.entrypoint.as_ref() != Some(*def_name))
+      .filter(|def_name| !self.pass_disabled(def_name, DefPass::Merge))
+      .cloned()
+      .collect::<IndexSet<_>>();
 
-    for def in self.defs.values_mut() {
-      if Term::subst_ref_to_ref(&mut def.rule_mut().body, name_map) {
-        updated_defs.push(def.name.clone());
+    let mut refs = vec![];
+    let mut groups = group_ids(defs.iter().map(|def_name| {
+      let mut body = self.defs[def_name].rule().body.clone();
+      let mut def_refs = vec![];
+      body.take_refs(&mut def_refs);
+      refs.push(def_refs);
+      body
+    }));
+
+    loop {
+      // The references to definitions that can't be merged are compared by name.
+      let new_groups = group_ids(refs.iter().zip(&groups).map(|(def_refs, group)| {
+        let ref_groups = def_refs
+          .iter()
+          .map(|nam| defs.get_index_of(nam).map(|idx| groups[idx]).ok_or(nam))
+          .collect::<Vec<_>>();
+        (*group, ref_groups)
+      }));
+      let changed = new_groups.iter().max() != groups.iter().max();
+      groups = new_groups;
+      if !changed {
+        break;
       }
     }
 
-    if !updated_defs.is_empty() {
-      self.merge(updated_defs.into_iter());
+    let mut equal_defs = IndexMap::<usize, Vec<Name>>::new();
+    for (def_name, group) in defs.into_iter().zip(groups) {
+      equal_defs.entry(group).or_default().push(def_name);
     }
+    equal_defs.into_values().collect()
   }
 }
 
+/// Gives the same id to equal keys, numbering them in order of first appearance.
+fn group_ids<T: Hash + Eq>(keys: impl Iterator<Item = T>) -> Vec<usize> {
+  let mut ids = IndexSet::new();
+  keys.map(|key| ids.insert_full(key).0).collect()
+}
+
 impl Term {
   /// Performs reference substitution within a term replacing any references found in
   /// `ref_map` with their corresponding targets.
@@ -108,4 +146,23 @@ impl Term {
       }
     })
   }
+
+  /// The names of the definitions referenced in the term.
+  fn refs(&self) -> IndexSet<Name> {
+    let mut refs = vec![];
+    self.clone().take_refs(&mut refs);
+    refs.into_iter().collect()
+  }
+
+  /// Takes the names of the references in the term, in order, leaving empty names in their place.
+  fn take_refs(&mut self, refs: &mut Vec<Name>) {
+    maybe_grow(|| {
+      if let Term::Ref { nam } = self {
+        refs.push(std::mem::replace(nam, Name::new("")));
+      }
+      for child in self.children_mut() {
+        child.take_refs(refs);
+      }
+    })
+  }
 }
@@ -1,6 +1,6 @@
 use crate::{
   diagnostics::WarningType,
-  fun::{Book, Ctx, Name, Term},
+  fun::{Book, Ctx, DefPass, Name, Term},
   maybe_grow,
 };
 use hvm::ast::{Net, Tree};
@@ -31,6 +31,14 @@ impl Ctx<'_> {
       self.book.find_used_definitions_from_term(&def.rule().body, Used::Main, &mut used);
     }
 
+    // Definitions marked with `#[no_prune]` are kept as if used by the entry point.
+    for def in self.book.defs.values() {
+      if self.book.pass_disabled(&def.name, DefPass::Prune) {
+        used.insert(def.name.clone(), Used::Main);
+        self.book.find_used_definitions_from_term(&def.rule().body, Used::Main, &mut used);
+      }
+    }
+
     // Get the functions that are accessible from non-builtins.
     for def in self.book.defs.values() {
       if !def.builtin && !(used.get(&def.name) == Some(&Used::Main)) {
@@ -0,0 +1,445 @@
+use crate::{
+  diagnostics::Diagnostics,
+  fun::{Adt, Book, CtrField, Ctx, Definition, FieldType, Name, Num, Op, Pattern, Rule, Term},
+  maybe_grow,
+};
+
+const SHOW: &str = "show";
+const EQUAL: &str = "equal";
+const COMPARE: &str = "compare";
+
+const STRING_APPEND: &str = "String/append";
+const U24_SHOW: &str = "U24/show";
+const I24_SHOW: &str = "I24/show";
+const NUM_COMPARE: &str = "Num/compare";
+const NUM_COMPARE_THEN: &str = "Num/compare/then";
+const PRINT_ANY: &str = "IO/print_any";
+const PRINT: &str = "IO/print";
+
+/// How a derived function handles a field that isn't recursive.
+enum FieldFn {
+  /// Compares the numbers with `==`.
+  NumEqual,
+  /// Calls a function with the field, like `U24/show`, the function of the field's type,
+  /// or the function of a builtin type applied to the functions of its type arguments, like `(List/show U24/show)`.
+  Call(Term),
+}
+
+impl Ctx<'_> {
+  /// Derives the functions `Type/show`, `Type/equal` and `Type/compare`
+  /// for each user defined type whose function is used but not defined.
+  ///
+  /// Recursive fields are handled with the derived function itself, and the other fields according
+  /// to their declared type: numbers with the number functions and other types with their own function,
+  /// which is also derived if needed. Fields without a type are expected to hold `u24` numbers.
+  /// The functions of the generic builtin types, like `List/show`, take the functions of their type arguments first.
+  /// Fields of types that don't have the function, like type parameters, are reported as errors.
+  ///
+  /// Example:
+  /// ```hvm
+  /// type Tree = (Node ~left ~right) | (Leaf value)
+  ///
+  /// # Derived:
+  /// (Tree/show (Tree/Node left right)) =
+  ///   (String/append "(Tree/Node " (String/append (Tree/show left) (String/append " " (String/append (Tree/show right) ")"))))
+  /// (Tree/show (Tree/Leaf value)) = (String/append "(Tree/Leaf " (String/append (U24/show value) ")"))
+  ///
+  /// (Tree/equal (Tree/Node a.left a.right) (Tree/Node b.left b.right)) = (& (Tree/equal a.left b.left) (Tree/equal a.right b.right))
+  /// (Tree/equal (Tree/Leaf a.value) (Tree/Leaf b.value)) = (== a.value b.value)
+  /// (Tree/equal * *) = 0
+  ///
+  /// (Tree/compare (Tree/Node a.left a.right) (Tree/Node b.left b.right)) = (Num/compare/then (Tree/compare a.left b.left) (Tree/compare a.right b.right))
+  /// (Tree/compare (Tree/Node * *) (Tree/Leaf *)) = -1
+  /// (Tree/compare (Tree/Leaf *) (Tree/Node * *)) = +1
+  /// (Tree/compare (Tree/Leaf a.value) (Tree/Leaf b.value)) = (Num/compare a.value b.value)
+  /// ```
+  ///
+  /// Also expands the uses of `IO/print_any`, see [`Ctx::expand_print_any`].
+  pub fn derive_defs(&mut self) -> Result<(), Diagnostics> {
+    self.info.start_pass();
+
+    let mut pending = vec![];
+    self.expand_print_any(&mut pending);
+    for def in self.book.defs.values() {
+      for rule in def.rules.iter() {
+        rule.body.collect_derivable_names(&mut pending);
+      }
+    }
+    pending.reverse();
+
+    let mut defs = vec![];
+    while let Some(name) = pending.pop() {
+      if self.book.defs.contains_key(&name) || defs.iter().any(|(nam, _)| *nam == name) {
+        continue;
+      }
+      let Some((adt_name, suffix)) = name.rsplit_once('/') else { continue };
+      let Some(adt) = self.book.adts.get(&Name::new(adt_name)) else { continue };
+      if adt.builtin {
+        continue;
+      }
+      let derive = match suffix {
+        SHOW => derive_show_rules as fn(&Name, &Adt, &dyn Fn(&CtrField) -> FieldFn) -> Vec<Rule>,
+        EQUAL => derive_equal_rules,
+        COMPARE => derive_compare_rules,
+        _ => continue,
+      };
+
+      let mut derivable = true;
+      for (ctr_name, fields) in adt.ctrs.iter() {
+        for field in fields.iter().filter(|field| !field.rec) {
+          match self.book.field_fn(suffix, field, &mut pending) {
+            Ok(_) => {}
+            Err(typ) => {
+              derivable = false;
+              self.info.add_book_error(format!(
+                "Can't derive '{name}', since the field '{}' of constructor '{ctr_name}' has type '{typ}', which has no '{suffix}' function. Define '{name}' to use it.",
+                field.nam
+              ));
+            }
+          }
+        }
+      }
+      if !derivable {
+        continue;
+      }
+      let field_fn = |field: &CtrField| self.book.field_fn(suffix, field, &mut vec![]).unwrap();
+      let rules = derive(&name, adt, &field_fn);
+      defs.push((name.clone(), Definition::new(name, rules, false)));
+    }
+    self.book.defs.extend(defs);
+
+    self.info.fatal(())
+  }
+}
+
+impl Ctx<'_> {
+  /// Replaces each `(IO/print_any value)` with `(IO/print (Type/show value))`,
+  /// using the `show` function of the type of `value`, which is derived if needed.
+  ///
+  /// Values don't carry their types at runtime, so the type must be clear from the term itself:
+  /// numbers, strings, lists, constructor applications and variables bound to them by `let`.
+  /// Other values are reported as errors.
+  fn expand_print_any(&mut self, needed: &mut Vec<Name>) {
+    let names =
+      self.book.defs.iter().filter(|(_, def)| def.rules.iter().any(|rule| rule.body.uses_print_any()));
+    let names = names.map(|(name, _)| name.clone()).collect::<Vec<_>>();
+    for name in names {
+      let mut rules = self.book.defs[&name].rules.clone();
+      let mut errs = vec![];
+      for rule in rules.iter_mut() {
+        self.book.expand_print_any(&mut rule.body, &mut vec![], needed, &mut errs);
+      }
+      for err in errs {
+        self.info.add_rule_error(err, name.clone());
+      }
+      self.book.defs.get_mut(&name).unwrap().rules = rules;
+    }
+  }
+}
+
+impl Book {
+  fn expand_print_any(
+    &self,
+    term: &mut Term,
+    scope: &mut Vec<(Name, Option<FieldType>)>,
+    needed: &mut Vec<Name>,
+    errs: &mut Vec<String>,
+  ) {
+    maybe_grow(|| match term {
+      Term::App { fun, arg, .. } if matches!(fun.as_ref(), Term::Var { nam } if nam == PRINT_ANY) => {
+        self.expand_print_any(arg, scope, needed, errs);
+        let Some(typ) = self.infer_type(arg, scope) else {
+          errs.push(format!("Can't tell the type of the value given to '{PRINT_ANY}'. Print it with '{PRINT}' and the 'show' function of its type instead."));
+          return;
+        };
+        match self.type_fn(SHOW, &typ, needed) {
+          Ok(show) => {
+            **fun = Term::r#ref(PRINT);
+            **arg = Term::app(show, std::mem::take(arg.as_mut()));
+          }
+          Err(typ) => errs.push(format!(
+            "Can't print a value of type '{typ}' with '{PRINT_ANY}', since it has no 'show' function."
+          )),
+        }
+      }
+      Term::Var { nam } if nam == PRINT_ANY => {
+        errs.push(format!("'{PRINT_ANY}' must be applied to the value to print."));
+      }
+      Term::Let { pat, val, nxt } => {
+        self.expand_print_any(val, scope, needed, errs);
+        let len = scope.len();
+        match pat.as_ref() {
+          Pattern::Var(Some(nam)) => scope.push((nam.clone(), self.infer_type(val, scope))),
+          pat => scope.extend(pat.binds().flatten().map(|nam| (nam.clone(), None))),
+        }
+        self.expand_print_any(nxt, scope, needed, errs);
+        scope.truncate(len);
+      }
+      _ => {
+        // The variables bound by other terms hide the ones with the same name, since their types aren't known.
+        let len = scope.len();
+        scope.extend(term.bound_names().into_iter().map(|nam| (nam, None)));
+        for child in term.children_mut() {
+          self.expand_print_any(child, scope, needed, errs);
+        }
+        scope.truncate(len);
+      }
+    })
+  }
+
+  /// The type of a term, if it's clear from the term itself.
+  ///
+  /// The type arguments of generic builtin types are taken from the fields of the constructor, or from the first
+  /// element of a list. The ones that can't be inferred, like the error type of a `Result/Ok`, are never shown,
+  /// so they're given as `u24`.
+  fn infer_type(&self, term: &Term, scope: &[(Name, Option<FieldType>)]) -> Option<FieldType> {
+    let typ = |nam: &str, args| FieldType { nam: Name::new(nam), args };
+    maybe_grow(|| match term {
+      Term::Num { val: Num::U24(_) } => Some(typ("u24", vec![])),
+      Term::Num { val: Num::I24(_) } => Some(typ("i24", vec![])),
+      Term::Num { val: Num::F24(_) } => Some(typ("f24", vec![])),
+      Term::Str { .. } => Some(typ("String", vec![])),
+      Term::List { els } => {
+        let el = match els.first() {
+          Some(el) => self.infer_type(el, scope)?,
+          None => typ("u24", vec![]),
+        };
+        Some(typ("List", vec![el]))
+      }
+      Term::Var { nam } if scope.iter().any(|(var, _)| var == nam) => {
+        scope.iter().rev().find(|(var, _)| var == nam).and_then(|(_, typ)| typ.clone())
+      }
+      Term::Var { .. } | Term::App { .. } => {
+        let mut args = vec![];
+        let mut head = term;
+        while let Term::App { fun, arg, .. } = head {
+          args.push(arg.as_ref());
+          head = fun;
+        }
+        args.reverse();
+        let Term::Var { nam } = head else { return None };
+        if scope.iter().any(|(var, _)| var == nam) {
+          return None;
+        }
+        let adt_name = self.ctrs.get(nam)?;
+        let adt = &self.adts[adt_name];
+        let fields = &adt.ctrs[nam];
+        if fields.len() != args.len() {
+          return None;
+        }
+        let mut params = vec![None; adt.params.len()];
+        for (field, arg) in fields.iter().zip(args) {
+          let Some(field_typ) = &field.typ else { continue };
+          if let Some(idx) = adt.params.iter().position(|param| *param == field_typ.nam) {
+            if params[idx].is_none() {
+              params[idx] = self.infer_type(arg, scope);
+            }
+          }
+        }
+        let params = params.into_iter().map(|param| param.unwrap_or_else(|| typ("u24", vec![])));
+        Some(FieldType { nam: adt_name.clone(), args: params.collect() })
+      }
+      _ => None,
+    })
+  }
+
+  /// The function that the derived `suffix` function of a type uses for one of its fields that isn't recursive,
+  /// or the name of the type without such function.
+  ///
+  /// Adds the functions of user defined types that it calls to `needed`, to derive them if they're not defined.
+  fn field_fn(&self, suffix: &str, field: &CtrField, needed: &mut Vec<Name>) -> Result<FieldFn, Name> {
+    let u24 = FieldType { nam: Name::new("u24"), args: vec![] };
+    let typ = field.typ.as_ref().unwrap_or(&u24);
+    match (suffix, typ.nam.as_ref()) {
+      (EQUAL, "u24" | "i24" | "f24") => Ok(FieldFn::NumEqual),
+      _ => self.type_fn(suffix, typ, needed).map(FieldFn::Call),
+    }
+  }
+
+  /// The `suffix` function of a type, as a term that can be applied to the values.
+  fn type_fn(&self, suffix: &str, typ: &FieldType, needed: &mut Vec<Name>) -> Result<Term, Name> {
+    match (suffix, typ.nam.as_ref()) {
+      (EQUAL, "u24" | "i24" | "f24") => {
+        let (a, b) = (Term::Var { nam: Name::new("a") }, Term::Var { nam: Name::new("b") });
+        let eq = Term::Oper { opr: Op::EQ, fst: Box::new(a), snd: Box::new(b) };
+        Ok(Term::rfold_lams(eq, [Some(Name::new("a")), Some(Name::new("b"))].into_iter()))
+      }
+      (COMPARE, "u24" | "i24" | "f24") => Ok(Term::r#ref(NUM_COMPARE)),
+      (SHOW, "u24") => Ok(Term::r#ref(U24_SHOW)),
+      (SHOW, "i24") => Ok(Term::r#ref(I24_SHOW)),
+      _ => {
+        let fun = Name::new(format!("{}/{suffix}", typ.nam));
+        match self.adts.get(&typ.nam) {
+          Some(adt) if adt.builtin && self.defs.contains_key(&fun) => {
+            let mut args = vec![];
+            for arg in typ.args.iter() {
+              args.push(self.type_fn(suffix, arg, needed)?);
+            }
+            Ok(Term::call(Term::r#ref(&fun), args))
+          }
+          Some(adt) if !adt.builtin => {
+            needed.push(fun.clone());
+            Ok(Term::r#ref(&fun))
+          }
+          _ if self.defs.contains_key(&fun) => Ok(Term::r#ref(&fun)),
+          _ => Err(typ.nam.clone()),
+        }
+      }
+    }
+  }
+}
+
+/// Shows values like the results of a program, as constructor applications.
+fn derive_show_rules(show: &Name, adt: &Adt, field_fn: &dyn Fn(&CtrField) -> FieldFn) -> Vec<Rule> {
+  let mut rules = vec![];
+  for (ctr_name, fields) in adt.ctrs.iter() {
+    let vars = fields.iter().map(|field| Pattern::Var(Some(field.nam.clone())));
+    let pat = Pattern::Ctr(ctr_name.clone(), vars.collect());
+
+    let body = if fields.is_empty() {
+      Term::str(ctr_name)
+    } else {
+      // "(Ctr " field1 " " ... " " fieldN ")"
+      let mut parts = vec![Term::str(&format!("({ctr_name} "))];
+      for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+          parts.push(Term::str(" "));
+        }
+        let field_show = match field_fn(field) {
+          _ if field.rec => Term::r#ref(show),
+          FieldFn::Call(fun) => fun,
+          // Only the numbers in `equal` are compared directly.
+          FieldFn::NumEqual => unreachable!(),
+        };
+        parts.push(Term::app(field_show, Term::Var { nam: field.nam.clone() }));
+      }
+      parts.push(Term::str(")"));
+
+      let last = parts.pop().unwrap();
+      parts.into_iter().rfold(last, |acc, part| Term::call(Term::r#ref(STRING_APPEND), [part, acc]))
+    };
+    rules.push(Rule { pats: vec![pat], body });
+  }
+  rules
+}
+
+/// Returns 1 if both values are built with the same constructor and all their fields are equal, 0 otherwise.
+fn derive_equal_rules(equal: &Name, adt: &Adt, field_fn: &dyn Fn(&CtrField) -> FieldFn) -> Vec<Rule> {
+  let mut rules = vec![];
+  for (ctr_name, fields) in adt.ctrs.iter() {
+    let pats = vec![ctr_pattern(ctr_name, fields, "a"), ctr_pattern(ctr_name, fields, "b")];
+    let body = fields
+      .iter()
+      .map(|field| {
+        let (a, b) = field_vars(field);
+        match field_fn(field) {
+          _ if field.rec => Term::call(Term::r#ref(equal), [a, b]),
+          FieldFn::Call(fun) => Term::call(fun, [a, b]),
+          FieldFn::NumEqual => Term::Oper { opr: Op::EQ, fst: Box::new(a), snd: Box::new(b) },
+        }
+      })
+      .reduce(|acc, eq| Term::Oper { opr: Op::AND, fst: Box::new(acc), snd: Box::new(eq) })
+      .unwrap_or(Term::Num { val: Num::U24(1) });
+    rules.push(Rule { pats, body });
+  }
+  if adt.ctrs.len() > 1 {
+    let pats = vec![Pattern::Var(None), Pattern::Var(None)];
+    rules.push(Rule { pats, body: Term::Num { val: Num::U24(0) } });
+  }
+  rules
+}
+
+/// Returns -1, +0 or +1 if the first value is smaller, equal or greater than the second.
+///
+/// Values are ordered first by the order their constructors were declared in, then by their fields, from left to right.
+fn derive_compare_rules(compare: &Name, adt: &Adt, field_fn: &dyn Fn(&CtrField) -> FieldFn) -> Vec<Rule> {
+  let mut rules = vec![];
+  for (a_idx, (a_ctr, a_fields)) in adt.ctrs.iter().enumerate() {
+    for (b_idx, (b_ctr, b_fields)) in adt.ctrs.iter().enumerate() {
+      let (pats, body) = if a_idx == b_idx {
+        let pats = vec![ctr_pattern(a_ctr, a_fields, "a"), ctr_pattern(b_ctr, b_fields, "b")];
+        let body = a_fields
+          .iter()
+          .map(|field| {
+            let (a, b) = field_vars(field);
+            let field_compare = match field_fn(field) {
+              _ if field.rec => Term::r#ref(compare),
+              FieldFn::Call(fun) => fun,
+              // Only the numbers in `equal` are compared directly.
+              FieldFn::NumEqual => unreachable!(),
+            };
+            Term::call(field_compare, [a, b])
+          })
+          .rev()
+          .reduce(|acc, cmp| Term::call(Term::r#ref(NUM_COMPARE_THEN), [cmp, acc]))
+          .unwrap_or(Term::Num { val: Num::I24(0) });
+        (pats, body)
+      } else {
+        let pats = vec![ctr_wildcard(a_ctr, a_fields), ctr_wildcard(b_ctr, b_fields)];
+        let body = Term::Num { val: Num::I24(if a_idx < b_idx { -1 } else { 1 }) };
+        (pats, body)
+      };
+      rules.push(Rule { pats, body });
+    }
+  }
+  rules
+}
+
+/// `(Ctr prefix.field1 .. prefix.fieldN)`
+fn ctr_pattern(ctr_name: &Name, fields: &[CtrField], prefix: &str) -> Pattern {
+  let vars = fields.iter().map(|field| Pattern::Var(Some(Name::new(format!("{prefix}.{}", field.nam)))));
+  Pattern::Ctr(ctr_name.clone(), vars.collect())
+}
+
+/// `(Ctr * .. *)`
+fn ctr_wildcard(ctr_name: &Name, fields: &[CtrField]) -> Pattern {
+  Pattern::Ctr(ctr_name.clone(), fields.iter().map(|_| Pattern::Var(None)).collect())
+}
+
+/// The variables `a.field` and `b.field` bound by the patterns of the derived binary functions.
+fn field_vars(field: &CtrField) -> (Term, Term) {
+  let a = Term::Var { nam: Name::new(format!("a.{}", field.nam)) };
+  let b = Term::Var { nam: Name::new(format!("b.{}", field.nam)) };
+  (a, b)
+}
+
+impl Term {
+  fn uses_print_any(&self) -> bool {
+    maybe_grow(|| match self {
+      Term::Var { nam } => nam == PRINT_ANY,
+      _ => self.children().any(Term::uses_print_any),
+    })
+  }
+
+  /// The names of the variables bound by this term in any of its children.
+  fn bound_names(&self) -> Vec<Name> {
+    let binds: Vec<&Option<Name>> = match self {
+      Term::Lam { pat, .. } | Term::Let { pat, .. } | Term::Ask { pat, .. } => pat.binds().collect(),
+      Term::Use { nam, .. } => vec![nam],
+      Term::Mat { bnd, with_bnd, .. } | Term::Fold { bnd, with_bnd, .. } => {
+        [bnd].into_iter().chain(with_bnd.iter()).collect()
+      }
+      Term::Swt { bnd, with_bnd, pred, .. } => [bnd, pred].into_iter().chain(with_bnd.iter()).collect(),
+      Term::Bend { bnd, .. } => bnd.iter().collect(),
+      _ => vec![],
+    };
+    binds.into_iter().flatten().cloned().collect()
+  }
+
+  /// Collects the names of the variables that could refer to a derived function.
+  fn collect_derivable_names(&self, names: &mut Vec<Name>) {
+    maybe_grow(|| {
+      if let Term::Var { nam } = self {
+        let derivable = [SHOW, EQUAL, COMPARE]
+          .iter()
+          .any(|suffix| nam.strip_suffix(suffix).is_some_and(|nam| nam.ends_with('/')));
+        if derivable && !names.contains(nam) {
+          names.push(nam.clone());
+        }
+      }
+      for child in self.children() {
+        child.collect_derivable_names(names);
+      }
+    })
+  }
+}
@@ -1,3 +1,4 @@
+use itertools::Itertools;
 use std::collections::HashSet;
 
 use crate::{
@@ -88,7 +89,8 @@ impl Term {
         for var in with_bnd.iter().flatten() {
           free_vars.remove(var);
         }
-        let free_vars = free_vars.into_iter().collect::<Vec<_>>();
+        // Sorted, so that the generated definitions don't depend on the order of the hash set.
+        let free_vars = free_vars.into_iter().sorted().collect::<Vec<_>>();
 
         let new_nam = Name::new(format!("{}__fold{}", def_name, fresh));
         *fresh += 1;
@@ -120,7 +122,7 @@ impl Term {
         let body = Term::rfold_lams(body, with_bnd.iter().cloned());
         let body = Term::rfold_lams(body, free_vars.iter().map(|nam| Some(nam.clone())));
         let body = Term::lam(Pattern::Var(Some(x_nam)), body);
-        let def = Definition { name: new_nam.clone(), rules: vec![Rule { pats: vec![], body }], builtin };
+        let def = Definition::new(new_nam.clone(), vec![Rule { pats: vec![], body }], builtin);
         new_defs.push(def);
 
         // Call the new function
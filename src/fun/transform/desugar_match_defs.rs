@@ -10,6 +10,7 @@ pub enum DesugarMatchDefErr {
   NumMissingDefault,
   TypeMismatch { expected: Type, found: Type, pat: Pattern },
   RepeatedBind { bind: Name },
+  UnreachableRule { pats: Vec<Pattern> },
 }
 
 impl Ctx<'_> {
@@ -27,6 +28,9 @@ impl Ctx<'_> {
           DesugarMatchDefErr::RepeatedBind { .. } => {
             self.info.add_rule_warning(err, WarningType::RepeatedBind, def_name.clone())
           }
+          DesugarMatchDefErr::UnreachableRule { .. } => {
+            self.info.add_rule_warning(err, WarningType::UnreachableMatch, def_name.clone())
+          }
         }
       }
     }
@@ -42,6 +46,13 @@ impl Definition {
     let repeated_bind_errs = fix_repeated_binds(&mut self.rules);
     errs.extend(repeated_bind_errs);
 
+    let unreachable_rule_errs = check_unreachable_rules(&self.rules, ctrs, adts);
+    errs.extend(unreachable_rule_errs);
+
+    for rule in self.rules.iter_mut() {
+      rule.body.desugar_match_lams(ctrs, adts, &mut errs);
+    }
+
     let args = (0..self.arity()).map(|i| Name::new(format!("%arg{i}"))).collect::<Vec<_>>();
     let rules = std::mem::take(&mut self.rules);
     match simplify_rule_match(args.clone(), rules, vec![], ctrs, adts) {
@@ -55,6 +66,31 @@ impl Definition {
   }
 }
 
+impl Term {
+  /// Converts lambdas that match on their argument into a lambda followed by the match tree of its pattern.
+  ///
+  /// Example: `λ(Some (a, b)) body` becomes `λ%lam match %lam { Some: let (a, b) = %lam.val; body }`.
+  fn desugar_match_lams(&mut self, ctrs: &Constructors, adts: &Adts, errs: &mut Vec<DesugarMatchDefErr>) {
+    maybe_grow(|| {
+      for child in self.children_mut() {
+        child.desugar_match_lams(ctrs, adts, errs);
+      }
+      if let Term::Lam { pat, bod, .. } = self {
+        if !pat.is_simple() {
+          let arg = Name::new("%lam");
+          let pat = std::mem::replace(pat.as_mut(), Pattern::Var(Some(arg.clone())));
+          let mut rules = vec![Rule { pats: vec![pat], body: std::mem::take(bod) }];
+          errs.extend(fix_repeated_binds(&mut rules));
+          match simplify_rule_match(vec![arg], rules, vec![], ctrs, adts) {
+            Ok(body) => **bod = body,
+            Err(e) => errs.push(e),
+          }
+        }
+      }
+    })
+  }
+}
+
 /// When a rule has repeated bind, the only one that is actually useful is the last one.
 ///
 /// Example: In `(Foo x x x x) = x`, the function should return the fourth argument.
@@ -85,6 +121,114 @@ fn fix_repeated_binds(rules: &mut [Rule]) -> Vec<DesugarMatchDefErr> {
   errs
 }
 
+/// Finds the rules that can never match, because the rules before them
+/// already cover all the values they could match.
+///
+/// ```hvm
+/// (Foo *)        = 0
+/// (Foo (Some x)) = x // Unreachable
+/// ```
+fn check_unreachable_rules(rules: &[Rule], ctrs: &Constructors, adts: &Adts) -> Vec<DesugarMatchDefErr> {
+  let mut errs = vec![];
+  for (i, rule) in rules.iter().enumerate().skip(1) {
+    let prev = rules[..i].iter().map(|rule| rule.pats.clone()).collect::<Vec<_>>();
+    if !is_useful(&prev, &rule.pats, ctrs, adts) {
+      errs.push(DesugarMatchDefErr::UnreachableRule { pats: rule.pats.clone() });
+    }
+  }
+  errs
+}
+
+/// The constructor at the head of a refutable pattern, with its arity.
+#[derive(Clone, PartialEq)]
+enum PatHead {
+  Ctr(Name, usize),
+  Fan(FanKind, Tag, usize),
+  Num(u32),
+}
+
+/// Whether there is some value matched by `row` that isn't matched by any of the `rows`.
+///
+/// Patterns that can't be analysed, like lists and strings that were not encoded,
+/// are considered to match some value that no other pattern matches.
+fn is_useful(rows: &[Vec<Pattern>], row: &[Pattern], ctrs: &Constructors, adts: &Adts) -> bool {
+  maybe_grow(|| {
+    let Some((fst, rest)) = row.split_first() else { return rows.is_empty() };
+    if fst.is_wildcard() {
+      let mut heads: Vec<PatHead> = vec![];
+      for head in rows.iter().filter_map(|row| PatHead::from_pat(&row[0])) {
+        if !heads.contains(&head) {
+          heads.push(head);
+        }
+      }
+      if PatHead::is_complete(&heads, ctrs, adts) {
+        heads.iter().any(|head| {
+          let row = head.specialize(row).unwrap();
+          is_useful(&head.specialize_rows(rows), &row, ctrs, adts)
+        })
+      } else {
+        let rows =
+          rows.iter().filter(|row| row[0].is_wildcard()).map(|row| row[1..].to_vec()).collect::<Vec<_>>();
+        is_useful(&rows, rest, ctrs, adts)
+      }
+    } else if let Some(head) = PatHead::from_pat(fst) {
+      let row = head.specialize(row).unwrap();
+      is_useful(&head.specialize_rows(rows), &row, ctrs, adts)
+    } else {
+      true
+    }
+  })
+}
+
+impl PatHead {
+  fn from_pat(pat: &Pattern) -> Option<PatHead> {
+    match pat {
+      Pattern::Ctr(nam, args) => Some(PatHead::Ctr(nam.clone(), args.len())),
+      Pattern::Fan(fan, tag, args) => Some(PatHead::Fan(*fan, tag.clone(), args.len())),
+      Pattern::Num(num) => Some(PatHead::Num(*num)),
+      Pattern::Var(_) | Pattern::Chn(_) | Pattern::Lst(_) | Pattern::Str(_) => None,
+    }
+  }
+
+  /// Whether the heads cover all the possible values of their type.
+  fn is_complete(heads: &[PatHead], ctrs: &Constructors, adts: &Adts) -> bool {
+    match heads.first() {
+      Some(PatHead::Ctr(nam, _)) => {
+        let Some(adt) = ctrs.get(nam).and_then(|adt| adts.get(adt)) else { return false };
+        adt.ctrs.keys().all(|ctr| heads.iter().any(|head| matches!(head, PatHead::Ctr(nam, _) if nam == ctr)))
+      }
+      Some(PatHead::Fan(..)) => true,
+      Some(PatHead::Num(_)) | None => false,
+    }
+  }
+
+  /// Replaces the first pattern of the row by its subpatterns, if it can match this head.
+  fn specialize(&self, row: &[Pattern]) -> Option<Vec<Pattern>> {
+    let (fst, rest) = row.split_first().unwrap();
+    let args = match (self, fst) {
+      (PatHead::Ctr(_, arity) | PatHead::Fan(.., arity), pat) if pat.is_wildcard() => {
+        vec![Pattern::Var(None); *arity]
+      }
+      (PatHead::Num(_), pat) if pat.is_wildcard() => vec![],
+      (PatHead::Ctr(nam, arity), Pattern::Ctr(pat_nam, args)) if nam == pat_nam && *arity == args.len() => {
+        args.clone()
+      }
+      (PatHead::Fan(fan, tag, arity), Pattern::Fan(pat_fan, pat_tag, args))
+        if fan == pat_fan && tag == pat_tag && *arity == args.len() =>
+      {
+        args.clone()
+      }
+      (PatHead::Num(num), Pattern::Num(pat_num)) if num == pat_num => vec![],
+      _ => return None,
+    };
+    Some(args.into_iter().chain(rest.iter().cloned()).collect())
+  }
+
+  fn specialize_rows(&self, rows: &[Vec<Pattern>]) -> Vec<Vec<Pattern>> {
+    rows.iter().filter_map(|row| self.specialize(row)).collect()
+  }
+}
+
 /// Creates the match tree for a given pattern matching function definition.
 /// For each constructor, a match case is created.
 ///
@@ -111,6 +255,9 @@ fn simplify_rule_match(
   } else if rules[0].pats.iter().all(|p| p.is_wildcard()) {
     Ok(irrefutable_fst_row_rule(args, rules.into_iter().next().unwrap()))
   } else {
+    // Match first on an argument that the first rule needs.
+    // Otherwise, we'd be creating branches that all lead to the first rule.
+    let (args, rules) = fst_row_needed_col_first(args, rules);
     let typ = Type::infer_from_def_arg(&rules, 0, ctrs)?;
     match typ {
       Type::Any => var_rule(args, rules, with, ctrs, adts),
@@ -121,26 +268,39 @@ fn simplify_rule_match(
   }
 }
 
+/// Moves the first argument that is matched by the first rule to the front.
+fn fst_row_needed_col_first(mut args: Vec<Name>, mut rules: Vec<Rule>) -> (Vec<Name>, Vec<Rule>) {
+  let col = rules[0].pats.iter().position(|p| !p.is_wildcard()).unwrap();
+  if col != 0 {
+    let arg = args.remove(col);
+    args.insert(0, arg);
+    for rule in rules.iter_mut() {
+      let pat = rule.pats.remove(col);
+      rule.pats.insert(0, pat);
+    }
+  }
+  (args, rules)
+}
+
+/// Binds the value matched by a wildcard pattern in the body of its rule.
+fn bind_wildcard(pat: &Pattern, val: Term, body: Term) -> Term {
+  match pat {
+    Pattern::Var(None) => body,
+    Pattern::Var(Some(var)) => Term::Use { nam: Some(var.clone()), val: Box::new(val), nxt: Box::new(body) },
+    Pattern::Chn(var) => {
+      Term::Let { pat: Box::new(Pattern::Chn(var.clone())), val: Box::new(val), nxt: Box::new(body) }
+    }
+    _ => unreachable!(),
+  }
+}
+
 /// Irrefutable first row rule.
 /// Short-circuits the encoding in case the first rule always matches.
 /// This is useful to avoid unnecessary pattern matching.
 fn irrefutable_fst_row_rule(args: Vec<Name>, rule: Rule) -> Term {
   let mut term = rule.body;
   for (arg, pat) in args.into_iter().zip(rule.pats.into_iter()) {
-    match pat {
-      Pattern::Var(None) => {}
-      Pattern::Var(Some(var)) => {
-        term = Term::Use { nam: Some(var), val: Box::new(Term::Var { nam: arg }), nxt: Box::new(term) };
-      }
-      Pattern::Chn(var) => {
-        term = Term::Let {
-          pat: Box::new(Pattern::Chn(var)),
-          val: Box::new(Term::Var { nam: arg }),
-          nxt: Box::new(term),
-        };
-      }
-      _ => unreachable!(),
-    }
+    term = bind_wildcard(&pat, Term::Var { nam: arg }, term);
   }
   term
 }
@@ -163,14 +323,7 @@ fn var_rule(
   for mut rule in rules {
     let new_pats = rule.pats.split_off(1);
     let pat = rule.pats.pop().unwrap();
-
-    if let Pattern::Var(Some(nam)) = &pat {
-      rule.body = Term::Use {
-        nam: Some(nam.clone()),
-        val: Box::new(Term::Var { nam: arg.clone() }),
-        nxt: Box::new(std::mem::take(&mut rule.body)),
-      };
-    }
+    rule.body = bind_wildcard(&pat, Term::Var { nam: arg.clone() }, rule.body);
 
     let new_rule = Rule { pats: new_pats, body: rule.body };
     new_rules.push(new_rule);
@@ -219,14 +372,11 @@ fn fan_rule(
     // Extract subpatterns from the tuple pattern
     let mut new_pats = match pat {
       Pattern::Fan(.., sub_pats) => sub_pats,
-      Pattern::Var(var) => {
-        if let Some(var) = var {
-          // Rebuild the tuple if it was a var pattern
-          let tup =
-            Term::Fan { fan, tag: tag.clone(), els: new_args.clone().map(|nam| Term::Var { nam }).collect() };
-          rule.body =
-            Term::Use { nam: Some(var), val: Box::new(tup), nxt: Box::new(std::mem::take(&mut rule.body)) };
-        }
+      pat if pat.is_wildcard() => {
+        // Rebuild the tuple if it was a var pattern
+        let tup =
+          Term::Fan { fan, tag: tag.clone(), els: new_args.clone().map(|nam| Term::Var { nam }).collect() };
+        rule.body = bind_wildcard(&pat, tup, rule.body);
         new_args.clone().map(|nam| Pattern::Var(Some(nam))).collect()
       }
       _ => unreachable!(),
@@ -268,7 +418,9 @@ fn num_rule(
 
   // Since numbers have infinite (2^60) constructors, they require special treatment.
   // We first iterate over each present number then get the default.
-  let nums = rules
+  // Numbers that are only reached after an irrefutable rule would just repeat the default case, so they're skipped.
+  let irrefutable = rules.iter().position(|r| r.pats.iter().all(|p| p.is_wildcard())).unwrap_or(rules.len());
+  let nums = rules[..irrefutable]
     .iter()
     .filter_map(|r| if let Pattern::Num(n) = r.pats[0] { Some(n) } else { None })
     .collect::<BTreeSet<_>>()
@@ -286,15 +438,8 @@ fn num_rule(
           let rule = Rule { pats: rule.pats[1..].to_vec(), body };
           new_rules.push(rule);
         }
-        Pattern::Var(var) => {
-          let mut body = rule.body.clone();
-          if let Some(var) = var {
-            body = Term::Use {
-              nam: Some(var.clone()),
-              val: Box::new(Term::Num { val: Num::U24(*num) }),
-              nxt: Box::new(std::mem::take(&mut body)),
-            };
-          }
+        pat if pat.is_wildcard() => {
+          let body = bind_wildcard(pat, Term::Num { val: Num::U24(*num) }, rule.body.clone());
           let rule = Rule { pats: rule.pats[1..].to_vec(), body };
           new_rules.push(rule);
         }
@@ -308,13 +453,13 @@ fn num_rule(
   // Default case
   let mut new_rules = vec![];
   for rule in rules {
-    if let Pattern::Var(var) = &rule.pats[0] {
-      let mut body = rule.body.clone();
-      if let Some(var) = var {
-        let last_num = *nums.last().unwrap();
-        let cur_num = 1 + last_num;
-        let var_recovered = Term::add_num(Term::Var { nam: pred_var.clone() }, Num::U24(cur_num));
-        body = Term::Use { nam: Some(var.clone()), val: Box::new(var_recovered), nxt: Box::new(body) };
+    let pat = &rule.pats[0];
+    if pat.is_wildcard() {
+      let last_num = *nums.last().unwrap();
+      let cur_num = 1 + last_num;
+      let var_recovered = Term::add_num(Term::Var { nam: pred_var.clone() }, Num::U24(cur_num));
+      let mut body = bind_wildcard(pat, var_recovered, rule.body.clone());
+      if let Pattern::Var(Some(var)) = pat {
         fast_pred_access(&mut body, cur_num, var, &pred_var);
       }
       let rule = Rule { pats: rule.pats[1..].to_vec(), body };
@@ -457,16 +602,12 @@ fn switch_rule(
         // becomes
         // arg0.field0 ... arg0.fieldM pat1 ... patN:
         //   use var = (Ctr arg0.field0 ... arg0.fieldM); body
-        Pattern::Var(var) => {
+        pat if pat.is_wildcard() => {
           let new_pats = new_args.clone().map(|n| Pattern::Var(Some(n)));
           let pats = new_pats.chain(old_pats.clone()).collect();
-          let mut body = rule.body.clone();
           let reconstructed_var =
             Term::call(Term::Ref { nam: ctr.clone() }, new_args.clone().map(|nam| Term::Var { nam }));
-          if let Some(var) = var {
-            body =
-              Term::Use { nam: Some(var.clone()), val: Box::new(reconstructed_var), nxt: Box::new(body) };
-          }
+          let body = bind_wildcard(pat, reconstructed_var, rule.body.clone());
           let rule = Rule { pats, body };
           new_rules.push(rule);
         }
@@ -582,6 +723,10 @@ impl std::fmt::Display for DesugarMatchDefErr {
       DesugarMatchDefErr::RepeatedBind { bind } => {
         write!(f, "Repeated bind in pattern matching rule: '{bind}'.")
       }
+      DesugarMatchDefErr::UnreachableRule { pats } => {
+        let pats = pats.iter().map(|pat| pat.to_string()).collect::<Vec<_>>().join(" ");
+        write!(f, "Unreachable pattern matching rule '{pats}'. It's covered by the rules before it.")
+      }
     }
   }
 }
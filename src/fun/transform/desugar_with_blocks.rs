@@ -3,6 +3,7 @@ use crate::{
   fun::{Ctx, Name, Pattern, Term},
   maybe_grow,
 };
+use itertools::Itertools;
 use std::collections::HashSet;
 
 impl Ctx<'_> {
@@ -75,7 +76,7 @@ impl Term {
   /// The user must call this lazy thunk by calling the builtin
   /// `undefer` function, or by applying `@x x` to the term.
   fn defer(self) -> Term {
-    let free_vars = self.free_vars().into_keys().collect::<Vec<_>>();
+    let free_vars = self.free_vars().into_keys().sorted().collect::<Vec<_>>();
     let term = Term::rfold_lams(self, free_vars.iter().cloned().map(Some));
     let term = Term::call(Term::Var { nam: Name::new("%x") }, [term]);
     let term = Term::call(term, free_vars.iter().cloned().map(|nam| Term::Var { nam }));
@@ -1,10 +1,12 @@
 use crate::{
-  fun::{Book, Definition, Name, Num, Pattern, Rule, Term},
+  fun::{Book, Definition, FanKind, Name, Num, Pattern, Rule, Tag, Term},
   AdtEncoding,
 };
 
 impl Book {
   /// Defines a function for each constructor in each ADT in the book.
+  ///
+  /// Types are encoded with `adt_encoding`, unless they chose another encoding.
   pub fn encode_adts(&mut self, adt_encoding: AdtEncoding) {
     let mut defs = vec![];
     let mut tags = vec![];
@@ -13,11 +15,16 @@ impl Book {
       for (ctr_idx, (ctr_name, fields)) in adt.ctrs.iter().enumerate() {
         let ctrs: Vec<_> = adt.ctrs.keys().cloned().collect();
 
-        let body = match adt_encoding {
+        let body = match adt.encoding.unwrap_or(adt_encoding) {
           AdtEncoding::Scott => encode_ctr_scott(fields.iter().map(|f| &f.nam), ctrs, ctr_name),
-          AdtEncoding::NumScott => {
+          encoding @ (AdtEncoding::NumScott | AdtEncoding::TaggedTuple) => {
             let tag = make_tag(adt_name == ctr_name, ctr_name);
-            let body = encode_ctr_num_scott(fields.iter().map(|f| &f.nam), &tag);
+            let fields = fields.iter().map(|f| &f.nam);
+            let body = if encoding == AdtEncoding::NumScott {
+              encode_ctr_num_scott(fields, &tag)
+            } else {
+              encode_ctr_tagged_tuple(fields, &tag)
+            };
             let tag_def = make_tag_def(ctr_idx, &tag, adt);
             tags.push((tag, tag_def));
             body
@@ -25,7 +32,7 @@ impl Book {
         };
 
         let rules = vec![Rule { pats: vec![], body }];
-        let def = Definition { name: ctr_name.clone(), rules, builtin: adt.builtin };
+        let def = Definition::new(ctr_name.clone(), rules, adt.builtin);
         defs.push((ctr_name.clone(), def));
       }
     }
@@ -65,7 +72,23 @@ fn encode_ctr_num_scott<'a>(ctr_args: impl DoubleEndedIterator<Item = &'a Name>
   Term::rfold_lams(term, ctr_args.cloned().map(Some))
 }
 
+fn encode_ctr_tagged_tuple<'a>(
+  ctr_args: impl DoubleEndedIterator<Item = &'a Name> + Clone,
+  tag: &str,
+) -> Term {
+  // λa1 .. λan (TAG, a1, .., an)
+  let mut els = vec![Term::r#ref(tag)];
+  els.extend(ctr_args.clone().cloned().map(|nam| Term::Var { nam }));
+  // Constructors without fields are still a pair, so that matching on them is the same.
+  // (TAG, *)
+  if els.len() == 1 {
+    els.push(Term::Era);
+  }
+  let term = Term::Fan { fan: FanKind::Tup, tag: Tag::Static, els };
+  Term::rfold_lams(term, ctr_args.cloned().map(Some))
+}
+
 fn make_tag_def(ctr_idx: usize, tag: &Name, adt: &crate::fun::Adt) -> Definition {
   let tag_rule = vec![Rule { pats: vec![], body: Term::Num { val: Num::U24(ctr_idx as u32) } }];
-  Definition { name: tag.clone(), rules: tag_rule, builtin: adt.builtin }
+  Definition::new(tag.clone(), tag_rule, adt.builtin)
 }
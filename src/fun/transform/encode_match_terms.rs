@@ -1,31 +1,43 @@
 use crate::{
-  fun::{Book, MatchRule, Name, Pattern, Term},
+  fun::{Book, FanKind, MatchRule, Name, Pattern, Tag, Term},
   maybe_grow, AdtEncoding,
 };
+use std::collections::HashMap;
+
+/// The encoding of the constructors of types that chose their own, instead of the default one.
+pub type CtrEncodings = HashMap<Name, AdtEncoding>;
 
 impl Book {
   /// Encodes pattern matching expressions in the book into their
   /// native/core form. Must be run after [`Ctr::fix_match_terms`].
   ///
-  /// ADT matches are encoded based on `adt_encoding`.
+  /// ADT matches are encoded based on `adt_encoding`,
+  /// or on the encoding chosen by the type of the matched constructors.
   ///
   /// Num matches are encoded as a sequence of native num matches (on 0 and 1+).
   ///
   /// Var and pair matches become a let expression.
   pub fn encode_matches(&mut self, adt_encoding: AdtEncoding) {
+    let mut ctr_encodings = CtrEncodings::new();
+    for adt in self.adts.values() {
+      if let Some(encoding) = adt.encoding {
+        ctr_encodings.extend(adt.ctrs.keys().map(|ctr| (ctr.clone(), encoding)));
+      }
+    }
+
     for def in self.defs.values_mut() {
       for rule in &mut def.rules {
-        rule.body.encode_matches(adt_encoding);
+        rule.body.encode_matches(adt_encoding, &ctr_encodings);
       }
     }
   }
 }
 
 impl Term {
-  pub fn encode_matches(&mut self, adt_encoding: AdtEncoding) {
+  pub fn encode_matches(&mut self, adt_encoding: AdtEncoding, ctr_encodings: &CtrEncodings) {
     maybe_grow(|| {
       for child in self.children_mut() {
-        child.encode_matches(adt_encoding)
+        child.encode_matches(adt_encoding, ctr_encodings)
       }
 
       if let Term::Mat { arg, bnd: _, with_bnd, with_arg, arms } = self {
@@ -33,6 +45,8 @@ impl Term {
         assert!(with_arg.is_empty());
         let arg = std::mem::take(arg.as_mut());
         let rules = std::mem::take(arms);
+        let ctr = rules.iter().find_map(|rule| rule.0.as_ref());
+        let adt_encoding = ctr.and_then(|ctr| ctr_encodings.get(ctr)).copied().unwrap_or(adt_encoding);
         *self = encode_match(arg, rules, adt_encoding);
       } else if let Term::Swt { arg, bnd: _, with_bnd, with_arg, pred, arms } = self {
         assert!(with_bnd.is_empty());
@@ -53,43 +67,71 @@ fn encode_match(arg: Term, rules: Vec<MatchRule>, adt_encoding: AdtEncoding) ->
       Term::call(arg, arms)
     }
     AdtEncoding::NumScott => {
-      fn make_switches(arms: &mut [Term]) -> Term {
-        maybe_grow(|| match arms {
-          [] => Term::Err,
-          [arm] => Term::lam(Pattern::Var(None), std::mem::take(arm)),
-          [arm, rest @ ..] => Term::lam(
-            Pattern::Var(Some(Name::new("%tag"))),
-            Term::Swt {
-              arg: Box::new(Term::Var { nam: Name::new("%tag") }),
-              bnd: None,
-              with_bnd: vec![],
-              with_arg: vec![],
-              pred: None,
-              arms: vec![std::mem::take(arm), make_switches(rest)],
-            },
-          ),
+      let arms = rules.into_iter().map(|rule| Term::rfold_lams(rule.2, rule.1.into_iter())).collect();
+      Term::call(arg, [switch_on_tag(arms)])
+    }
+    AdtEncoding::TaggedTuple => {
+      // let (%tag, %fields) = x; (λtag switch tag {0: λ(a1, .., an) Ctr0; _: ... } %tag %fields)
+      let arms = rules
+        .into_iter()
+        .map(|rule| {
+          let mut fields = rule.1.into_iter().map(Pattern::Var).collect::<Vec<_>>();
+          let pat = match fields.len() {
+            0 => Pattern::Var(None),
+            1 => fields.pop().unwrap(),
+            _ => Pattern::Fan(FanKind::Tup, Tag::Static, fields),
+          };
+          Term::lam(pat, rule.2)
         })
+        .collect();
+      let (tag, fields) = (Name::new("%tag"), Name::new("%fields"));
+      Term::Let {
+        pat: Box::new(Pattern::Fan(
+          FanKind::Tup,
+          Tag::Static,
+          vec![Pattern::Var(Some(tag.clone())), Pattern::Var(Some(fields.clone()))],
+        )),
+        val: Box::new(arg),
+        nxt: Box::new(Term::call(switch_on_tag(arms), [Term::Var { nam: tag }, Term::Var { nam: fields }])),
       }
-      let mut arms =
-        rules.into_iter().map(|rule| Term::rfold_lams(rule.2, rule.1.into_iter())).collect::<Vec<_>>();
-      let term = if arms.len() == 1 {
-        // λx (x λtag switch tag {0: Ctr0; _: * })
-        let arm = arms.pop().unwrap();
-        let term = Term::Swt {
+    }
+  }
+}
+
+/// Selects one of the arms with a numeric tag, with a cascade of native switches.
+fn switch_on_tag(mut arms: Vec<Term>) -> Term {
+  fn make_switches(arms: &mut [Term]) -> Term {
+    maybe_grow(|| match arms {
+      [] => Term::Err,
+      [arm] => Term::lam(Pattern::Var(None), std::mem::take(arm)),
+      [arm, rest @ ..] => Term::lam(
+        Pattern::Var(Some(Name::new("%tag"))),
+        Term::Swt {
           arg: Box::new(Term::Var { nam: Name::new("%tag") }),
           bnd: None,
           with_bnd: vec![],
           with_arg: vec![],
           pred: None,
-          arms: vec![arm, Term::Era],
-        };
-        Term::lam(Pattern::Var(Some(Name::new("%tag"))), term)
-      } else {
-        // λx (x λtag switch tag {0: Ctr0; _: switch tag-1 { ... } })
-        make_switches(arms.as_mut_slice())
-      };
-      Term::call(arg, [term])
-    }
+          arms: vec![std::mem::take(arm), make_switches(rest)],
+        },
+      ),
+    })
+  }
+  if arms.len() == 1 {
+    // λtag switch tag {0: Ctr0; _: * }
+    let arm = arms.pop().unwrap();
+    let term = Term::Swt {
+      arg: Box::new(Term::Var { nam: Name::new("%tag") }),
+      bnd: None,
+      with_bnd: vec![],
+      with_arg: vec![],
+      pred: None,
+      arms: vec![arm, Term::Era],
+    };
+    Term::lam(Pattern::Var(Some(Name::new("%tag"))), term)
+  } else {
+    // λtag switch tag {0: Ctr0; _: switch tag-1 { ... } }
+    make_switches(arms.as_mut_slice())
   }
 }
 
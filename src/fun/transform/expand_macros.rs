@@ -0,0 +1,318 @@
+use crate::{
+  diagnostics::Diagnostics,
+  fun::{Ctx, Macro, Macros, Name, Term},
+  maybe_grow,
+};
+use indexmap::IndexSet;
+
+/// How many macro uses can be expanded inside one another, to stop recursive macros.
+const MAX_EXPANSION_DEPTH: usize = 256;
+
+impl Ctx<'_> {
+  /// Replaces the uses of macros by their bodies, with the arguments in place of the parameters.
+  ///
+  /// Macros are hygienic: the variables bound inside a macro are renamed to fresh names,
+  /// so they can't capture the variables of the arguments.
+  /// The same way, the variables bound where a macro is used are renamed if they have the name
+  /// of a free variable of a macro, so they can't capture the definitions it refers to.
+  /// The `..rest` parameter of a macro receives a list with the remaining arguments.
+  ///
+  /// Example:
+  /// ```bend
+  /// macro twice(f, x) = (f (f x))
+  /// macro all(..xs) = (List/all xs)
+  /// main = (all (twice λx (+ x 1) 0) 1)
+  ///
+  /// // Transforms to:
+  /// main = (List/all [(λx (+ x 1) (λx (+ x 1) 0)), 1])
+  /// ```
+  pub fn expand_macros(&mut self) -> Result<(), Diagnostics> {
+    self.info.start_pass();
+
+    if self.book.macros.is_empty() {
+      return Ok(());
+    }
+
+    let free_names = self.book.macros.values().flat_map(Macro::free_names).collect::<IndexSet<_>>();
+
+    for def in self.book.defs.values_mut() {
+      let mut fresh = 0;
+      for rule in def.rules.iter_mut() {
+        if rule.body.uses_macros(&self.book.macros) {
+          let mut renamer =
+            MacroRenamer { params: vec![], only: Some(&free_names), binds: vec![], fresh: &mut fresh };
+          for bnd in rule.pats.iter_mut().flat_map(|pat| pat.binds_mut()) {
+            renamer.push(bnd);
+          }
+          renamer.expand(&mut rule.body);
+        }
+        let mut scope = rule.pats.iter().flat_map(|pat| pat.binds().flatten().cloned()).collect();
+        let res = rule.body.expand_macros(&self.book.macros, &mut scope, &mut fresh, 0);
+        self.info.take_rule_err(res, def.name.clone());
+      }
+    }
+
+    self.info.fatal(())
+  }
+}
+
+impl Term {
+  fn expand_macros(
+    &mut self,
+    macros: &Macros,
+    scope: &mut Vec<Name>,
+    fresh: &mut usize,
+    depth: usize,
+  ) -> Result<(), String> {
+    maybe_grow(|| {
+      if let Some(mac) = self.macro_use(macros, scope) {
+        if depth >= MAX_EXPANSION_DEPTH {
+          return Err(format!(
+            "Too many nested expansions of macro '{}'. Macros can't be used recursively.",
+            mac.name
+          ));
+        }
+        let mut args = vec![];
+        let mut head = std::mem::take(self);
+        while let Term::App { fun, arg, .. } = &mut head {
+          args.push(std::mem::take(arg.as_mut()));
+          head = std::mem::take(fun.as_mut());
+        }
+        args.reverse();
+        *self = mac.expand(args, fresh)?;
+        return self.expand_macros(macros, scope, fresh, depth + 1);
+      }
+
+      if let Term::Open { bod, .. } = self {
+        return bod.expand_macros(macros, scope, fresh, depth);
+      }
+      for (child, binds) in self.children_mut_with_binds() {
+        let len = scope.len();
+        scope.extend(binds.flatten().cloned());
+        child.expand_macros(macros, scope, fresh, depth)?;
+        scope.truncate(len);
+      }
+      Ok(())
+    })
+  }
+
+  /// Whether some variable of this term has the name of a macro.
+  fn uses_macros(&self, macros: &Macros) -> bool {
+    maybe_grow(|| match self {
+      Term::Var { nam } => macros.contains_key(nam),
+      _ => self.children().any(|child| child.uses_macros(macros)),
+    })
+  }
+
+  /// The macro applied by this term, if its head is a macro name not shadowed by a variable.
+  fn macro_use<'a>(&self, macros: &'a Macros, scope: &[Name]) -> Option<&'a Macro> {
+    let mut head = self;
+    while let Term::App { fun, .. } = head {
+      head = fun;
+    }
+    match head {
+      Term::Var { nam } if !scope.contains(nam) => macros.get(nam),
+      _ => None,
+    }
+  }
+}
+
+impl Macro {
+  /// The variables used in the body of the macro that aren't bound by it, like the definitions it calls.
+  fn free_names(&self) -> impl Iterator<Item = Name> + '_ {
+    self
+      .body
+      .free_vars()
+      .into_keys()
+      .filter(|nam| !self.params.contains(nam) && self.rest.as_ref() != Some(nam))
+  }
+
+  /// The body of the macro applied to the given arguments.
+  /// The arguments left after the parameters are applied to the result, unless there's a `..rest` parameter.
+  fn expand(&self, mut args: Vec<Term>, fresh: &mut usize) -> Result<Term, String> {
+    if args.len() < self.params.len() {
+      return Err(format!(
+        "Macro '{}' expects {} arguments, but got {}.",
+        self.name,
+        self.params.len(),
+        args.len()
+      ));
+    }
+    let extra = args.split_off(self.params.len());
+    let mut params = self.params.iter().cloned().zip(args).collect::<Vec<_>>();
+    let extra = if let Some(rest) = &self.rest {
+      params.push((rest.clone(), Term::List { els: extra }));
+      vec![]
+    } else {
+      extra
+    };
+
+    let mut body = self.body.clone();
+    let mut renamer = MacroRenamer { params, only: None, binds: vec![], fresh };
+    renamer.expand(&mut body);
+    Ok(Term::call(body, extra))
+  }
+}
+
+/// Gives fresh names to the variables bound in the body of a macro and replaces its parameters.
+struct MacroRenamer<'a> {
+  params: Vec<(Name, Term)>,
+  /// Only the binds with these names are renamed, or all of them if `None`.
+  only: Option<&'a IndexSet<Name>>,
+  /// The binds in scope, with their original and fresh names.
+  binds: Vec<(Name, Name)>,
+  fresh: &'a mut usize,
+}
+
+impl MacroRenamer<'_> {
+  fn expand(&mut self, term: &mut Term) {
+    // Note: we can't use the children iterators here because we mutate the binds,
+    // and because matches don't have the names of their fields before `fix_match_terms`.
+    maybe_grow(|| match term {
+      Term::Var { nam } => {
+        if let Some(new) = self.use_var(nam) {
+          *nam = new;
+        } else if let Some((_, arg)) = self.params.iter().find(|(param, _)| param == nam) {
+          *term = arg.clone();
+        }
+      }
+      Term::Open { typ: _, var, bod } => {
+        if let Some(new) = self.use_var(var) {
+          *var = new;
+        }
+        self.expand(bod);
+      }
+      Term::Mat { bnd, arg, with_bnd, with_arg, arms }
+      | Term::Fold { bnd, arg, with_bnd, with_arg, arms } => {
+        self.expand(arg);
+        for arg in with_arg {
+          self.expand(arg);
+        }
+        self.push(bnd);
+        for bnd in with_bnd.iter_mut() {
+          self.push(bnd);
+        }
+        for arm in arms {
+          for bnd in arm.1.iter_mut() {
+            self.push(bnd);
+          }
+          self.expand(&mut arm.2);
+          for bnd in arm.1.iter() {
+            self.pop(bnd);
+          }
+        }
+        for bnd in with_bnd.iter() {
+          self.pop(bnd);
+        }
+        self.pop(bnd);
+      }
+      Term::Swt { bnd, arg, with_bnd, with_arg, pred, arms } => {
+        self.expand(arg);
+        for arg in with_arg {
+          self.expand(arg);
+        }
+        self.push(bnd);
+        for bnd in with_bnd.iter_mut() {
+          self.push(bnd);
+        }
+        let (succ, nums) = arms.split_last_mut().unwrap();
+        for arm in nums {
+          self.expand(arm);
+        }
+        self.push(pred);
+        self.expand(succ);
+        self.pop(pred);
+        for bnd in with_bnd.iter() {
+          self.pop(bnd);
+        }
+        self.pop(bnd);
+      }
+      Term::Bend { bnd, arg, cond, step, base } => {
+        for arg in arg {
+          self.expand(arg);
+        }
+        for bnd in bnd.iter_mut() {
+          self.push(bnd);
+        }
+        self.expand(cond);
+        self.expand(step);
+        self.expand(base);
+        for bnd in bnd.iter() {
+          self.pop(bnd);
+        }
+      }
+      Term::Let { pat, val, nxt } | Term::Ask { pat, val, nxt } => {
+        self.expand(val);
+        for bnd in pat.binds_mut() {
+          self.push(bnd);
+        }
+        self.expand(nxt);
+        for bnd in pat.binds() {
+          self.pop(bnd);
+        }
+      }
+      Term::Use { nam, val, nxt } => {
+        self.expand(val);
+        self.push(nam);
+        self.expand(nxt);
+        self.pop(nam);
+      }
+      Term::Lam { tag: _, pat, bod } => {
+        for bnd in pat.binds_mut() {
+          self.push(bnd);
+        }
+        self.expand(bod);
+        for bnd in pat.binds() {
+          self.pop(bnd);
+        }
+      }
+      Term::Fan { fan: _, tag: _, els } | Term::List { els } => {
+        for el in els {
+          self.expand(el);
+        }
+      }
+      Term::App { tag: _, fun: fst, arg: snd } | Term::Oper { opr: _, fst, snd } => {
+        self.expand(fst);
+        self.expand(snd);
+      }
+      Term::With { typ: _, bod } => self.expand(bod),
+      Term::Link { .. }
+      | Term::Num { .. }
+      | Term::Nat { .. }
+      | Term::Str { .. }
+      | Term::Ref { .. }
+      | Term::Era
+      | Term::Err => {}
+    })
+  }
+
+  /// Renames a bind to a fresh name, keeping the original one to rename its uses.
+  fn push(&mut self, bnd: &mut Option<Name>) {
+    if let Some(nam) = bnd {
+      if self.only.is_some_and(|only| !only.contains(nam)) {
+        self.binds.push((nam.clone(), nam.clone()));
+        return;
+      }
+      let new = Name::new(format!("{nam}%m{}", self.fresh));
+      *self.fresh += 1;
+      self.binds.push((std::mem::replace(nam, new.clone()), new));
+    }
+  }
+
+  fn pop(&mut self, bnd: &Option<Name>) {
+    if bnd.is_some() {
+      self.binds.pop();
+    }
+  }
+
+  /// The fresh name of a variable bound in the macro.
+  /// The fields of a matched variable, like `x.head`, follow the name of the variable.
+  fn use_var(&self, nam: &Name) -> Option<Name> {
+    if let Some((_, new)) = self.binds.iter().rev().find(|(old, _)| old == nam) {
+      return Some(new.clone());
+    }
+    let (var, field) = nam.rsplit_once('.')?;
+    let new = self.use_var(&Name::new(var))?;
+    Some(Name::new(format!("{new}.{field}")))
+  }
+}
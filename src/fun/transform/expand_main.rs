@@ -37,6 +37,8 @@ impl Term {
       Term::Ref { nam } => {
         if seen.contains(nam) {
           // Don't expand recursive references
+        } else if nam.strip_suffix("/tag").is_some_and(|ctr| book.ctrs.contains_key(&Name::new(ctr))) {
+          // Don't expand constructor tags, so that tagged tuples can be read back as constructors
         } else {
           seen.push(nam.clone());
           let mut body = book.defs.get(nam).unwrap().rule().body.clone();
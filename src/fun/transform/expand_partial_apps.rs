@@ -0,0 +1,101 @@
+use crate::{
+  diagnostics::{Diagnostics, WarningType},
+  fun::{Ctx, Name, Term},
+  maybe_grow,
+};
+use std::collections::HashMap;
+
+impl Ctx<'_> {
+  /// Finds the references to pattern matching definitions that are applied
+  /// to some of the arguments they match on, but not to all of their arguments.
+  ///
+  /// Since the rules are compiled into a tree of matches, the matching on
+  /// the given arguments can happen before the function is fully applied.
+  ///
+  /// If `expand` is set, eta-expands these partial applications so that the function
+  /// only starts matching once all the arguments are given. Otherwise, warns about them.
+  ///
+  /// Example:
+  /// ```hvm
+  /// (Foo 0 b) = b
+  /// (Foo n b) = (Foo (- n 1) b)
+  /// main = (List/map [1, 2] (Foo 2))
+  /// ```
+  /// With `expand`, becomes:
+  /// ```hvm
+  /// main = (List/map [1, 2] λ%x1 (Foo 2 %x1))
+  /// ```
+  ///
+  /// Precondition: Refs are resolved.
+  pub fn expand_partial_apps(&mut self, expand: bool) -> Result<(), Diagnostics> {
+    self.info.start_pass();
+
+    // The position of the first matched argument and the arity of each pattern matching definition.
+    let mut arities = HashMap::new();
+    for def in self.book.defs.values() {
+      let fst_match =
+        def.rules.iter().filter_map(|rule| rule.pats.iter().position(|pat| !pat.is_wildcard())).min();
+      if let Some(fst_match) = fst_match {
+        arities.insert(def.name.to_string(), (fst_match, def.arity()));
+      }
+    }
+
+    for def in self.book.defs.values_mut() {
+      if def.builtin {
+        continue;
+      }
+      let mut partial = vec![];
+      for rule in def.rules.iter_mut() {
+        rule.body.expand_partial_apps(&arities, expand, &mut partial);
+      }
+      for (nam, n_args, arity) in partial {
+        let warn = format!(
+          "Partial application of pattern matching definition '{nam}', with {n_args} of its {arity} arguments."
+        );
+        self.info.add_rule_warning(warn, WarningType::PartialApplication, def.name.clone());
+      }
+    }
+
+    self.info.fatal(())
+  }
+}
+
+impl Term {
+  fn expand_partial_apps(
+    &mut self,
+    arities: &HashMap<String, (usize, usize)>,
+    expand: bool,
+    partial: &mut Vec<(Name, usize, usize)>,
+  ) {
+    maybe_grow(|| {
+      // Only the whole application spine counts as an application of its head.
+      let mut n_args = 0;
+      let mut head = &mut *self;
+      while let Term::App { fun, arg, .. } = head {
+        arg.expand_partial_apps(arities, expand, partial);
+        n_args += 1;
+        head = fun;
+      }
+      if n_args == 0 {
+        for child in head.children_mut() {
+          child.expand_partial_apps(arities, expand, partial);
+        }
+        return;
+      }
+      head.expand_partial_apps(arities, expand, partial);
+
+      let Term::Ref { nam } = head else { return };
+      let Some(&(fst_match, arity)) = arities.get(&**nam) else { return };
+      if n_args <= fst_match || n_args >= arity {
+        return;
+      }
+      if expand {
+        let vars = (n_args..arity).map(|i| Name::new(format!("%x{i}"))).collect::<Vec<_>>();
+        let app = Term::call(std::mem::take(self), vars.iter().map(|nam| Term::Var { nam: nam.clone() }));
+        *self = Term::rfold_lams(app, vars.into_iter().map(Some));
+      } else {
+        partial.push((nam.clone(), n_args, arity));
+      }
+    })
+  }
+}
@@ -1,6 +1,7 @@
 use crate::{
   diagnostics::Diagnostics,
-  fun::{Adts, Constructors, Ctx, Pattern},
+  fun::{Adts, Constructors, Ctx, Pattern, Term},
+  maybe_grow,
 };
 
 impl Ctx<'_> {
@@ -27,6 +28,8 @@ impl Ctx<'_> {
           pat.resolve_pat(&self.book.ctrs);
           pat.check_good_ctr(&self.book.ctrs, &self.book.adts, &mut errs);
         }
+
+        rule.body.fix_match_lams(&self.book.ctrs, &self.book.adts, &mut errs);
       }
 
       for err in errs {
@@ -38,6 +41,23 @@ impl Ctx<'_> {
   }
 }
 
+impl Term {
+  /// Fixes the patterns of lambdas that match on their argument, like rule patterns.
+  fn fix_match_lams(&mut self, ctrs: &Constructors, adts: &Adts, errs: &mut Vec<String>) {
+    maybe_grow(|| {
+      if let Term::Lam { pat, .. } = self {
+        if !pat.is_simple() {
+          pat.resolve_pat(ctrs);
+          pat.check_good_ctr(ctrs, adts, errs);
+        }
+      }
+      for child in self.children_mut() {
+        child.fix_match_lams(ctrs, adts, errs);
+      }
+    })
+  }
+}
+
 impl Pattern {
   /// If a var pattern actually refers to an ADT constructor, convert it into a constructor pattern.
   fn resolve_pat(&mut self, ctrs: &Constructors) {
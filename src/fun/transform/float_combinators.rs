@@ -1,5 +1,5 @@
 use crate::{
-  fun::{Book, Definition, Name, Pattern, Rule, Term},
+  fun::{Book, DefPass, Definition, Name, Pattern, Rule, Term},
   maybe_grow, multi_iterator,
 };
 use std::collections::{BTreeMap, HashSet};
@@ -42,6 +42,10 @@ impl Book {
         }
       }
 
+      if book.pass_disabled(def_name, DefPass::FloatCombinators) {
+        continue;
+      }
+
       let builtin = def.builtin;
       let body = &mut def.rule_mut().body;
       ctx.reset();
@@ -117,7 +121,7 @@ impl Term {
     let extracted_term = std::mem::replace(self, comb_ref);
 
     let rules = vec![Rule { body: extracted_term, pats: Vec::new() }];
-    let rule = Definition { name: comb_name.clone(), rules, builtin };
+    let rule = Definition::new(comb_name.clone(), rules, builtin);
     ctx.combinators.insert(comb_name, (is_safe, rule));
   }
 }
@@ -223,7 +227,7 @@ impl Term {
     }
   }
 
-  fn size(&self) -> usize {
+  pub fn size(&self) -> usize {
     maybe_grow(|| {
       let children_size: usize = self.children().map(|c| c.size()).sum();
       self.base_size() + children_size
@@ -1,6 +1,8 @@
 pub mod apply_args;
+pub mod balance_folds;
 pub mod definition_merge;
 pub mod definition_pruning;
+pub mod derive_defs;
 pub mod desugar_bend;
 pub mod desugar_fold;
 pub mod desugar_match_defs;
@@ -10,13 +12,18 @@ pub mod desugar_with_blocks;
 pub mod encode_adts;
 pub mod encode_match_terms;
 pub mod expand_generated;
+pub mod expand_macros;
 pub mod expand_main;
+pub mod expand_partial_apps;
 pub mod fix_match_defs;
 pub mod fix_match_terms;
 pub mod float_combinators;
 pub mod linearize_matches;
 pub mod linearize_vars;
 pub mod resolve_refs;
+pub mod resugar_adts;
 pub mod resugar_list;
 pub mod resugar_string;
+pub mod share_common_subterms;
+pub mod specialize_calls;
 pub mod unique_names;
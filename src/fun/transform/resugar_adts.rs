@@ -0,0 +1,100 @@
+use crate::{
+  fun::{Book, FanKind, Name, Pattern, Tag, Term},
+  maybe_grow, AdtEncoding,
+};
+
+impl Term {
+  /// Converts lambda-encoded constructor values back to applications of their constructors.
+  ///
+  /// Lists and strings should be resugared before this,
+  /// so that only the constructors that can't be shown as literals are converted.
+  ///
+  /// Scott-encoded values are just lambdas selecting a constructor by position,
+  /// so they can't be told apart from other functions and only types encoded with
+  /// [`AdtEncoding::NumScott`] are resugared.
+  pub fn resugar_adts(&mut self, book: &Book, adt_encoding: AdtEncoding) {
+    let is_num_scott =
+      book.adts.values().any(|adt| adt.encoding.unwrap_or(adt_encoding) == AdtEncoding::NumScott);
+    if is_num_scott {
+      self.resugar_adts_num_scott(book, adt_encoding);
+    }
+  }
+
+  /// Converts the tagged tuples `(Ctr/tag, a1, .., an)` of types encoded with
+  /// [`AdtEncoding::TaggedTuple`] to `(Ctr a1 .. an)`.
+  ///
+  /// Runs before lists and strings are resugared, since they are found by their constructor applications.
+  pub fn resugar_tagged_tuples(&mut self, book: &Book, adt_encoding: AdtEncoding) {
+    maybe_grow(|| {
+      for child in self.children_mut() {
+        child.resugar_tagged_tuples(book, adt_encoding);
+      }
+
+      let Term::Fan { fan: FanKind::Tup, tag: Tag::Static, els } = self else { return };
+      let [Term::Ref { nam: tag }, rest] = els.as_slice() else { return };
+      let Some(ctr) = tag.strip_suffix("/tag").map(Name::new) else { return };
+      let Some(adt) = book.ctrs.get(&ctr).and_then(|typ| book.adts.get(typ)) else { return };
+      if adt.encoding.unwrap_or(adt_encoding) != AdtEncoding::TaggedTuple {
+        return;
+      }
+
+      // The fields are read back as nested pairs: (a1, (a2, .. an)).
+      let arity = adt.ctrs[&ctr].len();
+      let mut fields = vec![];
+      let mut rest = rest;
+      if arity == 0 {
+        if !matches!(rest, Term::Era) {
+          return;
+        }
+      } else {
+        for _ in 1..arity {
+          let Term::Fan { fan: FanKind::Tup, tag: Tag::Static, els } = rest else { return };
+          let [fst, snd] = els.as_slice() else { return };
+          fields.push(fst.clone());
+          rest = snd;
+        }
+        fields.push(rest.clone());
+      }
+      *self = Term::call(Term::Ref { nam: ctr }, fields);
+    })
+  }
+
+  /// Converts `λx (x Ctr/tag a1 .. an)` to `(Ctr a1 .. an)`.
+  fn resugar_adts_num_scott(&mut self, book: &Book, adt_encoding: AdtEncoding) {
+    maybe_grow(|| {
+      for child in self.children_mut() {
+        child.resugar_adts_num_scott(book, adt_encoding);
+      }
+
+      let Term::Lam { tag: Tag::Static, pat, bod } = self else { return };
+      let Pattern::Var(Some(var_lam)) = pat.as_ref() else { return };
+      let (head, args) = bod.app_spine();
+      let Term::Var { nam: var_app } = head else { return };
+      let Some((Term::Ref { nam: tag }, fields)) = args.split_first() else { return };
+      let Some(ctr) = tag.strip_suffix("/tag").map(Name::new) else { return };
+      let Some(adt) = book.ctrs.get(&ctr).and_then(|typ| book.adts.get(typ)) else { return };
+      if var_lam != var_app
+        || adt.encoding.unwrap_or(adt_encoding) != AdtEncoding::NumScott
+        || adt.ctrs[&ctr].len() != fields.len()
+        || fields.iter().any(|field| field.free_vars().contains_key(var_lam))
+      {
+        return;
+      }
+
+      let fields = fields.iter().map(|field| (*field).clone()).collect::<Vec<_>>();
+      *self = Term::call(Term::Ref { nam: ctr }, fields);
+    })
+  }
+
+  /// Splits an application spine into its head and arguments, in application order.
+  fn app_spine(&self) -> (&Term, Vec<&Term>) {
+    let mut args = vec![];
+    let mut head = self;
+    while let Term::App { tag: Tag::Static, fun, arg } = head {
+      args.push(arg.as_ref());
+      head = fun;
+    }
+    args.reverse();
+    (head, args)
+  }
+}
@@ -8,7 +8,8 @@ impl Term {
   pub fn resugar_lists(&mut self, adt_encoding: AdtEncoding) {
     match adt_encoding {
       AdtEncoding::Scott => self.resugar_lists_scott(),
-      AdtEncoding::NumScott => self.resugar_lists_num_scott(),
+      // Tagged tuples were already converted to constructor applications by `resugar_tagged_tuples`.
+      AdtEncoding::NumScott | AdtEncoding::TaggedTuple => self.resugar_lists_num_scott(),
     }
   }
 
@@ -8,7 +8,8 @@ impl Term {
   pub fn resugar_strings(&mut self, adt_encoding: AdtEncoding) {
     match adt_encoding {
       AdtEncoding::Scott => self.resugar_strings_scott(),
-      AdtEncoding::NumScott => self.resugar_strings_num_scott(),
+      // Tagged tuples were already converted to constructor applications by `resugar_tagged_tuples`.
+      AdtEncoding::NumScott | AdtEncoding::TaggedTuple => self.resugar_strings_num_scott(),
     }
   }
 
@@ -0,0 +1,200 @@
+use crate::{
+  fun::{Book, FanKind, Name, Pattern, Term},
+  maybe_grow,
+};
+use indexmap::IndexMap;
+
+type Arities = IndexMap<Name, usize>;
+
+impl Book {
+  /// Shares the closed computations that appear more than once in the same body, so they're computed once.
+  ///
+  /// A closed computation is a numeric operation or a call with all the arguments of a function, but not of a constructor,
+  /// that has no free variables.
+  /// The repeated ones are bound by a `let` at the start of the lambda body or `switch` arm where they occur,
+  /// and their occurrences are replaced by the bound variable, which is then duplicated by [`Book::linearize_vars`].
+  /// Occurrences inside nested lambdas or `switch` arms are only shared with the ones in the same nested body.
+  /// Terms are compared without the names of the variables bound inside of them.
+  ///
+  /// Terms that have duplications inside are not shared, since duplicating them again
+  /// with the single label of the HVM runtimes could mix the copies.
+  ///
+  /// Example:
+  /// ```bend
+  /// main = λx (+ (Fib 20) (* x (Fib 20)))
+  /// ```
+  /// Becomes:
+  /// ```bend
+  /// main = λx let %shared0 = (Fib 20); (+ %shared0 (* x %shared0))
+  /// ```
+  ///
+  /// Precondition: The matches have been encoded.
+  pub fn share_common_subterms(&mut self) {
+    // The number of arguments that each function takes, to tell calls from partial applications.
+    let arities = self
+      .defs
+      .values()
+      .filter(|def| !self.ctrs.contains_key(&def.name))
+      .map(|def| (def.name.clone(), def.rule().body.lambda_count()))
+      .collect::<IndexMap<_, _>>();
+
+    for def in self.defs.values_mut() {
+      let mut fresh = 0;
+      for rule in def.rules.iter_mut() {
+        rule.body.share_common_subterms(&arities, &mut fresh);
+      }
+    }
+  }
+}
+
+impl Term {
+  /// Shares the repeated computations of a body, after sharing the ones of the bodies nested in it.
+  fn share_common_subterms(&mut self, arities: &Arities, fresh: &mut usize) {
+    self.share_in_nested_bodies(arities, fresh);
+
+    // Shares the largest repeated term first, so the terms inside of it are counted only once.
+    while let Some(term) = self.largest_repeated_subterm(arities) {
+      let nam = Name::new(format!("%shared{fresh}"));
+      *fresh += 1;
+      let mut val = None;
+      self.replace_subterm(&term, arities, &nam, &mut val);
+      let nxt = std::mem::take(self);
+      *self =
+        Term::Let { pat: Box::new(Pattern::Var(Some(nam))), val: Box::new(val.unwrap()), nxt: Box::new(nxt) };
+    }
+  }
+
+  fn share_in_nested_bodies(&mut self, arities: &Arities, fresh: &mut usize) {
+    maybe_grow(|| match self {
+      Term::Lam { bod, .. } => bod.share_common_subterms(arities, fresh),
+      Term::Swt { arg, with_arg, arms, .. } => {
+        arg.share_in_nested_bodies(arities, fresh);
+        for arg in with_arg {
+          arg.share_in_nested_bodies(arities, fresh);
+        }
+        for arm in arms {
+          arm.share_common_subterms(arities, fresh);
+        }
+      }
+      _ => {
+        for child in self.children_mut() {
+          child.share_in_nested_bodies(arities, fresh);
+        }
+      }
+    })
+  }
+
+  /// The largest shareable term that appears more than once, with the variables bound inside of it renamed.
+  fn largest_repeated_subterm(&self, arities: &Arities) -> Option<Term> {
+    let mut counts = IndexMap::new();
+    self.count_shareable(arities, &mut counts);
+    let repeated = counts.into_iter().filter(|(_, count)| *count > 1).map(|(term, _)| term);
+    repeated.rev().max_by_key(|term| term.size())
+  }
+
+  /// Counts the shareable subterms of this body, without entering the nested bodies.
+  fn count_shareable(&self, arities: &Arities, counts: &mut IndexMap<Term, usize>) {
+    maybe_grow(|| {
+      if self.is_shareable(arities) {
+        *counts.entry(self.renamed()).or_default() += 1;
+      }
+      match self {
+        Term::Lam { .. } => {}
+        Term::Swt { arg, with_arg, .. } => {
+          arg.count_shareable(arities, counts);
+          for arg in with_arg {
+            arg.count_shareable(arities, counts);
+          }
+        }
+        _ => {
+          for child in self.children() {
+            child.count_shareable(arities, counts);
+          }
+        }
+      }
+    })
+  }
+
+  /// Replaces the occurrences of the renamed `term` in this body by a variable, without entering the nested bodies.
+  /// Keeps the first occurrence in `val`.
+  fn replace_subterm(&mut self, term: &Term, arities: &Arities, nam: &Name, val: &mut Option<Term>) {
+    maybe_grow(|| {
+      if self.is_shareable(arities) && self.renamed() == *term {
+        let occurrence = std::mem::replace(self, Term::Var { nam: nam.clone() });
+        val.get_or_insert(occurrence);
+        return;
+      }
+      match self {
+        Term::Lam { .. } => {}
+        Term::Swt { arg, with_arg, .. } => {
+          arg.replace_subterm(term, arities, nam, val);
+          for arg in with_arg {
+            arg.replace_subterm(term, arities, nam, val);
+          }
+        }
+        _ => {
+          for child in self.children_mut() {
+            child.replace_subterm(term, arities, nam, val);
+          }
+        }
+      }
+    })
+  }
+
+  /// A copy of the term with the variables bound inside of it renamed, to compare it with others.
+  fn renamed(&self) -> Term {
+    let mut term = self.clone();
+    term.make_var_names_unique();
+    term
+  }
+
+  fn is_shareable(&self, arities: &Arities) -> bool {
+    self.is_computation(arities) && self.free_vars().is_empty() && !self.has_unscoped() && !self.has_dups()
+  }
+
+  /// Whether the term is an operation, an applied lambda or a call with all the arguments of a function.
+  fn is_computation(&self, arities: &Arities) -> bool {
+    let mut args = 0;
+    let mut head = self;
+    while let Term::App { fun, .. } = head {
+      args += 1;
+      head = fun;
+    }
+    match head {
+      _ if args == 0 => matches!(self, Term::Oper { .. }),
+      Term::Ref { nam } => arities.get(nam).is_some_and(|arity| args >= *arity),
+      Term::Lam { .. } => true,
+      _ => false,
+    }
+  }
+
+  fn lambda_count(&self) -> usize {
+    match self {
+      Term::Lam { bod, .. } => 1 + bod.lambda_count(),
+      _ => 0,
+    }
+  }
+
+  /// Whether the term duplicates something, explicitly or by using a variable more than once.
+  fn has_dups(&self) -> bool {
+    maybe_grow(|| {
+      let dups = match self {
+        Term::Fan { fan: FanKind::Dup, .. } => true,
+        Term::Let { pat, .. } if matches!(pat.as_ref(), Pattern::Fan(FanKind::Dup, ..)) => true,
+        Term::Lam { pat, bod: nxt, .. } | Term::Let { pat, nxt, .. } => {
+          pat.binds().flatten().any(|nam| nxt.var_uses(nam) > 1)
+        }
+        _ => false,
+      };
+      dups || self.children().any(Term::has_dups)
+    })
+  }
+
+  /// The number of occurrences of a variable, expecting the variable names to be unique.
+  fn var_uses(&self, nam: &Name) -> usize {
+    maybe_grow(|| match self {
+      Term::Var { nam: var } => (var == nam) as usize,
+      _ => self.children().map(|child| child.var_uses(nam)).sum(),
+    })
+  }
+}
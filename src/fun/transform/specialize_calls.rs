@@ -0,0 +1,225 @@
+use crate::{
+  fun::{Book, Definition, Name, Pattern, Rule, Tag, Term},
+  maybe_grow,
+};
+use indexmap::{IndexMap, IndexSet};
+
+/// A specialized copy of a definition: the original definition and the function given to each of its parameters,
+/// or `None` for the parameters that are kept.
+type SpecKey = (Name, Vec<Option<Name>>);
+
+struct Specializer<'a> {
+  book: &'a Book,
+  /// The definitions that can be given as arguments: functions that aren't constructors.
+  funcs: &'a IndexSet<Name>,
+  specs: &'a mut IndexMap<SpecKey, Name>,
+  new_defs: &'a mut Vec<Definition>,
+}
+
+impl Book {
+  /// Generates copies of the definitions that are called with other definitions as arguments,
+  /// with the given definition in place of the parameter, so the function isn't passed around as a closure.
+  ///
+  /// Each call with the same arguments uses the same copy, so recursive functions that pass
+  /// the function along to themselves become recursive copies.
+  /// The calls are only specialized in user definitions and in the copies generated from them.
+  ///
+  /// Example:
+  /// ```bend
+  /// Map (List/Cons x xs) f = (List/Cons (f x) (Map xs f))
+  /// Map List/Nil f = List/Nil
+  /// main = (Map [1, 2] Double)
+  /// ```
+  /// Becomes:
+  /// ```bend
+  /// Map__spec0 (List/Cons x xs) = (List/Cons (Double x) (Map__spec0 xs))
+  /// Map__spec0 List/Nil = List/Nil
+  /// main = (Map__spec0 [1, 2])
+  /// ```
+  ///
+  /// Precondition: The `with` blocks have been desugared and the match terms are fixed, but not linearized.
+  pub fn specialize_calls(&mut self) {
+    let funcs = self
+      .defs
+      .values()
+      .filter(|def| !self.ctrs.contains_key(&def.name) && matches!(def.rule().body, Term::Lam { .. }))
+      .map(|def| def.name.clone())
+      .collect::<IndexSet<_>>();
+
+    let mut specs = IndexMap::new();
+    let mut pending =
+      self.defs.values().filter(|def| !def.builtin).map(|def| def.name.clone()).collect::<Vec<_>>();
+    pending.reverse();
+    while let Some(def_name) = pending.pop() {
+      let mut body = self.defs[&def_name].rule().body.clone();
+      let mut new_defs = vec![];
+      let mut specializer =
+        Specializer { book: self, funcs: &funcs, specs: &mut specs, new_defs: &mut new_defs };
+      let changed = specializer.specialize(&mut body);
+      if changed {
+        self.defs.get_mut(&def_name).unwrap().rule_mut().body = body;
+      }
+      for def in new_defs.into_iter().rev() {
+        pending.push(def.name.clone());
+        self.defs.insert(def.name.clone(), def);
+      }
+    }
+  }
+}
+
+impl Specializer<'_> {
+  /// Replaces the calls in the term with the specialized copies of the called definitions.
+  /// Returns whether any call was replaced.
+  fn specialize(&mut self, term: &mut Term) -> bool {
+    maybe_grow(|| {
+      let mut changed = false;
+      if let Some(spec) = self.specialized_call(term) {
+        *term = spec;
+        changed = true;
+      }
+      for child in term.children_mut() {
+        changed |= self.specialize(child);
+      }
+      changed
+    })
+  }
+
+  /// The call to a specialized copy of the called definition,
+  /// if some of the arguments are functions that the definition can be specialized with.
+  fn specialized_call(&mut self, term: &Term) -> Option<Term> {
+    let mut args = vec![];
+    let mut head = term;
+    while let Term::App { tag: Tag::Static, fun, arg } = head {
+      args.push(arg.as_ref());
+      head = fun;
+    }
+    args.reverse();
+    let Term::Ref { nam } = head else { return None };
+
+    // Calls to copies are specialized from the original definition, so that each combination has a single copy.
+    let (orig, mut fixed) = match self.specs.iter().find(|(_, spec)| *spec == nam) {
+      Some(((orig, fixed), _)) => (orig.clone(), fixed.clone()),
+      None if self.funcs.contains(nam) => {
+        let params = self.book.defs[nam].rule().body.leading_params().len();
+        (nam.clone(), vec![None; params])
+      }
+      None => return None,
+    };
+
+    let params = self.book.defs[&orig].rule().body.leading_params();
+    let kept =
+      fixed.iter().enumerate().filter(|(_, fun)| fun.is_none()).map(|(pos, _)| pos).collect::<Vec<_>>();
+    let mut new_args = vec![];
+    for (i, arg) in args.iter().enumerate() {
+      match (kept.get(i), arg) {
+        (Some(&pos), Term::Ref { nam: fun })
+          if self.funcs.contains(fun) && matches!(params[pos], Pattern::Var(_)) =>
+        {
+          fixed[pos] = Some(fun.clone());
+        }
+        _ => new_args.push((*arg).clone()),
+      }
+    }
+    if new_args.len() == args.len() {
+      return None;
+    }
+
+    let spec = self.spec_def(orig, fixed);
+    Some(Term::call(Term::Ref { nam: spec }, new_args))
+  }
+
+  /// The name of the copy of `orig` with the given functions in place of its parameters, generating it if needed.
+  fn spec_def(&mut self, orig: Name, fixed: Vec<Option<Name>>) -> Name {
+    let key = (orig, fixed);
+    if let Some(spec) = self.specs.get(&key) {
+      return spec.clone();
+    }
+    let (orig, fixed) = &key;
+    let fresh = self.specs.keys().filter(|(other, _)| other == orig).count();
+    let name = Name::new(format!("{orig}__spec{fresh}"));
+
+    let def = &self.book.defs[orig];
+    let mut lams = vec![];
+    let mut body = &def.rule().body;
+    for _ in fixed {
+      let Term::Lam { tag, pat, bod } = body else { unreachable!() };
+      lams.push((tag.clone(), pat.as_ref().clone()));
+      body = bod;
+    }
+    let mut body = body.clone();
+    for ((tag, pat), fun) in lams.into_iter().zip(fixed).rev() {
+      match (fun, pat) {
+        (Some(fun), Pattern::Var(Some(param))) => body.subst(&param, &Term::Ref { nam: fun.clone() }),
+        (Some(_), _) => {}
+        (None, pat) => body = Term::Lam { tag, pat: Box::new(pat), bod: Box::new(body) },
+      }
+    }
+    body.subst_ref_binds();
+
+    self.new_defs.push(Definition::new(name.clone(), vec![Rule { pats: vec![], body }], def.builtin));
+    self.specs.insert(key, name.clone());
+    name
+  }
+}
+
+impl Term {
+  /// The patterns of the lambdas at the start of a definition body.
+  fn leading_params(&self) -> Vec<&Pattern> {
+    let mut params = vec![];
+    let mut term = self;
+    while let Term::Lam { pat, bod, .. } = term {
+      params.push(pat.as_ref());
+      term = bod;
+    }
+    params
+  }
+
+  /// Removes the `use`s and the `with` clauses of the matches that bind a definition,
+  /// substituting the definition where they're used, so the specialized function is called directly.
+  fn subst_ref_binds(&mut self) {
+    maybe_grow(|| {
+      while let Term::Use { nam, val, nxt } = self {
+        if !matches!(val.as_ref(), Term::Ref { .. }) {
+          break;
+        }
+        if let Some(nam) = nam {
+          nxt.subst(nam, val);
+        }
+        *self = std::mem::take(nxt.as_mut());
+      }
+      if let Term::Mat { with_bnd, with_arg, arms, .. } = self {
+        for (bnd, arg) in take_ref_withs(with_bnd, with_arg) {
+          for (_, _, arm) in arms.iter_mut() {
+            arm.subst(&bnd, &arg);
+          }
+        }
+      }
+      if let Term::Swt { with_bnd, with_arg, arms, .. } = self {
+        for (bnd, arg) in take_ref_withs(with_bnd, with_arg) {
+          for arm in arms.iter_mut() {
+            arm.subst(&bnd, &arg);
+          }
+        }
+      }
+      for child in self.children_mut() {
+        child.subst_ref_binds();
+      }
+    })
+  }
+}
+
+/// Takes out the `with` clauses whose value is a reference to a definition.
+fn take_ref_withs(with_bnd: &mut Vec<Option<Name>>, with_arg: &mut Vec<Term>) -> Vec<(Name, Term)> {
+  let mut taken = vec![];
+  let mut i = 0;
+  while i < with_arg.len() {
+    if matches!(with_arg[i], Term::Ref { .. }) {
+      let bnd = with_bnd.remove(i);
+      let arg = with_arg.remove(i);
+      taken.extend(bnd.map(|bnd| (bnd, arg)));
+    } else {
+      i += 1;
+    }
+  }
+  taken
+}
@@ -1,4 +1,4 @@
-use super::tree_children;
+use super::{prune::prune_hvm_book, tree_children};
 use crate::{diagnostics::Diagnostics, fun::Name};
 use hvm::ast::{Book, Net, Tree};
 
@@ -20,6 +20,27 @@ pub fn check_net_sizes(book: &Book, diagnostics: &mut Diagnostics) -> Result<(),
   diagnostics.fatal(())
 }
 
+/// Reports the definitions with more than `max` nodes, to catch code that grows unexpectedly during compilation.
+pub fn check_max_def_sizes(
+  book: &Book,
+  max: usize,
+  diagnostics: &mut Diagnostics,
+) -> Result<(), Diagnostics> {
+  diagnostics.start_pass();
+
+  for (name, net) in &book.defs {
+    let nodes = count_nodes(net);
+    if nodes > max {
+      diagnostics.add_rule_error(
+        format!("Definition has {nodes} nodes, more than the maximum of {max} given with '--max-def-size'."),
+        Name::new(name),
+      );
+    }
+  }
+
+  diagnostics.fatal(())
+}
+
 /// Utility function to count the amount of nodes in an hvm-core AST net
 pub fn count_nodes(net: &Net) -> usize {
   let mut visit: Vec<&Tree> = vec![&net.root];
@@ -39,3 +60,76 @@ pub fn count_nodes(net: &Net) -> usize {
   }
   count
 }
+
+/// Utility function to count the amount of duplication nodes in an hvm-core AST net.
+///
+/// The HVM runtimes have a single duplication label, so all of them are counted together.
+pub fn count_dups(net: &Net) -> usize {
+  let mut visit = super::net_trees(net).collect::<Vec<_>>();
+  let mut count = 0usize;
+  while let Some(tree) = visit.pop() {
+    if let Tree::Dup { .. } = tree {
+      count += 1;
+    }
+    visit.extend(tree_children(tree));
+  }
+  count
+}
+
+/// The sizes of the compiled definitions of a program.
+#[derive(Debug, Clone)]
+pub struct NetSizes {
+  /// The size of each definition, with the largest first.
+  pub defs: Vec<NetSize>,
+  pub entrypoint: String,
+  /// The definitions that can be expanded when running the entrypoint.
+  pub reachable: usize,
+  /// The nodes allocated by expanding each definition reachable from the entrypoint once.
+  ///
+  /// Since definitions are usually expanded many times, it's a lower bound of the nodes used at the peak.
+  pub min_peak_nodes: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct NetSize {
+  pub name: String,
+  pub nodes: usize,
+  pub dups: usize,
+}
+
+/// Size of a node of the HVM runtimes, a pair of 32-bit ports.
+const NODE_BYTES: usize = 8;
+
+pub fn net_sizes(book: &Book, entrypoint: &str) -> NetSizes {
+  let mut defs = book
+    .defs
+    .iter()
+    .map(|(name, net)| NetSize { name: name.clone(), nodes: count_nodes(net), dups: count_dups(net) })
+    .collect::<Vec<_>>();
+  defs.sort_by(|a, b| b.nodes.cmp(&a.nodes).then_with(|| a.name.cmp(&b.name)));
+
+  let mut reachable = Book { defs: book.defs.clone() };
+  prune_hvm_book(&mut reachable, &[entrypoint.to_string()]);
+  let min_peak_nodes =
+    defs.iter().filter(|def| reachable.defs.contains_key(&def.name)).map(|def| def.nodes).sum();
+
+  NetSizes { defs, entrypoint: entrypoint.to_string(), reachable: reachable.defs.len(), min_peak_nodes }
+}
+
+impl std::fmt::Display for NetSizes {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let width = self.defs.iter().map(|def| def.name.len()).max().unwrap_or(0).max("Definition".len());
+    writeln!(f, "{:width$}  {:>6}  {:>6}", "Definition", "Nodes", "Dups")?;
+    for def in &self.defs {
+      writeln!(f, "{:width$}  {:>6}  {:>6}", def.name, def.nodes, def.dups)?;
+    }
+    write!(
+      f,
+      "Entrypoint '{}' reaches {} definitions, using at least {} nodes ({} bytes) at its peak.",
+      self.entrypoint,
+      self.reachable,
+      self.min_peak_nodes,
+      self.min_peak_nodes * NODE_BYTES
+    )
+  }
+}
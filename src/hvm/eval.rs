@@ -0,0 +1,690 @@
+use crate::{diagnostics::ERR_INDENT_SIZE, fun::Name, maybe_grow};
+use hvm::{
+  ast,
+  hvm::{
+    APair, APort, Book, GNet, Numb, Pair, Port, Rule, TMem, ANNI, CALL, COMM, CON, DUP, ERA, ERAS, FP_DIV,
+    FP_REM, FP_SHL, FP_SHR, LINK, NONE, NUM, OPER, OPR, OP_ADD, OP_DIV, OP_REM, OP_SHL, OP_SHR, REF, ROOT,
+    SWI, SWIT, TY_F24, TY_I24, TY_SYM, TY_U24, VAR, VOID,
+  },
+};
+use std::{
+  alloc::{alloc_zeroed, dealloc, Layout},
+  collections::{BTreeMap, HashMap},
+  fmt::Display,
+  fs::File,
+  io::{BufWriter, Write},
+  path::PathBuf,
+  sync::atomic::AtomicU64,
+  time::{Duration, Instant},
+};
+
+/// Default amount of node and var slots, the same as `hvm run`.
+/// The root is the last var slot, so the vars always have this size.
+pub const DEFAULT_NET_SIZE: usize = 1 << 29;
+
+/// Number of definitions listed when reporting where the work went.
+const HOT_DEFS_SHOWN: usize = 5;
+
+/// Options for an in-process evaluation.
+#[derive(Debug, Clone, Default)]
+pub struct EvalOpts {
+  /// Maximum number of interactions (not counting variable links).
+  pub max_rewrites: Option<u64>,
+  /// Maximum amount of bytes of node and variable memory used.
+  pub max_memory: Option<u64>,
+  /// Attributes the interactions to the chains of definitions that caused them.
+  pub profile: bool,
+  /// When a limit is exceeded, returns the partially evaluated net, with its remaining redexes, instead of an error.
+  pub partial: bool,
+  /// Writes the rewrites to a file as they happen.
+  pub trace: Option<TraceOpts>,
+}
+
+/// Which rewrites of an evaluation are written to the trace file, and where.
+#[derive(Debug, Clone, Default)]
+pub struct TraceOpts {
+  /// The file the rewrites are written to, one per line.
+  pub path: PathBuf,
+  /// Only writes every N-th of the rewrites that pass the filter.
+  pub every: Option<u64>,
+  /// Only writes the rewrites of nodes created by this definition, or by the definitions generated from it,
+  /// and the expansions of it.
+  pub def: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum EvalError {
+  MissingMain,
+  /// Tried to duplicate a reference to a definition that contains duplications.
+  UnsafeDup {
+    def: String,
+  },
+  /// Exceeded `max_rewrites`. Includes the definitions with the most expansions.
+  MaxRewrites {
+    limit: u64,
+    hot_defs: Vec<(String, u64)>,
+  },
+  /// Exceeded `max_memory`. Includes the definitions with the most expansions.
+  MaxMemory {
+    limit: u64,
+    hot_defs: Vec<(String, u64)>,
+  },
+  /// Divided an integer by zero.
+  DivisionByZero,
+  /// Applied a numeric operation to a type it isn't defined for, like shifting a float.
+  UndefinedOperation {
+    op: &'static str,
+    typ: &'static str,
+  },
+  /// Ran out of space in the net.
+  OutOfMemory,
+  /// Couldn't allocate the memory of the net.
+  AllocFailed {
+    bytes: usize,
+  },
+  Readback,
+  /// The hook of [`eval_book_with`] stopped the evaluation.
+  Interrupted,
+  /// Couldn't write to the trace file.
+  Trace {
+    path: PathBuf,
+    err: String,
+  },
+}
+
+/// Statistics of an in-process evaluation.
+#[derive(Debug, Clone, Default)]
+pub struct EvalStats {
+  pub itrs: u64,
+  pub time: Duration,
+  /// The definitions that were expanded, with the most expanded first.
+  pub defs: Vec<DefStats>,
+  /// When profiling, the interactions done by each chain of calls, as `caller;callee` folded stacks.
+  pub profile: Vec<(String, u64)>,
+  /// The exceeded limit, if a partial evaluation was stopped before the end.
+  pub stopped: Option<EvalError>,
+}
+
+/// How much work was done by expanding a definition.
+#[derive(Debug, Clone)]
+pub struct DefStats {
+  pub name: String,
+  /// Number of times the definition was expanded.
+  pub calls: u64,
+  /// Number of nodes allocated by expanding the definition.
+  pub nodes: u64,
+}
+
+/// Evaluates the `main` definition of an HVM book in-process.
+///
+/// Uses the sequential interaction loop of the HVM library, so the result
+/// doesn't depend on the installed HVM binary or on the parallel runtimes.
+/// Since it shares the interaction rules of HVM, it's a check of the determinism of the other runtimes,
+/// not an independent implementation of the semantics.
+/// Returns the normalized net, or an error if the evaluation got stuck or went over the limits.
+pub fn eval_book(book: &ast::Book, opts: EvalOpts) -> Result<(ast::Net, EvalStats), EvalError> {
+  eval_book_with(book, opts, &mut |_| true)
+}
+
+/// Evaluates the `main` definition of an HVM book in-process, like [`eval_book`],
+/// calling `hook` before each expansion of a definition.
+///
+/// If `hook` returns false, the evaluation stops and the partially evaluated net is returned,
+/// with [`EvalError::Interrupted`] in [`EvalStats::stopped`].
+pub fn eval_book_with(
+  book: &ast::Book,
+  opts: EvalOpts,
+  hook: &mut dyn FnMut(&Paused) -> bool,
+) -> Result<(ast::Net, EvalStats), EvalError> {
+  if !book.defs.contains_key("main") {
+    return Err(EvalError::MissingMain);
+  }
+  let book = book.build();
+
+  let net = new_gnet(node_len(&book, opts.max_memory), DEFAULT_NET_SIZE)?;
+  let mut tm = TMem::new(0, 1);
+
+  // `build` always gives the entrypoint the id 0.
+  tm.rbag.push_redex(Pair::new(Port::new(REF, 0), ROOT));
+  net.vars_create(ROOT.get_val() as usize, NONE);
+
+  let start = Instant::now();
+  let mut itrs = 0;
+  // How many times each definition was expanded.
+  let mut calls = vec![0u64; book.defs.len()];
+  // The tracer uses the profiler to know which definition created each node.
+  let mut profiler = (opts.profile || opts.trace.is_some()).then(|| Profiler::new(net.nlen));
+  let mut tracer = opts.trace.as_ref().map(Tracer::new).transpose()?;
+  let mut stopped = None;
+  while let Some(redex) = tm.rbag.pop_redex() {
+    let mut a = redex.get_fst();
+    let mut b = redex.get_snd();
+    let mut rule = Port::get_rule(a, b);
+    if a.get_tag() == REF && b == ROOT {
+      rule = CALL;
+    } else if Port::should_swap(a, b) {
+      (a, b) = (b, a);
+    }
+
+    let fid = a.get_val() as usize & 0xFFFFFFF;
+    if rule == CALL && b.get_tag() == DUP && !book.defs[fid].safe {
+      return Err(EvalError::UnsafeDup { def: book.defs[fid].name.clone() });
+    }
+    if rule == OPER {
+      check_oper(&net, a, b)?;
+    }
+
+    let expands = rule == CALL && b.get_tag() != DUP;
+    if expands {
+      let paused = Paused { net: &net, tm: &tm, book: &book, fid, redex: (a, b), calls: calls[fid], itrs };
+      if !hook(&paused) {
+        // Put the redex back, so that it's shown as not reduced yet.
+        tm.rbag.push_redex(redex);
+        stopped = Some(EvalError::Interrupted);
+        break;
+      }
+    }
+    let chain = profiler.as_mut().map(|p| {
+      let parent = p.origin(a, b);
+      if let Some(tracer) = tracer.as_mut().filter(|_| rule != LINK) {
+        tracer.rewrite(itrs + 1, rule, a, b, p.def(parent), &book);
+      }
+      if expands {
+        p.call(parent, fid)
+      } else {
+        parent
+      }
+    });
+
+    let success = match rule {
+      LINK => tm.interact_link(&net, a, b),
+      CALL => tm.interact_call(&net, a, b, &book),
+      VOID => tm.interact_void(&net, a, b),
+      ERAS => tm.interact_eras(&net, a, b),
+      ANNI => tm.interact_anni(&net, a, b),
+      COMM => tm.interact_comm(&net, a, b),
+      OPER => tm.interact_oper(&net, a, b),
+      SWIT => tm.interact_swit(&net, a, b),
+      _ => unreachable!(),
+    };
+    // With a single thread, an interaction can only fail by not finding free memory.
+    if !success {
+      return Err(EvalError::OutOfMemory);
+    }
+    if expands {
+      calls[fid] += 1;
+    }
+    if rule != LINK {
+      itrs += 1;
+    }
+    if let (Some(p), Some(chain)) = (profiler.as_mut(), chain) {
+      let new_nodes = match rule {
+        CALL if expands => book.defs[fid].node.len(),
+        COMM => 4,
+        OPER => 1,
+        SWIT => 2,
+        _ => 0,
+      };
+      for loc in &tm.nloc[..new_nodes] {
+        p.origin[*loc] = chain;
+      }
+      if rule != LINK {
+        p.itrs[chain as usize] += 1;
+      }
+    }
+
+    let exceeded = match (opts.max_rewrites, opts.max_memory) {
+      (Some(limit), _) if itrs > limit => {
+        Some(EvalError::MaxRewrites { limit, hot_defs: hot_defs(&book, &calls) })
+      }
+      (_, Some(limit)) if used_memory(&tm, &net) > limit => {
+        Some(EvalError::MaxMemory { limit, hot_defs: hot_defs(&book, &calls) })
+      }
+      _ => None,
+    };
+    if let Some(err) = exceeded {
+      if !opts.partial {
+        return Err(err);
+      }
+      stopped = Some(err);
+      break;
+    }
+  }
+  let time = start.elapsed();
+
+  if let Some(tracer) = tracer {
+    tracer.finish()?;
+  }
+
+  // The readback is recursive on the depth of the result, so give it some room.
+  let res = stacker::grow(1 << 28, || readback_partial(&net, &tm, &book));
+  let Some(res) = res else {
+    return Err(EvalError::Readback);
+  };
+
+  let defs = def_stats(&book, &calls);
+  let profile = profiler.filter(|_| opts.profile).map(|p| p.folded(&book)).unwrap_or_default();
+  Ok((res, EvalStats { itrs, time, defs, profile, stopped }))
+}
+
+/// Reads back the net connected to the root, together with the redexes that weren't reduced yet.
+/// The redexes are empty when the evaluation finished.
+fn readback_partial(net: &GNet, tm: &TMem, book: &Book) -> Option<ast::Net> {
+  let mut res = ast::Net::readback(net, book)?;
+  let fids = book.defs.iter().enumerate().map(|(fid, def)| (fid as u32, def.name.clone())).collect();
+  for redex in tm.rbag.lo.iter().chain(tm.rbag.hi.iter()) {
+    let fst = ast::Tree::readback(net, redex.get_fst(), &fids)?;
+    let snd = ast::Tree::readback(net, redex.get_snd(), &fids)?;
+    res.rbag.push((false, fst, snd));
+  }
+  Some(res)
+}
+
+/// An evaluation that is about to expand a definition, as given to the hook of [`eval_book_with`].
+pub struct Paused<'a> {
+  net: &'a GNet<'a>,
+  tm: &'a TMem,
+  book: &'a Book,
+  fid: usize,
+  redex: (Port, Port),
+  /// How many times the definition was expanded before.
+  pub calls: u64,
+  /// How many rewrites were done so far.
+  pub itrs: u64,
+}
+
+impl Paused<'_> {
+  /// The name of the definition being expanded.
+  pub fn def(&self) -> &str {
+    &self.book.defs[self.fid].name
+  }
+
+  /// Reads back what the definition is connected to, which for a function call
+  /// is the chain of applications to its arguments, `(arg1 (arg2 .. ret))`.
+  pub fn arg(&self) -> Option<ast::Tree> {
+    peek_tree(self.net, self.redex.1, &self.fids())
+  }
+
+  /// Reads back the net connected to the root, with the redexes that weren't reduced yet,
+  /// including the expansion that is about to happen.
+  ///
+  /// Unlike the readback at the end of the evaluation, this doesn't modify the net,
+  /// so that the evaluation can continue afterwards.
+  pub fn net(&self) -> Option<ast::Net> {
+    let fids = self.fids();
+    let root = peek_tree(self.net, ROOT, &fids)?;
+    let mut rbag = vec![];
+    let redexes = self.tm.rbag.lo.iter().chain(self.tm.rbag.hi.iter()).map(|r| (r.get_fst(), r.get_snd()));
+    for (fst, snd) in [self.redex].into_iter().chain(redexes) {
+      rbag.push((false, peek_tree(self.net, fst, &fids)?, peek_tree(self.net, snd, &fids)?));
+    }
+    Some(ast::Net { root, rbag })
+  }
+
+  fn fids(&self) -> BTreeMap<u32, String> {
+    self.book.defs.iter().enumerate().map(|(fid, def)| (fid as u32, def.name.clone())).collect()
+  }
+}
+
+/// Reads back the tree at `port`, like [`ast::Tree::readback`], but without consuming the
+/// variable substitutions it goes through.
+fn peek_tree(net: &GNet, mut port: Port, fids: &BTreeMap<u32, String>) -> Option<ast::Tree> {
+  maybe_grow(|| {
+    while port.get_tag() == VAR {
+      let got = net.vars_load(port.get_val() as usize);
+      if got == NONE || got == Port(0) {
+        return Some(ast::Tree::Var { nam: format!("v{:x}", port.get_val()) });
+      }
+      port = got;
+    }
+    let tree = match port.get_tag() {
+      REF => ast::Tree::Ref { nam: fids.get(&(port.get_val() & 0xFFFFFFF))?.clone() },
+      ERA => ast::Tree::Era,
+      NUM => ast::Tree::Num { val: ast::Numb(port.get_val()) },
+      tag => {
+        let pair = net.node_load(port.get_val() as usize);
+        let fst = Box::new(peek_tree(net, pair.get_fst(), fids)?);
+        let snd = Box::new(peek_tree(net, pair.get_snd(), fids)?);
+        match tag {
+          CON => ast::Tree::Con { fst, snd },
+          DUP => ast::Tree::Dup { fst, snd },
+          OPR => ast::Tree::Opr { fst, snd },
+          SWI => ast::Tree::Swi { fst, snd },
+          _ => return None,
+        }
+      }
+    };
+    Some(tree)
+  })
+}
+
+/// Attributes each interaction to the chain of definition expansions that created the nodes involved.
+struct Profiler {
+  /// Interned call chains, as the parent chain and the expanded definition.
+  /// Chain 0 is the root, for nodes that don't come from any definition.
+  chains: Vec<(u32, usize)>,
+  chain_ids: HashMap<(u32, usize), u32>,
+  /// Interactions attributed to each chain.
+  itrs: Vec<u64>,
+  /// The chain that created each node.
+  origin: Vec<u32>,
+}
+
+impl Profiler {
+  fn new(nlen: usize) -> Self {
+    Self { chains: vec![(0, usize::MAX)], chain_ids: HashMap::new(), itrs: vec![0], origin: vec![0; nlen] }
+  }
+
+  /// The chain of the nodes of a redex.
+  fn origin(&self, a: Port, b: Port) -> u32 {
+    if b.is_nod() {
+      self.origin[b.get_val() as usize]
+    } else if a.is_nod() {
+      self.origin[a.get_val() as usize]
+    } else {
+      0
+    }
+  }
+
+  /// The definition expanded last in a chain, or `None` for the root chain.
+  fn def(&self, chain: u32) -> Option<usize> {
+    (chain != 0).then(|| self.chains[chain as usize].1)
+  }
+
+  /// The chain of expanding definition `fid` from chain `parent`.
+  /// Recursive calls reuse the chain of the outermost call, so the number of chains stays bounded.
+  fn call(&mut self, parent: u32, fid: usize) -> u32 {
+    let mut chain = parent;
+    while chain != 0 {
+      let (up, def) = self.chains[chain as usize];
+      if def == fid {
+        return chain;
+      }
+      chain = up;
+    }
+    if let Some(chain) = self.chain_ids.get(&(parent, fid)) {
+      return *chain;
+    }
+    let chain = self.chains.len() as u32;
+    self.chains.push((parent, fid));
+    self.itrs.push(0);
+    self.chain_ids.insert((parent, fid), chain);
+    chain
+  }
+
+  /// The interactions of each chain as folded stacks of Bend definitions.
+  ///
+  /// Generated definitions are attributed to the definition they were generated from,
+  /// and recursion is collapsed into the outermost call.
+  fn folded(&self, book: &Book) -> Vec<(String, u64)> {
+    let mut stacks = BTreeMap::new();
+    for (mut chain, itrs) in self.itrs.iter().enumerate() {
+      if *itrs == 0 {
+        continue;
+      }
+      let mut hvm_frames = vec![];
+      while chain != 0 {
+        let (up, def) = self.chains[chain];
+        hvm_frames.push(Name::new(&book.defs[def].name).def_name_from_generated());
+        chain = up as usize;
+      }
+      let mut frames: Vec<Name> = vec![];
+      for frame in hvm_frames.into_iter().rev() {
+        if let Some(pos) = frames.iter().position(|f| *f == frame) {
+          frames.truncate(pos + 1);
+        } else {
+          frames.push(frame);
+        }
+      }
+      let stack = if frames.is_empty() {
+        "[root]".to_string()
+      } else {
+        frames.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(";")
+      };
+      *stacks.entry(stack).or_default() += itrs;
+    }
+    stacks.into_iter().collect()
+  }
+}
+
+/// Writes the rewrites of an evaluation to a file, as selected by [`TraceOpts`].
+///
+/// Each line has the number of the rewrite, the interaction rule, the two interacting ports
+/// and the definition whose expansion created the nodes, or `[root]` if none did.
+struct Tracer {
+  out: BufWriter<File>,
+  path: PathBuf,
+  every: u64,
+  def: Option<String>,
+  /// How many rewrites passed the filter so far.
+  matched: u64,
+  /// The first error writing to the file. Once it fails, nothing else is written.
+  err: Option<std::io::Error>,
+}
+
+impl Tracer {
+  fn new(opts: &TraceOpts) -> Result<Self, EvalError> {
+    let file = File::create(&opts.path)
+      .map_err(|e| EvalError::Trace { path: opts.path.clone(), err: e.to_string() })?;
+    Ok(Self {
+      out: BufWriter::new(file),
+      path: opts.path.clone(),
+      every: opts.every.unwrap_or(1).max(1),
+      def: opts.def.clone(),
+      matched: 0,
+      err: None,
+    })
+  }
+
+  fn rewrite(&mut self, itr: u64, rule: Rule, a: Port, b: Port, origin: Option<usize>, book: &Book) {
+    if self.err.is_some() {
+      return;
+    }
+    let origin = origin.map(|fid| book.defs[fid].name.as_str());
+    if let Some(def) = &self.def {
+      let is_def = |name: &str| name == def || &*Name::new(name).def_name_from_generated() == def;
+      let callee = (rule == CALL).then(|| book.defs[a.get_val() as usize & 0xFFFFFFF].name.as_str());
+      if !origin.is_some_and(is_def) && !callee.is_some_and(is_def) {
+        return;
+      }
+    }
+    self.matched += 1;
+    if self.matched % self.every != 0 {
+      return;
+    }
+
+    let rule = match rule {
+      CALL => "CALL",
+      VOID => "VOID",
+      ERAS => "ERAS",
+      ANNI => "ANNI",
+      COMM => "COMM",
+      OPER => "OPER",
+      SWIT => "SWIT",
+      _ => unreachable!(),
+    };
+    let (a, b) = (show_port(a, book), show_port(b, book));
+    let origin = origin.unwrap_or("[root]");
+    if let Err(e) = writeln!(self.out, "{itr} {rule} {a} ~ {b} {origin}") {
+      self.err = Some(e);
+    }
+  }
+
+  fn finish(mut self) -> Result<(), EvalError> {
+    let res = match self.err.take() {
+      Some(e) => Err(e),
+      None => self.out.flush(),
+    };
+    res.map_err(|e| EvalError::Trace { path: self.path, err: e.to_string() })
+  }
+}
+
+fn show_port(port: Port, book: &Book) -> String {
+  match port.get_tag() {
+    _ if port == ROOT => "ROOT".to_string(),
+    VAR => "VAR".to_string(),
+    REF => format!("@{}", book.defs[port.get_val() as usize & 0xFFFFFFF].name),
+    ERA => "ERA".to_string(),
+    NUM => ast::Numb(port.get_val()).show(),
+    CON => "CON".to_string(),
+    DUP => "DUP".to_string(),
+    OPR => "OPR".to_string(),
+    SWI => "SWI".to_string(),
+    _ => unreachable!(),
+  }
+}
+
+/// Checks that a numeric operation is defined before doing it, since the HVM library panics on the ones that aren't.
+///
+/// `a` is the number and `b` the operation node, as given to `interact_oper`.
+fn check_oper(net: &GNet, a: Port, b: Port) -> Result<(), EvalError> {
+  let b1 = net.node_load(b.get_val() as usize).get_fst();
+  if b1.get_tag() != NUM {
+    return Ok(());
+  }
+  let (x, y) = (Numb(a.get_val()), Numb(b1.get_val()));
+  let (xt, yt) = (x.get_typ(), y.get_typ());
+  // Mirrors the dispatch of `Numb::operate`, where only an operator applied to a typed number is computed.
+  if xt == TY_SYM || yt == TY_SYM || (xt >= OP_ADD) == (yt >= OP_ADD) {
+    return Ok(());
+  }
+  let (op, lhs, typ, rhs) = if xt >= OP_ADD { (xt, x, yt, y) } else { (yt, y, xt, x) };
+
+  let is_zero = |n: &Numb| n.get_u24() == 0;
+  match (typ, op) {
+    (TY_U24 | TY_I24, OP_DIV | OP_REM) if is_zero(&rhs) => Err(EvalError::DivisionByZero),
+    (TY_U24 | TY_I24, FP_DIV | FP_REM) if is_zero(&lhs) => Err(EvalError::DivisionByZero),
+    (TY_I24 | TY_F24, OP_SHL | FP_SHL | OP_SHR | FP_SHR) => Err(EvalError::UndefinedOperation {
+      op: if matches!(op, OP_SHL | FP_SHL) { "<<" } else { ">>" },
+      typ: if typ == TY_I24 { "i24" } else { "f24" },
+    }),
+    _ => Ok(()),
+  }
+}
+
+/// Upper bound of the node and var memory used so far, in bytes.
+///
+/// Slots are handed out sequentially until the end of the buffers,
+/// so the allocation cursors are a high-water mark of the used memory.
+fn used_memory(tm: &TMem, net: &GNet) -> u64 {
+  let nodes = tm.nput.min(net.nlen) * std::mem::size_of::<APair>();
+  let vars = tm.vput.min(net.vlen) * std::mem::size_of::<APort>();
+  (nodes + vars) as u64
+}
+
+/// Number of node slots of the net.
+///
+/// With a memory limit, they only need to fit the limit and what one interaction allocates before it's checked.
+/// HVM places the vars at their index modulo the node slots, so there must be a node slot for each var too.
+fn node_len(book: &Book, max_memory: Option<u64>) -> usize {
+  let Some(limit) = max_memory else {
+    return DEFAULT_NET_SIZE;
+  };
+  let per_itr = book.defs.iter().map(|def| def.node.len().max(def.vars)).max().unwrap_or(0) + 4;
+  let slots = limit / std::mem::size_of::<APort>() as u64 + per_itr as u64;
+  slots.min(DEFAULT_NET_SIZE as u64) as usize
+}
+
+/// The definitions that were expanded, with the most expanded first.
+fn def_stats(book: &Book, calls: &[u64]) -> Vec<DefStats> {
+  let mut defs = book
+    .defs
+    .iter()
+    .zip(calls)
+    .filter(|(_, calls)| **calls != 0)
+    .map(|(def, calls)| DefStats {
+      name: def.name.clone(),
+      calls: *calls,
+      nodes: calls * def.node.len() as u64,
+    })
+    .collect::<Vec<_>>();
+  defs.sort_by(|a, b| b.calls.cmp(&a.calls).then_with(|| a.name.cmp(&b.name)));
+  defs
+}
+
+/// The definitions with the most expansions, in descending order.
+fn hot_defs(book: &Book, calls: &[u64]) -> Vec<(String, u64)> {
+  def_stats(book, calls).into_iter().take(HOT_DEFS_SHOWN).map(|def| (def.name, def.calls)).collect()
+}
+
+impl Display for EvalError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn show_hot_defs(f: &mut std::fmt::Formatter<'_>, hot_defs: &[(String, u64)]) -> std::fmt::Result {
+      if !hot_defs.is_empty() {
+        write!(f, "\nMost expanded definitions:")?;
+        for (name, n) in hot_defs {
+          write!(f, "\n{:ERR_INDENT_SIZE$}{name}: {n}", "")?;
+        }
+      }
+      Ok(())
+    }
+    match self {
+      EvalError::MissingMain => write!(f, "Missing 'main' definition in the HVM book."),
+      EvalError::DivisionByZero => write!(f, "Division by zero."),
+      EvalError::UndefinedOperation { op, typ } => {
+        write!(f, "The '{op}' operation is not defined for {typ} numbers.")
+      }
+      EvalError::UnsafeDup { def } => {
+        write!(f, "Attempt to clone a non-affine global reference to '{def}'.")
+      }
+      EvalError::MaxRewrites { limit, hot_defs } => {
+        write!(f, "Stopped evaluation after exceeding the maximum of {limit} rewrites.")?;
+        show_hot_defs(f, hot_defs)
+      }
+      EvalError::MaxMemory { limit, hot_defs } => {
+        write!(f, "Stopped evaluation after exceeding the maximum of {limit} bytes of memory.")?;
+        show_hot_defs(f, hot_defs)
+      }
+      EvalError::OutOfMemory => write!(f, "Out of memory while evaluating the program."),
+      EvalError::AllocFailed { bytes } => {
+        write!(f, "Failed to allocate {bytes} bytes of memory for the HVM net.")
+      }
+      EvalError::Readback => write!(f, "Failed to read back the result of the evaluation."),
+      EvalError::Interrupted => write!(f, "Stopped evaluation from the debugger."),
+      EvalError::Trace { path, err } => {
+        write!(f, "Couldn't write the evaluation trace to '{}'. {err}", path.display())
+      }
+    }
+  }
+}
+
+impl Display for EvalStats {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let secs = self.time.as_secs_f64();
+    writeln!(f, "- ITRS: {}", self.itrs)?;
+    writeln!(f, "- TIME: {:.2}s", secs)?;
+    writeln!(f, "- MIPS: {:.2}", self.itrs as f64 / secs / 1_000_000.0)
+  }
+}
+
+/// Creates a net with zeroed memory.
+///
+/// `GNet::new` leaves its buffers uninitialized, which breaks the free slot checks
+/// unless the allocator happens to return fresh pages.
+/// Since `GNet` frees both buffers on drop with the layout of an `APair`, we allocate
+/// them with that same layout.
+fn new_gnet<'a>(nlen: usize, vlen: usize) -> Result<GNet<'a>, EvalError> {
+  let layout = |len: usize| {
+    let bytes = len.saturating_mul(std::mem::size_of::<APair>());
+    Layout::array::<APair>(len).map_err(|_| EvalError::AllocFailed { bytes })
+  };
+  let nlay = layout(nlen)?;
+  let vlay = layout(vlen)?;
+  // SAFETY: Both layouts have a non-zero size, all-zeroes is a valid value for the atomics
+  // and an `APair` buffer has room for as many `APort`s.
+  // Each buffer is freed with the layout it was allocated with if the other allocation fails.
+  unsafe {
+    let nptr = alloc_zeroed(nlay) as *mut APair;
+    if nptr.is_null() {
+      return Err(EvalError::AllocFailed { bytes: nlay.size() });
+    }
+    let vptr = alloc_zeroed(vlay) as *mut APort;
+    if vptr.is_null() {
+      dealloc(nptr as *mut u8, nlay);
+      return Err(EvalError::AllocFailed { bytes: vlay.size() });
+    }
+    let node = std::slice::from_raw_parts_mut(nptr, nlen);
+    let vars = std::slice::from_raw_parts_mut(vptr, vlen);
+    Ok(GNet { nlen, vlen, node, vars, itrs: AtomicU64::new(0) })
+  }
+}
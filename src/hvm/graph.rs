@@ -0,0 +1,120 @@
+use crate::maybe_grow;
+use hvm::ast::{Net, Tree};
+use std::{collections::HashMap, fmt::Write};
+
+/// Shows a compiled net as a Graphviz DOT graph.
+///
+/// Each node is drawn with its principal port `0` on top and its auxiliary ports `1` and `2` below.
+/// Wires between two nodes are drawn as edges, labeled with the name of the variable that forms
+/// them, if any. Redexes are drawn in red, connecting the principal ports of the two nodes.
+/// The free port of the net, its root, is drawn as a point labeled with the name of the definition.
+pub fn net_to_dot(name: &str, net: &Net) -> String {
+  let mut graph = Graph::default();
+  writeln!(graph.out, "digraph \"{}\" {{", escape(name)).unwrap();
+  writeln!(graph.out, "  node [shape=record];").unwrap();
+  writeln!(graph.out, "  edge [dir=none];").unwrap();
+  writeln!(graph.out, "  root [shape=point, xlabel=\"@{}\"];", escape(name)).unwrap();
+
+  graph.link(&net.root, "root".to_string(), None);
+
+  for (pri, fst, snd) in net.rbag.iter() {
+    let label = if *pri { "!~" } else { "~" };
+    let style = Some(format!("color=red, label=\"{label}\""));
+    match graph.node(fst) {
+      Some(port) => graph.link(snd, port, style),
+      None => match graph.node(snd) {
+        Some(port) => graph.link(fst, port, style),
+        // A redex between two variables just connects the other ends of their wires.
+        None => {
+          let id = graph.new_id();
+          writeln!(graph.out, "  n{id} [shape=point];").unwrap();
+          graph.link(fst, format!("n{id}"), style.clone());
+          graph.link(snd, format!("n{id}"), style);
+        }
+      },
+    }
+  }
+
+  graph.out.push_str("}\n");
+  graph.out
+}
+
+#[derive(Default)]
+struct Graph {
+  out: String,
+  next_id: usize,
+  /// The ports of the variables seen only once so far, waiting for their other occurrence.
+  vars: HashMap<String, (String, Option<String>)>,
+}
+
+impl Graph {
+  fn new_id(&mut self) -> usize {
+    let id = self.next_id;
+    self.next_id += 1;
+    id
+  }
+
+  /// Connects the principal port of `tree` to the port `up`.
+  fn link(&mut self, tree: &Tree, up: String, style: Option<String>) {
+    if let Tree::Var { nam } = tree {
+      match self.vars.remove(nam) {
+        Some((other, other_style)) => {
+          let style = style.or(other_style).unwrap_or_default();
+          let sep = if style.is_empty() { "" } else { ", " };
+          writeln!(self.out, "  {other} -> {up} [label=\"{}\"{sep}{style}];", escape(nam)).unwrap();
+        }
+        None => {
+          self.vars.insert(nam.clone(), (up, style));
+        }
+      }
+    } else {
+      let port = self.node(tree).unwrap();
+      let style = style.map(|style| format!(" [{style}]")).unwrap_or_default();
+      writeln!(self.out, "  {up} -> {port}{style};").unwrap();
+    }
+  }
+
+  /// Adds the node at the top of `tree` and its children, returning its principal port.
+  /// Variables are not nodes, so they return `None`.
+  fn node(&mut self, tree: &Tree) -> Option<String> {
+    maybe_grow(|| {
+      let (label, children) = match tree {
+        Tree::Var { .. } => return None,
+        Tree::Era => ("ERA".to_string(), None),
+        Tree::Ref { nam } => (format!("@{nam}"), None),
+        Tree::Num { val } => (val.show(), None),
+        Tree::Con { fst, snd } => ("CON".to_string(), Some((fst, snd))),
+        Tree::Dup { fst, snd } => ("DUP".to_string(), Some((fst, snd))),
+        Tree::Opr { fst, snd } => ("OPR".to_string(), Some((fst, snd))),
+        Tree::Swi { fst, snd } => ("SWI".to_string(), Some((fst, snd))),
+      };
+      let id = self.new_id();
+      let label = escape_record(&label);
+      match children {
+        None => writeln!(self.out, "  n{id} [label=\"<p0> {label}\"];").unwrap(),
+        Some((fst, snd)) => {
+          writeln!(self.out, "  n{id} [label=\"{{<p0> {label}|{{<p1> 1|<p2> 2}}}}\"];").unwrap();
+          self.link(fst, format!("n{id}:p1"), None);
+          self.link(snd, format!("n{id}:p2"), None);
+        }
+      }
+      Some(format!("n{id}:p0"))
+    })
+  }
+}
+
+fn escape(s: &str) -> String {
+  s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes the characters that have a special meaning inside the label of a record node.
+fn escape_record(s: &str) -> String {
+  let mut out = String::new();
+  for c in escape(s).chars() {
+    if matches!(c, '{' | '}' | '|' | '<' | '>') {
+      out.push('\\');
+    }
+    out.push(c);
+  }
+  out
+}
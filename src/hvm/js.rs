@@ -0,0 +1,136 @@
+use crate::fun::{ExportSig, ExportType, Name};
+use hvm::ast;
+use std::fmt::Write;
+
+/// Compiles an HVM book to a JavaScript module that evaluates it with a sequential port of the
+/// interaction rules of the C runtime, so that it can run in browsers and Node without a WASM toolchain.
+///
+/// The module exports `run()`, which evaluates `main` and returns the result in HVM syntax, and a
+/// function for each exported definition that converts numbers, strings and arrays from and to
+/// λ-encoded values. IO is not supported.
+pub fn book_to_js(book: &ast::Book, exports: &[(Name, ExportSig)]) -> String {
+  let book = book.build();
+
+  let mut js = String::from("// Generated by `bend gen-js`.\n\n");
+  // Each definition is [name, safe, root, rbag, nodes, vars], with the pairs of the rbag and
+  // the nodes flattened to their ports.
+  js.push_str("const BOOK = [\n");
+  for def in book.defs.iter() {
+    writeln!(
+      js,
+      "  [{:?}, {}, {}, [{}], [{}], {}],",
+      def.name,
+      def.safe,
+      def.root.0,
+      show_pairs(&def.rbag),
+      show_pairs(&def.node),
+      def.vars
+    )
+    .unwrap();
+  }
+  js.push_str("];\n\n");
+
+  js.push_str(include_str!("js_runtime.js"));
+  // The functions are exported under other names, so they can't clash with the ones of the runtime.
+  let mut names = vec![];
+  for (i, (name, sig)) in exports.iter().enumerate() {
+    let params = (0..sig.args.len()).map(|i| format!("arg{i}")).collect::<Vec<_>>().join(", ");
+    let types = sig.args.iter().map(|typ| js_conv("injectors", typ)).collect::<Vec<_>>().join(", ");
+    writeln!(js, "\n/** {name}{sig} */").unwrap();
+    writeln!(js, "function export{i}({params}) {{").unwrap();
+    writeln!(
+      js,
+      "  return callExport({:?}, [{params}], [{types}], {});",
+      name.as_ref(),
+      js_conv("readers", &sig.ret)
+    )
+    .unwrap();
+    writeln!(js, "}}").unwrap();
+    names.push(format!("export{i} as {}", js_name(name)));
+  }
+  if !names.is_empty() {
+    writeln!(js, "\nexport {{ {} }};", names.join(", ")).unwrap();
+  }
+  js
+}
+
+fn show_pairs(pairs: &[hvm::hvm::Pair]) -> String {
+  pairs
+    .iter()
+    .flat_map(|pair| [pair.get_fst().0, pair.get_snd().0])
+    .map(|port| port.to_string())
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// The JS function of an exported definition, like `List_sum` for `List/sum`.
+/// Names that aren't valid in JS, or that the runtime already exports, get a `_` suffix.
+fn js_name(name: &Name) -> String {
+  const KEYWORDS: &[&str] = &[
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+    "run",
+    "BendError",
+  ];
+  let name = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect::<String>();
+  if KEYWORDS.contains(&name.as_str()) || name.starts_with(|c: char| c.is_ascii_digit()) {
+    format!("{name}_")
+  } else {
+    name
+  }
+}
+
+/// The converter of a type in `injectors` or `readers` of the runtime, like `readers.list(readers.u24)`.
+fn js_conv(convs: &str, typ: &ExportType) -> String {
+  match typ {
+    ExportType::U24 => format!("{convs}.u24"),
+    ExportType::I24 => format!("{convs}.i24"),
+    ExportType::F24 => format!("{convs}.f24"),
+    ExportType::String => format!("{convs}.string"),
+    ExportType::List(item) => format!("{convs}.list({})", js_conv(convs, item)),
+  }
+}
@@ -0,0 +1,585 @@
+// A sequential port of the interaction rules of HVM's C runtime.
+//
+// Ports are numbers with a 3-bit tag and a 29-bit value. Nodes are pairs of ports, stored in
+// `Net.node`, and variables hold the port they were linked to, or NONE, in `Net.vars`.
+
+const VAR = 0, REF = 1, ERA = 2, NUM = 3, CON = 4, DUP = 5, OPR = 6, SWI = 7;
+const LINK = 0, CALL = 1, VOID = 2, ERAS = 3, ANNI = 4, COMM = 5, OPER = 6, SWIT = 7;
+
+const TY_SYM = 0x00, TY_U24 = 0x01, TY_I24 = 0x02, TY_F24 = 0x03;
+const OP_ADD = 0x04, OP_SUB = 0x05, FP_SUB = 0x06, OP_MUL = 0x07, OP_DIV = 0x08, FP_DIV = 0x09;
+const OP_REM = 0x0A, FP_REM = 0x0B, OP_EQ = 0x0C, OP_NEQ = 0x0D, OP_LT = 0x0E, OP_GT = 0x0F;
+const OP_AND = 0x10, OP_OR = 0x11, OP_XOR = 0x12, OP_SHL = 0x13, FP_SHL = 0x14, OP_SHR = 0x15;
+const FP_SHR = 0x16;
+
+const NONE = 0xFFFFFFFF;
+// The variable that the result is linked to. Its slot is never freed.
+const ROOT = 0;
+
+const LIST_NIL = 0, LIST_CONS = 1;
+
+const RULES = [
+  //VAR  REF   ERA   NUM   CON   DUP   OPR   SWI
+  [LINK, LINK, LINK, LINK, LINK, LINK, LINK, LINK], // VAR
+  [LINK, VOID, VOID, VOID, CALL, CALL, CALL, CALL], // REF
+  [LINK, VOID, VOID, VOID, ERAS, ERAS, ERAS, ERAS], // ERA
+  [LINK, VOID, VOID, VOID, ERAS, ERAS, OPER, SWIT], // NUM
+  [LINK, CALL, ERAS, ERAS, ANNI, COMM, COMM, COMM], // CON
+  [LINK, CALL, ERAS, ERAS, COMM, ANNI, COMM, COMM], // DUP
+  [LINK, CALL, ERAS, OPER, COMM, COMM, ANNI, COMM], // OPR
+  [LINK, CALL, ERAS, SWIT, COMM, COMM, COMM, ANNI], // SWI
+];
+
+/** Raised when the evaluation fails, or when a result doesn't have the exported type. */
+export class BendError extends Error {}
+
+// Ports
+// -----
+
+const newPort = (tag, val) => ((val << 3) | tag) >>> 0;
+const getTag = (port) => port & 7;
+const getVal = (port) => port >>> 3;
+
+// Numbers
+// -------
+
+const F32 = new Float32Array(1);
+const U32 = new Uint32Array(F32.buffer);
+
+const getSym = (numb) => numb >>> 5;
+const newU24 = (val) => (((val & 0xFFFFFF) << 5) | TY_U24) >>> 0;
+const getU24 = (numb) => numb >>> 5;
+const newI24 = (val) => (((val & 0xFFFFFF) << 5) | TY_I24) >>> 0;
+const getI24 = (numb) => (numb << 3) >> 8;
+const getTyp = (numb) => numb & 0x1F;
+
+// f24 numbers are the upper 24 bits of an f32, rounded like in the C runtime.
+function newF24(val) {
+  F32[0] = val;
+  const bits = U32[0];
+  let shifted = bits >>> 8;
+  const lost = bits & 0xFF;
+  if (Number.isNaN(val)) {
+    // Ensures NaNs don't become infinities.
+    shifted |= 1;
+  } else {
+    shifted += (lost - ((lost >> 7) & (shifted === 0 ? 1 : 0))) >> 7;
+  }
+  return (((shifted & 0xFFFFFF) << 5) | TY_F24) >>> 0;
+}
+
+function getF24(numb) {
+  U32[0] = (numb << 3) & 0xFFFFFF00;
+  return F32[0];
+}
+
+const isNum = (numb) => getTyp(numb) >= TY_U24 && getTyp(numb) <= TY_F24;
+const isCast = (numb) => getTyp(numb) === TY_SYM && getSym(numb) >= TY_U24 && getSym(numb) <= TY_F24;
+
+const clamp = (val, min, max) => Math.min(Math.max(val, min), max);
+
+function cast(a, b) {
+  const to = getSym(a);
+  const from = getTyp(b);
+  if (to === from) return b;
+  if (to === TY_U24 && from === TY_I24) return newU24(getI24(b));
+  if (to === TY_U24 && from === TY_F24) {
+    const val = getF24(b);
+    return newU24(Number.isNaN(val) ? 0 : Math.trunc(clamp(val, 0, 0xFFFFFF)));
+  }
+  if (to === TY_I24 && from === TY_U24) return newI24(getU24(b));
+  if (to === TY_I24 && from === TY_F24) {
+    const val = getF24(b);
+    return newI24(Number.isNaN(val) ? 0 : Math.trunc(clamp(val, -0x800000, 0x7FFFFF)));
+  }
+  if (to === TY_F24 && from === TY_U24) return newF24(getU24(b));
+  if (to === TY_F24 && from === TY_I24) return newF24(getI24(b));
+  return newU24(0);
+}
+
+// Applies a number to another, where one of them carries the operation in its type.
+function operate(a, b) {
+  const at = getTyp(a);
+  const bt = getTyp(b);
+  if (at === TY_SYM && bt === TY_SYM) return newU24(0);
+  if (isCast(a) && isNum(b)) return cast(a, b);
+  if (isCast(b) && isNum(a)) return cast(b, a);
+  if (at === TY_SYM) return ((b & ~0x1F) | getSym(a)) >>> 0;
+  if (bt === TY_SYM) return ((a & ~0x1F) | getSym(b)) >>> 0;
+  if ((at >= OP_ADD) === (bt >= OP_ADD)) return newU24(0);
+  const [op, ty, x, y] = at >= OP_ADD ? [at, bt, a, b] : [bt, at, b, a];
+  switch (ty) {
+    case TY_U24: {
+      const av = getU24(x);
+      const bv = getU24(y);
+      if ((op === OP_DIV || op === OP_REM) && bv === 0) throw new BendError("Division by zero.");
+      if ((op === FP_DIV || op === FP_REM) && av === 0) throw new BendError("Division by zero.");
+      switch (op) {
+        case OP_ADD: return newU24(av + bv);
+        case OP_SUB: return newU24(av - bv);
+        case FP_SUB: return newU24(bv - av);
+        case OP_MUL: return newU24(Math.imul(av, bv));
+        case OP_DIV: return newU24(Math.floor(av / bv));
+        case FP_DIV: return newU24(Math.floor(bv / av));
+        case OP_REM: return newU24(av % bv);
+        case FP_REM: return newU24(bv % av);
+        case OP_EQ: return newU24(av === bv ? 1 : 0);
+        case OP_NEQ: return newU24(av !== bv ? 1 : 0);
+        case OP_LT: return newU24(av < bv ? 1 : 0);
+        case OP_GT: return newU24(av > bv ? 1 : 0);
+        case OP_AND: return newU24(av & bv);
+        case OP_OR: return newU24(av | bv);
+        case OP_XOR: return newU24(av ^ bv);
+        case OP_SHL: return newU24(av << (bv & 31));
+        case FP_SHL: return newU24(bv << (av & 31));
+        case OP_SHR: return newU24(av >>> (bv & 31));
+        case FP_SHR: return newU24(bv >>> (av & 31));
+        default: return newU24(0);
+      }
+    }
+    case TY_I24: {
+      const av = getI24(x);
+      const bv = getI24(y);
+      if ((op === OP_DIV || op === OP_REM) && bv === 0) throw new BendError("Division by zero.");
+      if ((op === FP_DIV || op === FP_REM) && av === 0) throw new BendError("Division by zero.");
+      switch (op) {
+        case OP_ADD: return newI24(av + bv);
+        case OP_SUB: return newI24(av - bv);
+        case FP_SUB: return newI24(bv - av);
+        case OP_MUL: return newI24(Math.imul(av, bv));
+        case OP_DIV: return newI24(Math.trunc(av / bv));
+        case FP_DIV: return newI24(Math.trunc(bv / av));
+        case OP_REM: return newI24(av % bv);
+        case FP_REM: return newI24(bv % av);
+        case OP_EQ: return newU24(av === bv ? 1 : 0);
+        case OP_NEQ: return newU24(av !== bv ? 1 : 0);
+        case OP_LT: return newU24(av < bv ? 1 : 0);
+        case OP_GT: return newU24(av > bv ? 1 : 0);
+        case OP_AND: return newI24(av & bv);
+        case OP_OR: return newI24(av | bv);
+        case OP_XOR: return newI24(av ^ bv);
+        case OP_SHL: case FP_SHL: case OP_SHR: case FP_SHR:
+          throw new BendError(`The '${op <= FP_SHL ? "<<" : ">>"}' operation is not defined for i24 numbers.`);
+        default: return newI24(0);
+      }
+    }
+    case TY_F24: {
+      const av = getF24(x);
+      const bv = getF24(y);
+      switch (op) {
+        case OP_ADD: return newF24(Math.fround(av + bv));
+        case OP_SUB: return newF24(Math.fround(av - bv));
+        case FP_SUB: return newF24(Math.fround(bv - av));
+        case OP_MUL: return newF24(Math.fround(av * bv));
+        case OP_DIV: return newF24(Math.fround(av / bv));
+        case FP_DIV: return newF24(Math.fround(bv / av));
+        case OP_REM: return newF24(Math.fround(av % bv));
+        case FP_REM: return newF24(Math.fround(bv % av));
+        case OP_EQ: return newU24(av === bv ? 1 : 0);
+        case OP_NEQ: return newU24(av !== bv ? 1 : 0);
+        case OP_LT: return newU24(av < bv ? 1 : 0);
+        case OP_GT: return newU24(av > bv ? 1 : 0);
+        case OP_AND: return newF24(Math.fround(Math.atan2(av, bv)));
+        case OP_OR: return newF24(Math.fround(Math.log(bv) / Math.log(av)));
+        case OP_XOR: return newF24(Math.fround(Math.pow(av, bv)));
+        case OP_SHL: case FP_SHL: case OP_SHR: case FP_SHR:
+          throw new BendError(`The '${op <= FP_SHL ? "<<" : ">>"}' operation is not defined for f24 numbers.`);
+        default: return newF24(0);
+      }
+    }
+    default: return newU24(0);
+  }
+}
+
+// Net
+// ---
+
+class Net {
+  constructor(maxRewrites) {
+    this.node = [];
+    this.vars = [NONE];
+    this.freeNodes = [];
+    this.freeVars = [];
+    // LINK, VOID, ERAS and ANNI redexes are reduced first, like in the C runtime.
+    this.high = [];
+    this.low = [];
+    this.rewrites = 0;
+    this.maxRewrites = maxRewrites;
+  }
+
+  nodeCreate(fst, snd) {
+    const loc = this.freeNodes.length > 0 ? this.freeNodes.pop() : this.node.length >> 1;
+    this.node[loc * 2] = fst;
+    this.node[loc * 2 + 1] = snd;
+    return loc;
+  }
+
+  nodeTake(loc) {
+    this.freeNodes.push(loc);
+    return [this.node[loc * 2], this.node[loc * 2 + 1]];
+  }
+
+  varsCreate() {
+    const loc = this.freeVars.length > 0 ? this.freeVars.pop() : this.vars.length;
+    this.vars[loc] = NONE;
+    return loc;
+  }
+
+  varsTake(loc) {
+    if (loc !== ROOT) this.freeVars.push(loc);
+  }
+
+  pushRedex(a, b) {
+    const rule = RULES[getTag(a)][getTag(b)];
+    const bag = rule === LINK || rule === VOID || rule === ERAS || rule === ANNI ? this.high : this.low;
+    bag.push(a, b);
+  }
+
+  // Follows the variables that were linked to a port, without consuming them.
+  peek(port) {
+    while (getTag(port) === VAR) {
+      const got = this.vars[getVal(port)];
+      if (got === NONE) break;
+      port = got;
+    }
+    return port;
+  }
+
+  // Follows the variables that were linked to a port, freeing them.
+  enter(port) {
+    while (getTag(port) === VAR) {
+      const got = this.vars[getVal(port)];
+      if (got === NONE) break;
+      this.vars[getVal(port)] = NONE;
+      this.varsTake(getVal(port));
+      port = got;
+    }
+    return port;
+  }
+
+  link(a, b) {
+    while (true) {
+      if (getTag(a) !== VAR && getTag(b) === VAR) [a, b] = [b, a];
+      if (getTag(a) !== VAR) {
+        this.pushRedex(a, b);
+        return;
+      }
+      b = this.enter(b);
+      const got = this.vars[getVal(a)];
+      this.vars[getVal(a)] = b;
+      if (got === NONE) return;
+      this.vars[getVal(a)] = NONE;
+      this.varsTake(getVal(a));
+      a = got;
+    }
+  }
+
+  // Reduces the redexes until there are none left.
+  normalize() {
+    while (this.high.length > 0 || this.low.length > 0) {
+      const bag = this.high.length > 0 ? this.high : this.low;
+      let b = bag.pop();
+      let a = bag.pop();
+      let rule = RULES[getTag(a)][getTag(b)];
+      if (getTag(a) === REF && b === newPort(VAR, ROOT)) {
+        rule = CALL;
+      } else if (getTag(b) < getTag(a)) {
+        [a, b] = [b, a];
+      }
+      if (rule !== LINK) {
+        this.rewrites += 1;
+        if (this.maxRewrites !== undefined && this.rewrites > this.maxRewrites) {
+          throw new BendError(`Stopped evaluation after exceeding the maximum of ${this.maxRewrites} rewrites.`);
+        }
+      }
+      switch (rule) {
+        case LINK: this.link(a, b); break;
+        case CALL: this.call(a, b); break;
+        case VOID: break;
+        case ERAS: this.eras(a, b); break;
+        case ANNI: this.anni(a, b); break;
+        case COMM: this.comm(a, b); break;
+        case OPER: this.oper(a, b); break;
+        case SWIT: this.swit(a, b); break;
+      }
+    }
+  }
+
+  // Expands a reference, copying the net of its definition.
+  call(a, b) {
+    const [, safe, root, rbag, node, vars] = BOOK[getVal(a) & 0xFFFFFFF];
+    // Copy optimization: a definition without duplications can be shared instead of copied.
+    if (safe && getTag(b) === DUP) {
+      this.eras(a, b);
+      return;
+    }
+    const vloc = [];
+    for (let i = 0; i < vars; ++i) vloc.push(this.varsCreate());
+    const nloc = [];
+    for (let i = 0; i < node.length; i += 2) nloc.push(this.nodeCreate(0, 0));
+    const adjust = (port) => {
+      const tag = getTag(port);
+      if (tag >= CON) return newPort(tag, nloc[getVal(port)]);
+      if (tag === VAR) return newPort(tag, vloc[getVal(port)]);
+      return port;
+    };
+    for (let i = 0; i < node.length; i += 2) {
+      this.node[nloc[i >> 1] * 2] = adjust(node[i]);
+      this.node[nloc[i >> 1] * 2 + 1] = adjust(node[i + 1]);
+    }
+    for (let i = 0; i < rbag.length; i += 2) {
+      this.link(adjust(rbag[i]), adjust(rbag[i + 1]));
+    }
+    this.link(adjust(root), b);
+  }
+
+  eras(a, b) {
+    const [b1, b2] = this.nodeTake(getVal(b));
+    this.link(a, b1);
+    this.link(a, b2);
+  }
+
+  anni(a, b) {
+    const [a1, a2] = this.nodeTake(getVal(a));
+    const [b1, b2] = this.nodeTake(getVal(b));
+    this.link(a1, b1);
+    this.link(a2, b2);
+  }
+
+  comm(a, b) {
+    const [a1, a2] = this.nodeTake(getVal(a));
+    const [b1, b2] = this.nodeTake(getVal(b));
+    const v = [0, 1, 2, 3].map(() => newPort(VAR, this.varsCreate()));
+    this.link(newPort(getTag(b), this.nodeCreate(v[0], v[1])), a1);
+    this.link(newPort(getTag(b), this.nodeCreate(v[2], v[3])), a2);
+    this.link(newPort(getTag(a), this.nodeCreate(v[0], v[2])), b1);
+    this.link(newPort(getTag(a), this.nodeCreate(v[1], v[3])), b2);
+  }
+
+  oper(a, b) {
+    const [b1, snd] = this.nodeTake(getVal(b));
+    const b2 = this.enter(snd);
+    if (getTag(b1) === NUM) {
+      this.link(newPort(NUM, operate(getVal(a), getVal(b1))), b2);
+    } else {
+      this.link(b1, newPort(OPR, this.nodeCreate(a, b2)));
+    }
+  }
+
+  swit(a, b) {
+    const av = getU24(getVal(a));
+    const [b1, b2] = this.nodeTake(getVal(b));
+    if (av === 0) {
+      this.link(newPort(CON, this.nodeCreate(b2, newPort(ERA, 0))), b1);
+    } else {
+      const succ = this.nodeCreate(newPort(NUM, newU24(av - 1)), b2);
+      this.link(newPort(CON, this.nodeCreate(newPort(ERA, 0), newPort(CON, succ))), b1);
+    }
+  }
+
+  // Reduces a reference until its value isn't a reference anymore.
+  expand(port) {
+    const old = this.vars[ROOT];
+    let got = this.peek(port);
+    while (getTag(got) === REF) {
+      this.vars[ROOT] = NONE;
+      this.pushRedex(got, newPort(VAR, ROOT));
+      this.normalize();
+      got = this.peek(this.vars[ROOT]);
+    }
+    this.vars[ROOT] = old;
+    return got;
+  }
+}
+
+// Readback
+// --------
+
+const SYMS = {
+  [TY_U24]: "u24", [TY_I24]: "i24", [TY_F24]: "f24",
+  [OP_ADD]: "+", [OP_SUB]: "-", [FP_SUB]: ":-", [OP_MUL]: "*", [OP_DIV]: "/", [FP_DIV]: ":/",
+  [OP_REM]: "%", [FP_REM]: ":%", [OP_EQ]: "=", [OP_NEQ]: "!", [OP_LT]: "<", [OP_GT]: ">",
+  [OP_AND]: "&", [OP_OR]: "|", [OP_XOR]: "^", [OP_SHL]: "<<", [FP_SHL]: ":<<", [OP_SHR]: ">>",
+  [FP_SHR]: ":>>",
+};
+
+function showNumb(numb) {
+  switch (getTyp(numb)) {
+    case TY_SYM: return `[${SYMS[getSym(numb)] ?? "?"}]`;
+    case TY_U24: return `${getU24(numb)}`;
+    case TY_I24: return getI24(numb) < 0 ? `${getI24(numb)}` : `+${getI24(numb)}`;
+    case TY_F24: {
+      const val = getF24(numb);
+      if (Number.isNaN(val)) return "+NaN";
+      if (!Number.isFinite(val)) return val < 0 ? "-inf" : "+inf";
+      // Like C's `%.7e`, with at least two digits in the exponent.
+      return val.toExponential(7).replace(/e([+-])(\d)$/, "e$10$2");
+    }
+    default: {
+      const hex = getU24(numb).toString(16).toUpperCase().padStart(7, "0");
+      return `[${SYMS[getTyp(numb)] ?? "?"}0x${hex}]`;
+    }
+  }
+}
+
+// Shows the tree connected to a port in HVM syntax.
+function showPort(net, port) {
+  let out = "";
+  const stack = [port];
+  while (stack.length > 0) {
+    const cur = stack.pop();
+    if (typeof cur === "string") {
+      out += cur;
+      continue;
+    }
+    switch (getTag(cur)) {
+      case VAR: {
+        const got = net.vars[getVal(cur)];
+        if (got !== NONE) {
+          stack.push(got);
+        } else {
+          out += `x${getVal(cur).toString(16)}`;
+        }
+        break;
+      }
+      case REF: out += `@${BOOK[getVal(cur) & 0xFFFFFFF][0]}`; break;
+      case ERA: out += "*"; break;
+      case NUM: out += showNumb(getVal(cur)); break;
+      default: {
+        const open = { [CON]: "(", [DUP]: "{", [OPR]: "$(", [SWI]: "?(" }[getTag(cur)];
+        const close = getTag(cur) === DUP ? "}" : ")";
+        const loc = getVal(cur);
+        out += open;
+        stack.push(close, net.node[loc * 2 + 1], " ", net.node[loc * 2]);
+      }
+    }
+  }
+  return out;
+}
+
+// Reads back a λ-encoded constructor, λt (t TAG arg0 arg1 ..), returning its tag and arguments.
+// Returns undefined if the port isn't a constructor.
+function readCtr(net, port) {
+  const lam = net.expand(port);
+  if (getTag(lam) !== CON) return undefined;
+  let app = net.expand(net.node[getVal(lam) * 2]);
+  if (getTag(app) !== CON) return undefined;
+  const tag = net.expand(net.node[getVal(app) * 2]);
+  if (getTag(tag) !== NUM) return undefined;
+  const args = [];
+  while (true) {
+    app = net.expand(net.node[getVal(app) * 2 + 1]);
+    if (getTag(app) !== CON) break;
+    args.push(net.expand(net.node[getVal(app) * 2]));
+  }
+  return { tag: getU24(getVal(tag)), args };
+}
+
+function readNumb(net, port, typ) {
+  const got = net.expand(port);
+  if (getTag(got) !== NUM || getTyp(getVal(got)) !== typ) return undefined;
+  return getVal(got);
+}
+
+const readers = {
+  u24(net, port) {
+    const numb = readNumb(net, port, TY_U24);
+    return numb === undefined ? undefined : getU24(numb);
+  },
+  i24(net, port) {
+    const numb = readNumb(net, port, TY_I24);
+    return numb === undefined ? undefined : getI24(numb);
+  },
+  f24(net, port) {
+    const numb = readNumb(net, port, TY_F24);
+    return numb === undefined ? undefined : getF24(numb);
+  },
+  string(net, port) {
+    const chars = readers.list(readers.u24)(net, port);
+    return chars === undefined ? undefined : String.fromCodePoint(...chars);
+  },
+  list(item) {
+    return (net, port) => {
+      const items = [];
+      while (true) {
+        const ctr = readCtr(net, port);
+        if (ctr?.tag === LIST_NIL && ctr.args.length === 0) return items;
+        if (ctr?.tag !== LIST_CONS || ctr.args.length !== 2) return undefined;
+        const head = item(net, ctr.args[0]);
+        if (head === undefined) return undefined;
+        items.push(head);
+        port = ctr.args[1];
+      }
+    };
+  },
+};
+
+// Injection
+// ---------
+
+// Creates a λ-encoded constructor: λt (t TAG arg0 arg1 ..).
+function injectCtr(net, tag, args) {
+  const t = newPort(VAR, net.varsCreate());
+  let app = t;
+  for (let i = args.length - 1; i >= 0; --i) {
+    app = newPort(CON, net.nodeCreate(args[i], app));
+  }
+  app = newPort(CON, net.nodeCreate(newPort(NUM, newU24(tag)), app));
+  return newPort(CON, net.nodeCreate(app, t));
+}
+
+const injectors = {
+  u24: (net, val) => newPort(NUM, newU24(val)),
+  i24: (net, val) => newPort(NUM, newI24(val)),
+  f24: (net, val) => newPort(NUM, newF24(val)),
+  string: (net, val) => injectors.list(injectors.u24)(net, Array.from(val, (c) => c.codePointAt(0))),
+  list(item) {
+    return (net, val) => {
+      let port = injectCtr(net, LIST_NIL, []);
+      for (let i = val.length - 1; i >= 0; --i) {
+        port = injectCtr(net, LIST_CONS, [item(net, val[i]), port]);
+      }
+      return port;
+    };
+  },
+};
+
+// Evaluation
+// ----------
+
+// Reduces the definition `name` applied to `args`, connecting the result to ROOT.
+function call(net, name, args) {
+  const fid = BOOK.findIndex((def) => def[0] === name);
+  // The application is the tree (arg0 (arg1 .. ROOT)).
+  let app = newPort(VAR, ROOT);
+  for (let i = args.length - 1; i >= 0; --i) {
+    app = newPort(CON, net.nodeCreate(args[i], app));
+  }
+  net.pushRedex(newPort(REF, fid), app);
+  net.normalize();
+}
+
+/**
+ * Runs the program, returning its result in HVM syntax and how many rewrites it took.
+ *
+ * `maxRewrites` stops the evaluation with a `BendError` after that many rewrites.
+ */
+export function run({ maxRewrites } = {}) {
+  const net = new Net(maxRewrites);
+  call(net, "main", []);
+  return { result: showPort(net, newPort(VAR, ROOT)), rewrites: net.rewrites };
+}
+
+// Calls an exported definition, converting its arguments and result.
+function callExport(name, args, types, ret) {
+  const net = new Net();
+  call(net, name, args.map((arg, i) => types[i](net, arg)));
+  const out = ret(net, newPort(VAR, ROOT));
+  if (out === undefined) {
+    throw new BendError(`The result of '${name}' doesn't have the type of its exported signature.`);
+  }
+  return out;
+}
+
+// Exported definitions
+// --------------------
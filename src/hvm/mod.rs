@@ -4,8 +4,12 @@ use hvm::ast::{Net, Tree};
 pub mod add_recursive_priority;
 pub mod check_net_size;
 pub mod eta_reduce;
+pub mod eval;
+pub mod graph;
 pub mod inline;
+pub mod js;
 pub mod mutual_recursion;
+pub mod native;
 pub mod prune;
 
 pub fn tree_children(tree: &Tree) -> impl DoubleEndedIterator<Item = &Tree> + Clone {
@@ -1,7 +1,7 @@
 use super::tree_children;
 use crate::{
   diagnostics::{Diagnostics, WarningType, ERR_INDENT_SIZE},
-  fun::transform::definition_merge::MERGE_SEPARATOR,
+  fun::{transform::definition_merge::MergedDefs, Name},
   maybe_grow,
 };
 use hvm::ast::{Book, Tree};
@@ -15,10 +15,14 @@ type RefSet = IndexSet<Ref>;
 #[derive(Default)]
 pub struct Graph(IndexMap<Ref, RefSet>);
 
-pub fn check_cycles(book: &Book, diagnostics: &mut Diagnostics) -> Result<(), Diagnostics> {
+pub fn check_cycles(
+  book: &Book,
+  merged: &MergedDefs,
+  diagnostics: &mut Diagnostics,
+) -> Result<(), Diagnostics> {
   diagnostics.start_pass();
 
-  let graph = Graph::from(book);
+  let graph = Graph::from(book).unmerge(merged);
   let cycles = graph.cycles();
 
   if !cycles.is_empty() {
@@ -28,15 +32,13 @@ pub fn check_cycles(book: &Book, diagnostics: &mut Diagnostics) -> Result<(), Di
 
   diagnostics.fatal(())
 }
-fn show_cycles(mut cycles: Vec<Vec<Ref>>) -> String {
+fn show_cycles(cycles: Vec<Vec<Ref>>) -> String {
   let tail = if cycles.len() > 5 {
     format!("\n{:ERR_INDENT_SIZE$}and {} other cycles...", "", cycles.len() - 5)
   } else {
     String::new()
   };
 
-  cycles = cycles.into_iter().flat_map(combinations_from_merges).collect::<Vec<_>>();
-
   let mut cycles = cycles
     .iter()
     .take(5)
@@ -48,7 +50,11 @@ fn show_cycles(mut cycles: Vec<Vec<Ref>>) -> String {
         .cloned()
         .collect::<Vec<_>>()
         .join(" -> ");
-      format!("{:ERR_INDENT_SIZE$}* {}", "", cycle_str)
+      // The last definition of the cycle is the one that references back to the first.
+      let (fst, lst) = (Name::new(cycle.first().unwrap()), Name::new(cycle.last().unwrap()));
+      let closing =
+        format!("closed by the reference to '{}' in '{}'", fst.display_origin(), lst.display_origin());
+      format!("{:ERR_INDENT_SIZE$}* {}\n{:ERR_INDENT_SIZE$}  {}", "", cycle_str, "", closing)
     })
     .collect::<Vec<String>>()
     .join("\n");
@@ -154,6 +160,31 @@ impl Graph {
   pub fn get(&self, r#ref: &Ref) -> Option<&RefSet> {
     self.0.get(r#ref)
   }
+
+  /// Splits the merged definitions back into the ones they were merged from, so that the cycles are
+  /// shown with the original names. Each one references the definitions it referenced before the merge.
+  pub fn unmerge(self, merged: &MergedDefs) -> Self {
+    let originals = |r#ref: &Ref| match merged.get(&Name::new(r#ref.as_str())) {
+      Some(defs) => defs.iter().map(|(nam, refs)| (nam.to_string(), Some(refs))).collect::<Vec<_>>(),
+      None => vec![(r#ref.clone(), None)],
+    };
+
+    let mut graph = Self::new();
+    for (r#ref, dependencies) in self.0.iter() {
+      for (orig, orig_refs) in originals(r#ref) {
+        graph.0.entry(orig.clone()).or_default();
+        for dep in dependencies {
+          for (dep_orig, _) in originals(dep) {
+            if orig_refs.map_or(true, |refs| refs.contains(&Name::new(dep_orig.as_str()))) {
+              graph.add(orig.clone(), dep_orig);
+            }
+          }
+        }
+      }
+    }
+    graph.0.sort_keys();
+    graph
+  }
 }
 
 impl Debug for Graph {
@@ -161,29 +192,3 @@ impl Debug for Graph {
     write!(f, "Graph{:?}", self.0)
   }
 }
-
-fn combinations_from_merges(cycle: Vec<Ref>) -> Vec<Vec<Ref>> {
-  let mut combinations: Vec<Vec<Ref>> = vec![vec![]];
-  for r#ref in cycle {
-    if let Some(index) = r#ref.find(MERGE_SEPARATOR) {
-      let (left, right) = r#ref.split_at(index);
-      let right = &right[MERGE_SEPARATOR.len()..]; // skip merge separator
-      let mut new_combinations = Vec::new();
-      for combination in &combinations {
-        let mut left_comb = combination.clone();
-        left_comb.push(left.to_string());
-        new_combinations.push(left_comb);
-
-        let mut right_comb = combination.clone();
-        right_comb.push(right.to_string());
-        new_combinations.push(right_comb);
-      }
-      combinations = new_combinations;
-    } else {
-      for combination in &mut combinations {
-        combination.push(r#ref.clone());
-      }
-    }
-  }
-  combinations
-}
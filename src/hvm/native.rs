@@ -0,0 +1,420 @@
+use crate::{
+  fun::{Book, ExportSig, ExportType, Name},
+  AdtEncoding,
+};
+use std::{
+  collections::HashMap,
+  fmt::Write,
+  path::{Path, PathBuf},
+};
+
+/// Writes the code of a runtime generated with `hvm gen-c` or `hvm gen-cu` as a project that
+/// builds to a static library, to be linked into other programs.
+///
+/// The project has the runtime, without its `main` function, a `bend.h` header exposing
+/// `bend_main()`, which runs the program and returns its result, and a Makefile to build `libbend.a`.
+pub fn write_native_project(dir: &Path, code: &str, cuda: bool, cc: &CcCommand) -> Result<(), String> {
+  let mut runtime = without_main(code)?;
+  let (runtime_file, makefile) = if cuda {
+    runtime.push_str("\nextern \"C\" void bend_run(void) {\n  hvm_cu((u32*)BOOK_BUF);\n}\n");
+    ("runtime.cu", include_str!("native_project/Makefile.cu"))
+  } else {
+    runtime.push_str("\nvoid bend_run(void) {\n  hvm_c((u32*)BOOK_BUF);\n}\n");
+    ("runtime.c", include_str!("native_project/Makefile.c"))
+  };
+
+  let makefile = with_compiler(makefile, cc);
+
+  write_files(
+    dir,
+    &[
+      (runtime_file, runtime.as_str()),
+      ("bend.h", include_str!("native_project/bend.h")),
+      ("bend.c", include_str!("native_project/bend.c")),
+      ("Makefile", makefile.as_str()),
+    ],
+  )
+}
+
+/// Returns the definitions exported with `#[export(..)]` and their signatures.
+///
+/// Lists and strings are passed to the runtime as λ-encoded constructors, which the C runtime
+/// only understands when they use the [`AdtEncoding::NumScott`] encoding.
+pub fn book_exports(book: &Book, adt_encoding: AdtEncoding) -> Result<Vec<(Name, ExportSig)>, String> {
+  let exports = book
+    .defs
+    .values()
+    .filter_map(|def| def.export.clone().map(|sig| (def.name.clone(), sig)))
+    .collect::<Vec<_>>();
+  if exports.is_empty() {
+    return Err("The program has no definitions exported with '#[export(..)]'.".to_string());
+  }
+
+  let mut c_names = HashMap::new();
+  for (name, sig) in exports.iter() {
+    let arity = book.defs[name].arity();
+    if arity > sig.args.len() {
+      return Err(format!(
+        "Definition '{name}' takes {arity} arguments, but its exported signature {sig} has {}.",
+        sig.args.len()
+      ));
+    }
+    if let Some(other) = c_names.insert(c_name(name), name) {
+      return Err(format!(
+        "Definitions '{other}' and '{name}' would both be exported as the C function '{}'.",
+        c_name(name)
+      ));
+    }
+    for typ in sig.args.iter().chain([&sig.ret]) {
+      for adt in typ.adts() {
+        let ctr = Name::new(format!("{adt}/Cons"));
+        if book.ctr_encoding(&ctr, adt_encoding) != AdtEncoding::NumScott {
+          return Err(format!(
+            "Definition '{name}' can't be exported, since its signature has the type '{adt}', which can only be passed to C with the 'num_scott' encoding."
+          ));
+        }
+      }
+    }
+  }
+  Ok(exports)
+}
+
+/// Writes the code of a runtime generated with `hvm gen-c` as a project that builds to a static
+/// library with a C function for each exported definition.
+///
+/// Each function takes the arguments of the definition as C values, converts them to λ-encoded
+/// values, runs the definition and converts its result back.
+pub fn write_lib_project(dir: &Path, code: &str, exports: &[(Name, ExportSig)]) -> Result<(), String> {
+  let (runtime, header, lib) = lib_sources(code, exports)?;
+  write_files(
+    dir,
+    &[
+      ("runtime.c", runtime.as_str()),
+      ("bend.h", header.as_str()),
+      ("bend.c", lib.as_str()),
+      ("Makefile", include_str!("native_project/Makefile.lib")),
+    ],
+  )
+}
+
+/// Writes a Python package calling the library of [`write_lib_project`] with `ctypes`,
+/// with a function for each exported definition that converts `int`, `float`, `str` and `list` values.
+///
+/// The library is built as `libbend.so` by the Makefile of the package.
+pub fn write_py_project(dir: &Path, code: &str, exports: &[(Name, ExportSig)]) -> Result<(), String> {
+  let (runtime, header, lib) = lib_sources(code, exports)?;
+
+  let mut py = String::from(include_str!("native_project/bend_init.py"));
+  for (name, sig) in exports {
+    let c_name = c_name(name);
+    let arg_types =
+      sig.args.iter().map(py_ctype).chain([format!("ctypes.POINTER({})", py_out_ctype(&sig.ret))]);
+    let params = (0..sig.args.len()).map(|i| format!("arg{i}")).collect::<Vec<_>>();
+    let args = sig.args.iter().enumerate().map(|(i, typ)| match typ {
+      ExportType::U24 | ExportType::I24 | ExportType::F24 => format!("arg{i}"),
+      _ => format!("_to_{}(arg{i}, keep)", c_suffix(typ)),
+    });
+    let args = args.chain(["ctypes.byref(out)".to_string()]).collect::<Vec<_>>().join(", ");
+    let ret = match &sig.ret {
+      ExportType::U24 | ExportType::I24 | ExportType::F24 => "out.value".to_string(),
+      typ => format!("_from_{}(out)", c_suffix(typ)),
+    };
+
+    writeln!(py, "\n\n_lib.{c_name}.argtypes = [{}]\n\n", arg_types.collect::<Vec<_>>().join(", ")).unwrap();
+    writeln!(py, "def {}({}):", py_name(name), params.join(", ")).unwrap();
+    writeln!(py, "    \"\"\"{name}{sig}\"\"\"").unwrap();
+    if sig.args.iter().any(|typ| matches!(typ, ExportType::String | ExportType::List(..))) {
+      // Holds the converted strings and lists during the call.
+      writeln!(py, "    keep = []").unwrap();
+    }
+    writeln!(py, "    out = {}()", py_out_ctype(&sig.ret)).unwrap();
+    writeln!(py, "    _check(_lib.{c_name}({args}), \"{name}\")").unwrap();
+    writeln!(py, "    return {ret}").unwrap();
+  }
+
+  write_files(
+    dir,
+    &[
+      ("runtime.c", runtime.as_str()),
+      ("bend.h", header.as_str()),
+      ("bend.c", lib.as_str()),
+      ("__init__.py", py.as_str()),
+      ("Makefile", include_str!("native_project/Makefile.py")),
+    ],
+  )
+}
+
+/// Compiles the code of a runtime generated with `hvm gen-c` to a standalone executable at `output`,
+/// with a `main` that passes its command line arguments to the program.
+///
+/// The C source is kept next to the executable, as `<output>.c`, with the compiler command in its
+/// first line. If it didn't change since the last build and the executable is still there, the
+/// compiler isn't called again. Returns whether the executable was compiled.
+pub fn build_binary(code: &str, output: &Path, cc: &CcCommand, force: bool) -> Result<bool, String> {
+  let src_path = PathBuf::from(format!("{}.c", output.display()));
+  let mut args = cc.flags.clone();
+  args.extend(["-o".to_string(), output.display().to_string(), src_path.display().to_string()]);
+  args.extend(["-lm".to_string(), "-lpthread".to_string()]);
+  let cmd = format!("{} {}", cc.cc, args.join(" "));
+  let mut src = format!("// {cmd}\n");
+  src.push_str(&without_main(code)?);
+  src.push_str(include_str!("native_project/build_main.c"));
+
+  let cached = std::fs::read_to_string(&src_path).is_ok_and(|old| old == src);
+  if cached && output.exists() && !force {
+    return Ok(false);
+  }
+  std::fs::write(&src_path, &src).map_err(|e| format!("Couldn't write '{}'. {e}", src_path.display()))?;
+
+  let output = std::process::Command::new(&cc.cc)
+    .args(&args)
+    .output()
+    .map_err(|e| format!("Couldn't run the C compiler '{}'. {e}", cc.cc))?;
+  if !output.status.success() {
+    // So that the next build doesn't take the old executable as up to date.
+    let _ = std::fs::remove_file(&src_path);
+    return Err(format!(
+      "Compiling the program with '{cmd}' failed. {}\n{}",
+      output.status,
+      String::from_utf8_lossy(&output.stderr)
+    ));
+  }
+  Ok(true)
+}
+
+/// How the generated C code is compiled, from the command line or from a config file.
+#[derive(Debug, Clone, Default)]
+pub struct CcOpts {
+  /// The C compiler.
+  pub cc: Option<String>,
+  /// The platform to compile for, as a target triple like `aarch64-linux-gnu`.
+  pub target: Option<String>,
+  /// Flags passed to the compiler after the default ones.
+  pub cflags: Vec<String>,
+}
+
+/// A C compiler and the flags to call it with.
+#[derive(Debug, Clone)]
+pub struct CcCommand {
+  pub cc: String,
+  pub flags: Vec<String>,
+}
+
+impl CcOpts {
+  /// The config file read when none is given, looked for in the directory of the program.
+  pub const CONFIG_FILE: &'static str = "bend.conf";
+
+  /// Reads the options from a config file, with one `key = value` option per line and `#` comments:
+  ///
+  /// ```text
+  /// cc = clang
+  /// target = aarch64-linux-gnu
+  /// cflags = -O3 -march=armv8-a
+  /// ```
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let code =
+      std::fs::read_to_string(path).map_err(|e| format!("Couldn't read '{}'. {e}", path.display()))?;
+    let mut opts = CcOpts::default();
+    for (i, line) in code.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let Some((key, val)) = line.split_once('=') else {
+        return Err(format!("Expected 'key = value' in line {} of '{}'.", i + 1, path.display()));
+      };
+      let val = val.trim();
+      match key.trim() {
+        "cc" => opts.cc = Some(val.to_string()),
+        "target" => opts.target = Some(val.to_string()),
+        "cflags" => opts.cflags.extend(val.split_whitespace().map(str::to_string)),
+        key => {
+          return Err(format!(
+            "Unknown option '{key}' in line {} of '{}'. The options are 'cc', 'target' and 'cflags'.",
+            i + 1,
+            path.display()
+          ))
+        }
+      }
+    }
+    Ok(opts)
+  }
+
+  /// Overrides these options with the ones of `other`, appending its flags.
+  pub fn merge(mut self, other: CcOpts) -> Self {
+    self.cc = other.cc.or(self.cc);
+    self.target = other.target.or(self.target);
+    self.cflags.extend(other.cflags);
+    self
+  }
+
+  /// The compiler command, with `-O2`, the flags to compile for the target and the extra flags.
+  ///
+  /// Without a compiler, `default_cc` is used, or `cc`. To compile for another target, the compiler
+  /// has to be clang, which gets a `--target` flag, or a compiler for that target, like
+  /// `aarch64-linux-gnu-gcc`, which is also the default one.
+  pub fn command(&self, default_cc: Option<String>) -> Result<CcCommand, String> {
+    let cc = self.cc.clone().or(default_cc);
+    let mut flags = vec!["-O2".to_string()];
+    let cc = match (&self.target, cc) {
+      (None, cc) => cc.unwrap_or_else(|| "cc".to_string()),
+      (Some(target), None) => format!("{target}-gcc"),
+      (Some(target), Some(cc)) => {
+        let name = Path::new(&cc).file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        if name.contains("clang") {
+          flags.push(format!("--target={target}"));
+        } else if !name.starts_with(&format!("{target}-")) {
+          return Err(format!(
+            "Can't compile for the target '{target}' with '{cc}'. Use clang, or a compiler for the target, like '{target}-gcc'."
+          ));
+        }
+        cc
+      }
+    };
+    flags.extend(self.cflags.iter().cloned());
+    Ok(CcCommand { cc, flags })
+  }
+}
+
+/// The C sources of a library with a function for each exported definition:
+/// the runtime, the header and the implementation of the functions.
+fn lib_sources(code: &str, exports: &[(Name, ExportSig)]) -> Result<(String, String, String), String> {
+  let runtime = without_main(code)?;
+
+  let mut protos = String::new();
+  let mut funcs = String::from(include_str!("native_project/lib_bend.c"));
+  for (name, sig) in exports {
+    let params = sig.args.iter().enumerate().map(|(i, typ)| format!("{} arg{i}", c_arg_type(typ)));
+    let out = format!("{}* out", c_ret_type(&sig.ret));
+    let proto =
+      format!("bend_status {}({})", c_name(name), params.chain([out]).collect::<Vec<_>>().join(", "));
+    writeln!(protos, "// {name}{sig}\n{proto};\n").unwrap();
+
+    writeln!(funcs, "\n{proto} {{").unwrap();
+    writeln!(funcs, "  Net* net = bend_start();").unwrap();
+    writeln!(funcs, "  if (!net) return BEND_ERR_OOM;").unwrap();
+    if sig.args.is_empty() {
+      writeln!(funcs, "  bend_call(net, \"{name}\", NULL, 0);").unwrap();
+    } else {
+      writeln!(funcs, "  Port args[] = {{").unwrap();
+      for (i, typ) in sig.args.iter().enumerate() {
+        writeln!(funcs, "    bend_inject_{}(net, arg{i}),", c_suffix(typ)).unwrap();
+      }
+      writeln!(funcs, "  }};").unwrap();
+      writeln!(funcs, "  bend_call(net, \"{name}\", args, {});", sig.args.len()).unwrap();
+    }
+    writeln!(funcs, "  bend_status status = bend_read_{}(net, ROOT, out);", c_suffix(&sig.ret)).unwrap();
+    writeln!(funcs, "  bend_finish(net);").unwrap();
+    writeln!(funcs, "  return status;").unwrap();
+    writeln!(funcs, "}}").unwrap();
+  }
+  let header = include_str!("native_project/lib_bend.h").replace("/* EXPORTS */\n\n", &protos);
+  Ok((runtime, header, funcs))
+}
+
+/// Sets the default C compiler and flags of a Makefile.
+fn with_compiler(makefile: &str, cc: &CcCommand) -> String {
+  makefile
+    .replace("CC ?= cc\n", &format!("CC ?= {}\n", cc.cc))
+    .replace("CFLAGS ?= -O2\n", &format!("CFLAGS ?= {}\n", cc.flags.join(" ")))
+}
+
+/// Removes the `main` function of the generated runtime.
+fn without_main(code: &str) -> Result<String, String> {
+  const WITH_MAIN: &str = "#define WITH_MAIN\n";
+  let Some(ini) = code.find(WITH_MAIN) else {
+    return Err(format!("Couldn't find '{}' in the generated runtime.", WITH_MAIN.trim()));
+  };
+  let mut runtime = code.to_string();
+  runtime.replace_range(ini..ini + WITH_MAIN.len(), "");
+  Ok(runtime)
+}
+
+fn write_files(dir: &Path, files: &[(&str, &str)]) -> Result<(), String> {
+  std::fs::create_dir_all(dir)
+    .map_err(|e| format!("Couldn't create the directory '{}'. {e}", dir.display()))?;
+  for (name, contents) in files {
+    let path = dir.join(name);
+    std::fs::write(&path, contents).map_err(|e| format!("Couldn't write '{}'. {e}", path.display()))?;
+  }
+  Ok(())
+}
+
+impl ExportType {
+  /// The builtin types that values of this type are made of.
+  fn adts(&self) -> Vec<&'static str> {
+    match self {
+      ExportType::U24 | ExportType::I24 | ExportType::F24 => vec![],
+      ExportType::String => vec!["String"],
+      ExportType::List(item) => [vec!["List"], item.adts()].concat(),
+    }
+  }
+}
+
+/// The name of the C function of an exported definition, like `bend_List_sum` for `List/sum`.
+fn c_name(name: &Name) -> String {
+  let name = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect::<String>();
+  format!("bend_{name}")
+}
+
+/// The suffix of the C helpers and list types for a type, like `list_u24`.
+fn c_suffix(typ: &ExportType) -> String {
+  match typ {
+    ExportType::U24 => "u24".to_string(),
+    ExportType::I24 => "i24".to_string(),
+    ExportType::F24 => "f24".to_string(),
+    ExportType::String => "string".to_string(),
+    ExportType::List(item) => format!("list_{}", c_suffix(item)),
+  }
+}
+
+/// The name of the Python function of an exported definition, like `List_sum` for `List/sum`.
+/// Names that aren't valid in Python get a `_` suffix.
+fn py_name(name: &Name) -> String {
+  const KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue", "def",
+    "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+    "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+  ];
+  let name = c_name(name)["bend_".len()..].to_string();
+  if KEYWORDS.contains(&name.as_str()) || name.starts_with(|c: char| c.is_ascii_digit()) {
+    format!("{name}_")
+  } else {
+    name
+  }
+}
+
+fn py_ctype(typ: &ExportType) -> String {
+  match typ {
+    ExportType::U24 => "ctypes.c_uint32".to_string(),
+    ExportType::I24 => "ctypes.c_int32".to_string(),
+    ExportType::F24 => "ctypes.c_float".to_string(),
+    ExportType::String => "ctypes.c_char_p".to_string(),
+    ExportType::List(..) => format!("_List_{}", c_suffix(typ).trim_start_matches("list_")),
+  }
+}
+
+/// The returned strings are kept as pointers, to free them after they're read.
+fn py_out_ctype(typ: &ExportType) -> String {
+  match typ {
+    ExportType::String => "ctypes.c_void_p".to_string(),
+    _ => py_ctype(typ),
+  }
+}
+
+fn c_arg_type(typ: &ExportType) -> String {
+  match typ {
+    ExportType::String => "const char*".to_string(),
+    _ => c_ret_type(typ),
+  }
+}
+
+fn c_ret_type(typ: &ExportType) -> String {
+  match typ {
+    ExportType::U24 => "uint32_t".to_string(),
+    ExportType::I24 => "int32_t".to_string(),
+    ExportType::F24 => "float".to_string(),
+    ExportType::String => "char*".to_string(),
+    ExportType::List(..) => format!("bend_{}", c_suffix(typ)),
+  }
+}
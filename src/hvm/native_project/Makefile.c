@@ -0,0 +1,19 @@
+# Builds the Bend program into the static library libbend.a.
+# Link it to your application with `-L<this dir> -lbend -lm -lpthread` and include bend.h.
+
+CC ?= cc
+CFLAGS ?= -O2
+
+libbend.a: runtime.o bend.o
+	ar rcs $@ $^
+
+runtime.o: runtime.c
+	$(CC) $(CFLAGS) -c runtime.c -o runtime.o
+
+bend.o: bend.c bend.h
+	$(CC) $(CFLAGS) -c bend.c -o bend.o
+
+clean:
+	rm -f libbend.a runtime.o bend.o
+
+.PHONY: clean
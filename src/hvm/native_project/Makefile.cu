@@ -0,0 +1,21 @@
+# Builds the Bend program into the static library libbend.a.
+# Link it to your application with `-L<this dir> -lbend -lcudart -lstdc++ -lm -lpthread` and include bend.h.
+
+CC ?= cc
+CFLAGS ?= -O2
+NVCC ?= nvcc
+NVCCFLAGS ?= -O3 -w
+
+libbend.a: runtime.o bend.o
+	ar rcs $@ $^
+
+runtime.o: runtime.cu
+	$(NVCC) $(NVCCFLAGS) -c runtime.cu -o runtime.o
+
+bend.o: bend.c bend.h
+	$(CC) $(CFLAGS) -c bend.c -o bend.o
+
+clean:
+	rm -f libbend.a runtime.o bend.o
+
+.PHONY: clean
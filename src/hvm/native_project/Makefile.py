@@ -0,0 +1,12 @@
+# Builds the Bend program into the shared library libbend.so, loaded by __init__.py.
+
+CC ?= cc
+CFLAGS ?= -O2
+
+libbend.so: bend.c bend.h runtime.c
+	$(CC) $(CFLAGS) -fPIC -shared bend.c -o libbend.so -lm -lpthread
+
+clean:
+	rm -f libbend.so
+
+.PHONY: clean
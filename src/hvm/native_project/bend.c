@@ -0,0 +1,60 @@
+// Implements bend.h on top of the generated runtime, which prints the result to stdout.
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "bend.h"
+
+// Defined at the end of the generated runtime.
+void bend_run(void);
+
+#define RESULT_MARKER "Result: "
+
+char* bend_main(void) {
+  // Captures the output of the runtime in a temporary file.
+  FILE* out = tmpfile();
+  if (!out) return NULL;
+  fflush(stdout);
+  int saved = dup(STDOUT_FILENO);
+  dup2(fileno(out), STDOUT_FILENO);
+  bend_run();
+  fflush(stdout);
+  dup2(saved, STDOUT_FILENO);
+  close(saved);
+
+  fseek(out, 0, SEEK_END);
+  long len = ftell(out);
+  rewind(out);
+  char* buf = malloc(len + 1);
+  if (!buf) {
+    fclose(out);
+    return NULL;
+  }
+  len = fread(buf, 1, len, out);
+  buf[len] = '\0';
+  fclose(out);
+
+  // The result is on the last line starting with the marker. What comes before it was printed by the program.
+  char* res = NULL;
+  for (char* got = strstr(buf, RESULT_MARKER); got; got = strstr(got + 1, RESULT_MARKER)) {
+    if (got == buf || got[-1] == '\n') res = got;
+  }
+  if (!res) {
+    fwrite(buf, 1, len, stdout);
+    free(buf);
+    return NULL;
+  }
+  fwrite(buf, 1, res - buf, stdout);
+  fflush(stdout);
+
+  res += strlen(RESULT_MARKER);
+  size_t res_len = strcspn(res, "\n");
+  char* result = malloc(res_len + 1);
+  if (result) {
+    memcpy(result, res, res_len);
+    result[res_len] = '\0';
+  }
+  free(buf);
+  return result;
+}
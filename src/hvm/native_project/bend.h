@@ -0,0 +1,21 @@
+// Interface of a Bend program compiled to a library with `bend gen-c --out-dir` or `bend gen-cu --out-dir`.
+#ifndef BEND_H
+#define BEND_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Runs the `main` definition of the program and returns its result,
+// as a net in the syntax of HVM, like `((1 2) 3)`.
+//
+// What the program prints with IO is written to stdout as usual.
+// Returns NULL if the runtime didn't give a result.
+// The returned string is allocated with malloc and must be freed by the caller.
+char* bend_main(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
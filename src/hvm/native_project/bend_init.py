@@ -0,0 +1,80 @@
+"""Python bindings of a Bend program, generated by `bend gen-py`.
+
+Build the shared library with `make` in this directory before importing the package.
+Each function runs the program from scratch, so they can't be called from several threads at once.
+"""
+
+import ctypes
+import os
+
+_lib = ctypes.CDLL(os.path.join(os.path.dirname(os.path.abspath(__file__)), "libbend.so"))
+
+
+class BendError(Exception):
+    """An exported function failed: its result didn't have the type of its signature,
+    or there wasn't enough memory to run it."""
+
+
+def _check(status, name):
+    if status == 2:
+        raise BendError(f"Not enough memory to run '{name}'. Use a smaller '--heap-size' with 'bend gen-py'.")
+    if status != 0:
+        raise BendError(f"The result of '{name}' doesn't have the type of its signature.")
+
+
+def _list_type(item):
+    class List(ctypes.Structure):
+        _fields_ = [("len", ctypes.c_size_t), ("items", ctypes.POINTER(item))]
+
+    return List
+
+
+_List_u24 = _list_type(ctypes.c_uint32)
+_List_i24 = _list_type(ctypes.c_int32)
+_List_f24 = _list_type(ctypes.c_float)
+_List_string = _list_type(ctypes.c_char_p)
+
+_lib.bend_free_string.argtypes = [ctypes.c_void_p]
+for _suffix in ["u24", "i24", "f24", "string"]:
+    getattr(_lib, f"bend_free_list_{_suffix}").argtypes = [globals()[f"_List_{_suffix}"]]
+
+
+def _to_string(value, keep):
+    return value.encode("utf-8")
+
+
+def _from_string(out):
+    value = ctypes.string_at(out.value).decode("utf-8")
+    _lib.bend_free_string(out)
+    return value
+
+
+def _to_list(list_type, item_type, convert):
+    def to_list(values, keep):
+        items = (item_type * len(values))(*[convert(value, keep) for value in values])
+        # The items must live until the call returns.
+        keep.append(items)
+        return list_type(len(values), items)
+
+    return to_list
+
+
+def _from_list(suffix, convert):
+    def from_list(out):
+        values = [convert(out.items[i]) for i in range(out.len)]
+        getattr(_lib, f"bend_free_list_{suffix}")(out)
+        return values
+
+    return from_list
+
+
+_to_list_u24 = _to_list(_List_u24, ctypes.c_uint32, lambda value, keep: value)
+_to_list_i24 = _to_list(_List_i24, ctypes.c_int32, lambda value, keep: value)
+_to_list_f24 = _to_list(_List_f24, ctypes.c_float, lambda value, keep: value)
+_to_list_string = _to_list(_List_string, ctypes.c_char_p, _to_string)
+_from_list_u24 = _from_list("u24", lambda item: item)
+_from_list_i24 = _from_list("i24", lambda item: item)
+_from_list_f24 = _from_list("f24", lambda item: item)
+_from_list_string = _from_list("string", lambda item: item.decode("utf-8"))
+
+# Exported functions
@@ -0,0 +1,104 @@
+
+// Entrypoint of a program built with `bend build`.
+// Each command line argument is passed to the program's main as a number, if it's written like
+// one, or otherwise as a string.
+
+// Creates a node, returning its location.
+static u32 bend_node(Net* net, Port fst, Port snd) {
+  u32 lps = 0;
+  u32 loc = node_alloc_1(net, tm[0], &lps);
+  node_create(net, loc, new_pair(fst, snd));
+  return loc;
+}
+
+// Creates a λ-encoded constructor: λt (t TAG arg0 arg1 ..).
+static Port bend_ctr(Net* net, u32 tag, Port* args, u32 args_len) {
+  u32 lps = 0;
+  u32 var = vars_alloc_1(net, tm[0], &lps);
+  vars_create(net, var, NONE);
+  Port app = new_port(VAR, var);
+  for (u32 i = args_len; i > 0; --i) {
+    app = new_port(CON, bend_node(net, args[i - 1], app));
+  }
+  app = new_port(CON, bend_node(net, new_port(NUM, new_u24(tag)), app));
+  return new_port(CON, bend_node(net, app, new_port(VAR, var)));
+}
+
+// Decodes the unicode scalar starting at `str[*i]`, advancing `i` past it.
+static u32 bend_utf8_decode(const unsigned char* str, size_t* i) {
+  u32 chr = str[(*i)++];
+  u32 extra = chr >= 0xF0 ? 3 : chr >= 0xE0 ? 2 : chr >= 0xC0 ? 1 : 0;
+  chr &= extra == 3 ? 0x07 : extra == 2 ? 0x0F : extra == 1 ? 0x1F : 0x7F;
+  for (u32 k = 0; k < extra && (str[*i] & 0xC0) == 0x80; ++k) {
+    chr = (chr << 6) | (str[(*i)++] & 0x3F);
+  }
+  return chr;
+}
+
+// Strings are built from the end: λt (t NIL) and λt (t CONS head tail).
+static Port bend_string(Net* net, const char* str) {
+  const unsigned char* bytes = (const unsigned char*)str;
+  size_t len = strlen(str);
+  u32* chars = malloc((len + 1) * sizeof(u32));
+  size_t chars_len = 0;
+  for (size_t i = 0; i < len;) {
+    chars[chars_len++] = bend_utf8_decode(bytes, &i);
+  }
+  Port port = bend_ctr(net, LIST_NIL, NULL, 0);
+  while (chars_len > 0) {
+    Port args[2] = {new_port(NUM, new_u24(chars[--chars_len])), port};
+    port = bend_ctr(net, LIST_CONS, args, 2);
+  }
+  free(chars);
+  return port;
+}
+
+// Reads an argument like a Bend literal: `1` is a u24, `+1` and `-1` are i24 and `1.0` is a f24.
+static Port bend_arg(Net* net, const char* arg) {
+  const char* digits = arg[0] == '+' || arg[0] == '-' ? arg + 1 : arg;
+  size_t int_len = strspn(digits, "0123456789");
+  if (int_len > 0) {
+    if (digits[int_len] == '\0') {
+      long val = strtol(arg, NULL, 10);
+      return new_port(NUM, digits == arg ? new_u24(val) : new_i24(val));
+    }
+    if (digits[int_len] == '.' && digits[int_len + 1 + strspn(digits + int_len + 1, "0123456789")] == '\0') {
+      return new_port(NUM, new_f24(strtof(arg, NULL)));
+    }
+  }
+  return bend_string(net, arg);
+}
+
+int main(int argc, char** argv) {
+  // The size of the net is set by the `--heap-size` option of `bend build`.
+  Book* book = malloc(sizeof(Book));
+  Net* net = malloc(sizeof(Net));
+  if (!book || !net) {
+    fprintf(stderr, "Out of memory: couldn't allocate the %zu bytes of the runtime.\n", sizeof(Book) + sizeof(Net));
+    fprintf(stderr, "Build the program with a smaller '--heap-size'.\n");
+    return 1;
+  }
+
+  alloc_static_tms();
+  book_init(book);
+  book_load(book, (u32*)BOOK_BUF);
+  net_init(net);
+
+  // Applies main to the arguments, as the tree (arg0 (arg1 .. ROOT)).
+  Port app = ROOT;
+  for (int i = argc - 1; i > 0; --i) {
+    app = new_port(CON, bend_node(net, bend_arg(net, argv[i]), app));
+  }
+  boot_redex(net, new_pair(new_port(REF, 0), app));
+
+  do_run_io(net, book, ROOT);
+
+  printf("Result: ");
+  pretty_print_port(net, book, enter(net, ROOT));
+  printf("\n");
+
+  free_static_tms();
+  free(net);
+  free(book);
+  return 0;
+}
@@ -0,0 +1,279 @@
+// Implements bend.h on top of the generated runtime, converting the arguments and results
+// of the exported functions from and to λ-encoded values.
+#include "runtime.c"
+#include "bend.h"
+
+// The book is loaded on the first call and kept for the next ones.
+static Book* bend_book = NULL;
+
+// Allocates the net of a call, or returns NULL if there isn't enough memory for it.
+// The size of the net is set by the `--heap-size` option of `bend gen-lib`.
+static Net* bend_start(void) {
+  if (!bend_book) {
+    Book* book = malloc(sizeof(Book));
+    if (!book) return NULL;
+    book_init(book);
+    book_load(book, (u32*)BOOK_BUF);
+    bend_book = book;
+  }
+  Net* net = malloc(sizeof(Net));
+  if (!net) return NULL;
+  alloc_static_tms();
+  net_init(net);
+  return net;
+}
+
+static void bend_finish(Net* net) {
+  free_static_tms();
+  free(net);
+}
+
+// Reduces the definition `name` applied to `args`, connecting the result to ROOT.
+static void bend_call(Net* net, const char* name, Port* args, u32 args_len) {
+  u32 fid = 0;
+  while (fid < bend_book->defs_len && strcmp(bend_book->defs_buf[fid].name, name) != 0) {
+    fid++;
+  }
+  // The application is the tree (arg0 (arg1 .. ROOT)).
+  Port app = ROOT;
+  for (u32 i = args_len; i > 0; --i) {
+    u32 lps = 0;
+    u32 loc = node_alloc_1(net, tm[0], &lps);
+    node_create(net, loc, new_pair(args[i - 1], app));
+    app = new_port(CON, loc);
+  }
+  boot_redex(net, new_pair(new_port(REF, fid), app));
+  normalize(net, bend_book);
+}
+
+// UTF-8
+// -----
+
+// Decodes the unicode scalar starting at `str[*i]`, advancing `i` past it.
+static u32 bend_utf8_decode(const unsigned char* str, size_t* i) {
+  u32 chr = str[(*i)++];
+  u32 extra = chr >= 0xF0 ? 3 : chr >= 0xE0 ? 2 : chr >= 0xC0 ? 1 : 0;
+  chr &= extra == 3 ? 0x07 : extra == 2 ? 0x0F : extra == 1 ? 0x1F : 0x7F;
+  for (u32 k = 0; k < extra && (str[*i] & 0xC0) == 0x80; ++k) {
+    chr = (chr << 6) | (str[(*i)++] & 0x3F);
+  }
+  return chr;
+}
+
+// Encodes a unicode scalar to `out`, returning how many bytes it took.
+static size_t bend_utf8_encode(u32 chr, char* out) {
+  if (chr < 0x80) {
+    out[0] = chr;
+    return 1;
+  } else if (chr < 0x800) {
+    out[0] = 0xC0 | (chr >> 6);
+    out[1] = 0x80 | (chr & 0x3F);
+    return 2;
+  } else if (chr < 0x10000) {
+    out[0] = 0xE0 | (chr >> 12);
+    out[1] = 0x80 | ((chr >> 6) & 0x3F);
+    out[2] = 0x80 | (chr & 0x3F);
+    return 3;
+  } else {
+    out[0] = 0xF0 | (chr >> 18);
+    out[1] = 0x80 | ((chr >> 12) & 0x3F);
+    out[2] = 0x80 | ((chr >> 6) & 0x3F);
+    out[3] = 0x80 | (chr & 0x3F);
+    return 4;
+  }
+}
+
+// Injection
+// ---------
+
+// Creates a node, returning its location.
+static u32 bend_node(Net* net, Port fst, Port snd) {
+  u32 lps = 0;
+  u32 loc = node_alloc_1(net, tm[0], &lps);
+  node_create(net, loc, new_pair(fst, snd));
+  return loc;
+}
+
+// Creates a λ-encoded constructor: λt (t TAG arg0 arg1 ..).
+static Port bend_inject_ctr(Net* net, u32 tag, Port* args, u32 args_len) {
+  u32 lps = 0;
+  u32 var = vars_alloc_1(net, tm[0], &lps);
+  vars_create(net, var, NONE);
+  Port app = new_port(VAR, var);
+  for (u32 i = args_len; i > 0; --i) {
+    app = new_port(CON, bend_node(net, args[i - 1], app));
+  }
+  app = new_port(CON, bend_node(net, new_port(NUM, new_u24(tag)), app));
+  return new_port(CON, bend_node(net, app, new_port(VAR, var)));
+}
+
+static Port bend_inject_u24(Net* net, uint32_t val) {
+  return new_port(NUM, new_u24(val));
+}
+
+static Port bend_inject_i24(Net* net, int32_t val) {
+  return new_port(NUM, new_i24(val));
+}
+
+static Port bend_inject_f24(Net* net, float val) {
+  return new_port(NUM, new_f24(val));
+}
+
+// Strings and lists are built from the end: λt (t NIL) and λt (t CONS head tail).
+static Port bend_inject_string(Net* net, const char* str) {
+  const unsigned char* bytes = (const unsigned char*)str;
+  size_t len = strlen(str);
+  u32* chars = malloc((len + 1) * sizeof(u32));
+  size_t chars_len = 0;
+  for (size_t i = 0; i < len;) {
+    chars[chars_len++] = bend_utf8_decode(bytes, &i);
+  }
+  Port port = bend_inject_ctr(net, LIST_NIL, NULL, 0);
+  while (chars_len > 0) {
+    Port args[2] = {new_port(NUM, new_u24(chars[--chars_len])), port};
+    port = bend_inject_ctr(net, LIST_CONS, args, 2);
+  }
+  free(chars);
+  return port;
+}
+
+#define BEND_INJECT_LIST(T)                                              \
+  static Port bend_inject_list_##T(Net* net, bend_list_##T list) {      \
+    Port port = bend_inject_ctr(net, LIST_NIL, NULL, 0);                 \
+    for (size_t i = list.len; i > 0; --i) {                              \
+      Port args[2] = {bend_inject_##T(net, list.items[i - 1]), port};    \
+      port = bend_inject_ctr(net, LIST_CONS, args, 2);                   \
+    }                                                                    \
+    return port;                                                         \
+  }
+
+BEND_INJECT_LIST(u24)
+BEND_INJECT_LIST(i24)
+BEND_INJECT_LIST(f24)
+BEND_INJECT_LIST(string)
+
+// Readback
+// --------
+
+// Reads back a number, checking its type.
+static bool bend_read_numb(Net* net, Port port, Tag typ, Numb* out) {
+  Port got = expand(net, bend_book, port);
+  if (get_tag(got) != NUM || get_typ(get_val(got)) != typ) return FALSE;
+  *out = get_val(got);
+  return TRUE;
+}
+
+static bend_status bend_read_u24(Net* net, Port port, uint32_t* out) {
+  Numb numb;
+  if (!bend_read_numb(net, port, TY_U24, &numb)) return BEND_ERR_RESULT;
+  *out = get_u24(numb);
+  return BEND_OK;
+}
+
+static bend_status bend_read_i24(Net* net, Port port, int32_t* out) {
+  Numb numb;
+  if (!bend_read_numb(net, port, TY_I24, &numb)) return BEND_ERR_RESULT;
+  *out = get_i24(numb);
+  return BEND_OK;
+}
+
+static bend_status bend_read_f24(Net* net, Port port, float* out) {
+  Numb numb;
+  if (!bend_read_numb(net, port, TY_F24, &numb)) return BEND_ERR_RESULT;
+  *out = get_f24(numb);
+  return BEND_OK;
+}
+
+// Reads back the elements of a λ-encoded list or string, returning the ports of their heads.
+// Returns NULL if it isn't a list.
+static Port* bend_read_items(Net* net, Port port, size_t* len) {
+  size_t cap = 16;
+  Port* items = malloc(cap * sizeof(Port));
+  *len = 0;
+  while (TRUE) {
+    normalize(net, bend_book);
+    Ctr ctr = readback_ctr(net, bend_book, peek(net, port));
+    if (ctr.tag == LIST_NIL && ctr.args_len == 0) return items;
+    if (ctr.tag != LIST_CONS || ctr.args_len != 2) {
+      free(items);
+      return NULL;
+    }
+    if (*len == cap) {
+      cap *= 2;
+      items = realloc(items, cap * sizeof(Port));
+    }
+    items[(*len)++] = ctr.args_buf[0];
+    boot_redex(net, new_pair(ctr.args_buf[1], ROOT));
+    port = ROOT;
+  }
+}
+
+static bend_status bend_read_string(Net* net, Port port, char** out) {
+  size_t len;
+  Port* chars = bend_read_items(net, port, &len);
+  if (!chars) return BEND_ERR_RESULT;
+  char* str = malloc(len * 4 + 1);
+  size_t str_len = 0;
+  for (size_t i = 0; i < len; ++i) {
+    if (get_tag(chars[i]) != NUM) {
+      free(chars);
+      free(str);
+      return BEND_ERR_RESULT;
+    }
+    str_len += bend_utf8_encode(get_u24(get_val(chars[i])), str + str_len);
+  }
+  str[str_len] = '\0';
+  free(chars);
+  *out = str;
+  return BEND_OK;
+}
+
+void bend_free_string(char* str) {
+  free(str);
+}
+
+void bend_free_list_u24(bend_list_u24 list) {
+  free(list.items);
+}
+
+void bend_free_list_i24(bend_list_i24 list) {
+  free(list.items);
+}
+
+void bend_free_list_f24(bend_list_f24 list) {
+  free(list.items);
+}
+
+void bend_free_list_string(bend_list_string list) {
+  for (size_t i = 0; i < list.len; ++i) {
+    free(list.items[i]);
+  }
+  free(list.items);
+}
+
+// The items of lists of strings are read after the whole list, since reading a string moves ROOT.
+#define BEND_READ_LIST(T, ITEM)                                                     \
+  static bend_status bend_read_list_##T(Net* net, Port port, bend_list_##T* out) {  \
+    size_t len;                                                                      \
+    Port* ports = bend_read_items(net, port, &len);                                  \
+    if (!ports) return BEND_ERR_RESULT;                                              \
+    ITEM* items = malloc((len + 1) * sizeof(ITEM));                                  \
+    for (size_t i = 0; i < len; ++i) {                                               \
+      if (bend_read_##T(net, ports[i], &items[i]) != BEND_OK) {                      \
+        bend_free_list_##T((bend_list_##T){i, items});                               \
+        free(ports);                                                                 \
+        return BEND_ERR_RESULT;                                                      \
+      }                                                                              \
+    }                                                                                \
+    free(ports);                                                                     \
+    *out = (bend_list_##T){len, items};                                              \
+    return BEND_OK;                                                                  \
+  }
+
+BEND_READ_LIST(u24, uint32_t)
+BEND_READ_LIST(i24, int32_t)
+BEND_READ_LIST(f24, float)
+BEND_READ_LIST(string, char*)
+
+// Exported functions
+// ------------------
@@ -0,0 +1,44 @@
+// Interface of a Bend program compiled to a library with `bend gen-lib`.
+#ifndef BEND_H
+#define BEND_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Lists taken and returned by the exported functions.
+typedef struct { size_t len; uint32_t* items; } bend_list_u24;
+typedef struct { size_t len; int32_t* items; } bend_list_i24;
+typedef struct { size_t len; float* items; } bend_list_f24;
+typedef struct { size_t len; char** items; } bend_list_string;
+
+typedef enum {
+  BEND_OK = 0,
+  // The result of the function doesn't have the type given in its signature.
+  BEND_ERR_RESULT = 1,
+  // There isn't enough memory for the nodes of the runtime. See the `--heap-size` option of `bend gen-lib`.
+  BEND_ERR_OOM = 2,
+} bend_status;
+
+// Frees the strings and lists returned by the exported functions.
+// The strings are allocated with malloc, so they can also be freed with free.
+void bend_free_string(char* str);
+void bend_free_list_u24(bend_list_u24 list);
+void bend_free_list_i24(bend_list_i24 list);
+void bend_free_list_f24(bend_list_f24 list);
+void bend_free_list_string(bend_list_string list);
+
+// Exported functions.
+// Each one runs the program from scratch, so they can't be called from several threads at once.
+// The result is written to `out` only when the function returns BEND_OK.
+
+/* EXPORTS */
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,449 @@
+use super::{AssignPattern, Definition, Expr, MatchArm, Stmt};
+use crate::fun::{
+  builtins::{LCONS, LNIL},
+  Name, Num, Op,
+};
+
+/// The variable that receives the variables of a loop when it ends.
+const LOOP_RESULT: &str = "%loop";
+/// The list or number that a 'for' loop is at.
+const FOR_ITER: &str = "%for";
+/// The end of the range of a 'for' loop.
+const FOR_END: &str = "%for_end";
+
+impl Definition {
+  /// Converts the `while` and `for` loops into `bend`s over the variables that the loop assigns,
+  /// which become tail-recursive functions when the `bend`s are desugared.
+  ///
+  /// Only the variables bound before a loop are carried from one iteration to the next,
+  /// the ones first assigned inside the loop are local to each iteration.
+  ///
+  /// ```py
+  /// while i < n:
+  ///   total += i
+  ///   i += 1
+  /// return total
+  /// # Becomes
+  /// bend total = total, i = i:
+  ///   when i < n:
+  ///     total += i
+  ///     i += 1
+  ///     %loop = fork(total, i)
+  ///   else:
+  ///     %loop = (total, i)
+  /// (total, i) = %loop
+  /// return total
+  /// ```
+  ///
+  /// A `for` loop also carries the list or number it's at:
+  ///
+  /// ```py
+  /// for x in range(a, b):
+  ///   total += x
+  /// # Becomes
+  /// %for_end = b
+  /// bend %for = a, total = total:
+  ///   when %for < %for_end:
+  ///     x = %for
+  ///     total += x
+  ///     %loop = fork(%for + 1, total)
+  ///   else:
+  ///     %loop = total
+  /// total = %loop
+  ///
+  /// for x in xs:
+  ///   total += x
+  /// # Becomes
+  /// bend %for = xs, total = total:
+  ///   when 1:
+  ///     match %for:
+  ///       case List/Nil:
+  ///         break
+  ///       case List/Cons:
+  ///         x = %for.head
+  ///         total += x
+  ///         %loop = fork(%for.tail, total)
+  ///   else:
+  ///     %loop = total
+  /// total = %loop
+  /// ```
+  pub fn desugar_loops(&mut self) -> Result<(), String> {
+    let mut bound = self.params.clone();
+    self.body.desugar_loops(&mut bound).map_err(|e| format!("In function '{}': {}", self.name, e))
+  }
+}
+
+impl Stmt {
+  /// Desugars the loops in the statement, where `bound` has the variables bound before it.
+  fn desugar_loops(&mut self, bound: &mut Vec<Name>) -> Result<(), String> {
+    match self {
+      Stmt::While { .. } => {
+        let Stmt::While { cond, mut bod, mut nxt } = std::mem::take(self) else { unreachable!() };
+        let len = bound.len();
+        bod.desugar_loops(bound)?;
+        bound.truncate(len);
+        if let Some(nxt) = &mut nxt {
+          nxt.desugar_loops(bound)?;
+        }
+        *self = while_to_bend(*cond, *bod, nxt, bound)?;
+      }
+      Stmt::For { .. } => {
+        let Stmt::For { pat, iter, mut bod, mut nxt } = std::mem::take(self) else { unreachable!() };
+        let len = bound.len();
+        pat.assigned_vars(bound);
+        bod.desugar_loops(bound)?;
+        bound.truncate(len);
+        if let Some(nxt) = &mut nxt {
+          nxt.desugar_loops(bound)?;
+        }
+        *self = for_to_bend(pat, *iter, *bod, nxt, bound)?;
+      }
+      Stmt::Assign { pat, val: _, nxt } => {
+        pat.assigned_vars(bound);
+        if let Some(nxt) = nxt {
+          nxt.desugar_loops(bound)?;
+        }
+      }
+      Stmt::Ask { pat, val: _, nxt } => {
+        pat.assigned_vars(bound);
+        nxt.desugar_loops(bound)?;
+      }
+      Stmt::Use { nam, val: _, nxt } => {
+        bound.push(nam.clone());
+        nxt.desugar_loops(bound)?;
+      }
+      Stmt::InPlace { nxt, .. } | Stmt::Open { nxt, .. } => nxt.desugar_loops(bound)?,
+      Stmt::If { cond: _, then, otherwise, nxt } => {
+        in_branches(bound, [then.as_mut(), otherwise.as_mut()], nxt)?;
+      }
+      Stmt::Match { arg: _, bnd, with_bnd, with_arg: _, arms, nxt }
+      | Stmt::Fold { arg: _, bnd, with_bnd, with_arg: _, arms, nxt } => {
+        bound.extend(bnd.iter().chain(with_bnd.iter().flatten()).cloned());
+        in_branches(bound, arms.iter_mut().map(|arm| &mut arm.rgt), nxt)?;
+      }
+      Stmt::Switch { arg: _, bnd, with_bnd, with_arg: _, arms, nxt } => {
+        bound.extend(bnd.iter().chain(with_bnd.iter().flatten()).cloned());
+        in_branches(bound, arms.iter_mut(), nxt)?;
+      }
+      Stmt::RangeSwitch { arg: _, bnd, with_bnd, with_arg: _, arms, default, nxt } => {
+        bound.extend(bnd.iter().chain(with_bnd.iter().flatten()).cloned());
+        let branches = arms.iter_mut().map(|(_, arm)| arm).chain([default.as_mut()]);
+        in_branches(bound, branches, nxt)?;
+      }
+      Stmt::Bend { bnd, arg: _, cond: _, step, base, nxt } => {
+        bound.extend(bnd.iter().flatten().cloned());
+        in_branches(bound, [step.as_mut(), base.as_mut()], nxt)?;
+      }
+      Stmt::With { typ: _, bod, nxt } => in_branches(bound, [bod.as_mut()], nxt)?,
+      Stmt::LocalDef { def, nxt } => {
+        def.desugar_loops()?;
+        bound.push(def.name.clone());
+        nxt.desugar_loops(bound)?;
+      }
+      Stmt::Return { .. } | Stmt::Break | Stmt::Pass | Stmt::Err => {}
+    }
+    Ok(())
+  }
+
+  /// Adds the variables assigned by this statement and the ones inside it to `vars`.
+  fn assigned_vars(&self, vars: &mut Vec<Name>) {
+    match self {
+      Stmt::Assign { pat, val: _, nxt } => {
+        pat.assigned_vars(vars);
+        if let Some(nxt) = nxt {
+          nxt.assigned_vars(vars);
+        }
+      }
+      Stmt::InPlace { op: _, pat, val: _, nxt } => {
+        pat.assigned_vars(vars);
+        nxt.assigned_vars(vars);
+      }
+      Stmt::Ask { pat, val: _, nxt } => {
+        pat.assigned_vars(vars);
+        nxt.assigned_vars(vars);
+      }
+      Stmt::If { cond: _, then, otherwise, nxt } => {
+        then.assigned_vars(vars);
+        otherwise.assigned_vars(vars);
+        if let Some(nxt) = nxt {
+          nxt.assigned_vars(vars);
+        }
+      }
+      Stmt::Match { arms, nxt, .. } | Stmt::Fold { arms, nxt, .. } => {
+        for arm in arms {
+          arm.rgt.assigned_vars(vars);
+        }
+        if let Some(nxt) = nxt {
+          nxt.assigned_vars(vars);
+        }
+      }
+      Stmt::Switch { arms, nxt, .. } => {
+        for arm in arms {
+          arm.assigned_vars(vars);
+        }
+        if let Some(nxt) = nxt {
+          nxt.assigned_vars(vars);
+        }
+      }
+      Stmt::RangeSwitch { arms, default, nxt, .. } => {
+        for (_, arm) in arms {
+          arm.assigned_vars(vars);
+        }
+        default.assigned_vars(vars);
+        if let Some(nxt) = nxt {
+          nxt.assigned_vars(vars);
+        }
+      }
+      Stmt::Bend { step, base, nxt, .. } => {
+        step.assigned_vars(vars);
+        base.assigned_vars(vars);
+        if let Some(nxt) = nxt {
+          nxt.assigned_vars(vars);
+        }
+      }
+      Stmt::While { bod, nxt, .. } | Stmt::For { bod, nxt, .. } | Stmt::With { bod, nxt, .. } => {
+        bod.assigned_vars(vars);
+        if let Some(nxt) = nxt {
+          nxt.assigned_vars(vars);
+        }
+      }
+      // The body of a local definition has its own variables.
+      Stmt::Open { nxt, .. } | Stmt::Use { nxt, .. } | Stmt::LocalDef { nxt, .. } => nxt.assigned_vars(vars),
+      Stmt::Return { .. } | Stmt::Break | Stmt::Pass | Stmt::Err => {}
+    }
+  }
+
+  /// Whether the statement returns from the function it's in.
+  /// The returns in the arms of a 'fold', the branches of a 'bend' and
+  /// the body of a 'with' or a local definition are their own.
+  fn has_return(&self) -> bool {
+    match self {
+      Stmt::Return { .. } => true,
+      Stmt::If { then, otherwise, nxt, .. } => {
+        then.has_return() || otherwise.has_return() || nxt.as_ref().is_some_and(|nxt| nxt.has_return())
+      }
+      Stmt::Match { arms, nxt, .. } => {
+        arms.iter().any(|arm| arm.rgt.has_return()) || nxt.as_ref().is_some_and(|nxt| nxt.has_return())
+      }
+      Stmt::Switch { arms, nxt, .. } => {
+        arms.iter().any(Stmt::has_return) || nxt.as_ref().is_some_and(|nxt| nxt.has_return())
+      }
+      Stmt::RangeSwitch { arms, default, nxt, .. } => {
+        arms.iter().any(|(_, arm)| arm.has_return())
+          || default.has_return()
+          || nxt.as_ref().is_some_and(|nxt| nxt.has_return())
+      }
+      Stmt::Assign { nxt, .. }
+      | Stmt::Fold { nxt, .. }
+      | Stmt::Bend { nxt, .. }
+      | Stmt::While { nxt, .. }
+      | Stmt::For { nxt, .. }
+      | Stmt::With { nxt, .. } => nxt.as_ref().is_some_and(|nxt| nxt.has_return()),
+      Stmt::InPlace { nxt, .. }
+      | Stmt::Ask { nxt, .. }
+      | Stmt::Open { nxt, .. }
+      | Stmt::Use { nxt, .. }
+      | Stmt::LocalDef { nxt, .. } => nxt.has_return(),
+      Stmt::Break | Stmt::Pass | Stmt::Err => false,
+    }
+  }
+
+  /// Puts `last` at the end of the block, where it would continue with the statements after it.
+  fn append(&mut self, last: Stmt) {
+    match self {
+      Stmt::Assign { nxt, .. }
+      | Stmt::If { nxt, .. }
+      | Stmt::Match { nxt, .. }
+      | Stmt::Switch { nxt, .. }
+      | Stmt::RangeSwitch { nxt, .. }
+      | Stmt::Fold { nxt, .. }
+      | Stmt::Bend { nxt, .. }
+      | Stmt::While { nxt, .. }
+      | Stmt::For { nxt, .. }
+      | Stmt::With { nxt, .. } => match nxt {
+        Some(nxt) => nxt.append(last),
+        None => *nxt = Some(Box::new(last)),
+      },
+      Stmt::InPlace { nxt, .. }
+      | Stmt::Ask { nxt, .. }
+      | Stmt::Open { nxt, .. }
+      | Stmt::Use { nxt, .. }
+      | Stmt::LocalDef { nxt, .. } => nxt.append(last),
+      Stmt::Pass => *self = last,
+      Stmt::Return { .. } | Stmt::Break | Stmt::Err => {}
+    }
+  }
+}
+
+/// The block with `last` at its end.
+fn append(mut bod: Stmt, last: Stmt) -> Stmt {
+  bod.append(last);
+  bod
+}
+
+impl AssignPattern {
+  fn assigned_vars(&self, vars: &mut Vec<Name>) {
+    match self {
+      AssignPattern::Var(nam) | AssignPattern::MapSet(nam, _) => vars.push(nam.clone()),
+      AssignPattern::Tup(els) | AssignPattern::Sup(els) => {
+        for el in els {
+          el.assigned_vars(vars);
+        }
+      }
+      AssignPattern::Eraser | AssignPattern::Chn(_) => {}
+    }
+  }
+}
+
+/// Desugars the branches of a statement, each with its own variables,
+/// and then the statement after it, where the variables assigned by the branches are bound.
+fn in_branches<'a>(
+  bound: &mut Vec<Name>,
+  branches: impl IntoIterator<Item = &'a mut Stmt>,
+  nxt: &mut Option<Box<Stmt>>,
+) -> Result<(), String> {
+  let mut assigned = vec![];
+  for branch in branches {
+    let len = bound.len();
+    branch.desugar_loops(bound)?;
+    bound.truncate(len);
+    branch.assigned_vars(&mut assigned);
+  }
+  if let Some(nxt) = nxt {
+    bound.extend(assigned);
+    nxt.desugar_loops(bound)?;
+  }
+  Ok(())
+}
+
+fn while_to_bend(cond: Expr, bod: Stmt, nxt: Option<Box<Stmt>>, bound: &[Name]) -> Result<Stmt, String> {
+  if bod.has_return() {
+    return Err("'return' can't be used inside a 'while' loop. Use 'break' to stop the loop.".to_string());
+  }
+  let vars = state_vars(&bod, bound, &[]);
+  if vars.is_empty() {
+    return Err(
+      "A 'while' loop must assign a variable bound before it, otherwise it can't stop.".to_string(),
+    );
+  }
+  Ok(loop_to_bend(vec![], cond, |fork| append(bod, fork), vars, nxt))
+}
+
+fn for_to_bend(
+  pat: AssignPattern,
+  iter: Expr,
+  bod: Stmt,
+  nxt: Option<Box<Stmt>>,
+  bound: &[Name],
+) -> Result<Stmt, String> {
+  if bod.has_return() {
+    return Err("'return' can't be used inside a 'for' loop. Use 'break' to stop the loop.".to_string());
+  }
+  let mut loop_vars = vec![];
+  pat.assigned_vars(&mut loop_vars);
+  let vars = state_vars(&bod, bound, &loop_vars);
+  let for_iter = || Expr::Var { nam: Name::new(FOR_ITER) };
+
+  match iter {
+    Expr::Call { fun, args, kwargs } if matches!(fun.as_ref(), Expr::Var { nam } if nam == "range") => {
+      if !kwargs.is_empty() || !(1..=2).contains(&args.len()) {
+        return Err(format!("'range' expects 1 or 2 arguments, but got {}.", args.len() + kwargs.len()));
+      }
+      let mut args = args.into_iter();
+      let (start, end) = match (args.next().unwrap(), args.next()) {
+        (end, None) => (Expr::Num { val: Num::U24(0) }, end),
+        (start, Some(end)) => (start, end),
+      };
+      let cond = Expr::Opr {
+        op: Op::LT,
+        lhs: Box::new(for_iter()),
+        rhs: Box::new(Expr::Var { nam: Name::new(FOR_END) }),
+      };
+      let next =
+        Expr::Opr { op: Op::ADD, lhs: Box::new(for_iter()), rhs: Box::new(Expr::Num { val: Num::U24(1) }) };
+      let bod = Stmt::Assign { pat, val: Box::new(for_iter()), nxt: Some(Box::new(bod)) };
+      let bend =
+        loop_to_bend(vec![(Name::new(FOR_ITER), start, next)], cond, |fork| append(bod, fork), vars, nxt);
+      Ok(Stmt::Assign {
+        pat: AssignPattern::Var(Name::new(FOR_END)),
+        val: Box::new(end),
+        nxt: Some(Box::new(bend)),
+      })
+    }
+    iter => {
+      let field = |field: &str| Expr::Var { nam: Name::new(format!("{FOR_ITER}.{field}")) };
+      let bod = Stmt::Assign { pat, val: Box::new(field("head")), nxt: Some(Box::new(bod)) };
+      // The next iteration starts inside the `List/Cons` arm, where the tail is bound,
+      // since the assignments in the arm aren't seen after the match when the body has other loops.
+      let step = |fork| {
+        let mut bod = bod;
+        bod.append(fork);
+        Stmt::Match {
+          arg: Box::new(for_iter()),
+          bnd: Some(Name::new(FOR_ITER)),
+          with_bnd: vec![],
+          with_arg: vec![],
+          arms: vec![
+            MatchArm { lft: Some(Name::new(LNIL)), rgt: Stmt::Break },
+            MatchArm { lft: Some(Name::new(LCONS)), rgt: bod },
+          ],
+          nxt: None,
+        }
+      };
+      let cond = Expr::Num { val: Num::U24(1) };
+      Ok(loop_to_bend(vec![(Name::new(FOR_ITER), iter, field("tail"))], cond, step, vars, nxt))
+    }
+  }
+}
+
+/// The variables bound before a loop that are assigned inside it, other than the `loop_vars`.
+fn state_vars(bod: &Stmt, bound: &[Name], loop_vars: &[Name]) -> Vec<Name> {
+  let mut assigned = vec![];
+  bod.assigned_vars(&mut assigned);
+  let mut vars = vec![];
+  for nam in assigned {
+    if bound.contains(&nam) && !loop_vars.contains(&nam) && !vars.contains(&nam) {
+      vars.push(nam);
+    }
+  }
+  vars
+}
+
+/// A 'bend' that repeats the `step` built with the `fork` to the next iteration while `cond` is true,
+/// carrying the `vars` from one iteration to the next.
+/// The `iter` variables are also carried, with their initial value and the one for the next iteration.
+fn loop_to_bend(
+  iter: Vec<(Name, Expr, Expr)>,
+  cond: Expr,
+  step: impl FnOnce(Stmt) -> Stmt,
+  vars: Vec<Name>,
+  nxt: Option<Box<Stmt>>,
+) -> Stmt {
+  let var_exprs = || vars.iter().map(|nam| Expr::Var { nam: nam.clone() }).collect::<Vec<_>>();
+  let result = || AssignPattern::Var(Name::new(LOOP_RESULT));
+  let (pat, val) = match vars.as_slice() {
+    [] => (AssignPattern::Eraser, Expr::Era),
+    [nam] => (AssignPattern::Var(nam.clone()), Expr::Var { nam: nam.clone() }),
+    _ => {
+      let pats = vars.iter().map(|nam| AssignPattern::Var(nam.clone())).collect();
+      (AssignPattern::Tup(pats), Expr::Tup { els: var_exprs() })
+    }
+  };
+
+  let (iter_bnd, (iter_arg, iter_next)): (Vec<_>, (Vec<_>, Vec<_>)) =
+    iter.into_iter().map(|(bnd, arg, next)| (Some(bnd), (arg, next))).unzip();
+  let args = iter_next.into_iter().chain(var_exprs()).collect();
+  let fork = Expr::Call { fun: Box::new(Expr::Var { nam: Name::new("fork") }), args, kwargs: vec![] };
+  let bod = step(Stmt::Assign { pat: result(), val: Box::new(fork), nxt: None });
+  let base = Stmt::Assign { pat: result(), val: Box::new(val), nxt: None };
+  let nxt = Stmt::Assign { pat, val: Box::new(Expr::Var { nam: Name::new(LOOP_RESULT) }), nxt };
+
+  Stmt::Bend {
+    bnd: iter_bnd.into_iter().chain(vars.iter().cloned().map(Some)).collect(),
+    arg: iter_arg.into_iter().chain(var_exprs()).collect(),
+    cond: Box::new(cond),
+    step: Box::new(bod),
+    base: Box::new(base),
+    nxt: Some(Box::new(nxt)),
+  }
+}
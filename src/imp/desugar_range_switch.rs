@@ -0,0 +1,147 @@
+use super::{AssignPattern, Definition, Expr, Stmt};
+use crate::fun::{Name, Num, Op};
+use std::ops::Range;
+
+impl Definition {
+  /// Converts the `switch` statements with cases other than the consecutive numbers from 0
+  /// into chains of `if` statements, with one comparison per range of numbers in a case.
+  ///
+  /// ```py
+  /// switch x = f(y):
+  ///   case 1, 5..10:
+  ///     A
+  ///   case _:
+  ///     B
+  /// # Becomes
+  /// x = f(y)
+  /// if (x == 1) | ((x >= 5) & (x < 10)):
+  ///   A
+  /// else:
+  ///   B
+  /// ```
+  pub fn desugar_range_switches(&mut self) {
+    self.body.desugar_range_switches();
+  }
+}
+
+impl Stmt {
+  fn desugar_range_switches(&mut self) {
+    match self {
+      Stmt::RangeSwitch { arms, default, nxt, .. } => {
+        for (_, arm) in arms.iter_mut() {
+          arm.desugar_range_switches();
+        }
+        default.desugar_range_switches();
+        if let Some(nxt) = nxt {
+          nxt.desugar_range_switches();
+        }
+        let Stmt::RangeSwitch { arg, bnd, with_bnd, with_arg, arms, default, nxt } = std::mem::take(self)
+        else {
+          unreachable!()
+        };
+        *self = range_switch_to_ifs(*arg, bnd.unwrap(), with_bnd, with_arg, arms, *default, nxt);
+      }
+      Stmt::Assign { nxt, .. } => {
+        if let Some(nxt) = nxt {
+          nxt.desugar_range_switches();
+        }
+      }
+      Stmt::If { then, otherwise, nxt, .. } => {
+        then.desugar_range_switches();
+        otherwise.desugar_range_switches();
+        if let Some(nxt) = nxt {
+          nxt.desugar_range_switches();
+        }
+      }
+      Stmt::Match { arms, nxt, .. } | Stmt::Fold { arms, nxt, .. } => {
+        for arm in arms.iter_mut() {
+          arm.rgt.desugar_range_switches();
+        }
+        if let Some(nxt) = nxt {
+          nxt.desugar_range_switches();
+        }
+      }
+      Stmt::Switch { arms, nxt, .. } => {
+        for arm in arms.iter_mut() {
+          arm.desugar_range_switches();
+        }
+        if let Some(nxt) = nxt {
+          nxt.desugar_range_switches();
+        }
+      }
+      Stmt::Bend { step, base, nxt, .. } => {
+        step.desugar_range_switches();
+        base.desugar_range_switches();
+        if let Some(nxt) = nxt {
+          nxt.desugar_range_switches();
+        }
+      }
+      Stmt::With { bod, nxt, .. } => {
+        bod.desugar_range_switches();
+        if let Some(nxt) = nxt {
+          nxt.desugar_range_switches();
+        }
+      }
+      Stmt::LocalDef { def, nxt } => {
+        def.desugar_range_switches();
+        nxt.desugar_range_switches();
+      }
+      Stmt::InPlace { nxt, .. } | Stmt::Ask { nxt, .. } | Stmt::Open { nxt, .. } | Stmt::Use { nxt, .. } => {
+        nxt.desugar_range_switches();
+      }
+      Stmt::While { .. } | Stmt::For { .. } => unreachable!("Loops are desugared before this pass"),
+      Stmt::Return { .. } | Stmt::Break | Stmt::Pass | Stmt::Err => {}
+    }
+  }
+}
+
+fn range_switch_to_ifs(
+  arg: Expr,
+  bnd: Name,
+  with_bnd: Vec<Option<Name>>,
+  with_arg: Vec<Expr>,
+  arms: Vec<(Vec<Range<u32>>, Stmt)>,
+  default: Stmt,
+  nxt: Option<Box<Stmt>>,
+) -> Stmt {
+  let mut stmt = arms.into_iter().rfold(default, |otherwise, (case, then)| Stmt::If {
+    cond: Box::new(case_cond(&bnd, case)),
+    then: Box::new(then),
+    otherwise: Box::new(otherwise),
+    nxt: None,
+  });
+  if let Stmt::If { nxt: if_nxt, .. } = &mut stmt {
+    *if_nxt = nxt;
+  }
+
+  // The switch arg and the `with` args are bound before the chain so that every case can use them.
+  let binds = std::iter::once((Some(bnd), arg)).chain(with_bnd.into_iter().zip(with_arg));
+  for (nam, val) in binds.rev() {
+    match (nam, val) {
+      (Some(nam), Expr::Var { nam: var }) if nam == var => {}
+      (Some(nam), val) => {
+        stmt = Stmt::Assign { pat: AssignPattern::Var(nam), val: Box::new(val), nxt: Some(Box::new(stmt)) };
+      }
+      (None, _) => {}
+    }
+  }
+  stmt
+}
+
+/// Checks if the value of `var` is in any of the ranges of a switch case.
+fn case_cond(var: &Name, case: Vec<Range<u32>>) -> Expr {
+  let var = || Expr::Var { nam: var.clone() };
+  let num = |val| Expr::Num { val: Num::U24(val) };
+  let opr = |op, lhs, rhs| Expr::Opr { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+
+  let conds = case.into_iter().map(|range| {
+    if range.end == range.start + 1 {
+      opr(Op::EQ, var(), num(range.start))
+    } else if range.start == 0 {
+      opr(Op::LT, var(), num(range.end))
+    } else {
+      opr(Op::AND, opr(Op::GE, var(), num(range.start)), opr(Op::LT, var(), num(range.end)))
+    }
+  });
+  conds.reduce(|acc, cond| opr(Op::OR, acc, cond)).unwrap()
+}
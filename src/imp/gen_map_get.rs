@@ -103,6 +103,8 @@ impl Stmt {
           *self = gen_get(self, substitutions);
         }
       }
+      Stmt::RangeSwitch { .. } => unreachable!("Range switches are desugared before this pass"),
+      Stmt::While { .. } | Stmt::For { .. } => unreachable!("Loops are desugared before this pass"),
       Stmt::Bend { bnd: _, arg: init, cond, step, base, nxt } => {
         step.gen_map_get(id);
         base.gen_map_get(id);
@@ -139,7 +141,7 @@ impl Stmt {
           *self = gen_get(self, substitutions);
         }
       }
-      Stmt::Err => {}
+      Stmt::Break | Stmt::Pass | Stmt::Err => {}
     }
   }
 }
@@ -168,10 +170,16 @@ impl Expr {
         Expr::Lam { bod, .. } => {
           go(bod, substitutions, id);
         }
-        Expr::Opr { lhs, rhs, .. } => {
+        Expr::Opr { lhs, rhs, .. } | Expr::And { lhs, rhs } | Expr::Or { lhs, rhs } => {
           go(lhs, substitutions, id);
           go(rhs, substitutions, id);
         }
+        Expr::Cmp { fst, rest } => {
+          go(fst, substitutions, id);
+          for (_, rhs) in rest {
+            go(rhs, substitutions, id);
+          }
+        }
         Expr::Lst { els } | Expr::Tup { els } | Expr::Sup { els } => {
           for el in els {
             go(el, substitutions, id);
@@ -198,7 +206,7 @@ impl Expr {
           go(left, substitutions, id);
           go(right, substitutions, id);
         }
-        Expr::TreeLeaf { val } => {
+        Expr::TreeLeaf { val } | Expr::Not { val } => {
           go(val, substitutions, id);
         }
         Expr::Era | Expr::Str { .. } | Expr::Var { .. } | Expr::Chn { .. } | Expr::Num { .. } => {}
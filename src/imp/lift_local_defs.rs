@@ -63,6 +63,8 @@ impl Stmt {
         }
         Ok(())
       }
+      Stmt::RangeSwitch { .. } => unreachable!("Range switches are desugared before this pass"),
+      Stmt::While { .. } | Stmt::For { .. } => unreachable!("Loops are desugared before this pass"),
       Stmt::Switch { arg: _, bnd: _, with_bnd: _, with_arg: _, arms, nxt } => {
         for arm in arms.iter_mut() {
           arm.lift_local_defs(parent, defs, gen)?;
@@ -93,7 +95,7 @@ impl Stmt {
       | Stmt::Open { typ: _, var: _, nxt }
       | Stmt::Use { nam: _, val: _, nxt } => nxt.lift_local_defs(parent, defs, gen),
 
-      Stmt::Return { .. } | Stmt::Err => Ok(()),
+      Stmt::Return { .. } | Stmt::Break | Stmt::Pass | Stmt::Err => Ok(()),
     }
   }
 }
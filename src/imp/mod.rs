@@ -1,11 +1,14 @@
+pub mod desugar_loops;
+pub mod desugar_range_switch;
 pub mod gen_map_get;
 pub mod lift_local_defs;
 mod order_kwargs;
 pub mod parser;
 pub mod to_fun;
 
-use crate::fun::{CtrField, Name, Num, Op};
+use crate::fun::{CtrField, Name, Num, Op, Pattern};
 use interner::global::GlobalString;
+use std::ops::Range;
 
 #[derive(Clone, Debug)]
 pub enum Expr {
@@ -19,10 +22,18 @@ pub enum Expr {
   Num { val: Num },
   // {fun}({args},{kwargs},)
   Call { fun: Box<Expr>, args: Vec<Expr>, kwargs: Vec<(Name, Expr)> },
-  // "lambda" {names}* ":" {bod}
-  Lam { names: Vec<(Name, bool)>, bod: Box<Expr> },
+  // "lambda" {pats}* ":" {bod}
+  Lam { pats: Vec<Pattern>, bod: Box<Expr> },
   // {lhs} {op} {rhs}
   Opr { op: Op, lhs: Box<Expr>, rhs: Box<Expr> },
+  // {fst} ({op} {rhs})+, with 2 or more comparison operators
+  Cmp { fst: Box<Expr>, rest: Vec<(Op, Expr)> },
+  // {lhs} "and" {rhs}
+  And { lhs: Box<Expr>, rhs: Box<Expr> },
+  // {lhs} "or" {rhs}
+  Or { lhs: Box<Expr>, rhs: Box<Expr> },
+  // "not" {val}
+  Not { val: Box<Expr> },
   // "\"" ... "\""
   Str { val: GlobalString },
   // "[" ... "]"
@@ -97,8 +108,8 @@ pub enum Stmt {
   },
   // "if" {cond} ":"
   //  {then}
-  // "else" ":"
-  //  {otherwise}
+  // ("else" ":"
+  //  {otherwise})?
   // <nxt>?
   If {
     cond: Box<Expr>,
@@ -130,6 +141,21 @@ pub enum Stmt {
     arms: Vec<Stmt>,
     nxt: Option<Box<Stmt>>,
   },
+  // "switch" ({bind} "=")? {arg} ({with_clause})? ":"
+  //   case ({num} | {num} ".." {num}),+ ":" {rgt}
+  //   ...
+  //   case "_" ":" {default}
+  // <nxt>?
+  // A switch with cases other than the consecutive numbers from 0, see `desugar_range_switches`.
+  RangeSwitch {
+    arg: Box<Expr>,
+    bnd: Option<Name>,
+    with_bnd: Vec<Option<Name>>,
+    with_arg: Vec<Expr>,
+    arms: Vec<(Vec<Range<u32>>, Stmt)>,
+    default: Box<Stmt>,
+    nxt: Option<Box<Stmt>>,
+  },
   // "bend" ({bind} ("=" {init})?)* "while" {cond} ":"
   //   {step}
   // "then" ":"
@@ -155,6 +181,25 @@ pub enum Stmt {
     arms: Vec<MatchArm>,
     nxt: Option<Box<Stmt>>,
   },
+  // "while" {cond} ":"
+  //   {bod}
+  // <nxt>?
+  // Desugared to a 'bend' over the variables assigned by the loop, see `desugar_loops`.
+  While {
+    cond: Box<Expr>,
+    bod: Box<Stmt>,
+    nxt: Option<Box<Stmt>>,
+  },
+  // "for" {pat} "in" {iter} ":"
+  //   {bod}
+  // <nxt>?
+  // Iterates over a list, or over the numbers of a `range(start?, end)`.
+  For {
+    pat: AssignPattern,
+    iter: Box<Expr>,
+    bod: Box<Stmt>,
+    nxt: Option<Box<Stmt>>,
+  },
   // "with" {fun} ":"
   //   {block}
   // <nxt>?
@@ -173,6 +218,12 @@ pub enum Stmt {
   Return {
     term: Box<Expr>,
   },
+  // "break" ";"?
+  // Only in the 'when' branch of a 'bend', where it ends the branch with the 'else' branch.
+  Break,
+  // The missing 'else' of an 'if', or the end of a block after an in-place operation,
+  // that continues with the statements after it.
+  Pass,
   // "open" {typ} ":" {var} ";"? {nxt}
   Open {
     typ: Name,
@@ -213,6 +264,7 @@ pub struct Definition {
 #[derive(Clone, Debug)]
 pub struct Enum {
   pub name: Name,
+  pub params: Vec<Name>,
   pub variants: Vec<Variant>,
 }
 
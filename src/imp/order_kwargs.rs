@@ -50,6 +50,8 @@ impl Stmt {
           nxt.order_kwargs(book)?;
         }
       }
+      Stmt::RangeSwitch { .. } => unreachable!("Range switches are desugared before this pass"),
+      Stmt::While { .. } | Stmt::For { .. } => unreachable!("Loops are desugared before this pass"),
       Stmt::Switch { arg, arms, nxt, .. } => {
         arg.order_kwargs(book)?;
         for arm in arms {
@@ -93,7 +95,7 @@ impl Stmt {
         nxt.order_kwargs(book)?;
       }
       Stmt::Return { term } => term.order_kwargs(book)?,
-      Stmt::Err => {}
+      Stmt::Break | Stmt::Pass | Stmt::Err => {}
     }
     Ok(())
   }
@@ -130,10 +132,16 @@ impl Expr {
         }
       }
       Expr::Lam { bod, .. } => bod.order_kwargs(book)?,
-      Expr::Opr { lhs, rhs, .. } => {
+      Expr::Opr { lhs, rhs, .. } | Expr::And { lhs, rhs } | Expr::Or { lhs, rhs } => {
         lhs.order_kwargs(book)?;
         rhs.order_kwargs(book)?;
       }
+      Expr::Cmp { fst, rest } => {
+        fst.order_kwargs(book)?;
+        for (_, rhs) in rest {
+          rhs.order_kwargs(book)?;
+        }
+      }
       Expr::Lst { els } | Expr::Tup { els } | Expr::Sup { els } => {
         for el in els {
           el.order_kwargs(book)?;
@@ -167,7 +175,7 @@ impl Expr {
         left.order_kwargs(book)?;
         right.order_kwargs(book)?;
       }
-      Expr::TreeLeaf { val } => {
+      Expr::TreeLeaf { val } | Expr::Not { val } => {
         val.order_kwargs(book)?;
       }
       Expr::Era | Expr::Var { .. } | Expr::Chn { .. } | Expr::Num { .. } | Expr::Str { .. } => {}
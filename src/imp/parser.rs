@@ -1,21 +1,29 @@
 use crate::{
   fun::{
-    parser::{is_num_char, Indent, ParseResult, ParserCommons},
-    CtrField, Name, Num, Op, STRINGS,
+    parser::{is_num_char, resolve_infix, Indent, InfixOps, ParseResult, ParserCommons, MAX_INFIX_PREC},
+    CtrField, FanKind, Name, Num, Op, Pattern, Tag, STRINGS,
   },
   imp::{AssignPattern, Definition, Enum, Expr, InPlaceOp, MatchArm, Stmt, Variant},
   maybe_grow,
 };
+use std::{ops::Range, path::PathBuf};
 use TSPL::Parser;
 
+/// The values matched by a switch case, or `None` for the default case.
+type SwitchCase = Option<Vec<Range<u32>>>;
+
 pub struct PyParser<'i> {
   pub input: &'i str,
   pub index: usize,
+  /// The infix operators declared before the definition being parsed.
+  pub infix_ops: InfixOps,
+  /// The directory that the paths of `include` expressions are relative to, instead of the current one.
+  pub dir: Option<PathBuf>,
 }
 
 impl<'a> PyParser<'a> {
   pub fn new(input: &'a str) -> Self {
-    Self { input, index: 0 }
+    Self { input, index: 0, infix_ops: InfixOps::new(), dir: None }
   }
 }
 
@@ -118,6 +126,9 @@ impl<'a> PyParser<'a> {
       // Era
       self.advance_one();
       Expr::Era
+    } else if let Some(contents) = self.try_parse_include(self.dir.clone().as_deref())? {
+      // Include
+      Expr::Str { val: STRINGS.get(contents) }
     } else if let Some(c) = self.peek_one() {
       if is_num_char(c) {
         // Number
@@ -167,15 +178,24 @@ impl<'a> PyParser<'a> {
       }
     }
 
-    // map get
+    // map get or list slice
     if self.starts_with("[") {
-      if let Expr::Var { nam } = base {
-        self.advance_one();
-        let key = self.parse_expr(false)?;
+      let end_idx = *self.index();
+      self.advance_one();
+      self.skip_trivia();
+      let key = if self.starts_with(":") { None } else { Some(self.parse_expr(false)?) };
+      self.skip_trivia();
+      if self.try_consume_exactly(":") {
+        self.skip_trivia();
+        let end = if self.starts_with("]") { None } else { Some(self.parse_expr(false)?) };
         self.consume("]")?;
+        return Ok(list_slice(base, key, end));
+      }
+      let key = key.unwrap();
+      self.consume("]")?;
+      if let Expr::Var { nam } = base {
         return Ok(Expr::MapGet { nam, key: Box::new(key) });
       } else {
-        let end_idx = *self.index();
         return self.expected_spanned("Map variable name", ini_idx..end_idx);
       }
     }
@@ -221,11 +241,15 @@ impl<'a> PyParser<'a> {
     if self.try_consume("}") {
       return Ok(Expr::Map { entries: vec![] });
     }
+    self.skip_trivia();
+    let ini_idx = *self.index();
     let head = self.parse_expr(false)?;
+    let end_idx = *self.index();
     self.skip_trivia();
     if self.try_consume(",") {
       self.parse_sup(head)
     } else if self.try_consume(":") {
+      self.check_map_key(&head, ini_idx..end_idx)?;
       self.parse_map_init(head)
     } else {
       self.expected("',' or ':'")
@@ -277,12 +301,25 @@ impl<'a> PyParser<'a> {
   }
 
   fn parse_map_entry(&mut self) -> ParseResult<(Expr, Expr)> {
+    self.skip_trivia();
+    let ini_idx = *self.index();
     let key = self.parse_expr(false)?;
+    let end_idx = *self.index();
+    self.check_map_key(&key, ini_idx..end_idx)?;
     self.consume(":")?;
     let val = self.parse_expr(false)?;
     Ok((key, val))
   }
 
+  /// The keys of a map are `u24` numbers, so a string can't be one.
+  fn check_map_key(&mut self, key: &Expr, span: Range<usize>) -> ParseResult<()> {
+    if let Expr::Str { .. } = key {
+      let msg = "Map keys must be u24 values, like numbers, characters or symbols, not strings.";
+      return self.with_ctx(Err(msg), span);
+    }
+    Ok(())
+  }
+
   fn parse_list_or_comprehension(&mut self) -> ParseResult<Expr> {
     self.consume_exactly("[")?;
 
@@ -321,19 +358,10 @@ impl<'a> PyParser<'a> {
     }
   }
 
-  /// "λ" (<name> ","?)+ ":" <expr>
+  /// "λ" (<pattern> ","?)+ ":" <expr>
   /// | "open" <type> ":" <var>
   /// | <infix>
   fn parse_expr(&mut self, inline: bool) -> ParseResult<Expr> {
-    fn parse_lam_var(p: &mut PyParser) -> ParseResult<(Name, bool)> {
-      if p.starts_with("$") {
-        p.advance_one();
-        Ok((p.parse_bend_name()?, true))
-      } else {
-        Ok((p.parse_bend_name()?, false))
-      }
-    }
-
     if inline {
       self.skip_trivia_inline()?;
     } else {
@@ -342,12 +370,90 @@ impl<'a> PyParser<'a> {
 
     // lambda
     if self.try_parse_keyword("lambda") | self.try_consume_exactly("λ") {
-      let names = self.list_like(|p| parse_lam_var(p), "", ":", ",", false, 1)?;
+      let pats = self.list_like(|p| p.parse_lam_pattern(), "", ":", ",", false, 1)?;
       let bod = self.parse_expr(inline)?;
-      return Ok(Expr::Lam { names, bod: Box::new(bod) });
+      return Ok(Expr::Lam { pats, bod: Box::new(bod) });
+    }
+
+    self.parse_or_expr(inline)
+  }
+
+  /// <and> ("or" <and>)*
+  fn parse_or_expr(&mut self, inline: bool) -> ParseResult<Expr> {
+    let mut lhs = self.parse_and_expr(inline)?;
+    while self.try_parse_keyword("or") {
+      let rhs = self.parse_and_expr(inline)?;
+      lhs = Expr::Or { lhs: Box::new(lhs), rhs: Box::new(rhs) };
     }
+    Ok(lhs)
+  }
+
+  /// <not> ("and" <not>)*
+  fn parse_and_expr(&mut self, inline: bool) -> ParseResult<Expr> {
+    let mut lhs = self.parse_not_expr(inline)?;
+    while self.try_parse_keyword("and") {
+      let rhs = self.parse_not_expr(inline)?;
+      lhs = Expr::And { lhs: Box::new(lhs), rhs: Box::new(rhs) };
+    }
+    Ok(lhs)
+  }
+
+  /// "not" <not> | <infix>
+  fn parse_not_expr(&mut self, inline: bool) -> ParseResult<Expr> {
+    maybe_grow(|| {
+      if inline {
+        self.skip_trivia_inline()?;
+      } else {
+        self.skip_trivia();
+      }
+      if self.try_parse_keyword("not") {
+        let val = self.parse_not_expr(inline)?;
+        return Ok(Expr::Not { val: Box::new(val) });
+      }
+      self.parse_infix_expr(0, inline)
+    })
+  }
 
-    self.parse_infix_expr(0, inline)
+  /// Pattern of a lambda argument. Patterns other than variables and tuples match on the argument.
+  ///
+  /// <name> | "$" <name> | "*" | <num> | <string>
+  /// | "(" <pattern> ("," <pattern>)* ")"
+  /// | "[" (<pattern> ","?)* "]"
+  /// | <ctr> "(" (<pattern> ","?)* ")"
+  fn parse_lam_pattern(&mut self) -> ParseResult<Pattern> {
+    maybe_grow(|| {
+      self.skip_trivia();
+      if self.try_consume_exactly("*") {
+        return Ok(Pattern::Var(None));
+      }
+      if self.try_consume_exactly("$") {
+        return Ok(Pattern::Chn(self.parse_bend_name()?));
+      }
+      if self.starts_with("(") {
+        let mut els = self.list_like(|p| p.parse_lam_pattern(), "(", ")", ",", true, 1)?;
+        return if els.len() == 1 {
+          Ok(els.pop().unwrap())
+        } else {
+          Ok(Pattern::Fan(FanKind::Tup, Tag::Static, els))
+        };
+      }
+      if self.starts_with("[") {
+        let els = self.list_like(|p| p.parse_lam_pattern(), "[", "]", ",", false, 0)?;
+        return Ok(Pattern::Lst(els));
+      }
+      if self.starts_with("\"") {
+        return Ok(Pattern::Str(STRINGS.get(self.parse_quoted_string()?)));
+      }
+      if self.peek_one().is_some_and(|c| c.is_ascii_digit()) {
+        return Ok(Pattern::Num(self.parse_u32()?));
+      }
+      let nam = self.parse_bend_name()?;
+      if self.starts_with("(") {
+        let els = self.list_like(|p| p.parse_lam_pattern(), "(", ")", ",", false, 0)?;
+        return Ok(Pattern::Ctr(nam, els));
+      }
+      Ok(Pattern::Var(Some(nam)))
+    })
   }
 
   /// Named argument of a function call.
@@ -377,7 +483,7 @@ impl<'a> PyParser<'a> {
       } else {
         self.skip_trivia();
       }
-      if prec > Op::max_precedence() {
+      if prec > MAX_INFIX_PREC {
         return self.parse_simple_expr(inline);
       }
       let mut lhs = self.parse_infix_expr(prec + 1, inline)?;
@@ -386,11 +492,55 @@ impl<'a> PyParser<'a> {
       } else {
         self.skip_trivia();
       }
-      while let Some(op) = self.peek_oper() {
-        if op.precedence() == prec {
+      // Whether `lhs` is a comparison that the next one is chained to.
+      let mut chained = false;
+      loop {
+        let sym = self.peek_op_symbol();
+        if let Some(op) = self.infix_ops.get(sym).filter(|op| op.prec == prec).cloned() {
+          // User defined operators, resolved to function calls.
+          // The ones of this precedence are grouped by their associativity, like in the functional syntax.
+          let ini_idx = *self.index();
+          let mut rest = vec![];
+          let mut op = Some(op);
+          while let Some(nxt) = op {
+            let sym = self.peek_op_symbol();
+            self.advance_many(sym.len());
+            let rhs = self.parse_infix_expr(prec + 1, inline)?;
+            rest.push((nxt, rhs));
+            self.skip_trivia_inline()?;
+            let sym = self.peek_op_symbol();
+            op = self.infix_ops.get(sym).filter(|op| op.prec == prec).cloned();
+          }
+          let end_idx = *self.index();
+          let apply = |fun, lhs, rhs| {
+            let fun = Box::new(Expr::Var { nam: fun });
+            Expr::Call { fun, args: vec![lhs, rhs], kwargs: vec![] }
+          };
+          lhs = match resolve_infix(lhs, rest, &apply) {
+            Ok(lhs) => lhs,
+            Err(msg) => return self.with_ctx(Err(msg), ini_idx..end_idx),
+          };
+          chained = false;
+        } else if self.infix_ops.contains_key(sym) {
+          break;
+        } else if let Some(op) = self.peek_oper() {
+          if op.precedence() != prec {
+            break;
+          }
           self.try_parse_oper().unwrap();
           let rhs = self.parse_infix_expr(prec + 1, inline)?;
-          lhs = Expr::Opr { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+          // Chained comparisons, like `a < b < c`, compare each operand with the next one.
+          lhs = match lhs {
+            Expr::Opr { op: prv, lhs: fst, rhs: mid } if chained && op.is_comparison() => {
+              Expr::Cmp { fst, rest: vec![(prv, *mid), (op, rhs)] }
+            }
+            Expr::Cmp { fst, mut rest } if chained && op.is_comparison() => {
+              rest.push((op, rhs));
+              Expr::Cmp { fst, rest }
+            }
+            lhs => Expr::Opr { op, lhs: Box::new(lhs), rhs: Box::new(rhs) },
+          };
+          chained = op.is_comparison();
           self.skip_trivia_inline()?;
         } else {
           break;
@@ -423,6 +573,8 @@ impl<'a> PyParser<'a> {
     maybe_grow(|| {
       if self.try_parse_keyword("return") {
         self.parse_return()
+      } else if self.try_parse_keyword("break") {
+        self.parse_break()
       } else if self.try_parse_keyword("def") {
         self.parse_local_def(indent)
       } else if self.try_parse_keyword("if") {
@@ -435,6 +587,10 @@ impl<'a> PyParser<'a> {
         self.parse_fold(indent)
       } else if self.try_parse_keyword("bend") {
         self.parse_bend(indent)
+      } else if self.try_parse_keyword("while") {
+        self.parse_while(indent)
+      } else if self.try_parse_keyword("for") {
+        self.parse_for(indent)
       } else if self.try_parse_keyword("with") {
         self.parse_with(indent)
       } else if self.try_parse_keyword("open") {
@@ -498,8 +654,13 @@ impl<'a> PyParser<'a> {
       let val = self.parse_expr(true)?;
       self.skip_trivia_inline()?;
       self.try_consume_exactly(";");
-      self.consume_indent_exactly(*indent)?;
-      let (nxt, nxt_indent) = self.parse_statement(indent)?;
+      if !self.is_eof() {
+        self.consume_new_line()?;
+      }
+      let nxt_indent = self.advance_newlines()?;
+      // At the end of a block, continues with the statements after the block.
+      let (nxt, nxt_indent) =
+        if nxt_indent == *indent { self.parse_statement(indent)? } else { (Stmt::Pass, nxt_indent) };
       let stmt = Stmt::InPlace { op, pat: Box::new(pat), val: Box::new(val), nxt: Box::new(nxt) };
       return Ok((stmt, nxt_indent));
     }
@@ -550,6 +711,16 @@ impl<'a> PyParser<'a> {
     Ok((Stmt::Return { term: Box::new(term) }, indent))
   }
 
+  fn parse_break(&mut self) -> ParseResult<(Stmt, Indent)> {
+    self.skip_trivia_inline()?;
+    self.try_consume_exactly(";");
+    if !self.is_eof() {
+      self.consume_new_line()?;
+    }
+    let indent = self.advance_newlines()?;
+    Ok((Stmt::Break, indent))
+  }
+
   fn parse_if(&mut self, indent: &mut Indent) -> ParseResult<(Stmt, Indent)> {
     let cond = self.parse_expr(true)?;
     self.skip_trivia_inline()?;
@@ -557,34 +728,35 @@ impl<'a> PyParser<'a> {
     indent.enter_level();
 
     self.consume_indent_exactly(*indent)?;
-    let (then, nxt_indent) = self.parse_statement(indent)?;
+    let (then, mut nxt_indent) = self.parse_statement(indent)?;
     indent.exit_level();
 
-    if nxt_indent != *indent {
-      return self.expected_indent(*indent, nxt_indent);
-    }
     let mut elifs = Vec::new();
-    while self.try_parse_keyword("elif") {
+    while nxt_indent == *indent && self.try_parse_keyword("elif") {
       let cond = self.parse_expr(true)?;
       self.skip_trivia_inline()?;
       self.consume_exactly(":")?;
       indent.enter_level();
       self.consume_indent_exactly(*indent)?;
-      let (then, nxt_indent) = self.parse_statement(indent)?;
+      let (then, elif_indent) = self.parse_statement(indent)?;
       indent.exit_level();
-
-      if nxt_indent != *indent {
-        return self.expected_indent(*indent, nxt_indent);
-      }
+      nxt_indent = elif_indent;
       elifs.push((cond, then));
     }
-    self.parse_keyword("else")?;
-    self.skip_trivia_inline()?;
-    self.consume_exactly(":")?;
-    indent.enter_level();
+    // Without an 'else', the statements after the 'if' run when the condition is false.
+    let otherwise = if nxt_indent == *indent && self.try_parse_keyword("else") {
+      self.skip_trivia_inline()?;
+      self.consume_exactly(":")?;
+      indent.enter_level();
 
-    self.consume_indent_exactly(*indent)?;
-    let (otherwise, nxt_indent) = self.parse_statement(indent)?;
+      self.consume_indent_exactly(*indent)?;
+      let (otherwise, else_indent) = self.parse_statement(indent)?;
+      indent.exit_level();
+      nxt_indent = else_indent;
+      otherwise
+    } else {
+      Stmt::Pass
+    };
     let otherwise = elifs.into_iter().rfold(otherwise, |acc, (cond, then)| Stmt::If {
       cond: Box::new(cond),
       then: Box::new(then),
@@ -592,7 +764,6 @@ impl<'a> PyParser<'a> {
       nxt: None,
     });
 
-    indent.exit_level();
     if nxt_indent == *indent {
       let (nxt, nxt_indent) = self.parse_statement(indent)?;
       let stmt = Stmt::If {
@@ -617,10 +788,10 @@ impl<'a> PyParser<'a> {
     indent.enter_level();
 
     self.consume_indent_exactly(*indent)?;
-    let (case, mut nxt_indent) = self.parse_match_case(indent)?;
+    let (case, mut nxt_indent) = self.parse_match_case(indent, bnd.as_ref())?;
     let mut arms = vec![case];
     while nxt_indent == *indent {
-      let (case, nxt_indent_) = self.parse_match_case(indent)?;
+      let (case, nxt_indent_) = self.parse_match_case(indent, bnd.as_ref())?;
       nxt_indent = nxt_indent_;
       arms.push(case);
     }
@@ -674,7 +845,12 @@ impl<'a> PyParser<'a> {
     }
   }
 
-  fn parse_match_case(&mut self, indent: &mut Indent) -> ParseResult<(MatchArm, Indent)> {
+  /// Parses a `case` of a `match` or `fold`.
+  ///
+  /// In a `match`, `bnd` is the name of the matched value, which can also be bound to another
+  /// name with `case Ctr as name:`. Since the value is rebuilt from its fields inside the arm,
+  /// this becomes an assignment of `bnd` to the name at the start of the arm.
+  fn parse_match_case(&mut self, indent: &mut Indent, bnd: Option<&Name>) -> ParseResult<(MatchArm, Indent)> {
     self.parse_keyword("case")?;
     self.skip_trivia_inline()?;
     let pat = if self.try_consume_exactly("_") {
@@ -684,14 +860,28 @@ impl<'a> PyParser<'a> {
       Some(nam)
     };
     self.skip_trivia_inline()?;
+    let as_bnd = match bnd {
+      Some(bnd) if self.try_parse_keyword("as") => {
+        self.skip_trivia_inline()?;
+        let nam = self.labelled(|p| p.parse_bend_name(), "name of the 'as' binding")?;
+        self.skip_trivia_inline()?;
+        Some((nam, bnd.clone()))
+      }
+      _ => None,
+    };
     self.consume_exactly(":")?;
     self.consume_new_line()?;
     indent.enter_level();
 
     self.consume_indent_exactly(*indent)?;
-    let (body, nxt_indent) = self.parse_statement(indent)?;
+    let (mut body, nxt_indent) = self.parse_statement(indent)?;
     indent.exit_level();
 
+    if let Some((nam, bnd)) = as_bnd {
+      let val = Box::new(Expr::Var { nam: bnd });
+      body = Stmt::Assign { pat: AssignPattern::Var(nam), val, nxt: Some(Box::new(body)) };
+    }
+
     let stmt = MatchArm { lft: pat, rgt: body };
     Ok((stmt, nxt_indent))
   }
@@ -703,58 +893,73 @@ impl<'a> PyParser<'a> {
     indent.enter_level();
 
     self.consume_indent_exactly(*indent)?;
-    let ini_idx = *self.index();
-    let (fst_case, fst_stmt, mut nxt_indent) = self.parse_switch_case(indent)?;
-    let end_idx = *self.index();
-    if fst_case != Some(0) {
-      return self.expected_spanned("case 0", ini_idx..end_idx);
-    }
-    let mut arms = vec![fst_stmt];
-    let mut should_continue = fst_case == Some(0);
-    let mut expected_num = 1;
-    while should_continue {
+    let mut cases = vec![];
+    let mut seen = vec![];
+    let (default, mut nxt_indent) = loop {
+      let ini_idx = *self.index();
+      let (case, stmt, nxt_indent) = self.parse_switch_case(indent, &mut seen)?;
+      let end_idx = *self.index();
+      let Some(case) = case else {
+        if cases.is_empty() {
+          return self.expected_spanned("numbered case", ini_idx..end_idx);
+        }
+        break (stmt, nxt_indent);
+      };
       if nxt_indent != *indent {
         return self.expected_indent(*indent, nxt_indent);
       }
-      let (case, stmt, nxt_indent_) = self.parse_switch_case(indent)?;
-      nxt_indent = nxt_indent_;
-      if let Some(case) = case {
-        if case != expected_num {
-          return self.expected(&format!("case {}", expected_num));
-        }
-        should_continue = true;
-        arms.push(stmt);
-        expected_num += 1;
-      } else {
-        should_continue = false;
-        arms.push(stmt);
-      }
-    }
+      cases.push((case, stmt));
+    };
     indent.exit_level();
-    if nxt_indent == *indent {
-      let (nxt, nxt_indent) = self.parse_statement(indent)?;
-      let stmt = Stmt::Switch { arg: Box::new(arg), bnd, with_bnd, with_arg, arms, nxt: Some(Box::new(nxt)) };
-      Ok((stmt, nxt_indent))
+
+    let nxt = if nxt_indent == *indent {
+      let (nxt, nxt_indent_) = self.parse_statement(indent)?;
+      nxt_indent = nxt_indent_;
+      Some(Box::new(nxt))
     } else {
-      let stmt = Stmt::Switch { arg: Box::new(arg), bnd, with_bnd, with_arg, arms, nxt: None };
-      Ok((stmt, nxt_indent))
-    }
+      None
+    };
+
+    // Switches on the consecutive numbers from 0 are native, any others are desugared later.
+    let is_native = cases
+      .iter()
+      .enumerate()
+      .all(|(i, (case, _))| matches!(case.as_slice(), [r] if *r == (i as u32..i as u32 + 1)));
+    let stmt = if is_native {
+      let mut arms = cases.into_iter().map(|(_, stmt)| stmt).collect::<Vec<_>>();
+      arms.push(default);
+      Stmt::Switch { arg: Box::new(arg), bnd, with_bnd, with_arg, arms, nxt }
+    } else {
+      let default = Box::new(default);
+      Stmt::RangeSwitch { arg: Box::new(arg), bnd, with_bnd, with_arg, arms: cases, default, nxt }
+    };
+    Ok((stmt, nxt_indent))
   }
 
-  fn parse_switch_case(&mut self, indent: &mut Indent) -> ParseResult<(Option<u32>, Stmt, Indent)> {
+  /// "case" ("_" | ({num} | {num} ".." {num}),+) ":" {stmt}
+  /// The values of the previous cases are in `seen`, to check that each value is only matched by one case.
+  fn parse_switch_case(
+    &mut self,
+    indent: &mut Indent,
+    seen: &mut Vec<Range<u32>>,
+  ) -> ParseResult<(SwitchCase, Stmt, Indent)> {
     self.parse_keyword("case")?;
     self.skip_trivia_inline()?;
-    let case = if let Some(c) = self.peek_one() {
-      match c {
-        '_' => {
-          self.advance_one();
-          None
+    let case = if self.try_consume_exactly("_") {
+      None
+    } else {
+      let mut case = vec![];
+      loop {
+        let range = self.parse_switch_range(seen)?;
+        seen.push(range.clone());
+        case.push(range);
+        self.skip_trivia_inline()?;
+        if !self.try_consume_exactly(",") {
+          break;
         }
-        c if c.is_ascii_digit() => Some(self.parse_u32()?),
-        _ => return self.expected("number or '_'"),
+        self.skip_trivia_inline()?;
       }
-    } else {
-      return self.expected("number or '_'")?;
+      Some(case)
     };
 
     self.skip_trivia_inline()?;
@@ -780,10 +985,10 @@ impl<'a> PyParser<'a> {
     indent.enter_level();
 
     self.consume_indent_exactly(*indent)?;
-    let (case, mut nxt_indent) = self.parse_match_case(indent)?;
+    let (case, mut nxt_indent) = self.parse_match_case(indent, None)?;
     let mut arms = vec![case];
     while nxt_indent == *indent {
-      let (case, nxt_indent_) = self.parse_match_case(indent)?;
+      let (case, nxt_indent_) = self.parse_match_case(indent, None)?;
       nxt_indent = nxt_indent_;
       arms.push(case);
     }
@@ -860,6 +1065,63 @@ impl<'a> PyParser<'a> {
     }
   }
 
+  /// "while" <cond> ":"
+  ///   <bod>
+  /// <nxt>?
+  fn parse_while(&mut self, indent: &mut Indent) -> ParseResult<(Stmt, Indent)> {
+    let cond = self.parse_expr(true)?;
+    self.skip_trivia_inline()?;
+    self.consume_exactly(":")?;
+    self.consume_new_line()?;
+    indent.enter_level();
+
+    self.consume_indent_exactly(*indent)?;
+    let (bod, nxt_indent) = self.parse_statement(indent)?;
+    indent.exit_level();
+
+    if nxt_indent == *indent {
+      let (nxt, nxt_indent) = self.parse_statement(indent)?;
+      let stmt = Stmt::While { cond: Box::new(cond), bod: Box::new(bod), nxt: Some(Box::new(nxt)) };
+      Ok((stmt, nxt_indent))
+    } else {
+      let stmt = Stmt::While { cond: Box::new(cond), bod: Box::new(bod), nxt: None };
+      Ok((stmt, nxt_indent))
+    }
+  }
+
+  /// "for" <pat> "in" <iter> ":"
+  ///   <bod>
+  /// <nxt>?
+  fn parse_for(&mut self, indent: &mut Indent) -> ParseResult<(Stmt, Indent)> {
+    self.skip_trivia_inline()?;
+    let ini_idx = *self.index();
+    let pat = self.parse_assign_pattern()?;
+    if let AssignPattern::MapSet(..) = pat {
+      let end_idx = *self.index();
+      return self.expected_spanned("loop variable", ini_idx..end_idx);
+    }
+    self.skip_trivia_inline()?;
+    self.parse_keyword("in")?;
+    let iter = self.parse_expr(true)?;
+    self.skip_trivia_inline()?;
+    self.consume_exactly(":")?;
+    self.consume_new_line()?;
+    indent.enter_level();
+
+    self.consume_indent_exactly(*indent)?;
+    let (bod, nxt_indent) = self.parse_statement(indent)?;
+    indent.exit_level();
+
+    if nxt_indent == *indent {
+      let (nxt, nxt_indent) = self.parse_statement(indent)?;
+      let stmt = Stmt::For { pat, iter: Box::new(iter), bod: Box::new(bod), nxt: Some(Box::new(nxt)) };
+      Ok((stmt, nxt_indent))
+    } else {
+      let stmt = Stmt::For { pat, iter: Box::new(iter), bod: Box::new(bod), nxt: None };
+      Ok((stmt, nxt_indent))
+    }
+  }
+
   /// "with" <typ> ":"
   ///   <bod>
   /// <nxt>?
@@ -1012,6 +1274,7 @@ impl<'a> PyParser<'a> {
 
     self.skip_trivia_inline()?;
     let typ_name = self.parse_top_level_name()?;
+    let params = self.parse_type_params()?;
     self.skip_trivia_inline()?;
     self.consume_exactly(":")?;
     self.consume_new_line()?;
@@ -1029,7 +1292,7 @@ impl<'a> PyParser<'a> {
     }
     indent.exit_level();
 
-    let enum_ = Enum { name: typ_name, variants };
+    let enum_ = Enum { name: typ_name, params, variants };
     Ok((enum_, nxt_indent))
   }
 
@@ -1044,7 +1307,7 @@ impl<'a> PyParser<'a> {
     Ok(Variant { name: ctr_name, fields })
   }
 
-  pub fn parse_object(&mut self, indent: Indent) -> ParseResult<(Variant, Indent)> {
+  pub fn parse_object(&mut self, indent: Indent) -> ParseResult<(Variant, Vec<Name>, Indent)> {
     if indent != Indent::Val(0) {
       let msg = "Indentation error. Types defined with 'object' must be at the start of the line.";
       let idx = *self.index();
@@ -1053,6 +1316,7 @@ impl<'a> PyParser<'a> {
 
     self.skip_trivia_inline()?;
     let name = self.parse_top_level_name()?;
+    let params = self.parse_type_params()?;
     self.skip_trivia_inline()?;
     let fields = if self.starts_with("{") {
       self.list_like(|p| p.parse_variant_field(), "{", "}", ",", true, 0)?
@@ -1063,14 +1327,16 @@ impl<'a> PyParser<'a> {
       self.consume_new_line()?;
     }
     let nxt_indent = self.advance_newlines()?;
-    Ok((Variant { name, fields }, nxt_indent))
+    Ok((Variant { name, fields }, params, nxt_indent))
   }
 
   fn parse_variant_field(&mut self) -> ParseResult<CtrField> {
     let rec = self.try_consume_exactly("~");
     self.skip_trivia();
     let nam = self.parse_bend_name()?;
-    Ok(CtrField { nam, rec })
+    self.skip_trivia();
+    let typ = if self.starts_with(":") { Some(self.parse_field_type(rec)?) } else { None };
+    Ok(CtrField { nam, rec, typ })
   }
 
   fn expected_indent<T>(&mut self, expected: Indent, got: Indent) -> ParseResult<T> {
@@ -1099,6 +1365,19 @@ impl<'a> PyParser<'a> {
   }
 }
 
+/// `xs[start:end]`, the elements of a list from `start` up to, but not including, `end`.
+/// Without a `start` it begins at the head of the list, and without an `end` it goes until its last element.
+fn list_slice(list: Expr, start: Option<Expr>, end: Option<Expr>) -> Expr {
+  let call =
+    |fun: &str, args| Expr::Call { fun: Box::new(Expr::Var { nam: Name::new(fun) }), args, kwargs: vec![] };
+  match (start, end) {
+    (Some(start), Some(end)) => call("List/slice", vec![list, start, end]),
+    (Some(start), None) => call("List/drop", vec![list, start]),
+    (None, Some(end)) => call("List/take", vec![list, end]),
+    (None, None) => list,
+  }
+}
+
 impl Op {
   fn precedence(&self) -> usize {
     match self {
@@ -1123,7 +1402,8 @@ impl Op {
       Op::LOG => todo!(),
     }
   }
-  fn max_precedence() -> usize {
-    8
+
+  fn is_comparison(&self) -> bool {
+    matches!(self, Op::LT | Op::GT | Op::LE | Op::GE)
   }
 }
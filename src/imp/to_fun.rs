@@ -1,8 +1,11 @@
 use super::{AssignPattern, Definition, Expr, InPlaceOp, Stmt};
-use crate::fun::{
-  self,
-  builtins::{LCONS, LNIL},
-  Name,
+use crate::{
+  fun::{
+    self,
+    builtins::{LCONS, LNIL},
+    Name,
+  },
+  maybe_grow,
 };
 
 impl Definition {
@@ -10,7 +13,7 @@ impl Definition {
     let body = self.body.into_fun().map_err(|e| format!("In function '{}': {}", self.name, e))?;
     let body = match body {
       StmtToFun::Return(term) => term,
-      StmtToFun::Assign(..) => {
+      StmtToFun::Assign(..) | StmtToFun::Continue(..) => {
         return Err(format!("Function '{}' doesn't end with a return statement", self.name));
       }
     };
@@ -18,7 +21,7 @@ impl Definition {
     let rule =
       fun::Rule { pats: self.params.into_iter().map(|param| fun::Pattern::Var(Some(param))).collect(), body };
 
-    let def = fun::Definition { name: self.name, rules: vec![rule], builtin };
+    let def = fun::Definition::new(self.name, vec![rule], builtin);
     Ok(def)
   }
 }
@@ -45,32 +48,41 @@ impl AssignPattern {
 enum StmtToFun {
   Return(fun::Term),
   Assign(fun::Pattern, fun::Term),
+  /// Returns in some paths, and in the others continues with the statements that come next,
+  /// which are put in place of the `CONT` variables.
+  Continue(fun::Term),
+}
+
+/// Where the statements that come after a statement that returns early are put.
+const CONT: &str = "%cont";
+/// Where the 'else' branch of a 'bend' is put when its 'when' branch breaks.
+const BREAK: &str = "%break";
+
+impl StmtToFun {
+  /// Wraps the term of the statement, keeping how it ends.
+  fn map(self, f: impl FnOnce(fun::Term) -> fun::Term) -> StmtToFun {
+    match self {
+      StmtToFun::Return(term) => StmtToFun::Return(f(term)),
+      StmtToFun::Assign(pat, term) => StmtToFun::Assign(pat, f(term)),
+      StmtToFun::Continue(term) => StmtToFun::Continue(f(term)),
+    }
+  }
 }
 
 impl Stmt {
   fn into_fun(self) -> Result<StmtToFun, String> {
-    // TODO: Refactor this to not repeat everything.
     // TODO: When we have an error with an assignment, we should show the offending assignment (eg. "{pat} = ...").
     let stmt_to_fun = match self {
       Stmt::LocalDef { .. } => todo!(),
       Stmt::Assign { pat: AssignPattern::MapSet(map, key), val, nxt: Some(nxt) } => {
-        let (nxt_pat, nxt) = match nxt.into_fun()? {
-          StmtToFun::Return(term) => (None, term),
-          StmtToFun::Assign(pat, term) => (Some(pat), term),
-        };
-        let term = fun::Term::Let {
+        nxt.into_fun()?.map(|nxt| fun::Term::Let {
           pat: Box::new(fun::Pattern::Var(Some(map.clone()))),
           val: Box::new(fun::Term::call(
             fun::Term::Ref { nam: fun::Name::new("Map/set") },
             [fun::Term::Var { nam: map }, key.to_fun(), val.to_fun()],
           )),
           nxt: Box::new(nxt),
-        };
-        if let Some(pat) = nxt_pat {
-          StmtToFun::Assign(pat, term)
-        } else {
-          StmtToFun::Return(term)
-        }
+        })
       }
       Stmt::Assign { pat: AssignPattern::MapSet(..), val: _, nxt: None } => {
         return Err("Branch ends with map assignment.".to_string());
@@ -78,16 +90,11 @@ impl Stmt {
       Stmt::Assign { pat, val, nxt: Some(nxt) } => {
         let pat = pat.into_fun();
         let val = val.to_fun();
-        let (nxt_pat, nxt) = match nxt.into_fun()? {
-          StmtToFun::Return(term) => (None, term),
-          StmtToFun::Assign(pat, term) => (Some(pat), term),
-        };
-        let term = fun::Term::Let { pat: Box::new(pat), val: Box::new(val), nxt: Box::new(nxt) };
-        if let Some(pat) = nxt_pat {
-          StmtToFun::Assign(pat, term)
-        } else {
-          StmtToFun::Return(term)
-        }
+        nxt.into_fun()?.map(|nxt| fun::Term::Let {
+          pat: Box::new(pat),
+          val: Box::new(val),
+          nxt: Box::new(nxt),
+        })
       }
       Stmt::Assign { pat, val, nxt: None } => {
         let pat = pat.into_fun();
@@ -95,14 +102,11 @@ impl Stmt {
         StmtToFun::Assign(pat, val)
       }
       Stmt::InPlace { op, pat, val, nxt } => {
-        let (nxt_pat, nxt) = match nxt.into_fun()? {
-          StmtToFun::Return(term) => (None, term),
-          StmtToFun::Assign(pat, term) => (Some(pat), term),
-        };
+        let nxt = nxt.into_fun()?;
 
         // if it is a mapper operation
         if let InPlaceOp::Map = op {
-          let term = match &*pat {
+          return Ok(nxt.map(|nxt| match &*pat {
             AssignPattern::MapSet(map, key) => {
               let rhs = fun::Term::call(
                 fun::Term::r#ref("Map/map"),
@@ -118,152 +122,74 @@ impl Stmt {
               let rhs = fun::Term::call(val.to_fun(), [pat.clone().into_fun().to_term()]);
               fun::Term::Let { pat: Box::new(pat.into_fun()), val: Box::new(rhs), nxt: Box::new(nxt) }
             }
-          };
-
-          if let Some(pat) = nxt_pat {
-            return Ok(StmtToFun::Assign(pat, term));
-          } else {
-            return Ok(StmtToFun::Return(term));
-          }
+          }));
         }
 
         // otherwise
         match *pat {
-          AssignPattern::Var(var) => {
-            let term = fun::Term::Let {
-              pat: Box::new(fun::Pattern::Var(Some(var.clone()))),
-              val: Box::new(fun::Term::Oper {
-                opr: op.to_lang_op(),
-                fst: Box::new(fun::Term::Var { nam: var }),
-                snd: Box::new(val.to_fun()),
-              }),
-              nxt: Box::new(nxt),
-            };
-            if let Some(pat) = nxt_pat {
-              StmtToFun::Assign(pat, term)
-            } else {
-              StmtToFun::Return(term)
-            }
-          }
+          AssignPattern::Var(var) => nxt.map(|nxt| fun::Term::Let {
+            pat: Box::new(fun::Pattern::Var(Some(var.clone()))),
+            val: Box::new(fun::Term::Oper {
+              opr: op.to_lang_op(),
+              fst: Box::new(fun::Term::Var { nam: var }),
+              snd: Box::new(val.to_fun()),
+            }),
+            nxt: Box::new(nxt),
+          }),
           AssignPattern::MapSet(map, key) => {
             let temp = Name::new("%0");
             let partial =
               Expr::Opr { op: op.to_lang_op(), lhs: Box::new(Expr::Var { nam: temp.clone() }), rhs: val };
-            let map_fn = Expr::Lam { names: vec![(temp, false)], bod: Box::new(partial) };
+            let map_fn = Expr::Lam { pats: vec![fun::Pattern::Var(Some(temp))], bod: Box::new(partial) };
             let map_term = fun::Term::call(
               fun::Term::r#ref("Map/map"),
               [fun::Term::Var { nam: map.clone() }, key.to_fun(), map_fn.to_fun()],
             );
-            let term = fun::Term::Let {
+            nxt.map(|nxt| fun::Term::Let {
               pat: Box::new(fun::Pattern::Var(Some(map))),
               val: Box::new(map_term),
               nxt: Box::new(nxt),
-            };
-            if let Some(pat) = nxt_pat {
-              StmtToFun::Assign(pat, term)
-            } else {
-              StmtToFun::Return(term)
-            }
+            })
           }
           _ => unreachable!(),
         }
       }
       Stmt::If { cond, then, otherwise, nxt } => {
-        let (pat, then, else_) = match (then.into_fun()?, otherwise.into_fun()?) {
-          (StmtToFun::Return(t), StmtToFun::Return(e)) => (None, t, e),
-          (StmtToFun::Assign(tp, t), StmtToFun::Assign(ep, e)) if tp == ep => (Some(tp), t, e),
-          (StmtToFun::Assign(..), StmtToFun::Assign(..)) => {
-            return Err("'if' branches end with different assignments.".to_string());
-          }
-          (StmtToFun::Return(..), StmtToFun::Assign(..)) => {
-            return Err(
-              "Expected 'else' branch from 'if' to return, but it ends with assignment.".to_string(),
-            );
-          }
-          (StmtToFun::Assign(..), StmtToFun::Return(..)) => {
-            return Err(
-              "Expected 'else' branch from 'if' to end with assignment, but it returns.".to_string(),
-            );
-          }
-        };
-        let arms = vec![else_, then];
-        let term = fun::Term::Swt {
+        let branches = vec![otherwise.into_fun()?, then.into_fun()?];
+        join_branches("'if' branches", branches, nxt, |arms| fun::Term::Swt {
           arg: Box::new(cond.to_fun()),
           bnd: Some(Name::new("%pred")),
           with_bnd: vec![],
           with_arg: vec![],
           pred: Some(Name::new("%pred-1")),
           arms,
-        };
-        wrap_nxt_assign_stmt(term, nxt, pat)?
+        })?
       }
       Stmt::Match { arg, bnd, with_bnd, with_arg, arms, nxt } => {
         let arg = arg.to_fun();
-        let mut fun_arms = vec![];
-        let mut arms = arms.into_iter();
-        let fst = arms.next().unwrap();
-        let (fst_pat, fst_rgt) = match fst.rgt.into_fun()? {
-          StmtToFun::Return(term) => (None, term),
-          StmtToFun::Assign(pat, term) => (Some(pat), term),
-        };
         let with_arg = with_arg.into_iter().map(Expr::to_fun).collect();
-        fun_arms.push((fst.lft, vec![], fst_rgt));
+        let mut ctrs = vec![];
+        let mut branches = vec![];
         for arm in arms {
-          let (arm_pat, arm_rgt) = match arm.rgt.into_fun()? {
-            StmtToFun::Return(term) => (None, term),
-            StmtToFun::Assign(pat, term) => (Some(pat), term),
-          };
-          match (&arm_pat, &fst_pat) {
-            (Some(arm_pat), Some(fst_pat)) if arm_pat != fst_pat => {
-              return Err("'match' arms end with different assignments.".to_string());
-            }
-            (Some(_), None) => {
-              return Err("Expected 'match' arms to end with assignment, but it returns.".to_string());
-            }
-            (None, Some(_)) => {
-              return Err("Expected 'match' arms to return, but it ends with assignment.".to_string());
-            }
-            (Some(_), Some(_)) => fun_arms.push((arm.lft, vec![], arm_rgt)),
-            (None, None) => fun_arms.push((arm.lft, vec![], arm_rgt)),
-          }
+          ctrs.push(arm.lft);
+          branches.push(arm.rgt.into_fun()?);
         }
-        let term = fun::Term::Mat { arg: Box::new(arg), bnd, with_bnd, with_arg, arms: fun_arms };
-        wrap_nxt_assign_stmt(term, nxt, fst_pat)?
+        join_branches("'match' arms", branches, nxt, |rgts| {
+          let arms = ctrs.into_iter().zip(rgts).map(|(ctr, rgt)| (ctr, vec![], rgt)).collect();
+          fun::Term::Mat { arg: Box::new(arg), bnd, with_bnd, with_arg, arms }
+        })?
       }
       Stmt::Switch { arg, bnd, with_bnd, with_arg, arms, nxt } => {
         let arg = arg.to_fun();
-        let mut fun_arms = vec![];
-        let mut arms = arms.into_iter();
-        let fst = arms.next().unwrap();
-        let (fst_pat, fst) = match fst.into_fun()? {
-          StmtToFun::Return(term) => (None, term),
-          StmtToFun::Assign(pat, term) => (Some(pat), term),
-        };
         let with_arg = with_arg.into_iter().map(Expr::to_fun).collect();
-        fun_arms.push(fst);
-        for arm in arms {
-          let (arm_pat, arm) = match arm.into_fun()? {
-            StmtToFun::Return(term) => (None, term),
-            StmtToFun::Assign(pat, term) => (Some(pat), term),
-          };
-          match (&arm_pat, &fst_pat) {
-            (Some(arm_pat), Some(fst_pat)) if arm_pat != fst_pat => {
-              return Err("'switch' arms end with different assignments.".to_string());
-            }
-            (Some(_), None) => {
-              return Err("Expected 'switch' arms to end with assignment, but it returns.".to_string());
-            }
-            (None, Some(_)) => {
-              return Err("Expected 'switch' arms to return, but it ends with assignment.".to_string());
-            }
-            (Some(_), Some(_)) => fun_arms.push(arm),
-            (None, None) => fun_arms.push(arm),
-          }
-        }
-        let pred = Some(Name::new(format!("{}-{}", bnd.clone().unwrap(), fun_arms.len() - 1)));
-        let term = fun::Term::Swt { arg: Box::new(arg), bnd, with_bnd, with_arg, pred, arms: fun_arms };
-        wrap_nxt_assign_stmt(term, nxt, fst_pat)?
+        let branches = arms.into_iter().map(Stmt::into_fun).collect::<Result<Vec<_>, _>>()?;
+        join_branches("'switch' arms", branches, nxt, |arms| {
+          let pred = Some(Name::new(format!("{}-{}", bnd.clone().unwrap(), arms.len() - 1)));
+          fun::Term::Swt { arg: Box::new(arg), bnd, with_bnd, with_arg, pred, arms }
+        })?
       }
+      Stmt::RangeSwitch { .. } => unreachable!("Range switches are desugared before this pass"),
+      Stmt::While { .. } | Stmt::For { .. } => unreachable!("Loops are desugared before this pass"),
       Stmt::Fold { arg, bnd, with_bnd, with_arg, arms, nxt } => {
         let arg = arg.to_fun();
         let mut fun_arms = vec![];
@@ -272,6 +198,7 @@ impl Stmt {
         let (fst_pat, fst_rgt) = match fst.rgt.into_fun()? {
           StmtToFun::Return(term) => (None, term),
           StmtToFun::Assign(pat, term) => (Some(pat), term),
+          StmtToFun::Continue(_) => return Err(early_return_err("fold")),
         };
         fun_arms.push((fst.lft, vec![], fst_rgt));
         let with_arg = with_arg.into_iter().map(Expr::to_fun).collect();
@@ -279,6 +206,7 @@ impl Stmt {
           let (arm_pat, arm_rgt) = match arm.rgt.into_fun()? {
             StmtToFun::Return(term) => (None, term),
             StmtToFun::Assign(pat, term) => (Some(pat), term),
+            StmtToFun::Continue(_) => return Err(early_return_err("fold")),
           };
           match (&arm_pat, &fst_pat) {
             (Some(arm_pat), Some(fst_pat)) if arm_pat != fst_pat => {
@@ -300,23 +228,30 @@ impl Stmt {
       Stmt::Bend { bnd, arg, cond, step, base, nxt } => {
         let arg = arg.into_iter().map(Expr::to_fun).collect();
         let cond = cond.to_fun();
-        let (pat, step, base) = match (step.into_fun()?, base.into_fun()?) {
-          (StmtToFun::Return(s), StmtToFun::Return(b)) => (None, s, b),
-          (StmtToFun::Assign(sp, s), StmtToFun::Assign(bp, b)) if sp == bp => (Some(sp), s, b),
-          (StmtToFun::Assign(..), StmtToFun::Assign(..)) => {
+        let (pat, base) = match base.into_fun()? {
+          StmtToFun::Return(term) => (None, term),
+          StmtToFun::Assign(pat, term) => (Some(pat), term),
+          StmtToFun::Continue(_) => return Err(early_return_err("bend")),
+        };
+        // A 'break' returns the value of the 'else' branch from the 'when' branch.
+        let mut step = *step;
+        step.mark_breaks(pat.is_some())?;
+        let step = match (step.into_fun()?, &pat) {
+          (StmtToFun::Return(step), _) => step,
+          (StmtToFun::Assign(sp, step), Some(bp)) if sp == *bp => step,
+          (StmtToFun::Assign(..), Some(_)) => {
             return Err("'bend' branches end with different assignments.".to_string());
           }
-          (StmtToFun::Return(..), StmtToFun::Assign(..)) => {
-            return Err(
-              "Expected 'else' branch from 'bend' to return, but it ends with assignment.".to_string(),
-            );
-          }
-          (StmtToFun::Assign(..), StmtToFun::Return(..)) => {
+          (StmtToFun::Assign(..), None) => {
             return Err(
               "Expected 'else' branch from 'bend' to end with assignment, but it returns.".to_string(),
             );
           }
+          // The paths that don't break end with the assignment.
+          (StmtToFun::Continue(step), Some(bp)) => fill_holes(step, CONT, &bp.clone().to_term()),
+          (StmtToFun::Continue(_), None) => return Err(early_return_err("bend")),
         };
+        let step = fill_holes(step, BREAK, &base);
         let term =
           fun::Term::Bend { bnd, arg, cond: Box::new(cond), step: Box::new(step), base: Box::new(base) };
         wrap_nxt_assign_stmt(term, nxt, pat)?
@@ -325,52 +260,170 @@ impl Stmt {
         let (pat, bod) = match bod.into_fun()? {
           StmtToFun::Return(term) => (None, term),
           StmtToFun::Assign(pat, term) => (Some(pat), term),
+          StmtToFun::Continue(_) => {
+            return Err("A 'with' block can't return early and continue after the block.".to_string());
+          }
         };
         let term = fun::Term::With { typ, bod: Box::new(bod) };
         wrap_nxt_assign_stmt(term, nxt, pat)?
       }
-      Stmt::Ask { pat, val, nxt } => {
-        let (nxt_pat, nxt) = match nxt.into_fun()? {
-          StmtToFun::Return(term) => (None, term),
-          StmtToFun::Assign(pat, term) => (Some(pat), term),
-        };
-        let term =
-          fun::Term::Ask { pat: Box::new(pat.into_fun()), val: Box::new(val.to_fun()), nxt: Box::new(nxt) };
-        if let Some(pat) = nxt_pat {
-          StmtToFun::Assign(pat, term)
-        } else {
-          StmtToFun::Return(term)
-        }
-      }
+      Stmt::Ask { pat, val, nxt } => nxt.into_fun()?.map(|nxt| fun::Term::Ask {
+        pat: Box::new(pat.into_fun()),
+        val: Box::new(val.to_fun()),
+        nxt: Box::new(nxt),
+      }),
       Stmt::Open { typ, var, nxt } => {
-        let (nxt_pat, nxt) = match nxt.into_fun()? {
-          StmtToFun::Return(term) => (None, term),
-          StmtToFun::Assign(pat, term) => (Some(pat), term),
-        };
-        let term = fun::Term::Open { typ, var, bod: Box::new(nxt) };
-        if let Some(pat) = nxt_pat {
-          StmtToFun::Assign(pat, term)
-        } else {
-          StmtToFun::Return(term)
-        }
-      }
-      Stmt::Use { nam, val, nxt } => {
-        let (nxt_pat, nxt) = match nxt.into_fun()? {
-          StmtToFun::Return(term) => (None, term),
-          StmtToFun::Assign(pat, term) => (Some(pat), term),
-        };
-        let term = fun::Term::Use { nam: Some(nam), val: Box::new(val.to_fun()), nxt: Box::new(nxt) };
-        if let Some(pat) = nxt_pat {
-          StmtToFun::Assign(pat, term)
-        } else {
-          StmtToFun::Return(term)
-        }
+        nxt.into_fun()?.map(|nxt| fun::Term::Open { typ, var, bod: Box::new(nxt) })
       }
+      Stmt::Use { nam, val, nxt } => nxt.into_fun()?.map(|nxt| fun::Term::Use {
+        nam: Some(nam),
+        val: Box::new(val.to_fun()),
+        nxt: Box::new(nxt),
+      }),
       Stmt::Return { term } => StmtToFun::Return(term.to_fun()),
+      Stmt::Break => return Err("'break' can only be used in the 'when' branch of a 'bend'.".to_string()),
+      Stmt::Pass => StmtToFun::Continue(fun::Term::Var { nam: Name::new(CONT) }),
       Stmt::Err => unreachable!(),
     };
     Ok(stmt_to_fun)
   }
+
+  /// Replaces the 'break's in the 'when' branch of a 'bend' with returns of the `BREAK` variable.
+  /// If the 'bend' ends with an assignment, the branch can't return.
+  fn mark_breaks(&mut self, assigns: bool) -> Result<(), String> {
+    match self {
+      Stmt::Break => *self = Stmt::Return { term: Box::new(Expr::Var { nam: Name::new(BREAK) }) },
+      Stmt::Return { .. } if assigns => {
+        return Err(
+          "The 'when' branch of a 'bend' that ends with an assignment can't return. Use 'break' to end it with the 'else' branch."
+            .to_string(),
+        );
+      }
+      Stmt::If { then, otherwise, nxt, .. } => {
+        then.mark_breaks(assigns)?;
+        otherwise.mark_breaks(assigns)?;
+        if let Some(nxt) = nxt {
+          nxt.mark_breaks(assigns)?;
+        }
+      }
+      Stmt::Match { arms, nxt, .. } => {
+        for arm in arms {
+          arm.rgt.mark_breaks(assigns)?;
+        }
+        if let Some(nxt) = nxt {
+          nxt.mark_breaks(assigns)?;
+        }
+      }
+      Stmt::Switch { arms, nxt, .. } => {
+        for arm in arms {
+          arm.mark_breaks(assigns)?;
+        }
+        if let Some(nxt) = nxt {
+          nxt.mark_breaks(assigns)?;
+        }
+      }
+      Stmt::With { bod, nxt, .. } => {
+        bod.mark_breaks(assigns)?;
+        if let Some(nxt) = nxt {
+          nxt.mark_breaks(assigns)?;
+        }
+      }
+      // The branches of a 'bend' and the arms of a 'fold' are other functions, with their own returns.
+      Stmt::Assign { nxt, .. } | Stmt::Bend { nxt, .. } | Stmt::Fold { nxt, .. } => {
+        if let Some(nxt) = nxt {
+          nxt.mark_breaks(assigns)?;
+        }
+      }
+      Stmt::InPlace { nxt, .. }
+      | Stmt::Ask { nxt, .. }
+      | Stmt::Open { nxt, .. }
+      | Stmt::Use { nxt, .. }
+      | Stmt::LocalDef { nxt, .. } => nxt.mark_breaks(assigns)?,
+      Stmt::RangeSwitch { .. } => unreachable!("Range switches are desugared before this pass"),
+      Stmt::While { .. } | Stmt::For { .. } => unreachable!("Loops are desugared before this pass"),
+      Stmt::Return { .. } | Stmt::Pass | Stmt::Err => {}
+    }
+    Ok(())
+  }
+}
+
+/// Joins the branches of an 'if', 'match' or 'switch', whose term is made with `build`.
+///
+/// If all the branches return, or all assign the same pattern, so does the statement.
+/// Otherwise, the branches that don't return continue with the statements that come after it.
+fn join_branches(
+  branches_name: &str,
+  branches: Vec<StmtToFun>,
+  nxt: Option<Box<Stmt>>,
+  build: impl FnOnce(Vec<fun::Term>) -> fun::Term,
+) -> Result<StmtToFun, String> {
+  if branches.iter().all(|branch| matches!(branch, StmtToFun::Return(_))) {
+    let terms = branches.into_iter().map(|branch| branch.into_term()).collect();
+    return wrap_nxt_assign_stmt(build(terms), nxt, None);
+  }
+  if let Some(StmtToFun::Assign(fst_pat, _)) = branches.first() {
+    if branches.iter().all(|branch| matches!(branch, StmtToFun::Assign(..))) {
+      let fst_pat = fst_pat.clone();
+      if branches.iter().any(|branch| matches!(branch, StmtToFun::Assign(pat, _) if *pat != fst_pat)) {
+        return Err(format!("{branches_name} end with different assignments."));
+      }
+      let terms = branches.into_iter().map(|branch| branch.into_term()).collect();
+      return wrap_nxt_assign_stmt(build(terms), nxt, Some(fst_pat));
+    }
+  }
+
+  let cont = fun::Term::Var { nam: Name::new(CONT) };
+  let terms = branches
+    .into_iter()
+    .map(|branch| match branch {
+      StmtToFun::Assign(pat, term) => {
+        fun::Term::Let { pat: Box::new(pat), val: Box::new(term), nxt: Box::new(cont.clone()) }
+      }
+      branch => branch.into_term(),
+    })
+    .collect();
+  let term = build(terms);
+  let Some(nxt) = nxt else {
+    return Ok(StmtToFun::Continue(term));
+  };
+  let stmt_to_fun = match nxt.into_fun()? {
+    StmtToFun::Return(nxt) => StmtToFun::Return(fill_holes(term, CONT, &nxt)),
+    StmtToFun::Assign(pat, nxt) => {
+      let nxt = fun::Term::Let { pat: Box::new(pat), val: Box::new(nxt), nxt: Box::new(cont) };
+      StmtToFun::Continue(fill_holes(term, CONT, &nxt))
+    }
+    StmtToFun::Continue(nxt) => StmtToFun::Continue(fill_holes(term, CONT, &nxt)),
+  };
+  Ok(stmt_to_fun)
+}
+
+impl StmtToFun {
+  fn into_term(self) -> fun::Term {
+    match self {
+      StmtToFun::Return(term) | StmtToFun::Assign(_, term) | StmtToFun::Continue(term) => term,
+    }
+  }
+}
+
+/// Replaces the variable `hole`, which can't be written by users, with copies of `val`.
+fn fill_holes(mut term: fun::Term, hole: &str, val: &fun::Term) -> fun::Term {
+  fn go(term: &mut fun::Term, hole: &str, val: &fun::Term) {
+    maybe_grow(|| {
+      if matches!(term, fun::Term::Var { nam } if nam.as_ref() == hole) {
+        *term = val.clone();
+      } else {
+        for child in term.children_mut() {
+          go(child, hole, val);
+        }
+      }
+    })
+  }
+  go(&mut term, hole, val);
+  term
+}
+
+fn early_return_err(kind: &str) -> String {
+  format!("'{kind}' branches can't return early, since they're the cases of a recursive function.")
 }
 
 impl Expr {
@@ -385,14 +438,53 @@ impl Expr {
         let args = args.into_iter().map(Self::to_fun);
         fun::Term::call(fun.to_fun(), args)
       }
-      Expr::Lam { names, bod } => names.into_iter().rfold(bod.to_fun(), |acc, (name, link)| fun::Term::Lam {
+      Expr::Lam { pats, bod } => pats.into_iter().rfold(bod.to_fun(), |acc, pat| fun::Term::Lam {
         tag: fun::Tag::Static,
-        pat: Box::new(if link { fun::Pattern::Chn(name) } else { fun::Pattern::Var(Some(name)) }),
+        pat: Box::new(pat),
         bod: Box::new(acc),
       }),
       Expr::Opr { op, lhs, rhs } => {
         fun::Term::Oper { opr: op, fst: Box::new(lhs.to_fun()), snd: Box::new(rhs.to_fun()) }
       }
+      // a < b < c => let %cmp1 = b; switch (a < %cmp1) { 0: 0; _: (%cmp1 < c) }
+      Expr::Cmp { fst, mut rest } => {
+        let cmp_var = |i: usize| Name::new(format!("%cmp{i}"));
+        let (last_op, last) = rest.pop().unwrap();
+        let mut term = fun::Term::Oper {
+          opr: last_op,
+          fst: Box::new(fun::Term::Var { nam: cmp_var(rest.len()) }),
+          snd: Box::new(last.to_fun()),
+        };
+        let mut fst = Some(fst.to_fun());
+        for (i, (op, mid)) in rest.into_iter().enumerate().rev() {
+          let lhs = if i == 0 { fst.take().unwrap() } else { fun::Term::Var { nam: cmp_var(i) } };
+          let cmp = fun::Term::Oper {
+            opr: op,
+            fst: Box::new(lhs),
+            snd: Box::new(fun::Term::Var { nam: cmp_var(i + 1) }),
+          };
+          term = fun::Term::Let {
+            pat: Box::new(fun::Pattern::Var(Some(cmp_var(i + 1)))),
+            val: Box::new(mid.to_fun()),
+            nxt: Box::new(bool_switch(cmp, fun::Term::Num { val: fun::Num::U24(0) }, term)),
+          };
+        }
+        term
+      }
+      // a and b => switch a { 0: 0; _: switch b { 0: 0; _: 1 } }
+      Expr::And { lhs, rhs } => {
+        bool_switch(lhs.to_fun(), fun::Term::Num { val: fun::Num::U24(0) }, to_bool(rhs.to_fun()))
+      }
+      // a or b => switch a { 0: switch b { 0: 0; _: 1 }; _: 1 }
+      Expr::Or { lhs, rhs } => {
+        bool_switch(lhs.to_fun(), to_bool(rhs.to_fun()), fun::Term::Num { val: fun::Num::U24(1) })
+      }
+      // not a => switch a { 0: 1; _: 0 }
+      Expr::Not { val } => bool_switch(
+        val.to_fun(),
+        fun::Term::Num { val: fun::Num::U24(1) },
+        fun::Term::Num { val: fun::Num::U24(0) },
+      ),
       Expr::Str { val } => fun::Term::Str { val },
       Expr::Lst { els } => fun::Term::List { els: els.into_iter().map(Self::to_fun).collect() },
       Expr::Tup { els } => fun::Term::Fan {
@@ -462,6 +554,24 @@ impl Expr {
   }
 }
 
+/// A 'switch' on a number used as a boolean, with the arms for when it's false (0) and true.
+/// The arm that isn't taken is never evaluated, so boolean operators short-circuit.
+fn bool_switch(arg: fun::Term, false_arm: fun::Term, true_arm: fun::Term) -> fun::Term {
+  fun::Term::Swt {
+    arg: Box::new(arg),
+    bnd: Some(Name::new("%bool")),
+    with_bnd: vec![],
+    with_arg: vec![],
+    pred: Some(Name::new("%bool-1")),
+    arms: vec![false_arm, true_arm],
+  }
+}
+
+/// Turns a number used as a boolean into 0 or 1.
+fn to_bool(term: fun::Term) -> fun::Term {
+  bool_switch(term, fun::Term::Num { val: fun::Num::U24(0) }, fun::Term::Num { val: fun::Num::U24(1) })
+}
+
 fn map_init(entries: Vec<(Expr, Expr)>) -> fun::Term {
   let mut map = fun::Term::Ref { nam: fun::Name::new("Map/empty") };
   for (key, value) in entries {
@@ -480,16 +590,11 @@ fn wrap_nxt_assign_stmt(
 ) -> Result<StmtToFun, String> {
   if let Some(nxt) = nxt {
     if let Some(pat) = pat {
-      let (nxt_pat, nxt) = match nxt.into_fun()? {
-        StmtToFun::Return(term) => (None, term),
-        StmtToFun::Assign(pat, term) => (Some(pat), term),
-      };
-      let term = fun::Term::Let { pat: Box::new(pat), val: Box::new(term), nxt: Box::new(nxt) };
-      if let Some(pat) = nxt_pat {
-        Ok(StmtToFun::Assign(pat, term))
-      } else {
-        Ok(StmtToFun::Return(term))
-      }
+      Ok(nxt.into_fun()?.map(|nxt| fun::Term::Let {
+        pat: Box::new(pat),
+        val: Box::new(term),
+        nxt: Box::new(nxt),
+      }))
     } else {
       Err("Statement ends with return but is not at end of function.".to_string())
     }
@@ -1,23 +1,29 @@
 use crate::{
-  fun::{book_to_hvm, net_to_term::net_to_term, term_to_net::Labels, Book, Ctx, Term},
+  fun::{
+    check::target_support::check_target_io, net_to_term::net_to_term, term_to_net::Labels, Book, FanKind,
+    Name, Op, Pattern, Tag, Term,
+  },
   hvm::{
-    add_recursive_priority::add_recursive_priority,
-    check_net_size::{check_net_sizes, MAX_NET_SIZE},
-    eta_reduce::eta_reduce_hvm_net,
+    check_net_size::{check_max_def_sizes, net_sizes, NetSizes},
+    eval::{eval_book_with, DefStats, EvalOpts, Paused, TraceOpts},
     hvm_book_show_pretty,
-    inline::inline_hvm_book,
-    mutual_recursion,
-    prune::prune_hvm_book,
   },
+  pipeline::{CompileState, Pipeline},
 };
-use diagnostics::{Diagnostics, DiagnosticsConfig, ERR_INDENT_SIZE};
+use diagnostics::{DiagnosticOrigin, Diagnostics, DiagnosticsConfig, Severity};
 use net::hvm_to_net::hvm_to_net;
+use std::{
+  collections::HashSet,
+  time::{Duration, Instant},
+};
 
+pub mod debugger;
 pub mod diagnostics;
 pub mod fun;
 pub mod hvm;
 pub mod imp;
 pub mod net;
+pub mod pipeline;
 mod utils;
 
 pub use fun::load_book::load_file_to_book;
@@ -25,15 +31,38 @@ pub use fun::load_book::load_file_to_book;
 pub const ENTRY_POINT: &str = "main";
 pub const HVM1_ENTRY_POINT: &str = "Main";
 pub const HVM_OUTPUT_END_MARKER: &str = "Result: ";
-
+/// Line printed by the C and Cuda runtimes when their node buffer is full.
+pub const HVM_OOM_MARKER: &str = "OOM";
+/// Size of the node buffer of the HVM runtimes.
+pub const HVM_NODE_LEN: u64 = 1 << 29;
+/// Run command that evaluates the program in-process instead of calling the HVM binary.
+pub const EVAL_CMD: &str = "eval";
+
+/// Checks that the program compiles.
+///
+/// With a `target` backend, one of [`fun::Cfg::TARGETS`], also checks that the program can run on it:
+/// that it only uses the IO functions of the backend and, for CUDA, that its definitions fit in the net size limit.
 pub fn check_book(
   book: &mut Book,
   diagnostics_cfg: DiagnosticsConfig,
-  compile_opts: CompileOpts,
-) -> Result<Diagnostics, Diagnostics> {
+  mut compile_opts: CompileOpts,
+  check_opts: &CheckOpts,
+) -> Result<CheckResult, Diagnostics> {
+  let target = check_opts.target.as_deref();
+  if target == Some("cuda") {
+    compile_opts.check_net_size = true;
+  }
+  let io_calls = book.io_calls();
   // TODO: Do the checks without having to do full compilation
-  let res = compile_book(book, compile_opts, diagnostics_cfg, None)?;
-  Ok(res.diagnostics)
+  let mut res = compile_book(book, compile_opts, diagnostics_cfg, None)?;
+  if let Some(max) = check_opts.max_def_size {
+    check_max_def_sizes(&res.hvm_book, max, &mut res.diagnostics)?;
+  }
+  if let Some(target) = target {
+    check_target_io(&io_calls, &res.hvm_book, book.hvm_entrypoint(), target, &mut res.diagnostics)?;
+  }
+  let sizes = net_sizes(&res.hvm_book, book.hvm_entrypoint());
+  Ok(CheckResult { diagnostics: res.diagnostics, sizes })
 }
 
 pub fn compile_book(
@@ -42,40 +71,26 @@ pub fn compile_book(
   diagnostics_cfg: DiagnosticsConfig,
   args: Option<Vec<Term>>,
 ) -> Result<CompileResult, Diagnostics> {
-  let mut diagnostics = desugar_book(book, opts.clone(), diagnostics_cfg, args)?;
-
-  let (mut hvm_book, labels) = book_to_hvm(book, &mut diagnostics)?;
-
-  if opts.eta {
-    hvm_book.defs.values_mut().for_each(eta_reduce_hvm_net);
-  }
-
-  mutual_recursion::check_cycles(&hvm_book, &mut diagnostics)?;
-
-  if opts.eta {
-    hvm_book.defs.values_mut().for_each(eta_reduce_hvm_net);
-  }
-
-  if opts.inline {
-    diagnostics.start_pass();
-    if let Err(e) = inline_hvm_book(&mut hvm_book) {
-      diagnostics.add_book_error(format!("During inlining:\n{:ERR_INDENT_SIZE$}{}", "", e));
-    }
-    diagnostics.fatal(())?;
-  }
-
-  if opts.prune {
-    let prune_entrypoints = vec![book.hvm_entrypoint().to_string()];
-    prune_hvm_book(&mut hvm_book, &prune_entrypoints);
-  }
-
-  if opts.check_net_size {
-    check_net_sizes(&hvm_book, &mut diagnostics)?;
-  }
-
-  add_recursive_priority(&mut hvm_book);
+  compile_book_with(book, &Pipeline::new(&opts), diagnostics_cfg, args)
+}
 
-  Ok(CompileResult { hvm_book, labels, diagnostics })
+/// Compiles the book with the given passes, like [`compile_book`].
+pub fn compile_book_with(
+  book: &mut Book,
+  pipeline: &Pipeline,
+  diagnostics_cfg: DiagnosticsConfig,
+  args: Option<Vec<Term>>,
+) -> Result<CompileResult, Diagnostics> {
+  let mut state = CompileState::new(book, diagnostics_cfg, args);
+  pipeline.run(&mut state, None)?;
+
+  let desugar_passes = pipeline.desugar_passes().iter().map(|pass| pass.name).collect::<HashSet<_>>();
+  let (desugar, encode): (Vec<_>, Vec<_>) =
+    state.timings.iter().partition(|(name, _)| desugar_passes.contains(name));
+  let desugar = desugar.iter().map(|(_, time)| *time).sum();
+  let encode = encode.iter().map(|(_, time)| *time).sum();
+  let timings = Timings { desugar, encode, ..Default::default() };
+  Ok(CompileResult { hvm_book: state.hvm_book, labels: state.labels, diagnostics: state.ctx.info, timings })
 }
 
 pub fn desugar_book(
@@ -84,91 +99,127 @@ pub fn desugar_book(
   diagnostics_cfg: DiagnosticsConfig,
   args: Option<Vec<Term>>,
 ) -> Result<Diagnostics, Diagnostics> {
-  let mut ctx = Ctx::new(book, diagnostics_cfg);
-
-  ctx.check_shared_names();
-
-  ctx.set_entrypoint();
-
-  ctx.book.encode_adts(opts.adt_encoding);
-
-  ctx.fix_match_defs()?;
-
-  ctx.apply_args(args)?;
-
-  ctx.desugar_open()?;
-
-  ctx.book.encode_builtins();
-
-  ctx.resolve_refs()?;
-
-  ctx.desugar_match_defs()?;
-
-  ctx.fix_match_terms()?;
-
-  ctx.desugar_bend()?;
-  ctx.desugar_fold()?;
-  ctx.desugar_with_blocks()?;
-
-  ctx.check_unbound_vars()?;
+  desugar_book_until(book, opts, diagnostics_cfg, args, None)
+}
 
-  // Auto match linearization
-  ctx.book.make_var_names_unique();
-  ctx.book.desugar_use();
-  match opts.linearize_matches {
-    OptLevel::Disabled => (),
-    OptLevel::Alt => ctx.book.linearize_match_binds(),
-    OptLevel::Enabled => ctx.book.linearize_matches(),
+/// Runs the desugaring passes, like [`desugar_book`], but stops after the pass named `until`,
+/// leaving the book as that pass left it.
+///
+/// The pass names are the ones listed by [`Pipeline::passes`], before [`pipeline::BOOK_TO_HVM`].
+pub fn desugar_book_until(
+  book: &mut Book,
+  opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+  args: Option<Vec<Term>>,
+  until: Option<&str>,
+) -> Result<Diagnostics, Diagnostics> {
+  let pipeline = Pipeline::new(&opts);
+  let desugar_passes = pipeline.desugar_passes();
+  if let Some(until) = until {
+    let msg = match desugar_passes.iter().find(|pass| pass.name == until) {
+      Some(pass) if pass.enabled => None,
+      Some(_) => Some(format!("The '{until}' pass is disabled with the current options.")),
+      None => {
+        let names = desugar_passes.iter().map(|pass| pass.name).collect::<Vec<_>>().join(", ");
+        Some(format!("Unknown desugaring pass '{until}'. Expected one of: {names}."))
+      }
+    };
+    if let Some(msg) = msg {
+      let mut diags = Diagnostics::new(diagnostics_cfg);
+      diags.add_book_error(msg);
+      return Err(diags);
+    }
   }
-  // Manual match linearization
-  ctx.book.linearize_match_with();
 
-  ctx.book.encode_matches(opts.adt_encoding);
+  let mut state = CompileState::new(book, diagnostics_cfg, args);
+  let until = until.or(desugar_passes.last().map(|pass| pass.name));
+  pipeline.run(&mut state, until)?;
 
-  // sanity check
-  ctx.check_unbound_vars()?;
+  let info = state.ctx.info;
+  if !info.has_errors() {
+    Ok(info)
+  } else {
+    Err(info)
+  }
+}
 
-  ctx.book.make_var_names_unique();
-  ctx.book.desugar_use();
+pub fn run_book(
+  book: Book,
+  run_opts: RunOpts,
+  compile_opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+  args: Option<Vec<Term>>,
+  cmd: &str,
+) -> Result<Option<(Term, RunStats, Diagnostics)>, Diagnostics> {
+  run_book_with(book, run_opts, compile_opts, diagnostics_cfg, args, cmd, &mut |_| true)
+}
 
-  ctx.book.make_var_names_unique();
-  ctx.book.linearize_vars();
+/// Runs a program in-process, like [`run_book`] with [`EVAL_CMD`],
+/// calling `on_break` before each expansion of a definition.
+///
+/// If `on_break` returns false, the evaluation stops and the partially evaluated result is returned,
+/// with a warning.
+pub fn debug_book(
+  book: Book,
+  run_opts: RunOpts,
+  compile_opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+  args: Option<Vec<Term>>,
+  on_break: &mut dyn FnMut(&Breakpoint) -> bool,
+) -> Result<Option<(Term, RunStats, Diagnostics)>, Diagnostics> {
+  run_book_with(book, run_opts, compile_opts, diagnostics_cfg, args, EVAL_CMD, on_break)
+}
 
-  // sanity check
-  ctx.check_unbound_vars()?;
+/// An expansion of a definition where [`debug_book`] paused the evaluation.
+pub struct Breakpoint<'a> {
+  paused: &'a Paused<'a>,
+  book: &'a Book,
+  labels: &'a Labels,
+  linear: bool,
+  adt_encoding: AdtEncoding,
+}
 
-  if opts.float_combinators {
-    ctx.book.float_combinators(MAX_NET_SIZE);
+impl Breakpoint<'_> {
+  /// The name of the definition about to be expanded, as compiled to HVM.
+  pub fn def(&self) -> &str {
+    self.paused.def()
   }
-  // sanity check
-  ctx.check_unbound_refs()?;
 
-  // Optimizing passes
-  ctx.prune(opts.prune);
-  if opts.merge {
-    ctx.book.merge_definitions();
+  /// How many times the definition was expanded before.
+  pub fn calls(&self) -> u64 {
+    self.paused.calls
   }
 
-  ctx.book.expand_main();
+  /// How many rewrites were done so far.
+  pub fn itrs(&self) -> u64 {
+    self.paused.itrs
+  }
 
-  ctx.book.make_var_names_unique();
+  /// What the definition is applied to, in HVM syntax.
+  pub fn arg(&self) -> String {
+    self.paused.arg().map(|tree| tree.show()).unwrap_or_else(|| "<unreadable>".to_string())
+  }
 
-  if !ctx.info.has_errors() {
-    Ok(ctx.info)
-  } else {
-    Err(ctx.info)
+  /// Reads back the partially evaluated program,
+  /// with the parts that weren't reduced yet shown as `<unreduced: N redexes>`.
+  pub fn readback(&self) -> (Term, Diagnostics) {
+    match self.paused.net() {
+      Some(net) => readback_hvm_net(&net, self.book, self.labels, self.linear, self.adt_encoding),
+      None => (Term::Err, Diagnostics::default()),
+    }
   }
 }
 
-pub fn run_book(
+fn run_book_with(
   mut book: Book,
   run_opts: RunOpts,
   compile_opts: CompileOpts,
   diagnostics_cfg: DiagnosticsConfig,
   args: Option<Vec<Term>>,
   cmd: &str,
-) -> Result<Option<(Term, String, Diagnostics)>, Diagnostics> {
-  let CompileResult { hvm_book: core_book, labels, diagnostics } =
+  on_break: &mut dyn FnMut(&Breakpoint) -> bool,
+) -> Result<Option<(Term, RunStats, Diagnostics)>, Diagnostics> {
+  let CompileResult { hvm_book: core_book, labels, diagnostics, mut timings } =
     compile_book(&mut book, compile_opts.clone(), diagnostics_cfg, args)?;
 
   // TODO: Printing should be taken care by the cli module, but we'd
@@ -176,14 +227,226 @@ pub fn run_book(
   // cancel the run if a problem is detected.
   eprint!("{diagnostics}");
 
-  let out = run_hvm(&core_book, cmd, &run_opts)?;
-  let (net, stats) = parse_hvm_output(&out)?;
-  let (term, diags) =
+  let eval_opts = EvalOpts {
+    max_rewrites: run_opts.max_rewrites,
+    max_memory: run_opts.max_memory,
+    profile: run_opts.profile,
+    partial: run_opts.partial,
+    trace: run_opts.trace.clone(),
+  };
+  let mut in_process_opts = run_opts.in_process_opts();
+  if !in_process_opts.is_empty() && cmd != "run" && cmd != EVAL_CMD {
+    return Err(
+      format!(
+        "Rewrite limits, memory limits, per-definition stats, profiling and tracing are not supported with '{cmd}'."
+      )
+      .into(),
+    );
+  }
+
+  // The HVM binary may run in parallel, so deterministic runs are also evaluated in-process,
+  // while the C runtime is restricted to a single thread.
+  if run_opts.deterministic {
+    in_process_opts.push("deterministic runs");
+  }
+  let in_process = cmd == "run" && !in_process_opts.is_empty();
+  if in_process {
+    let mut diags = Diagnostics::default();
+    let msg = format!(
+      "The HVM binary doesn't support {}, so the program is evaluated in-process, one interaction at a time, instead of with 'hvm run'.",
+      join_words(&in_process_opts)
+    );
+    diags.add_diagnostic(msg, Severity::Warning, DiagnosticOrigin::Book);
+    eprint!("{diags}");
+  }
+
+  let mut run_opts = run_opts;
+  if run_opts.deterministic {
+    match cmd {
+      "run-c" if run_opts.threads.is_some_and(|threads| threads != 1) => {
+        return Err("A deterministic run of 'run-c' always uses a single thread.".to_string().into());
+      }
+      "run-c" => run_opts.threads = Some(1),
+      "run-cu" => {
+        return Err(
+          "Deterministic runs are not supported with 'run-cu', since the CUDA runtime is always parallel."
+            .to_string()
+            .into(),
+        );
+      }
+      _ => (),
+    }
+  }
+
+  if run_opts.fallback_to_c && cmd != "run-cu" {
+    return Err("Falling back to the C runtime is only supported with 'run-cu'.".to_string().into());
+  }
+  if run_opts.pin_threads.is_some() && cmd != "run-c" {
+    return Err("Pinning threads to CPUs is only supported with 'run-c'.".to_string().into());
+  }
+
+  let has_sizes =
+    run_opts.heap_size.is_some() || run_opts.threads.is_some() || run_opts.pin_threads.is_some();
+  if has_sizes && !matches!(cmd, "run-c" | "run-cu") {
+    return Err(
+      "The heap size and thread count are only supported with 'run-c' and 'run-cu'.".to_string().into(),
+    );
+  }
+  if run_opts.threads.is_some() && cmd == "run-cu" {
+    return Err("The thread count of 'run-cu' is fixed by the CUDA runtime.".to_string().into());
+  }
+
+  let start = Instant::now();
+  let (net, mut stats, stopped) = if cmd == EVAL_CMD || in_process {
+    let mut hook = |paused: &Paused| {
+      let linear = run_opts.linear_readback;
+      let adt_encoding = compile_opts.adt_encoding;
+      on_break(&Breakpoint { paused, book: &book, labels: &labels, linear, adt_encoding })
+    };
+    let (net, stats) = eval_book_with(&core_book, eval_opts, &mut hook).map_err(|e| e.to_string())?;
+    let summary = stats.to_string();
+    let defs = if run_opts.def_stats { stats.defs } else { vec![] };
+    (net, RunStats { summary, defs, profile: stats.profile, ..Default::default() }, stats.stopped)
+  } else {
+    let run = |cmd: &str| {
+      let out = if has_sizes {
+        run_hvm_native(&core_book, cmd, &run_opts)?
+      } else {
+        run_hvm(&core_book, cmd, &run_opts)?
+      };
+      parse_hvm_output(&out)
+    };
+    let (net, summary) = match run(cmd) {
+      Err(e) if cmd == "run-cu" && run_opts.fallback_to_c => {
+        let mut diags = Diagnostics::default();
+        let msg = format!(
+          "Couldn't run the program with the CUDA runtime, running it with the C runtime instead.\n{e}"
+        );
+        diags.add_diagnostic(msg, Severity::Warning, DiagnosticOrigin::Book);
+        eprint!("{diags}");
+        run("run-c")?
+      }
+      res => res?,
+    };
+    (net, RunStats { summary, ..Default::default() }, None)
+  };
+  timings.normalize = start.elapsed();
+
+  let start = Instant::now();
+  let (term, mut diags) =
     readback_hvm_net(&net, &book, &labels, run_opts.linear_readback, compile_opts.adt_encoding);
+  timings.readback = start.elapsed();
+  if let Some(err) = stopped {
+    let msg = format!("{err}\nShowing the partially evaluated result.");
+    diags.add_diagnostic(msg, Severity::Warning, DiagnosticOrigin::Readback);
+  }
+  stats.timings = timings;
 
   Ok(Some((term, stats, diags)))
 }
 
+/// The outcome of comparing an HVM runtime against the reference evaluator, see [`selftest_book`].
+#[derive(Debug)]
+pub enum SelftestResult {
+  /// The runtime gave the same result as the reference evaluator.
+  Pass(String),
+  /// The runtime gave a different result, or failed where the reference evaluator didn't.
+  Fail { expected: String, got: Result<String, Diagnostics> },
+  /// The reference evaluator couldn't run the program, so there's nothing to compare against.
+  Skip(Diagnostics),
+}
+
+/// Runs a program both with the tree-walking evaluator of [`fun::eval`] and with the HVM runtime `cmd`
+/// and compares the results, which are the readback term preceded by any readback diagnostics.
+///
+/// The reference evaluator doesn't use interaction nets, so this checks the compilation to nets,
+/// the optimizations and the interaction rules of the runtime, not only its determinism.
+/// The variables of both results are renamed in the order they're bound, so that only the structure is compared.
+///
+/// The reference path desugars with the default options and the tested path compiles with `compile_opts`.
+pub fn selftest_book(
+  book: Book,
+  run_opts: RunOpts,
+  compile_opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+  cmd: &str,
+) -> SelftestResult {
+  let expected = match eval_reference(book.clone(), compile_opts.eta, diagnostics_cfg) {
+    Ok(mut term) => {
+      normalize_result(&mut term);
+      term.to_string()
+    }
+    Err(diags) => return SelftestResult::Skip(diags),
+  };
+  let got = run_book(book, run_opts, compile_opts, diagnostics_cfg, None, cmd).map(|res| {
+    let (mut term, _, diags) = res.unwrap();
+    normalize_result(&mut term);
+    format!("{diags}{term}")
+  });
+  match got {
+    Ok(got) if got == expected => SelftestResult::Pass(got),
+    got => SelftestResult::Fail { expected, got },
+  }
+}
+
+/// Evaluates a program with the tree-walking evaluator of [`fun::eval`],
+/// resugaring the result like [`readback_hvm_net`].
+fn eval_reference(
+  mut book: Book,
+  eta: bool,
+  diagnostics_cfg: DiagnosticsConfig,
+) -> Result<Term, Diagnostics> {
+  let compile_opts = CompileOpts::default();
+  desugar_book(&mut book, compile_opts.clone(), diagnostics_cfg, None)?;
+  let mut term = fun::eval::eval_book(&book, eta).map_err(|e| e.to_string())?;
+  resugar_result(&mut term, &book, compile_opts.adt_encoding);
+  Ok(term)
+}
+
+/// Writes a result in a form that doesn't depend on how it was read back from a net:
+/// the variables are named in the order they're bound, the numbers come first in the commutative
+/// operations, and `(*, x)` is written as `λ* x`, since a tuple and a lambda are the same node.
+fn normalize_result(term: &mut Term) {
+  fn go(term: &mut Term) {
+    maybe_grow(|| {
+      match term {
+        Term::Oper { opr, fst, snd }
+          if !matches!(**fst, Term::Num { .. }) && matches!(**snd, Term::Num { .. }) =>
+        {
+          let flipped = match opr {
+            Op::ADD | Op::MUL | Op::EQ | Op::NEQ | Op::AND | Op::OR | Op::XOR => Some(*opr),
+            Op::LT => Some(Op::GT),
+            Op::GT => Some(Op::LT),
+            _ => None,
+          };
+          if let Some(flipped) = flipped {
+            *opr = flipped;
+            std::mem::swap(fst, snd);
+          }
+        }
+        // A pair with an erased or bound first element is the same node as a lambda,
+        // and is read back from the nets as one.
+        Term::Fan { fan: FanKind::Tup, tag: Tag::Static, els }
+          if els.len() == 2 && matches!(els[0], Term::Era | Term::Var { .. }) =>
+        {
+          let pat = match els[0] {
+            Term::Era => Pattern::Var(None),
+            _ => Pattern::Var(Some(Name::new("%pair"))),
+          };
+          let bod = els.pop().unwrap();
+          *term = Term::Lam { tag: Tag::Static, pat: Box::new(pat), bod: Box::new(bod) };
+        }
+        _ => (),
+      }
+      for child in term.children_mut() {
+        go(child);
+      }
+    })
+  }
+  go(term);
+  term.make_var_names_unique();
+}
+
 pub fn readback_hvm_net(
   net: &::hvm::ast::Net,
   book: &Book,
@@ -192,82 +455,254 @@ pub fn readback_hvm_net(
   adt_encoding: AdtEncoding,
 ) -> (Term, Diagnostics) {
   let mut diags = Diagnostics::default();
+  // The runtimes only return nets with redexes when the evaluation was stopped early.
+  let mark_unreduced = !net.rbag.is_empty();
   let net = hvm_to_net(net);
-  let mut term = net_to_term(&net, book, labels, linear, &mut diags);
+  let mut term = net_to_term(&net, book, labels, linear, mark_unreduced, &mut diags);
+  resugar_result(&mut term, book, adt_encoding);
+  (term, diags)
+}
+
+/// Turns the encoded definitions, strings, lists and constructors of a result back into their syntax.
+fn resugar_result(term: &mut Term, book: &Book, adt_encoding: AdtEncoding) {
   let recursive_defs = book.recursive_defs();
   term.expand_generated(book, &recursive_defs);
+  term.resugar_tagged_tuples(book, adt_encoding);
   term.resugar_strings(adt_encoding);
   term.resugar_lists(adt_encoding);
-  (term, diags)
+  term.resugar_adts(book, adt_encoding);
 }
 
-/// Runs an HVM book by invoking HVM as a subprocess.
-fn run_hvm(book: &::hvm::ast::Book, cmd: &str, run_opts: &RunOpts) -> Result<String, String> {
-  fn filter_hvm_output(
-    mut stream: impl std::io::Read + Send,
-    mut output: impl std::io::Write + Send,
-  ) -> Result<String, String> {
-    let mut capturing = false;
-    let mut result = String::new();
-    let mut buf = [0u8; 1024];
-    loop {
-      let num_read = match stream.read(&mut buf) {
-        Ok(n) => n,
-        Err(e) => {
-          eprintln!("{e}");
-          break;
-        }
-      };
-      if num_read == 0 {
+/// Prints the output of an HVM runtime until the result, and returns the result.
+///
+/// Stops reading with an error if the runtime reports that it ran out of its `node_len` nodes.
+fn filter_hvm_output(
+  mut stream: impl std::io::Read + Send,
+  mut output: impl std::io::Write + Send,
+  node_len: u64,
+) -> Result<String, String> {
+  let mut capturing = false;
+  let mut result = String::new();
+  let mut line = String::new();
+  let mut buf = [0u8; 1024];
+  loop {
+    let num_read = match stream.read(&mut buf) {
+      Ok(n) => n,
+      Err(e) => {
+        eprintln!("{e}");
         break;
       }
-      let new_buf = &buf[..num_read];
-      // TODO: Does this lead to broken characters if printing too much at once?
-      let new_str = String::from_utf8_lossy(new_buf);
-      if capturing {
-        // Store the result
-        result.push_str(&new_str);
-      } else if let Some((before, after)) = new_str.split_once(HVM_OUTPUT_END_MARKER) {
-        // If result started in the middle of the buffer, print what came before and start capturing.
-        if let Err(e) = output.write_all(before.as_bytes()) {
-          eprintln!("Error writing HVM output. {e}");
-        };
-        result.push_str(after);
-        capturing = true;
-      } else {
-        // Otherwise, don't capture anything
-        if let Err(e) = output.write_all(new_buf) {
-          eprintln!("Error writing HVM output. {e}");
-        }
-      }
+    };
+    if num_read == 0 {
+      break;
     }
-
+    let new_buf = &buf[..num_read];
+    // TODO: Does this lead to broken characters if printing too much at once?
+    let new_str = String::from_utf8_lossy(new_buf);
     if capturing {
-      Ok(result)
+      // Store the result
+      result.push_str(&new_str);
+    } else if let Some((before, after)) = new_str.split_once(HVM_OUTPUT_END_MARKER) {
+      // If result started in the middle of the buffer, print what came before and start capturing.
+      if let Err(e) = output.write_all(before.as_bytes()) {
+        eprintln!("Error writing HVM output. {e}");
+      };
+      result.push_str(after);
+      capturing = true;
     } else {
-      Err("Failed to parse result from HVM.".into())
+      // Otherwise, don't capture anything
+      if let Err(e) = output.write_all(new_buf) {
+        eprintln!("Error writing HVM output. {e}");
+      }
+      // The C and Cuda runtimes print 'OOM' lines when their node buffer is full and
+      // keep running, so without stopping them the result would be wrong or never come.
+      for c in new_str.chars() {
+        if c != '\n' {
+          line.push(c);
+        } else if line.trim_end() == HVM_OOM_MARKER {
+          return Err(format!(
+            "Out of memory: all the {node_len} nodes of the runtime are in use.\n\
+             Use '--heap-size' to run with a larger node buffer with 'run-c' and 'run-cu'."
+          ));
+        } else {
+          line.clear();
+        }
+      }
     }
   }
 
+  if capturing {
+    Ok(result)
+  } else {
+    Err("Failed to parse result from HVM.".into())
+  }
+}
+
+/// Runs an HVM book by invoking HVM as a subprocess.
+fn run_hvm(book: &::hvm::ast::Book, cmd: &str, run_opts: &RunOpts) -> Result<String, String> {
   let out_path = ".out.hvm";
   std::fs::write(out_path, hvm_book_show_pretty(book)).map_err(|x| x.to_string())?;
-  let mut process = std::process::Command::new(run_opts.hvm_path.clone())
+  let process = std::process::Command::new(run_opts.hvm_path.clone())
     .arg(cmd)
     .arg(out_path)
     .stdout(std::process::Stdio::piped())
     .spawn()
-    .map_err(|e| format!("Failed to start hvm process.\n{e}"))?;
+    .map_err(|e| format!("Failed to start hvm process.\n{e}"));
+  let result = process.and_then(|process| wait_hvm_process(process, HVM_NODE_LEN));
+
+  if let Err(e) = std::fs::remove_file(out_path) {
+    eprintln!("Error removing HVM output file. {e}");
+  }
+
+  result
+}
 
+/// Waits for an HVM runtime process to finish, printing its output until the result and returning the result.
+/// Kills the process if the output can't be read, as when the runtime runs out of nodes.
+fn wait_hvm_process(mut process: std::process::Child, node_len: u64) -> Result<String, String> {
   let child_out = std::mem::take(&mut process.stdout).expect("Failed to attach to hvm output");
-  let thread_out = std::thread::spawn(move || filter_hvm_output(child_out, std::io::stdout()));
+  let thread_out = std::thread::spawn(move || filter_hvm_output(child_out, std::io::stdout(), node_len));
 
+  let result = thread_out.join().map_err(|_| "HVM output thread panicked.".to_string())?;
+  if result.is_err() {
+    let _ = process.kill();
+  }
   let _ = process.wait().expect("Failed to wait on hvm subprocess");
-  if let Err(e) = std::fs::remove_file(out_path) {
+  result
+}
+
+/// Runs an HVM book with the C or CUDA runtime generated by HVM,
+/// compiled with the heap size and thread count of `run_opts`.
+fn run_hvm_native(book: &::hvm::ast::Book, cmd: &str, run_opts: &RunOpts) -> Result<String, String> {
+  let (gen_cmd, src_path, compiler, compiler_args) = match cmd {
+    "run-c" => ("gen-c", ".out.c", "gcc", &["-O2", "-lm", "-lpthread"][..]),
+    "run-cu" => ("gen-cu", ".out.cu", "nvcc", &["-O3", "-w"][..]),
+    _ => unreachable!(),
+  };
+  let hvm_path = ".out.hvm";
+  let bin_path = "./.out.bin";
+
+  std::fs::write(hvm_path, hvm_book_show_pretty(book)).map_err(|x| x.to_string())?;
+  let gen = std::process::Command::new(run_opts.hvm_path.clone()).arg(gen_cmd).arg(hvm_path).output();
+  if let Err(e) = std::fs::remove_file(hvm_path) {
     eprintln!("Error removing HVM output file. {e}");
   }
+  let gen = gen.map_err(|e| format!("Failed to start hvm process.\n{e}"))?;
+  if !gen.status.success() {
+    return Err(format!("Failed to generate the runtime.\n{}", String::from_utf8_lossy(&gen.stderr)));
+  }
+  let code = configure_runtime(&String::from_utf8_lossy(&gen.stdout), cmd, run_opts)?;
+  let code = if cmd == "run-c" { exit_on_oom(&code) } else { code };
+  std::fs::write(src_path, code).map_err(|x| x.to_string())?;
+
+  let compiled = std::process::Command::new(compiler)
+    .arg(src_path)
+    .arg("-o")
+    .arg(bin_path)
+    .args(compiler_args)
+    .output()
+    .map_err(|e| format!("Failed to start {compiler}.\n{e}"));
+  if let Err(e) = std::fs::remove_file(src_path) {
+    eprintln!("Error removing generated runtime file. {e}");
+  }
+  let compiled = compiled?;
+  if !compiled.status.success() {
+    return Err(format!("Failed to compile the runtime.\n{}", String::from_utf8_lossy(&compiled.stderr)));
+  }
+
+  let node_len =
+    run_opts.heap_size.map_or(Ok(HVM_NODE_LEN), |size| heap_node_len_l2(size).map(|l2| 1 << l2))?;
+  let process = std::process::Command::new(bin_path).stdout(std::process::Stdio::piped()).spawn();
+  let process = process.map_err(|e| format!("Failed to start the compiled runtime.\n{e}"));
+  let result = process.and_then(|process| wait_hvm_process(process, node_len));
+
+  if let Err(e) = std::fs::remove_file(bin_path) {
+    eprintln!("Error removing compiled runtime. {e}");
+  }
 
-  let result = thread_out.join().map_err(|_| "HVM output thread panicked.".to_string())??;
-  Ok(result)
+  result
+}
+
+/// Returns the log2 of how many nodes fit in `heap_size` bytes, rounded down to a power of two
+/// so that the node buffer can be split evenly between the threads of the runtime.
+fn heap_node_len_l2(heap_size: u64) -> Result<u32, String> {
+  // Each node is a pair of 32 bit ports.
+  const NODE_BYTES: u64 = 8;
+  let nodes = heap_size / NODE_BYTES;
+  if nodes == 0 {
+    return Err(format!("A heap size of {heap_size} bytes doesn't fit any nodes."));
+  }
+  Ok(nodes.ilog2().min(31))
+}
+
+/// Makes a generated C runtime exit when it runs out of nodes,
+/// instead of printing 'OOM' lines while waiting for a free node forever.
+pub fn exit_on_oom(code: &str) -> String {
+  code.replace(
+    &format!("printf(\"{HVM_OOM_MARKER}\\n\");"),
+    &format!("{{ printf(\"{HVM_OOM_MARKER}\\n\"); exit(1); }}"),
+  )
+}
+
+/// Sets the size of the node buffer of a runtime generated with `hvm gen-c` to fit in `heap_size` bytes.
+pub fn with_heap_size(code: &str, heap_size: u64) -> Result<String, String> {
+  let mut code = code.to_string();
+  // The variables buffer keeps its size, since the root variable is at its end.
+  let nodes_l2 = heap_node_len_l2(heap_size)?;
+  replace_line(&mut code, "#define G_NODE_LEN ", format!("#define G_NODE_LEN (1ul << {nodes_l2})"))?;
+  Ok(code)
+}
+
+/// Replaces the line of the code of a generated runtime that starts with `prefix`.
+fn replace_line(code: &mut String, prefix: &str, line: String) -> Result<(), String> {
+  let Some(ini) = code.find(prefix) else {
+    return Err(format!("Couldn't find '{prefix}' in the generated runtime."));
+  };
+  let end = code[ini..].find('\n').map_or(code.len(), |len| ini + len);
+  code.replace_range(ini..end, &line);
+  Ok(())
+}
+
+/// Replaces the heap size and thread count constants of the code of a generated runtime,
+/// and pins its threads to CPUs if asked to.
+fn configure_runtime(code: &str, cmd: &str, run_opts: &RunOpts) -> Result<String, String> {
+  let mut code = code.to_string();
+
+  if let Some(heap_size) = run_opts.heap_size {
+    if cmd == "run-c" {
+      code = with_heap_size(&code, heap_size)?;
+    } else {
+      let nodes_l2 = heap_node_len_l2(heap_size)?;
+      replace_line(&mut code, "const u32 G_NODE_LEN ", format!("const u32 G_NODE_LEN = 1 << {nodes_l2};"))?;
+    }
+  }
+  if let Some(threads) = run_opts.threads {
+    let threads_l2 = threads.max(1).ilog2();
+    replace_line(&mut code, "#define TPC_L2 ", format!("#define TPC_L2 {threads_l2}"))?;
+  }
+  if let Some(pinning) = run_opts.pin_threads {
+    let cpu = match pinning {
+      ThreadPinning::Compact => "t",
+      ThreadPinning::Spread => "t * ncpu / TPC",
+    };
+    let spawn = "    pthread_create(&threads[t], NULL, thread_func, &thread_arg[t]);";
+    replace_line(
+      &mut code,
+      spawn,
+      format!(
+        "{spawn}
+    cpu_set_t cpus;
+    sched_getaffinity(0, sizeof(cpus), &cpus);
+    u32 ncpu = CPU_COUNT(&cpus);
+    CPU_ZERO(&cpus);
+    CPU_SET(({cpu}) % ncpu, &cpus);
+    pthread_setaffinity_np(threads[t], sizeof(cpus), &cpus);"
+      ),
+    )?;
+    // The affinity functions are GNU extensions, which must be enabled before any include.
+    code.insert_str(0, "#define _GNU_SOURCE\n#include <sched.h>\n");
+  }
+  Ok(code)
 }
 
 /// Reads the final output from HVM and separates the extra information.
@@ -285,16 +720,158 @@ fn parse_hvm_output(out: &str) -> Result<(::hvm::ast::Net, String), String> {
   Ok((net, stats.to_string()))
 }
 
+/// Options of a program run.
+///
+/// The HVM binary can't change the heap size, thread count or thread pinning of the C and CUDA runtimes,
+/// so with any of them the runtime is generated and compiled for the run.
+/// The options that only the in-process evaluation supports are listed by [`RunOpts::in_process_opts`].
 #[derive(Clone, Debug)]
 pub struct RunOpts {
   pub linear_readback: bool,
   pub pretty: bool,
   pub hvm_path: String,
+  /// Stops the evaluation after this many rewrites. See [`RunOpts::in_process_opts`].
+  pub max_rewrites: Option<u64>,
+  /// Stops the evaluation after using this many bytes of memory. See [`RunOpts::in_process_opts`].
+  pub max_memory: Option<u64>,
+  /// Collects how much work each definition did. See [`RunOpts::in_process_opts`].
+  pub def_stats: bool,
+  /// Attributes the interactions to the chains of definitions that caused them. See [`RunOpts::in_process_opts`].
+  pub profile: bool,
+  /// When `max_rewrites` or `max_memory` stop the evaluation, reads back the partial result
+  /// instead of failing, showing the parts that weren't reduced yet.
+  pub partial: bool,
+  /// Writes the rewrites of the evaluation to a file. See [`RunOpts::in_process_opts`].
+  pub trace: Option<TraceOpts>,
+  /// Reduces the redexes one at a time in a fixed order, so that the rewrite counts and traces are the same on every run.
+  /// With `run-c`, the runtime uses a single thread. See [`RunOpts::in_process_opts`].
+  pub deterministic: bool,
+  /// The memory, in bytes, for the node buffer of the C and CUDA runtimes.
+  pub heap_size: Option<u64>,
+  /// How many threads the C runtime uses, rounded down to a power of two.
+  pub threads: Option<u64>,
+  /// Pins each thread of the C runtime to a CPU, instead of letting the OS move them around.
+  pub pin_threads: Option<ThreadPinning>,
+  /// When the CUDA runtime can't run the program, like on a machine without a compatible GPU,
+  /// warns and runs it with the C runtime instead.
+  pub fallback_to_c: bool,
+}
+
+impl RunOpts {
+  /// The options that only the in-process evaluation supports, described for the user.
+  ///
+  /// With any of them, `run` evaluates the program in-process, one interaction at a time, with the
+  /// sequential evaluator of [`hvm::eval`], and warns about it. The other runtimes reject them.
+  /// Deterministic runs are also evaluated in-process by `run`, but `run-c` supports them with a single thread.
+  pub fn in_process_opts(&self) -> Vec<&'static str> {
+    let opts = [
+      (self.max_rewrites.is_some() || self.max_memory.is_some(), "rewrite and memory limits"),
+      (self.def_stats, "per-definition stats"),
+      (self.profile, "profiling"),
+      (self.trace.is_some(), "tracing"),
+    ];
+    opts.into_iter().filter(|(enabled, _)| *enabled).map(|(_, opt)| opt).collect()
+  }
+}
+
+/// Joins words into a list like "a, b and c".
+fn join_words(words: &[&str]) -> String {
+  match words {
+    [] => String::new(),
+    [word] => word.to_string(),
+    [init @ .., last] => format!("{} and {last}", init.join(", ")),
+  }
+}
+
+/// How the threads of the C runtime are pinned to CPUs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadPinning {
+  /// Thread `i` runs on CPU `i`, keeping the threads together, on the same socket when they fit.
+  Compact,
+  /// The threads are spread evenly over all the CPUs, so that they use the memory of every socket.
+  Spread,
 }
 
 impl Default for RunOpts {
   fn default() -> Self {
-    RunOpts { linear_readback: false, pretty: false, hvm_path: "hvm".to_string() }
+    RunOpts {
+      linear_readback: false,
+      pretty: false,
+      hvm_path: "hvm".to_string(),
+      max_rewrites: None,
+      max_memory: None,
+      def_stats: false,
+      profile: false,
+      partial: false,
+      trace: None,
+      deterministic: false,
+      heap_size: None,
+      threads: None,
+      pin_threads: None,
+      fallback_to_c: false,
+    }
+  }
+}
+
+/// Statistics of a program run.
+#[derive(Clone, Debug, Default)]
+pub struct RunStats {
+  /// The interaction count, time and speed reported by the runtime.
+  pub summary: String,
+  /// The work done by each expanded definition, with the most expanded first.
+  /// Only collected when [`RunOpts::def_stats`] is set.
+  pub defs: Vec<DefStats>,
+  /// The interactions done by each chain of calls, as `caller;callee` folded stacks.
+  /// Only collected when [`RunOpts::profile`] is set.
+  pub profile: Vec<(String, u64)>,
+  /// How long each step of compiling and running the program took.
+  pub timings: Timings,
+}
+
+/// How long each step of compiling and running a program took.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+  /// Parsing the program. Books are parsed before being compiled, so this is set by the caller.
+  pub parse: Duration,
+  /// The lambda-term level passes, see [`desugar_book`].
+  pub desugar: Duration,
+  /// Converting the desugared book to HVM nets and the net level passes.
+  pub encode: Duration,
+  /// Normalizing the program, including starting the HVM runtime when one is used.
+  pub normalize: Duration,
+  /// Reading the result net back into a term.
+  pub readback: Duration,
+}
+
+impl std::fmt::Display for Timings {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let steps = [
+      ("Parse", self.parse),
+      ("Desugar", self.desugar),
+      ("Encode", self.encode),
+      ("Normalize", self.normalize),
+      ("Readback", self.readback),
+    ];
+    for (name, time) in steps {
+      writeln!(f, "{:<10} {:.3}s", format!("{name}:"), time.as_secs_f64())?;
+    }
+    Ok(())
+  }
+}
+
+impl std::fmt::Display for RunStats {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.summary)?;
+    if !self.defs.is_empty() {
+      let names =
+        self.defs.iter().map(|def| Name::new(&def.name).display_origin().to_string()).collect::<Vec<_>>();
+      let width = names.iter().map(|name| name.chars().count()).chain(["Definition".len()]).max().unwrap();
+      writeln!(f, "\n{:<width$}  {:>12}  {:>12}", "Definition", "Calls", "Nodes")?;
+      for (name, def) in names.iter().zip(&self.defs) {
+        writeln!(f, "{:<width$}  {:>12}  {:>12}", name, def.calls, def.nodes)?;
+      }
+    }
+    Ok(())
   }
 }
 
@@ -339,6 +916,18 @@ pub struct CompileOpts {
   /// Enables [hvm::check_net_size].
   pub check_net_size: bool,
 
+  /// Enables the eta-expansion of partial applications in [fun::transform::expand_partial_apps].
+  pub expand_partial_apps: bool,
+
+  /// Enables [fun::transform::balance_folds].
+  pub balance_folds: bool,
+
+  /// Enables [fun::transform::share_common_subterms].
+  pub share_subterms: bool,
+
+  /// Enables [fun::transform::specialize_calls].
+  pub specialize: bool,
+
   /// Determines the encoding of constructors and matches.
   pub adt_encoding: AdtEncoding,
 }
@@ -355,6 +944,10 @@ impl CompileOpts {
       inline: true,
       linearize_matches: OptLevel::Enabled,
       check_net_size: self.check_net_size,
+      expand_partial_apps: self.expand_partial_apps,
+      balance_folds: true,
+      share_subterms: true,
+      specialize: true,
       adt_encoding: self.adt_encoding,
     }
   }
@@ -370,6 +963,10 @@ impl CompileOpts {
       merge: false,
       inline: false,
       check_net_size: self.check_net_size,
+      expand_partial_apps: self.expand_partial_apps,
+      balance_folds: false,
+      share_subterms: false,
+      specialize: false,
       adt_encoding: self.adt_encoding,
     }
   }
@@ -400,15 +997,21 @@ impl Default for CompileOpts {
       merge: false,
       inline: false,
       check_net_size: false,
+      expand_partial_apps: false,
+      balance_folds: false,
+      share_subterms: false,
+      specialize: false,
       adt_encoding: AdtEncoding::NumScott,
     }
   }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AdtEncoding {
   Scott,
   NumScott,
+  /// Constructors are tuples of a numeric tag and their fields, matched with a `switch` on the tag.
+  TaggedTuple,
 }
 
 impl std::fmt::Display for AdtEncoding {
@@ -416,14 +1019,31 @@ impl std::fmt::Display for AdtEncoding {
     match self {
       AdtEncoding::Scott => write!(f, "Scott"),
       AdtEncoding::NumScott => write!(f, "NumScott"),
+      AdtEncoding::TaggedTuple => write!(f, "TaggedTuple"),
     }
   }
 }
 
+/// The checks done by [`check_book`] besides compiling the program.
+#[derive(Clone, Debug, Default)]
+pub struct CheckOpts {
+  /// The backend the program must be able to run on.
+  pub target: Option<String>,
+  /// The maximum number of nodes of a compiled definition.
+  pub max_def_size: Option<usize>,
+}
+
+pub struct CheckResult {
+  pub diagnostics: Diagnostics,
+  pub sizes: NetSizes,
+}
+
 pub struct CompileResult {
   pub diagnostics: Diagnostics,
   pub hvm_book: ::hvm::ast::Book,
   pub labels: Labels,
+  /// Only the desugar and encode times are set.
+  pub timings: Timings,
 }
 
 fn maybe_grow<R, F>(f: F) -> R
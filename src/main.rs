@@ -1,14 +1,30 @@
 use bend::{
-  check_book, compile_book, desugar_book,
+  check_book, compile_book, debug_book,
+  debugger::Debugger,
+  desugar_book_until,
   diagnostics::{Diagnostics, DiagnosticsConfig, Severity},
-  fun::{Book, Name},
-  hvm::hvm_book_show_pretty,
-  load_file_to_book, run_book, AdtEncoding, CompileOpts, OptLevel, RunOpts,
+  exit_on_oom,
+  fun::{load_book::load_project_to_book, manifest::Manifest, migrate::migrate_syntax, Book, Cfg, Name},
+  hvm::{
+    eval::TraceOpts,
+    graph::net_to_dot,
+    hvm_book_show_pretty,
+    js::book_to_js,
+    native::{
+      book_exports, build_binary, write_lib_project, write_native_project, write_py_project, CcCommand,
+      CcOpts,
+    },
+  },
+  load_file_to_book,
+  pipeline::Pipeline,
+  run_book, selftest_book, with_heap_size, AdtEncoding, Breakpoint, CheckOpts, CompileOpts, OptLevel,
+  RunOpts, SelftestResult, ThreadPinning, Timings, ENTRY_POINT, EVAL_CMD, HVM1_ENTRY_POINT,
 };
 use clap::{Args, CommandFactory, Parser, Subcommand};
 use std::{
   path::{Path, PathBuf},
   process::ExitCode,
+  time::{Duration, Instant},
 };
 
 #[derive(Parser, Debug)]
@@ -17,14 +33,51 @@ struct Cli {
   #[command(subcommand)]
   pub mode: Mode,
 
-  #[arg(short, long, global = true)]
+  #[arg(
+    short,
+    long,
+    global = true,
+    help = "Show the parsed program, the rewrite counts and how long each compilation step took"
+  )]
   pub verbose: bool,
 
+  #[arg(
+    short,
+    long,
+    global = true,
+    conflicts_with = "verbose",
+    help = "Only show the result and the errors, hiding warnings and stats"
+  )]
+  pub quiet: bool,
+
   #[arg(long, global = true, help = "HVM command or path to HVM binary")]
   pub hvm_bin: Option<String>,
 
-  #[arg(short = 'e', long, global = true, help = "Use other entrypoint rather than main or Main")]
+  #[arg(
+    short = 'e',
+    long,
+    visible_alias = "entry",
+    global = true,
+    help = "Use another definition as the entrypoint instead of main or Main"
+  )]
   pub entrypoint: Option<String>,
+
+  #[arg(
+    long,
+    global = true,
+    help = "Load the dependencies of the project only from its 'vendor' directory, as copied by 'bend vendor'"
+  )]
+  pub vendored: bool,
+
+  #[arg(
+    long = "feature",
+    visible_alias = "features",
+    value_name = "FEATURE",
+    value_delimiter = ',',
+    global = true,
+    help = "Enable a feature for the '#[cfg(feature = \"..\")]' attributes. Can be given multiple times or as a comma separated list"
+  )]
+  pub features: Vec<String>,
 }
 
 #[derive(Subcommand, Clone, Debug)]
@@ -43,8 +96,26 @@ enum Mode {
     #[command(flatten)]
     warn_opts: CliWarnOpts,
 
-    #[arg(help = "Path to the input file")]
-    path: PathBuf,
+    #[arg(
+      long,
+      value_parser = clap::builder::PossibleValuesParser::new(Cfg::TARGETS),
+      help = "Also checks that the program can run on the given backend"
+    )]
+    target: Option<String>,
+
+    #[arg(long, value_name = "NODES", help = "Fails if a compiled definition has more than this many nodes")]
+    max_def_size: Option<usize>,
+
+    #[arg(
+      long,
+      help = "Show the number of nodes and dups of each compiled definition, and an estimate of the memory used by the entrypoint"
+    )]
+    sizes: bool,
+
+    #[arg(
+      help = "Path to the input file. Defaults to the main file of the project in the current directory"
+    )]
+    path: Option<PathBuf>,
   },
   /// Compiles the program and runs it with the Rust HVM implementation.
   Run(RunArgs),
@@ -52,12 +123,38 @@ enum Mode {
   RunC(RunArgs),
   /// Compiles the program and runs it with the Cuda HVM implementation.
   RunCu(RunArgs),
+  /// Runs the program in-process and writes how many interactions each chain of calls did.
+  ///
+  /// The output is in the folded stacks format, which can be turned into a flamegraph with
+  /// tools like `inferno-flamegraph` or `flamegraph.pl`.
+  Profile(ProfileArgs),
+  /// Runs the program in-process, pausing at the expansions of the given definitions to inspect the evaluation.
+  ///
+  /// Without breakpoints, it pauses when the entrypoint is expanded. Type 'help' when paused to see the commands.
+  Debug(DebugArgs),
   /// Compiles the program to hvm and prints to stdout.
-  GenHvm(GenArgs),
+  GenHvm(GenHvmArgs),
   /// Compiles the program to standalone C and prints to stdout.
-  GenC(GenArgs),
+  GenC(GenNativeArgs),
   /// Compiles the program to standalone Cuda and prints to stdout.
-  GenCu(GenArgs),
+  GenCu(GenNativeArgs),
+  /// Compiles the program to a C static library, with a function for each definition exported with `#[export(..)]`.
+  GenLib(GenLibArgs),
+  /// Compiles the program to a Python package, with a function for each definition exported with `#[export(..)]`.
+  GenPy(GenLibArgs),
+  /// Compiles the program to a JavaScript module and prints to stdout.
+  ///
+  /// The module exports `run()`, which evaluates the program, and a function for each definition
+  /// exported with `#[export(..)]`.
+  GenJs(GenArgs),
+  /// Compiles the program to a standalone executable, through C and the system's C compiler.
+  ///
+  /// The command line arguments of the executable are passed to main, as numbers or strings.
+  Build(BuildArgs),
+  /// Compiles the program and prints the net of one definition as a Graphviz DOT graph.
+  ///
+  /// The output can be rendered with tools like `dot -Tsvg`.
+  Graph(GraphArgs),
   /// Runs the lambda-term level desugaring passes.
   Desugar {
     #[arg(
@@ -72,9 +169,98 @@ enum Mode {
     #[arg(short = 'p', help = "Debug and normalization pretty printing")]
     pretty: bool,
 
+    #[arg(long, help = "Stop after the given desugaring pass, as listed by 'bend opts --explain'")]
+    until: Option<String>,
+
     #[command(flatten)]
     warn_opts: CliWarnOpts,
 
+    #[arg(help = "Path to the input file")]
+    path: PathBuf,
+  },
+  /// Shows the compiler options resulting from the given optimization flags.
+  Opts {
+    #[arg(long, help = "List the compilation passes in order and whether each of them is enabled")]
+    explain: bool,
+
+    #[arg(
+      short = 'O',
+      value_delimiter = ' ',
+      action = clap::ArgAction::Append,
+      long_help = r#"Enables or disables the given optimizations
+      float_combinators is enabled by default on strict mode."#,
+    )]
+    comp_opts: Vec<OptArgs>,
+  },
+  /// Lists the compilation passes in the order they run, whether each of them is enabled
+  /// with the given optimization flags, and the passes each of them depends on.
+  Passes {
+    #[arg(
+      short = 'O',
+      value_delimiter = ' ',
+      action = clap::ArgAction::Append,
+      long_help = r#"Enables or disables the given optimizations
+      float_combinators is enabled by default on strict mode."#,
+    )]
+    comp_opts: Vec<OptArgs>,
+  },
+  /// Runs programs both with a reference evaluator and with an HVM runtime and compares the results.
+  ///
+  /// The reference path desugars the program with the default options and evaluates it
+  /// by walking its terms, without compiling it to interaction nets.
+  /// It checks the compilation, the optimizations and the interaction rules of the runtime.
+  /// The tested path compiles with the given options and runs the program with the given runtime.
+  /// Programs the reference evaluator can't run, like ones with unscoped variables, are skipped.
+  Selftest {
+    #[arg(
+      long,
+      value_enum,
+      default_value = "run",
+      help = "HVM runtime to test against the reference evaluator"
+    )]
+    runtime: RuntimeArgs,
+
+    #[arg(
+      short = 'O',
+      value_delimiter = ' ',
+      action = clap::ArgAction::Append,
+      long_help = r#"Enables or disables the given optimizations for the tested path
+      float_combinators is enabled by default on strict mode."#,
+    )]
+    comp_opts: Vec<OptArgs>,
+
+    #[command(flatten)]
+    warn_opts: CliWarnOpts,
+
+    #[arg(required = true, help = "Paths to the programs or to directories of programs to test")]
+    paths: Vec<PathBuf>,
+  },
+  /// Creates a project in a new directory, with a `bend.toml` manifest, a `src/main.bend` program and a
+  /// `tests` directory.
+  New {
+    #[arg(long, help = "Name of the project. Defaults to the name of the directory")]
+    name: Option<String>,
+
+    #[arg(help = "Path to the directory to create")]
+    path: PathBuf,
+  },
+  /// Creates a project in an existing directory, keeping the files that are already there.
+  Init {
+    #[arg(long, help = "Name of the project. Defaults to the name of the directory")]
+    name: Option<String>,
+
+    #[arg(help = "Path to the directory of the project. Defaults to the current directory")]
+    path: Option<PathBuf>,
+  },
+  /// Copies the dependencies of the project in the current directory to its `vendor` directory.
+  ///
+  /// With `--vendored`, the dependencies are then loaded only from there, without needing their original directories.
+  Vendor,
+  /// Rewrites a program written with an older version of the syntax to the current syntax and prints it to stdout.
+  Migrate {
+    #[arg(short = 'w', long, help = "Overwrite the input file instead of printing the result")]
+    write: bool,
+
     #[arg(help = "Path to the input file")]
     path: PathBuf,
   },
@@ -85,6 +271,14 @@ struct RunArgs {
   #[arg(short = 'p', help = "Debug and normalization pretty printing")]
   pretty: bool,
 
+  #[arg(
+    long,
+    value_enum,
+    default_value = "text",
+    help = "How to print the result. With 'json', the stats are printed to stderr"
+  )]
+  output_format: OutputFormat,
+
   #[command(flatten)]
   run_opts: CliRunOpts,
 
@@ -100,13 +294,43 @@ struct RunArgs {
   #[command(flatten)]
   warn_opts: CliWarnOpts,
 
-  #[arg(help = "Path to the input file")]
-  path: PathBuf,
+  #[arg(help = "Path to the input file. Defaults to the main file of the project in the current directory")]
+  path: Option<PathBuf>,
 
-  #[arg(value_parser = |arg: &str| bend::fun::parser::TermParser::new(arg).parse_term())]
+  #[arg(
+    value_parser = |arg: &str| bend::fun::parser::TermParser::new(arg).parse_whole_term(),
+    help = "Arguments applied to the entrypoint, as Bend expressions. Put them after '--' if they start with '-'"
+  )]
   arguments: Option<Vec<bend::fun::Term>>,
 }
 
+#[derive(Args, Clone, Debug)]
+struct ProfileArgs {
+  #[command(flatten)]
+  run: RunArgs,
+
+  #[arg(
+    short = 'o',
+    long,
+    help = "Path to write the folded stacks to. Defaults to the input path with a '.folded' extension"
+  )]
+  output: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct DebugArgs {
+  #[command(flatten)]
+  run: RunArgs,
+
+  #[arg(
+    short = 'b',
+    long = "break",
+    value_name = "DEF",
+    help = "Pause whenever this definition is expanded. Can be given multiple times"
+  )]
+  breaks: Vec<String>,
+}
+
 #[derive(Args, Clone, Debug)]
 struct GenArgs {
   #[arg(
@@ -121,8 +345,115 @@ struct GenArgs {
   #[command(flatten)]
   warn_opts: CliWarnOpts,
 
-  #[arg(help = "Path to the input file")]
-  path: PathBuf,
+  #[arg(help = "Path to the input file. Defaults to the main file of the project in the current directory")]
+  path: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct GenNativeArgs {
+  #[command(flatten)]
+  gen: GenArgs,
+
+  #[arg(
+    long,
+    value_name = "DIR",
+    help = "Write a project that builds the program as a library, with a header exposing bend_main() and a Makefile, instead of printing the code"
+  )]
+  out_dir: Option<PathBuf>,
+
+  #[command(flatten)]
+  cc_args: CcArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+struct CcArgs {
+  #[arg(long, value_name = "CMD", help = "The C compiler [default: $CC, or cc]")]
+  cc: Option<String>,
+
+  #[arg(long, value_name = "TRIPLE", help = "Compile for another platform, like aarch64-linux-gnu")]
+  target: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "FLAGS",
+    allow_hyphen_values = true,
+    help = "Extra flags for the C compiler, like \"-O3 -march=native\""
+  )]
+  cflags: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "FILE",
+    help = "Read the C compiler options from a file [default: bend.conf in the directory of the program, if it exists]"
+  )]
+  config: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct GenLibArgs {
+  #[command(flatten)]
+  gen: GenArgs,
+
+  #[arg(short = 'o', long, value_name = "DIR", help = "Where to write the library project")]
+  out_dir: PathBuf,
+
+  #[arg(
+    long,
+    value_parser = parse_mem_size,
+    help = "Memory for the nodes of each call, in bytes or with a K/M/G suffix"
+  )]
+  heap_size: Option<u64>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct BuildArgs {
+  #[command(flatten)]
+  gen: GenArgs,
+
+  #[arg(
+    short = 'o',
+    long,
+    value_name = "FILE",
+    help = "Where to write the executable [default: the name of the file]"
+  )]
+  output: Option<PathBuf>,
+
+  #[command(flatten)]
+  cc_args: CcArgs,
+
+  #[arg(long, help = "Compile the executable even if the program didn't change since it was last built")]
+  force: bool,
+
+  #[arg(
+    long,
+    value_parser = parse_mem_size,
+    help = "Memory for the nodes of the runtime, in bytes or with a K/M/G suffix"
+  )]
+  heap_size: Option<u64>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct GenHvmArgs {
+  #[command(flatten)]
+  gen: GenArgs,
+
+  #[arg(
+    long,
+    num_args = 2,
+    value_names = ["A", "B"],
+    allow_hyphen_values = true,
+    help = "Show how the output differs between two sets of optimizations, like \"-Oall\" \"-Ono-all\""
+  )]
+  diff_opts: Option<Vec<String>>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct GraphArgs {
+  #[command(flatten)]
+  gen: GenArgs,
+
+  #[arg(short = 'd', long, help = "Name of the definition to show. Defaults to the entrypoint")]
+  def: Option<String>,
 }
 
 #[derive(Args, Clone, Debug)]
@@ -130,8 +461,82 @@ struct CliRunOpts {
   #[arg(short = 'l', help = "Linear readback (show explicit dups)")]
   linear: bool,
 
-  #[arg(short = 's', long = "stats", help = "Shows runtime stats and rewrite counts")]
-  print_stats: bool,
+  #[arg(
+    short = 's',
+    long = "stats",
+    value_enum,
+    num_args = 0..=1,
+    require_equals = true,
+    default_missing_value = "summary",
+    help = "Shows runtime stats and rewrite counts. With '=defs', also shows the work done by each definition"
+  )]
+  stats: Option<StatsArgs>,
+
+  #[arg(long, help = "Stops the evaluation after this many rewrites")]
+  max_rewrites: Option<u64>,
+
+  #[arg(
+    long,
+    value_parser = parse_mem_size,
+    help = "Stops the evaluation after using this much memory, in bytes or with a K/M/G suffix"
+  )]
+  max_memory: Option<u64>,
+
+  #[arg(
+    long,
+    help = "When '--max-rewrites' or '--max-memory' stop the evaluation, shows the partially evaluated result instead of an error"
+  )]
+  partial: bool,
+
+  #[arg(
+    long,
+    value_name = "FILE",
+    num_args = 0..=1,
+    require_equals = true,
+    help = "Writes each rewrite to a file. Defaults to the input path with a '.trace' extension"
+  )]
+  trace: Option<Option<PathBuf>>,
+
+  #[arg(long, value_name = "N", requires = "trace", help = "Only writes every N-th rewrite to the trace")]
+  trace_every: Option<u64>,
+
+  #[arg(
+    long,
+    value_name = "DEF",
+    requires = "trace",
+    help = "Only writes the rewrites of nodes created by the given definition, and its expansions, to the trace"
+  )]
+  trace_def: Option<String>,
+
+  #[arg(
+    long,
+    help = "Reduce in a fixed order on a single thread, so that the rewrite counts and traces are the same on every run"
+  )]
+  deterministic: bool,
+
+  #[arg(
+    long,
+    value_parser = parse_mem_size,
+    help = "Memory for the nodes of the C and Cuda runtimes, in bytes or with a K/M/G suffix"
+  )]
+  heap_size: Option<u64>,
+
+  #[arg(long, help = "How many threads the C runtime uses, rounded down to a power of two")]
+  threads: Option<u64>,
+
+  #[arg(
+    long,
+    value_enum,
+    help = "Pin the threads of the C runtime to CPUs, packed together ('compact') or spread over all of them ('spread')"
+  )]
+  pin_threads: Option<PinThreadsArgs>,
+
+  #[arg(
+    long,
+    value_enum,
+    help = "When the CUDA runtime can't run the program, warn and run it with the given runtime instead"
+  )]
+  fallback: Option<FallbackArgs>,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -184,8 +589,17 @@ pub enum OptArgs {
   NoInline,
   CheckNetSize,
   NoCheckNetSize,
+  ExpandPartialApps,
+  NoExpandPartialApps,
+  BalanceFolds,
+  NoBalanceFolds,
+  ShareSubterms,
+  NoShareSubterms,
+  Specialize,
+  NoSpecialize,
   AdtScott,
   AdtNumScott,
+  AdtTaggedTuple,
 }
 
 fn compile_opts_from_cli(args: &Vec<OptArgs>) -> CompileOpts {
@@ -208,6 +622,14 @@ fn compile_opts_from_cli(args: &Vec<OptArgs>) -> CompileOpts {
       NoInline => opts.inline = false,
       CheckNetSize => opts.check_net_size = true,
       NoCheckNetSize => opts.check_net_size = false,
+      ExpandPartialApps => opts.expand_partial_apps = true,
+      NoExpandPartialApps => opts.expand_partial_apps = false,
+      BalanceFolds => opts.balance_folds = true,
+      NoBalanceFolds => opts.balance_folds = false,
+      ShareSubterms => opts.share_subterms = true,
+      NoShareSubterms => opts.share_subterms = false,
+      Specialize => opts.specialize = true,
+      NoSpecialize => opts.specialize = false,
 
       LinearizeMatches => opts.linearize_matches = OptLevel::Enabled,
       LinearizeMatchesAlt => opts.linearize_matches = OptLevel::Alt,
@@ -215,12 +637,187 @@ fn compile_opts_from_cli(args: &Vec<OptArgs>) -> CompileOpts {
 
       AdtScott => opts.adt_encoding = AdtEncoding::Scott,
       AdtNumScott => opts.adt_encoding = AdtEncoding::NumScott,
+      AdtTaggedTuple => opts.adt_encoding = AdtEncoding::TaggedTuple,
     }
   }
 
   opts
 }
 
+fn trace_opts_from_cli(
+  trace: Option<Option<PathBuf>>,
+  every: Option<u64>,
+  def: Option<String>,
+  input: &Path,
+) -> Option<TraceOpts> {
+  let path = trace?.unwrap_or_else(|| input.with_extension("trace"));
+  Some(TraceOpts { path, every, def })
+}
+
+/// The C compiler command, from the command line options over the ones of the config file.
+fn cc_command_from_cli(args: CcArgs, input: &Path) -> Result<CcCommand, String> {
+  let config = match args.config {
+    Some(config) => CcOpts::load(&config)?,
+    None => {
+      let config = input.parent().unwrap_or(Path::new("")).join(CcOpts::CONFIG_FILE);
+      if config.is_file() {
+        CcOpts::load(&config)?
+      } else {
+        CcOpts::default()
+      }
+    }
+  };
+  let cli = CcOpts {
+    cc: args.cc,
+    target: args.target,
+    cflags: args.cflags.iter().flat_map(|flags| flags.split_whitespace().map(str::to_string)).collect(),
+  };
+  config.merge(cli).command(std::env::var("CC").ok())
+}
+
+/// The name of a directory, which is the default name of a project created in it.
+fn dir_name(path: &Path) -> String {
+  let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+  path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// The input file, the compile options and the manifest of a command that can run on a project.
+///
+/// Without a path, the input is the main file of the project whose `bend.toml` is in the current
+/// directory or one of its parents. The compile options of the manifest come before the ones of the
+/// command line, so these can override them.
+fn project_input(
+  path: Option<PathBuf>,
+  comp_opts: Vec<OptArgs>,
+) -> Result<(PathBuf, Vec<OptArgs>, Option<Manifest>), String> {
+  let dir = path.as_ref().and_then(|path| path.parent()).unwrap_or(Path::new(""));
+  let manifest = Manifest::find(dir)?;
+  let path = match (path, &manifest) {
+    (Some(path), _) => path,
+    (None, Some(manifest)) => manifest.main_path(),
+    (None, None) => {
+      return Err(format!(
+        "No input file was given and there's no '{}' in the current directory or its parents.",
+        Manifest::FILE
+      ))
+    }
+  };
+  let mut opts = vec![];
+  if let Some(manifest) = &manifest {
+    for opt in manifest.opts.iter() {
+      let Ok(opt) = <OptArgs as clap::ValueEnum>::from_str(opt, false) else {
+        return Err(format!(
+          "Unknown compile option '{opt}' in '{}'.",
+          manifest.dir.join(Manifest::FILE).display()
+        ));
+      };
+      opts.push(opt);
+    }
+  }
+  opts.extend(comp_opts);
+  Ok((path, opts, manifest))
+}
+
+fn parse_mem_size(arg: &str) -> Result<u64, String> {
+  let (num, shift) = match arg.char_indices().last() {
+    Some((i, 'K' | 'k')) => (&arg[..i], 10),
+    Some((i, 'M' | 'm')) => (&arg[..i], 20),
+    Some((i, 'G' | 'g')) => (&arg[..i], 30),
+    _ => (arg, 0),
+  };
+  let num = num.parse::<u64>().map_err(|e| e.to_string())?;
+  num.checked_mul(1 << shift).ok_or_else(|| "memory size is too large".to_string())
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum StatsArgs {
+  Summary,
+  Defs,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum OutputFormat {
+  Text,
+  Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum PinThreadsArgs {
+  Compact,
+  Spread,
+}
+
+impl PinThreadsArgs {
+  fn pinning(self) -> ThreadPinning {
+    match self {
+      PinThreadsArgs::Compact => ThreadPinning::Compact,
+      PinThreadsArgs::Spread => ThreadPinning::Spread,
+    }
+  }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum FallbackArgs {
+  C,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum RuntimeArgs {
+  Run,
+  RunC,
+  RunCu,
+}
+
+impl RuntimeArgs {
+  fn cmd(self) -> &'static str {
+    match self {
+      RuntimeArgs::Run => "run",
+      RuntimeArgs::RunC => "run-c",
+      RuntimeArgs::RunCu => "run-cu",
+    }
+  }
+}
+
+/// Parses a set of optimization flags given as a single string, like "-Oall -Ono-eta".
+fn parse_opt_args(arg: &str) -> Result<Vec<OptArgs>, String> {
+  use clap::ValueEnum;
+  let mut opts = vec![];
+  for flag in arg.split_whitespace() {
+    let flag = flag.strip_prefix("-O").unwrap_or(flag);
+    opts.push(OptArgs::from_str(flag, false).map_err(|_| format!("Invalid optimization flag '{flag}'."))?);
+  }
+  Ok(opts)
+}
+
+/// Shows the definitions that differ between two compiled books, labeling them with the options that generated them.
+fn diff_hvm_books(a: &hvm::ast::Book, b: &hvm::ast::Book, a_lbl: &str, b_lbl: &str) -> String {
+  use std::fmt::Write;
+  let show = |book: &hvm::ast::Book, name: &str| {
+    book.defs.get(name).map(|net| format!("@{name} = {}", net.show())).unwrap_or_default()
+  };
+  let mut names = a.defs.keys().chain(b.defs.keys()).collect::<Vec<_>>();
+  names.sort();
+  names.dedup();
+
+  let mut out = format!("--- {a_lbl}\n+++ {b_lbl}\n");
+  let mut changed = 0;
+  for name in names {
+    let (a_def, b_def) = (show(a, name), show(b, name));
+    if a_def != b_def {
+      changed += 1;
+      for line in a_def.lines() {
+        writeln!(out, "- {line}").unwrap();
+      }
+      for line in b_def.lines() {
+        writeln!(out, "+ {line}").unwrap();
+      }
+      writeln!(out).unwrap();
+    }
+  }
+  writeln!(out, "{changed} definition(s) differ").unwrap();
+  out
+}
+
 #[derive(clap::ValueEnum, Clone, Debug)]
 pub enum WarningArgs {
   All,
@@ -230,6 +827,10 @@ pub enum WarningArgs {
   UnusedDefinition,
   RepeatedBind,
   RecursionCycle,
+  PartialApplication,
+  ShadowedBind,
+  UnusedVariable,
+  ImplicitDup,
 }
 
 fn main() -> ExitCode {
@@ -247,11 +848,35 @@ fn main() -> ExitCode {
 
 fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
   let arg_verbose = cli.verbose;
+  let arg_quiet = cli.quiet;
   let entrypoint = cli.entrypoint.take();
+  let vendored = cli.vendored;
+  // The backend that the program is compiled to, for the `#[cfg(target = "..")]` attributes.
+  let target = match &cli.mode {
+    Mode::RunC(..) | Mode::GenC(..) | Mode::GenLib(..) | Mode::GenPy(..) | Mode::Build(..) => "c",
+    Mode::Selftest { runtime: RuntimeArgs::RunC, .. } => "c",
+    Mode::RunCu(..) | Mode::GenCu(..) | Mode::Selftest { runtime: RuntimeArgs::RunCu, .. } => "cuda",
+    Mode::GenJs(..) => "js",
+    Mode::Check { target: Some(target), .. } => target.as_str(),
+    _ => "rust",
+  };
+  let cfg = Cfg::new(target, std::mem::take(&mut cli.features));
+  let parse_time = std::cell::Cell::new(Duration::ZERO);
 
   let load_book = |path: &Path| -> Result<Book, Diagnostics> {
-    let mut book = load_file_to_book(path)?;
-    book.entrypoint = entrypoint.map(Name::new);
+    let start = Instant::now();
+    let manifest = Manifest::find(path.parent().unwrap_or(Path::new("")))?;
+    let mut book = match &manifest {
+      Some(manifest) => load_project_to_book(path, manifest, vendored, &cfg)?,
+      None => load_file_to_book(path, &cfg)?,
+    };
+    parse_time.set(start.elapsed());
+    // The entrypoint of the manifest is only used for the main file of the project.
+    let is_main = |manifest: &Manifest| {
+      path.canonicalize().ok().is_some_and(|path| manifest.main_path().canonicalize().ok() == Some(path))
+    };
+    let manifest_entrypoint = manifest.filter(is_main).and_then(|manifest| manifest.entrypoint);
+    book.entrypoint = entrypoint.clone().or(manifest_entrypoint).map(Name::new);
 
     if arg_verbose {
       println!("{book}");
@@ -260,6 +885,15 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
     Ok(book)
   };
 
+  let set_warning_cfg = |cfg: DiagnosticsConfig, warn_opts: CliWarnOpts| {
+    let cfg = set_warning_cfg_from_cli(cfg, warn_opts);
+    if arg_quiet {
+      cfg.without_warnings()
+    } else {
+      cfg
+    }
+  };
+
   // Path/command for the HVM binary
   // CLI option -> Env var -> Default
   let hvm_bin = if let Some(hvm_bin) = cli.hvm_bin {
@@ -271,7 +905,7 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
   };
 
   let gen_cmd = match &cli.mode {
-    Mode::GenC(..) => "gen-c",
+    Mode::GenC(..) | Mode::GenLib(..) | Mode::GenPy(..) | Mode::Build(..) => "gen-c",
     Mode::GenCu(..) => "gen-cu",
     _ => "gen",
   };
@@ -282,18 +916,26 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
     _ => "run",
   };
 
+  let gen_py = matches!(cli.mode, Mode::GenPy(..));
+
   match cli.mode {
-    Mode::Check { comp_opts, warn_opts, path } => {
-      let diagnostics_cfg = set_warning_cfg_from_cli(DiagnosticsConfig::default(), warn_opts);
+    Mode::Check { comp_opts, warn_opts, target, max_def_size, sizes, path } => {
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::default(), warn_opts);
       let compile_opts = compile_opts_from_cli(&comp_opts);
+      let check_opts = CheckOpts { target, max_def_size };
 
       let mut book = load_book(&path)?;
-      let diagnostics = check_book(&mut book, diagnostics_cfg, compile_opts)?;
-      eprintln!("{}", diagnostics);
+      let res = check_book(&mut book, diagnostics_cfg, compile_opts, &check_opts)?;
+      eprintln!("{}", res.diagnostics);
+      if sizes {
+        println!("{}", res.sizes);
+      }
     }
 
-    Mode::GenHvm(GenArgs { comp_opts, warn_opts, path, .. }) => {
-      let diagnostics_cfg = set_warning_cfg_from_cli(DiagnosticsConfig::default(), warn_opts);
+    Mode::GenHvm(GenHvmArgs { gen: GenArgs { comp_opts, warn_opts, path }, diff_opts: None }) => {
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::default(), warn_opts);
       let opts = compile_opts_from_cli(&comp_opts);
 
       let mut book = load_book(&path)?;
@@ -303,40 +945,317 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
       println!("{}", hvm_book_show_pretty(&compile_res.hvm_book));
     }
 
-    Mode::Run(RunArgs { pretty, run_opts, comp_opts, warn_opts, path, arguments })
-    | Mode::RunC(RunArgs { pretty, run_opts, comp_opts, warn_opts, path, arguments })
-    | Mode::RunCu(RunArgs { pretty, run_opts, comp_opts, warn_opts, path, arguments }) => {
-      let CliRunOpts { linear, print_stats } = run_opts;
+    Mode::GenHvm(GenHvmArgs { gen: GenArgs { comp_opts, warn_opts, path }, diff_opts: Some(diff_opts) }) => {
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::default(), warn_opts);
+
+      let mut outs = vec![];
+      for diff_opt in &diff_opts {
+        let mut opts = comp_opts.clone();
+        opts.extend(parse_opt_args(diff_opt)?);
+        let opts = compile_opts_from_cli(&opts);
+
+        let mut book = load_book(&path)?;
+        let compile_res = compile_book(&mut book, opts, diagnostics_cfg, None)?;
+        eprint!("{}", compile_res.diagnostics);
+        outs.push(compile_res.hvm_book);
+      }
+
+      print!("{}", diff_hvm_books(&outs[0], &outs[1], &diff_opts[0], &diff_opts[1]));
+    }
+
+    Mode::Graph(GraphArgs { gen: GenArgs { comp_opts, warn_opts, path }, def }) => {
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::default(), warn_opts);
+      let opts = compile_opts_from_cli(&comp_opts);
+
+      let mut book = load_book(&path)?;
+      let compile_res = compile_book(&mut book, opts, diagnostics_cfg, None)?;
+      eprint!("{}", compile_res.diagnostics);
 
-      let diagnostics_cfg =
-        set_warning_cfg_from_cli(DiagnosticsConfig::new(Severity::Allow, arg_verbose), warn_opts);
+      // The entrypoint is always compiled to 'main'.
+      let def = def.unwrap_or_else(|| "main".to_string());
+      let Some(net) = compile_res.hvm_book.defs.get(&def) else {
+        return Err(format!("Definition '{def}' not found in the compiled program.").into());
+      };
+      print!("{}", net_to_dot(&def, net));
+    }
+
+    Mode::New { name, path } => {
+      if path.exists() {
+        return Err(
+          format!("'{}' already exists. Use 'bend init' to create a project in it.", path.display()).into(),
+        );
+      }
+      let name = name.unwrap_or_else(|| dir_name(&path));
+      Manifest::create_project(&path, &name)?;
+      eprintln!("Created the project '{name}' in '{}'.", path.display());
+    }
+
+    Mode::Init { name, path } => {
+      let path = path.unwrap_or_else(|| PathBuf::from("."));
+      let name = name.unwrap_or_else(|| dir_name(&path));
+      Manifest::create_project(&path, &name)?;
+      eprintln!("Created the project '{name}' in '{}'.", path.display());
+    }
+
+    Mode::Vendor => {
+      let Some(manifest) = Manifest::find(Path::new(""))? else {
+        return Err(
+          format!("There's no '{}' in the current directory or its parents.", Manifest::FILE).into(),
+        );
+      };
+      for (name, dep) in manifest.vendor()? {
+        eprintln!("Vendored '{name}' {}.", dep.version);
+      }
+    }
+
+    Mode::Migrate { write, path } => {
+      let code = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+      let migrated = migrate_syntax(&code);
+      if write {
+        std::fs::write(&path, migrated).map_err(|e| e.to_string())?;
+      } else {
+        print!("{migrated}");
+      }
+    }
+
+    Mode::Opts { explain, comp_opts } => {
+      let opts = compile_opts_from_cli(&comp_opts);
+      if explain {
+        for pass in Pipeline::new(&opts).passes() {
+          println!("[{}] {}", if pass.enabled { "x" } else { " " }, pass.name);
+        }
+      } else {
+        println!("{opts:#?}");
+      }
+    }
+
+    Mode::Passes { comp_opts } => {
+      let opts = compile_opts_from_cli(&comp_opts);
+      for pass in Pipeline::new(&opts).passes() {
+        print!("[{}] {}", if pass.enabled { "x" } else { " " }, pass.name);
+        if !pass.after.is_empty() {
+          print!(" (after {})", pass.after.join(", "));
+        }
+        println!();
+      }
+    }
+
+    Mode::Run(RunArgs { pretty, output_format, run_opts, comp_opts, warn_opts, path, arguments })
+    | Mode::RunC(RunArgs { pretty, output_format, run_opts, comp_opts, warn_opts, path, arguments })
+    | Mode::RunCu(RunArgs { pretty, output_format, run_opts, comp_opts, warn_opts, path, arguments }) => {
+      let CliRunOpts {
+        linear,
+        stats: print_stats,
+        max_rewrites,
+        max_memory,
+        partial,
+        trace,
+        trace_every,
+        trace_def,
+        deterministic,
+        heap_size,
+        threads,
+        pin_threads,
+        fallback,
+      } = run_opts;
+
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::new(Severity::Allow, arg_verbose), warn_opts);
 
       let compile_opts = compile_opts_from_cli(&comp_opts);
 
       compile_opts.check_for_strict();
 
-      let run_opts = RunOpts { linear_readback: linear, pretty, hvm_path: hvm_bin };
+      let def_stats = matches!(print_stats, Some(StatsArgs::Defs));
+      let run_opts = RunOpts {
+        linear_readback: linear,
+        pretty,
+        hvm_path: hvm_bin,
+        max_rewrites,
+        max_memory,
+        def_stats,
+        profile: false,
+        partial,
+        trace: trace_opts_from_cli(trace, trace_every, trace_def, &path),
+        deterministic,
+        heap_size,
+        threads,
+        pin_threads: pin_threads.map(PinThreadsArgs::pinning),
+        fallback_to_c: matches!(fallback, Some(FallbackArgs::C)),
+      };
 
       let book = load_book(&path)?;
-      if let Some((term, stats, diags)) =
+      if let Some((term, stats, mut diags)) =
         run_book(book, run_opts, compile_opts, diagnostics_cfg, arguments, run_cmd)?
       {
+        if arg_quiet {
+          diags.remove_warnings();
+        }
+        eprint!("{diags}");
+        print_result(&term, pretty, output_format);
+        if (print_stats.is_some() || arg_verbose) && !arg_quiet {
+          print_stats_for(&stats, output_format);
+        }
+        if arg_verbose {
+          eprint!("{}", Timings { parse: parse_time.get(), ..stats.timings });
+        }
+      }
+    }
+
+    Mode::Profile(ProfileArgs {
+      run: RunArgs { pretty, output_format, run_opts, comp_opts, warn_opts, path, arguments },
+      output,
+    }) => {
+      let CliRunOpts {
+        linear,
+        stats: print_stats,
+        max_rewrites,
+        max_memory,
+        partial,
+        trace,
+        trace_every,
+        trace_def,
+        deterministic,
+        heap_size,
+        threads,
+        pin_threads,
+        fallback,
+      } = run_opts;
+
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::new(Severity::Allow, arg_verbose), warn_opts);
+
+      let compile_opts = compile_opts_from_cli(&comp_opts);
+
+      compile_opts.check_for_strict();
+
+      let def_stats = matches!(print_stats, Some(StatsArgs::Defs));
+      let run_opts = RunOpts {
+        linear_readback: linear,
+        pretty,
+        hvm_path: hvm_bin,
+        max_rewrites,
+        max_memory,
+        def_stats,
+        profile: true,
+        partial,
+        trace: trace_opts_from_cli(trace, trace_every, trace_def, &path),
+        deterministic,
+        heap_size,
+        threads,
+        pin_threads: pin_threads.map(PinThreadsArgs::pinning),
+        fallback_to_c: matches!(fallback, Some(FallbackArgs::C)),
+      };
+
+      let book = load_book(&path)?;
+      if let Some((term, stats, mut diags)) =
+        run_book(book, run_opts, compile_opts, diagnostics_cfg, arguments, EVAL_CMD)?
+      {
+        if arg_quiet {
+          diags.remove_warnings();
+        }
+        eprint!("{diags}");
+
+        let out_path = output.unwrap_or_else(|| path.with_extension("folded"));
+        let folded: String = stats.profile.iter().map(|(stack, itrs)| format!("{stack} {itrs}\n")).collect();
+        std::fs::write(&out_path, folded)
+          .map_err(|e| format!("Couldn't write profile to '{}'. {e}", out_path.display()))?;
+
+        print_result(&term, pretty, output_format);
+        if (print_stats.is_some() || arg_verbose) && !arg_quiet {
+          print_stats_for(&stats, output_format);
+        }
+        if arg_verbose {
+          eprint!("{}", Timings { parse: parse_time.get(), ..stats.timings });
+        }
+        eprintln!("Wrote profile to '{}'", out_path.display());
+      }
+    }
+
+    Mode::Debug(DebugArgs {
+      run: RunArgs { pretty, output_format, run_opts, comp_opts, warn_opts, path, arguments },
+      breaks,
+    }) => {
+      let CliRunOpts {
+        linear,
+        stats: print_stats,
+        max_rewrites,
+        max_memory,
+        partial,
+        trace,
+        trace_every,
+        trace_def,
+        deterministic,
+        heap_size,
+        threads,
+        pin_threads,
+        fallback,
+      } = run_opts;
+
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::new(Severity::Allow, arg_verbose), warn_opts);
+
+      let compile_opts = compile_opts_from_cli(&comp_opts);
+
+      compile_opts.check_for_strict();
+
+      let def_stats = matches!(print_stats, Some(StatsArgs::Defs));
+      let run_opts = RunOpts {
+        linear_readback: linear,
+        pretty,
+        hvm_path: hvm_bin,
+        max_rewrites,
+        max_memory,
+        def_stats,
+        profile: false,
+        partial,
+        trace: trace_opts_from_cli(trace, trace_every, trace_def, &path),
+        deterministic,
+        heap_size,
+        threads,
+        pin_threads: pin_threads.map(PinThreadsArgs::pinning),
+        fallback_to_c: matches!(fallback, Some(FallbackArgs::C)),
+      };
+
+      let book = load_book(&path)?;
+      let mut debugger = Debugger::new(std::io::stdin().lock(), std::io::stdout(), breaks, pretty);
+      let mut on_break = |bp: &Breakpoint| debugger.on_break(bp);
+      if let Some((term, stats, mut diags)) =
+        debug_book(book, run_opts, compile_opts, diagnostics_cfg, arguments, &mut on_break)?
+      {
+        if arg_quiet {
+          diags.remove_warnings();
+        }
         eprint!("{diags}");
-        if pretty {
-          println!("Result:\n{}", term.display_pretty(0));
-        } else {
-          println!("Result: {}", term);
+        print_result(&term, pretty, output_format);
+        if (print_stats.is_some() || arg_verbose) && !arg_quiet {
+          print_stats_for(&stats, output_format);
         }
-        if print_stats {
-          println!("{stats}");
+        if arg_verbose {
+          eprint!("{}", Timings { parse: parse_time.get(), ..stats.timings });
         }
       }
     }
 
-    Mode::GenC(GenArgs { comp_opts, warn_opts, path })
-    | Mode::GenCu(GenArgs { comp_opts, warn_opts, path }) => {
-      let diagnostics_cfg = set_warning_cfg_from_cli(DiagnosticsConfig::default(), warn_opts);
+    Mode::GenC(GenNativeArgs { gen: GenArgs { comp_opts, warn_opts, path }, out_dir, cc_args })
+    | Mode::GenCu(GenNativeArgs { gen: GenArgs { comp_opts, warn_opts, path }, out_dir, cc_args }) => {
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::default(), warn_opts);
       let opts = compile_opts_from_cli(&comp_opts);
+      let has_cc_args = cc_args.cc.is_some() || cc_args.target.is_some() || cc_args.cflags.is_some();
+      if out_dir.is_none() && (has_cc_args || cc_args.config.is_some()) {
+        return Err("The C compiler options can only be used with '--out-dir'.".to_string().into());
+      }
+      // Read before compiling, so that a wrong config fails early.
+      let out_dir = match out_dir {
+        Some(out_dir) => Some((out_dir, cc_command_from_cli(cc_args, &path)?)),
+        None => None,
+      };
 
       let mut book = load_book(&path)?;
       let compile_res = compile_book(&mut book, opts, diagnostics_cfg, None)?;
@@ -352,6 +1271,7 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
 
       let std::process::Output { stdout, stderr, status } = gen_fn(out_path)?;
       let out = String::from_utf8_lossy(&stdout);
+      let out = if gen_cmd == "gen-c" { exit_on_oom(&out) } else { out.to_string() };
       let err = String::from_utf8_lossy(&stderr);
       let status = if !status.success() { status.to_string() } else { String::new() };
 
@@ -359,18 +1279,147 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
         eprintln!("Error removing HVM output file. {e}");
       }
 
-      eprintln!("{err}");
-      println!("{out}");
-      println!("{status}");
+      if let Some((out_dir, cc)) = out_dir {
+        eprint!("{err}");
+        if !status.is_empty() {
+          return Err(format!("Couldn't generate the runtime. {status}").into());
+        }
+        write_native_project(&out_dir, &out, gen_cmd == "gen-cu", &cc)?;
+      } else {
+        eprintln!("{err}");
+        println!("{out}");
+        println!("{status}");
+      }
     }
 
-    Mode::Desugar { path, comp_opts, warn_opts, pretty } => {
-      let diagnostics_cfg = set_warning_cfg_from_cli(DiagnosticsConfig::default(), warn_opts);
+    Mode::GenLib(GenLibArgs { gen: GenArgs { comp_opts, warn_opts, path }, out_dir, heap_size })
+    | Mode::GenPy(GenLibArgs { gen: GenArgs { comp_opts, warn_opts, path }, out_dir, heap_size }) => {
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::default(), warn_opts);
+      let opts = compile_opts_from_cli(&comp_opts);
+
+      let mut book = load_book(&path)?;
+      let exports = book_exports(&book, opts.adt_encoding)?;
+      // A library doesn't need a main function, so the first export is used as the entrypoint.
+      let has_main =
+        [ENTRY_POINT, HVM1_ENTRY_POINT].iter().any(|main| book.defs.contains_key(&Name::new(*main)));
+      if book.entrypoint.is_none() && !has_main {
+        book.entrypoint = Some(exports[0].0.clone());
+      }
+      let compile_res = compile_book(&mut book, opts, diagnostics_cfg, None)?;
+      eprint!("{}", compile_res.diagnostics);
+      for (name, _) in exports.iter() {
+        if !compile_res.hvm_book.defs.contains_key(name.as_ref()) {
+          return Err(format!("Exported definition '{name}' isn't in the compiled program.").into());
+        }
+      }
+
+      let out_path = ".out.hvm";
+      std::fs::write(out_path, hvm_book_show_pretty(&compile_res.hvm_book)).map_err(|x| x.to_string())?;
+      let mut process = std::process::Command::new(hvm_bin);
+      process.arg(gen_cmd).arg(out_path);
+      let output = process.output().map_err(|e| format!("While running hvm: {e}"));
+      if let Err(e) = std::fs::remove_file(out_path) {
+        eprintln!("Error removing HVM output file. {e}");
+      }
+      let std::process::Output { stdout, stderr, status } = output?;
+
+      eprint!("{}", String::from_utf8_lossy(&stderr));
+      if !status.success() {
+        return Err(format!("Couldn't generate the runtime. {status}").into());
+      }
+      let mut code = exit_on_oom(&String::from_utf8_lossy(&stdout));
+      if let Some(heap_size) = heap_size {
+        code = with_heap_size(&code, heap_size)?;
+      }
+      if gen_py {
+        write_py_project(&out_dir, &code, &exports)?;
+      } else {
+        write_lib_project(&out_dir, &code, &exports)?;
+      }
+    }
 
+    Mode::GenJs(GenArgs { comp_opts, warn_opts, path }) => {
+      let (path, comp_opts, _) = project_input(path, comp_opts)?;
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::default(), warn_opts);
       let opts = compile_opts_from_cli(&comp_opts);
 
       let mut book = load_book(&path)?;
-      let diagnostics = desugar_book(&mut book, opts, diagnostics_cfg, None)?;
+      let exports = if book.defs.values().any(|def| def.export.is_some()) {
+        book_exports(&book, opts.adt_encoding)?
+      } else {
+        vec![]
+      };
+      // A module with exported functions doesn't need a main function, so the first export is used as the entrypoint.
+      let has_main =
+        [ENTRY_POINT, HVM1_ENTRY_POINT].iter().any(|main| book.defs.contains_key(&Name::new(*main)));
+      if book.entrypoint.is_none() && !has_main && !exports.is_empty() {
+        book.entrypoint = Some(exports[0].0.clone());
+      }
+      let compile_res = compile_book(&mut book, opts, diagnostics_cfg, None)?;
+      eprint!("{}", compile_res.diagnostics);
+      for (name, _) in exports.iter() {
+        if !compile_res.hvm_book.defs.contains_key(name.as_ref()) {
+          return Err(format!("Exported definition '{name}' isn't in the compiled program.").into());
+        }
+      }
+
+      print!("{}", book_to_js(&compile_res.hvm_book, &exports));
+    }
+
+    Mode::Build(BuildArgs {
+      gen: GenArgs { comp_opts, warn_opts, path },
+      output,
+      cc_args,
+      force,
+      heap_size,
+    }) => {
+      let is_project = path.is_none();
+      let (path, comp_opts, manifest) = project_input(path, comp_opts)?;
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::default(), warn_opts);
+      let opts = compile_opts_from_cli(&comp_opts);
+      // A project is built to an executable named after it, in the directory of the project.
+      let output = output.unwrap_or_else(|| match manifest.filter(|_| is_project) {
+        Some(manifest) => manifest.dir.join(&manifest.name),
+        None => PathBuf::from(path.file_stem().unwrap_or(path.as_os_str())),
+      });
+      let cc = cc_command_from_cli(cc_args, &path)?;
+
+      let mut book = load_book(&path)?;
+      let compile_res = compile_book(&mut book, opts, diagnostics_cfg, None)?;
+      eprint!("{}", compile_res.diagnostics);
+
+      let out_path = ".out.hvm";
+      std::fs::write(out_path, hvm_book_show_pretty(&compile_res.hvm_book)).map_err(|x| x.to_string())?;
+      let mut process = std::process::Command::new(hvm_bin);
+      process.arg(gen_cmd).arg(out_path);
+      let output_res = process.output().map_err(|e| format!("While running hvm: {e}"));
+      if let Err(e) = std::fs::remove_file(out_path) {
+        eprintln!("Error removing HVM output file. {e}");
+      }
+      let std::process::Output { stdout, stderr, status } = output_res?;
+
+      eprint!("{}", String::from_utf8_lossy(&stderr));
+      if !status.success() {
+        return Err(format!("Couldn't generate the runtime. {status}").into());
+      }
+      let mut code = exit_on_oom(&String::from_utf8_lossy(&stdout));
+      if let Some(heap_size) = heap_size {
+        code = with_heap_size(&code, heap_size)?;
+      }
+      if !build_binary(&code, &output, &cc, force)? {
+        eprintln!("'{}' is up to date.", output.display());
+      }
+    }
+
+    Mode::Desugar { path, comp_opts, warn_opts, pretty, until } => {
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::default(), warn_opts);
+
+      let opts = compile_opts_from_cli(&comp_opts);
+
+      let mut book = load_book(&path)?;
+      let until = until.map(|pass| pass.replace('-', "_"));
+      let diagnostics = desugar_book_until(&mut book, opts, diagnostics_cfg, None, until.as_deref())?;
 
       eprint!("{diagnostics}");
       if pretty {
@@ -379,10 +1428,90 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
         println!("{book}");
       }
     }
+
+    Mode::Selftest { runtime, comp_opts, warn_opts, paths } => {
+      let diagnostics_cfg = set_warning_cfg(DiagnosticsConfig::new(Severity::Allow, arg_verbose), warn_opts);
+      let compile_opts = compile_opts_from_cli(&comp_opts);
+      let run_opts = RunOpts { hvm_path: hvm_bin, ..RunOpts::default() };
+
+      let mut files = vec![];
+      for path in paths {
+        collect_bend_files(&path, &mut files).map_err(|e| format!("Reading '{}': {e}", path.display()))?;
+      }
+
+      let show = |res: &Result<String, Diagnostics>| match res {
+        Ok(term) => term.trim_end().replace('\n', "\n    "),
+        Err(diags) => format!("Error:\n{diags}").trim_end().replace('\n', "\n    "),
+      };
+
+      let (mut failed, mut skipped) = (0, 0);
+      for file in &files {
+        let res = match load_book(file) {
+          Ok(book) => {
+            selftest_book(book, run_opts.clone(), compile_opts.clone(), diagnostics_cfg, runtime.cmd())
+          }
+          Err(diags) => SelftestResult::Skip(diags),
+        };
+        match res {
+          SelftestResult::Pass(_) => println!("ok   {}", file.display()),
+          SelftestResult::Fail { expected, got } => {
+            failed += 1;
+            println!("FAIL {}", file.display());
+            println!("  reference: {}", show(&Ok(expected)));
+            println!("  {:<9}: {}", runtime.cmd(), show(&got));
+          }
+          // Not something we can compare against, usually an invalid or IO-dependent program.
+          SelftestResult::Skip(diags) => {
+            skipped += 1;
+            println!("skip {}", file.display());
+            if arg_verbose {
+              println!("  reference: {}", show(&Err(diags)));
+            }
+          }
+        }
+      }
+
+      println!("{} passed, {} failed, {} skipped", files.len() - failed - skipped, failed, skipped);
+      if failed != 0 {
+        return Err(format!("Results differ from the reference evaluator in {failed} program(s).").into());
+      }
+    }
   };
   Ok(())
 }
 
+fn print_result(term: &bend::fun::Term, pretty: bool, output_format: OutputFormat) {
+  match output_format {
+    OutputFormat::Json => println!("{}", term.display_json()),
+    OutputFormat::Text if pretty => println!("Result:\n{}", term.display_pretty(0)),
+    OutputFormat::Text => println!("Result: {}", term),
+  }
+}
+
+/// Keeps stdout as a single JSON value when the result is printed as JSON.
+fn print_stats_for(stats: &bend::RunStats, output_format: OutputFormat) {
+  match output_format {
+    OutputFormat::Json => eprintln!("{stats}"),
+    OutputFormat::Text => println!("{stats}"),
+  }
+}
+
+/// Collects the '.bend' files in a path, recursing into directories in name order.
+fn collect_bend_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+  if path.is_dir() {
+    let mut entries = std::fs::read_dir(path)?.map(|e| e.map(|e| e.path())).collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+      if entry.is_dir() || entry.extension().is_some_and(|x| x == "bend") {
+        collect_bend_files(&entry, files)?;
+      }
+    }
+  } else {
+    files.push(path.to_path_buf());
+  }
+  Ok(())
+}
+
 fn set_warning_cfg_from_cli(mut cfg: DiagnosticsConfig, warn_opts: CliWarnOpts) -> DiagnosticsConfig {
   fn set(cfg: &mut DiagnosticsConfig, severity: Severity, cli_val: WarningArgs) {
     match cli_val {
@@ -393,6 +1522,10 @@ fn set_warning_cfg_from_cli(mut cfg: DiagnosticsConfig, warn_opts: CliWarnOpts)
         cfg.unused_definition = severity;
         cfg.repeated_bind = severity;
         cfg.recursion_cycle = severity;
+        cfg.partial_application = severity;
+        cfg.shadowed_bind = severity;
+        cfg.unused_variable = severity;
+        cfg.implicit_dup = severity;
       }
       WarningArgs::IrrefutableMatch => cfg.irrefutable_match = severity,
       WarningArgs::RedundantMatch => cfg.redundant_match = severity,
@@ -400,6 +1533,10 @@ fn set_warning_cfg_from_cli(mut cfg: DiagnosticsConfig, warn_opts: CliWarnOpts)
       WarningArgs::UnusedDefinition => cfg.unused_definition = severity,
       WarningArgs::RepeatedBind => cfg.repeated_bind = severity,
       WarningArgs::RecursionCycle => cfg.recursion_cycle = severity,
+      WarningArgs::PartialApplication => cfg.partial_application = severity,
+      WarningArgs::ShadowedBind => cfg.shadowed_bind = severity,
+      WarningArgs::UnusedVariable => cfg.unused_variable = severity,
+      WarningArgs::ImplicitDup => cfg.implicit_dup = severity,
     }
   }
 
@@ -0,0 +1,362 @@
+//! The ordered list of passes that compile a book, from the parsed program to the HVM nets.
+//!
+//! [`Pipeline::new`] builds the passes used by [`crate::compile_book`] for a set of [`CompileOpts`].
+//! Programs that embed the compiler can disable passes or insert their own ones before running it
+//! with [`crate::compile_book_with`].
+
+use crate::{
+  diagnostics::{Diagnostics, DiagnosticsConfig, ERR_INDENT_SIZE},
+  fun::{
+    book_to_hvm, term_to_net::Labels, transform::definition_merge::MergedDefs, Book, Ctx, DefPass, Term,
+  },
+  hvm::{
+    add_recursive_priority::add_recursive_priority, check_net_size::check_net_sizes,
+    check_net_size::MAX_NET_SIZE, eta_reduce::eta_reduce_hvm_net, inline::inline_hvm_book, mutual_recursion,
+    prune::prune_hvm_book,
+  },
+  CompileOpts, OptLevel,
+};
+use std::time::{Duration, Instant};
+
+/// The name of the pass that compiles the desugared book to HVM nets.
+/// The passes before it are the desugaring passes, and the ones after it work on the nets.
+pub const BOOK_TO_HVM: &str = "book_to_hvm";
+
+type RunPass = Box<dyn Fn(&mut CompileState) -> Result<(), Diagnostics>>;
+
+/// A step of the compilation.
+pub struct Pass {
+  pub name: &'static str,
+  /// The passes that must run before this one.
+  pub after: &'static [&'static str],
+  /// Whether the pass runs. Disabled passes are skipped.
+  pub enabled: bool,
+  run: RunPass,
+}
+
+/// The program being compiled, as the passes leave it.
+pub struct CompileState<'book> {
+  /// The book being desugared and the diagnostics of the passes.
+  pub ctx: Ctx<'book>,
+  /// The arguments given to the entrypoint, taken by the `apply_args` pass.
+  pub args: Option<Vec<Term>>,
+  /// The compiled nets, filled by the `book_to_hvm` pass.
+  pub hvm_book: ::hvm::ast::Book,
+  pub labels: Labels,
+  /// The definitions merged by the `definition_merge` pass, to report the cycles between them.
+  pub merged: MergedDefs,
+  /// How long each of the passes that ran took, in order.
+  pub timings: Vec<(&'static str, Duration)>,
+}
+
+/// The passes of the compilation, in the order they run.
+pub struct Pipeline {
+  passes: Vec<Pass>,
+}
+
+impl Pass {
+  pub fn new(
+    name: &'static str,
+    after: &'static [&'static str],
+    run: impl Fn(&mut CompileState) -> Result<(), Diagnostics> + 'static,
+  ) -> Self {
+    Self { name, after, enabled: true, run: Box::new(run) }
+  }
+
+  #[must_use]
+  pub fn enabled(self, enabled: bool) -> Self {
+    Self { enabled, ..self }
+  }
+}
+
+impl<'book> CompileState<'book> {
+  pub fn new(book: &'book mut Book, diagnostics_cfg: DiagnosticsConfig, args: Option<Vec<Term>>) -> Self {
+    Self {
+      ctx: Ctx::new(book, diagnostics_cfg),
+      args,
+      hvm_book: ::hvm::ast::Book { defs: Default::default() },
+      labels: Labels::default(),
+      merged: MergedDefs::new(),
+      timings: vec![],
+    }
+  }
+}
+
+impl Pipeline {
+  /// The passes that compile a book with the given options.
+  pub fn new(opts: &CompileOpts) -> Self {
+    let CompileOpts {
+      eta,
+      prune,
+      linearize_matches,
+      float_combinators,
+      merge,
+      inline,
+      check_net_size,
+      expand_partial_apps,
+      balance_folds,
+      share_subterms,
+      specialize,
+      adt_encoding,
+    } = opts.clone();
+
+    let passes = vec![
+      Pass::new("check_shared_names", &[], |s| {
+        s.ctx.check_shared_names();
+        Ok(())
+      }),
+      Pass::new("check_type_params", &[], |s| {
+        s.ctx.check_type_params();
+        Ok(())
+      }),
+      Pass::new("expand_macros", &[], |s| s.ctx.expand_macros()),
+      Pass::new("set_entrypoint", &[], |s| {
+        s.ctx.set_entrypoint();
+        Ok(())
+      }),
+      Pass::new("derive_defs", &["check_type_params"], |s| s.ctx.derive_defs()),
+      Pass::new("encode_adts", &["derive_defs"], move |s| {
+        s.ctx.book.encode_adts(adt_encoding);
+        Ok(())
+      }),
+      Pass::new("fix_match_defs", &["encode_adts"], |s| s.ctx.fix_match_defs()),
+      Pass::new("apply_args", &["set_entrypoint"], |s| {
+        let args = s.args.take();
+        s.ctx.apply_args(args)
+      }),
+      Pass::new("desugar_open", &["fix_match_defs"], |s| s.ctx.desugar_open()),
+      Pass::new("check_shadowed_binds", &["desugar_open"], |s| s.ctx.check_shadowed_binds()),
+      Pass::new("check_unused_vars", &["desugar_open"], |s| s.ctx.check_unused_vars()),
+      Pass::new("check_implicit_dups", &["desugar_open"], |s| s.ctx.check_implicit_dups()),
+      Pass::new("check_unscoped_vars", &["desugar_open"], |s| s.ctx.check_unscoped_vars()),
+      Pass::new("encode_builtins", &["expand_macros"], |s| {
+        s.ctx.book.encode_builtins();
+        Ok(())
+      }),
+      Pass::new("resolve_refs", &["encode_adts", "desugar_open", "encode_builtins"], |s| {
+        s.ctx.resolve_refs()
+      }),
+      Pass::new("expand_partial_apps", &["resolve_refs"], |s| s.ctx.expand_partial_apps(true))
+        .enabled(expand_partial_apps),
+      // Warns about the partial applications that weren't expanded.
+      Pass::new("check_partial_apps", &["resolve_refs"], |s| s.ctx.expand_partial_apps(false)),
+      Pass::new("desugar_match_defs", &["fix_match_defs", "resolve_refs"], |s| s.ctx.desugar_match_defs()),
+      Pass::new("fix_match_terms", &["desugar_match_defs"], |s| s.ctx.fix_match_terms()),
+      Pass::new("desugar_bend", &["fix_match_terms"], |s| s.ctx.desugar_bend()),
+      Pass::new("balance_folds", &["resolve_refs", "fix_match_terms"], |s| {
+        s.ctx.balance_folds();
+        Ok(())
+      })
+      .enabled(balance_folds),
+      Pass::new("desugar_fold", &["fix_match_terms"], |s| s.ctx.desugar_fold()),
+      Pass::new("desugar_with_blocks", &["fix_match_terms"], |s| s.ctx.desugar_with_blocks()),
+      Pass::new(
+        "check_unbound_vars",
+        &["resolve_refs", "desugar_bend", "desugar_fold", "desugar_with_blocks"],
+        |s| s.ctx.check_unbound_vars(),
+      ),
+      Pass::new("specialize_calls", &["fix_match_terms", "desugar_with_blocks"], |s| {
+        s.ctx.book.specialize_calls();
+        Ok(())
+      })
+      .enabled(specialize),
+      // Auto match linearization
+      Pass::new("make_var_names_unique", &["resolve_refs"], |s| {
+        s.ctx.book.make_var_names_unique();
+        Ok(())
+      }),
+      Pass::new("desugar_use", &["make_var_names_unique"], |s| {
+        s.ctx.book.desugar_use();
+        Ok(())
+      }),
+      Pass::new("linearize_matches", &["fix_match_terms", "desugar_use"], |s| {
+        s.ctx.book.linearize_matches();
+        Ok(())
+      })
+      .enabled(matches!(linearize_matches, OptLevel::Enabled)),
+      Pass::new("linearize_match_binds", &["fix_match_terms", "desugar_use"], |s| {
+        s.ctx.book.linearize_match_binds();
+        Ok(())
+      })
+      .enabled(matches!(linearize_matches, OptLevel::Alt)),
+      // Manual match linearization
+      Pass::new("linearize_match_with", &["desugar_with_blocks", "desugar_use"], |s| {
+        s.ctx.book.linearize_match_with();
+        Ok(())
+      }),
+      Pass::new("encode_matches", &["desugar_bend", "desugar_fold", "linearize_match_with"], move |s| {
+        s.ctx.book.encode_matches(adt_encoding);
+        Ok(())
+      }),
+      Pass::new("share_common_subterms", &["encode_matches"], |s| {
+        s.ctx.book.share_common_subterms();
+        Ok(())
+      })
+      .enabled(share_subterms),
+      Pass::new("linearize_vars", &["check_unbound_vars", "encode_matches"], |s| {
+        // sanity check
+        s.ctx.check_unbound_vars()?;
+
+        s.ctx.book.make_var_names_unique();
+        s.ctx.book.desugar_use();
+
+        s.ctx.book.make_var_names_unique();
+        s.ctx.book.linearize_vars();
+
+        // sanity check
+        s.ctx.check_unbound_vars()
+      }),
+      Pass::new("float_combinators", &["linearize_vars"], |s| {
+        s.ctx.book.float_combinators(MAX_NET_SIZE);
+        Ok(())
+      })
+      .enabled(float_combinators),
+      // sanity check
+      Pass::new("check_unbound_refs", &["resolve_refs"], |s| s.ctx.check_unbound_refs()),
+      // Optimizing passes
+      Pass::new("prune_unused_builtins", &["resolve_refs"], |s| {
+        s.ctx.prune(false);
+        Ok(())
+      })
+      .enabled(!prune),
+      Pass::new("prune_unused_definitions", &["resolve_refs"], |s| {
+        s.ctx.prune(true);
+        Ok(())
+      })
+      .enabled(prune),
+      Pass::new("definition_merge", &["linearize_vars"], |s| {
+        s.merged = s.ctx.book.merge_definitions();
+        Ok(())
+      })
+      .enabled(merge),
+      Pass::new("expand_main", &["set_entrypoint", "resolve_refs"], |s| {
+        s.ctx.book.expand_main();
+        Ok(())
+      }),
+      Pass::new("rename_main", &["expand_main"], |s| {
+        s.ctx.book.rename_main();
+        s.ctx.book.make_var_names_unique();
+        Ok(())
+      }),
+      Pass::new(BOOK_TO_HVM, &["linearize_vars", "check_unbound_refs", "rename_main"], |s| {
+        // The errors of the desugaring passes that didn't stop the compilation.
+        if s.ctx.info.has_errors() {
+          return Err(std::mem::take(&mut s.ctx.info));
+        }
+        (s.hvm_book, s.labels) = book_to_hvm(s.ctx.book, &mut s.ctx.info)?;
+        Ok(())
+      }),
+      Pass::new("eta_reduce", &[BOOK_TO_HVM], |s| {
+        s.hvm_book.defs.values_mut().for_each(eta_reduce_hvm_net);
+        Ok(())
+      })
+      .enabled(eta),
+      Pass::new("check_cycles", &[BOOK_TO_HVM], |s| {
+        mutual_recursion::check_cycles(&s.hvm_book, &s.merged, &mut s.ctx.info)
+      }),
+      // Reducing some nodes can make others reducible.
+      Pass::new("eta_reduce_again", &["eta_reduce"], |s| {
+        s.hvm_book.defs.values_mut().for_each(eta_reduce_hvm_net);
+        Ok(())
+      })
+      .enabled(eta),
+      Pass::new("inline", &[BOOK_TO_HVM], |s| {
+        s.ctx.info.start_pass();
+        if let Err(e) = inline_hvm_book(&mut s.hvm_book) {
+          s.ctx.info.add_book_error(format!("During inlining:\n{:ERR_INDENT_SIZE$}{}", "", e));
+        }
+        s.ctx.info.fatal(())
+      })
+      .enabled(inline),
+      Pass::new("prune", &[BOOK_TO_HVM], |s| {
+        let book = &s.ctx.book;
+        let mut prune_entrypoints = vec![book.hvm_entrypoint().to_string()];
+        let kept = book.defs.keys().filter(|name| book.pass_disabled(name, DefPass::Prune));
+        prune_entrypoints.extend(kept.map(|name| name.to_string()));
+        prune_hvm_book(&mut s.hvm_book, &prune_entrypoints);
+        Ok(())
+      })
+      .enabled(prune),
+      Pass::new("check_net_size", &[BOOK_TO_HVM], |s| check_net_sizes(&s.hvm_book, &mut s.ctx.info))
+        .enabled(check_net_size),
+      Pass::new("add_recursive_priority", &[BOOK_TO_HVM], |s| {
+        add_recursive_priority(&mut s.hvm_book);
+        Ok(())
+      }),
+    ];
+    Self { passes }
+  }
+
+  pub fn passes(&self) -> &[Pass] {
+    &self.passes
+  }
+
+  /// The passes that run before the book is compiled to HVM nets.
+  pub fn desugar_passes(&self) -> &[Pass] {
+    let end = self.passes.iter().position(|pass| pass.name == BOOK_TO_HVM).unwrap_or(self.passes.len());
+    &self.passes[..end]
+  }
+
+  pub fn disable(&mut self, name: &str) -> Result<(), String> {
+    let pos = self.position(name)?;
+    self.passes[pos].enabled = false;
+    Ok(())
+  }
+
+  pub fn insert_before(&mut self, name: &str, pass: Pass) -> Result<(), String> {
+    let pos = self.position(name)?;
+    self.insert(pos, pass)
+  }
+
+  pub fn insert_after(&mut self, name: &str, pass: Pass) -> Result<(), String> {
+    let pos = self.position(name)?;
+    self.insert(pos + 1, pass)
+  }
+
+  fn insert(&mut self, pos: usize, pass: Pass) -> Result<(), String> {
+    if self.position(pass.name).is_ok() {
+      return Err(format!("There's already a pass named '{}'.", pass.name));
+    }
+    self.passes.insert(pos, pass);
+    Ok(())
+  }
+
+  fn position(&self, name: &str) -> Result<usize, String> {
+    self.passes.iter().position(|pass| pass.name == name).ok_or_else(|| format!("Unknown pass '{name}'."))
+  }
+
+  /// Checks that the passes each of the enabled passes depends on are enabled and run before it.
+  pub fn check(&self) -> Result<(), String> {
+    for (pos, pass) in self.passes.iter().enumerate().filter(|(_, pass)| pass.enabled) {
+      for dep in pass.after {
+        let msg = match self.passes.iter().position(|other| other.name == *dep) {
+          None => "which isn't in the pipeline",
+          Some(dep_pos) if !self.passes[dep_pos].enabled => "which is disabled",
+          Some(dep_pos) if dep_pos > pos => "which runs after it",
+          Some(_) => continue,
+        };
+        return Err(format!("The '{}' pass depends on '{dep}', {msg}.", pass.name));
+      }
+    }
+    Ok(())
+  }
+
+  /// Runs the enabled passes in order, stopping after the pass named `until`.
+  pub fn run(&self, state: &mut CompileState, until: Option<&str>) -> Result<(), Diagnostics> {
+    if let Err(e) = self.check() {
+      state.ctx.info.add_book_error(e);
+      return Err(std::mem::take(&mut state.ctx.info));
+    }
+    for pass in &self.passes {
+      if pass.enabled {
+        let start = Instant::now();
+        (pass.run)(state)?;
+        state.timings.push((pass.name, start.elapsed()));
+      }
+      if until == Some(pass.name) {
+        break;
+      }
+    }
+    Ok(())
+  }
+}
@@ -1,10 +1,17 @@
 use bend::{
-  compile_book, desugar_book,
+  compile_book, debug_book,
+  debugger::Debugger,
+  desugar_book,
   diagnostics::{Diagnostics, DiagnosticsConfig, Severity},
   fun::{load_book::do_parse_book, net_to_term::net_to_term, term_to_net::Labels, Book, Ctx, Name, Term},
-  hvm::hvm_book_show_pretty,
+  hvm::{
+    eval::TraceOpts,
+    hvm_book_show_pretty,
+    js::book_to_js,
+    native::{book_exports, write_py_project},
+  },
   net::hvm_to_net::hvm_to_net,
-  run_book, AdtEncoding, CompileOpts, RunOpts,
+  run_book, selftest_book, AdtEncoding, Breakpoint, CompileOpts, RunOpts, RunStats, SelftestResult,
 };
 use insta::assert_snapshot;
 use itertools::Itertools;
@@ -88,7 +95,7 @@ pub fn run_book_simple(
   compile_opts: CompileOpts,
   diagnostics_cfg: DiagnosticsConfig,
   args: Option<Vec<Term>>,
-) -> Result<(Term, String, Diagnostics), Diagnostics> {
+) -> Result<(Term, RunStats, Diagnostics), Diagnostics> {
   run_book(book, run_opts, compile_opts, diagnostics_cfg, args, "run").map(Option::unwrap)
 }
 
@@ -105,7 +112,11 @@ fn compile_file() {
   run_golden_test_dir(function_name!(), &|code, path| {
     let mut book = do_parse_book(code, path, Book::builtins())?;
     let compile_opts = CompileOpts::default();
-    let diagnostics_cfg = DiagnosticsConfig { unused_definition: Severity::Allow, ..Default::default() };
+    let diagnostics_cfg = DiagnosticsConfig {
+      unused_definition: Severity::Allow,
+      unused_variable: Severity::Allow,
+      ..Default::default()
+    };
 
     let res = compile_book(&mut book, compile_opts, diagnostics_cfg, None)?;
     Ok(format!("{}{}", res.diagnostics, hvm_book_show_pretty(&res.hvm_book)))
@@ -120,6 +131,7 @@ fn compile_file_o_all() {
     let diagnostics_cfg = DiagnosticsConfig {
       recursion_cycle: Severity::Warning,
       unused_definition: Severity::Allow,
+      unused_variable: Severity::Allow,
       ..Default::default()
     };
 
@@ -167,6 +179,9 @@ fn run_file() {
       let book = do_parse_book(code, path, Book::builtins())?;
       let diagnostics_cfg = DiagnosticsConfig {
         unused_definition: Severity::Allow,
+        unused_variable: Severity::Allow,
+        shadowed_bind: Severity::Allow,
+        implicit_dup: Severity::Allow,
         ..DiagnosticsConfig::new(Severity::Error, true)
       };
       let run_opts = RunOpts::default();
@@ -195,6 +210,9 @@ fn run_lazy() {
     let diagnostics_cfg = DiagnosticsConfig {
       recursion_cycle: Severity::Allow,
       unused_definition: Severity::Allow,
+      unused_variable: Severity::Allow,
+      shadowed_bind: Severity::Allow,
+      implicit_dup: Severity::Allow,
       ..DiagnosticsConfig::new(Severity::Error, true)
     };
     let run_opts = RunOpts::lazy();
@@ -213,7 +231,7 @@ fn readback_hvm() {
     let book = Book::default();
     let compat_net = hvm_to_net(&net);
     let mut diags = Diagnostics::default();
-    let term = net_to_term(&compat_net, &book, &Labels::default(), false, &mut diags);
+    let term = net_to_term(&compat_net, &book, &Labels::default(), false, false, &mut diags);
     Ok(format!("{}{}", diags, term))
   })
 }
@@ -221,7 +239,10 @@ fn readback_hvm() {
 #[test]
 fn simplify_matches() {
   run_golden_test_dir(function_name!(), &|code, path| {
-    let diagnostics_cfg = DiagnosticsConfig::new(Severity::Error, true);
+    let diagnostics_cfg = DiagnosticsConfig {
+      unreachable_match: Severity::Allow,
+      ..DiagnosticsConfig::new(Severity::Error, true)
+    };
     let mut book = do_parse_book(code, path, Book::builtins())?;
     let mut ctx = Ctx::new(&mut book, diagnostics_cfg);
 
@@ -312,6 +333,9 @@ fn desugar_file() {
     let compile_opts = CompileOpts::default();
     let diagnostics_cfg = DiagnosticsConfig {
       unused_definition: Severity::Allow,
+      unused_variable: Severity::Allow,
+      shadowed_bind: Severity::Allow,
+      implicit_dup: Severity::Allow,
       ..DiagnosticsConfig::new(Severity::Error, true)
     };
     let mut book = do_parse_book(code, path, Book::builtins())?;
@@ -321,27 +345,18 @@ fn desugar_file() {
 }
 
 #[test]
-#[ignore = "bug - the subprocess created by run_book leaks"]
 fn hangs() {
-  let expected_normalization_time = 5;
+  let max_rewrites = 1 << 20;
 
   run_golden_test_dir(function_name!(), &move |code, path| {
-    let _guard = RUN_MUTEX.lock().unwrap();
     let book = do_parse_book(code, path, Book::builtins())?;
     let compile_opts = CompileOpts::default().set_all();
     let diagnostics_cfg = DiagnosticsConfig::new(Severity::Allow, false);
+    let run_opts = RunOpts { max_rewrites: Some(max_rewrites), ..RunOpts::default() };
 
-    let thread = std::thread::spawn(move || {
-      run_book_simple(book, RunOpts::default(), compile_opts, diagnostics_cfg, None)
-    });
-    std::thread::sleep(std::time::Duration::from_secs(expected_normalization_time));
-
-    if !thread.is_finished() {
-      Ok("Hangs".into())
-    } else if let Err(diags) = thread.join().unwrap() {
-      Err(format!("Doesn't hang. (Compilation failed)\n{diags}").into())
-    } else {
-      Err("Doesn't hang. (Ran to the end)".to_string().into())
+    match run_book_simple(book, run_opts, compile_opts, diagnostics_cfg, None) {
+      Err(diags) => Ok(diags.to_string()),
+      Ok(_) => Err("Doesn't hang. (Ran to the end)".to_string().into()),
     }
   })
 }
@@ -358,7 +373,6 @@ fn compile_entrypoint() {
 }
 
 #[test]
-#[ignore = "while execution with different entrypoints is not implemented for hvm32"]
 fn run_entrypoint() {
   run_golden_test_dir(function_name!(), &|code, path| {
     let _guard = RUN_MUTEX.lock().unwrap();
@@ -392,6 +406,210 @@ fn cli() {
   })
 }
 
+/// Runs each project in the directory without an input file, reading its `bend.toml`.
+#[test]
+fn run_project() {
+  run_golden_test_dir(function_name!(), &|_code, path| {
+    let _guard = RUN_MUTEX.lock().unwrap();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(path.parent().unwrap());
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bend"))
+      .arg("run")
+      .current_dir(&dir)
+      .output()
+      .expect("Run command");
+    let res =
+      format!("{}{}", String::from_utf8_lossy(&output.stderr), String::from_utf8_lossy(&output.stdout));
+    Ok(res.replace(&format!("{}/", env!("CARGO_MANIFEST_DIR")), ""))
+  })
+}
+
+/// Creates a project with `bend new` in a temporary directory and runs it,
+/// then runs another created project that depends on it.
+#[test]
+fn new_project() {
+  let _guard = RUN_MUTEX.lock().unwrap();
+  let tmp = std::env::temp_dir().join("bend_new_project");
+  let _ = std::fs::remove_dir_all(&tmp);
+  std::fs::create_dir_all(&tmp).unwrap();
+
+  let mut res = String::new();
+  for (dir, args) in [(".", &["new", "hello"][..]), ("hello", &["run"]), ("hello", &["init"])] {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bend"))
+      .args(args)
+      .current_dir(tmp.join(dir))
+      .output()
+      .expect("Run command");
+    res.push_str(&String::from_utf8_lossy(&output.stderr));
+    res.push_str(&String::from_utf8_lossy(&output.stdout));
+  }
+  res.push_str(&std::fs::read_to_string(tmp.join("hello").join("bend.toml")).unwrap());
+
+  // A project that depends on the created one, which has its own `main`.
+  let run = |dir: &str, args: &[&str]| {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bend"))
+      .args(args)
+      .current_dir(tmp.join(dir))
+      .output()
+      .expect("Run command");
+    format!("{}{}", String::from_utf8_lossy(&output.stderr), String::from_utf8_lossy(&output.stdout))
+  };
+  res.push_str(&run(".", &["new", "app"]));
+  let hello_main = tmp.join("hello").join("src").join("main.bend");
+  let mut code = std::fs::read_to_string(&hello_main).unwrap();
+  code.push_str("\ndef greet(name):\n  return (\"Hello\", name)\n");
+  std::fs::write(&hello_main, code).unwrap();
+  let app_manifest = tmp.join("app").join("bend.toml");
+  let manifest = std::fs::read_to_string(&app_manifest).unwrap();
+  std::fs::write(
+    &app_manifest,
+    manifest.replace("[dependencies]\n", "[dependencies]\nhello = { path = \"../hello\" }\n"),
+  )
+  .unwrap();
+  std::fs::write(tmp.join("app").join("src").join("main.bend"), "def main():\n  return greet(\"app\")\n")
+    .unwrap();
+  res.push_str(&run("app", &["run"]));
+  let _ = std::fs::remove_dir_all(&tmp);
+
+  let mut settings = insta::Settings::clone_current();
+  settings.set_prepend_module_to_snapshot(false);
+  settings.set_omit_expression(true);
+  settings.bind(|| {
+    assert_snapshot!("new_project", res);
+  });
+}
+
+/// Vendors the dependencies of a copy of a project, and runs it after removing the original ones.
+#[test]
+fn vendor_project() {
+  fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+      let path = entry.unwrap().path();
+      let to = to.join(path.file_name().unwrap());
+      if path.is_dir() {
+        copy_dir(&path, &to)
+      } else {
+        std::fs::copy(&path, &to).unwrap();
+      }
+    }
+  }
+
+  let _guard = RUN_MUTEX.lock().unwrap();
+  let tmp = std::env::temp_dir().join("bend_vendor_project");
+  let _ = std::fs::remove_dir_all(&tmp);
+  let project = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden_tests/run_project/app");
+  copy_dir(&project, &tmp);
+  let tmp = tmp.canonicalize().unwrap();
+
+  let run = |args: &[&str]| {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bend"))
+      .args(args)
+      .current_dir(&tmp)
+      .output()
+      .expect("Run command");
+    format!("{}{}", String::from_utf8_lossy(&output.stderr), String::from_utf8_lossy(&output.stdout))
+  };
+  let mut res = run(&["vendor"]);
+  std::fs::remove_dir_all(tmp.join("deps")).unwrap();
+  res.push_str(&run(&["run", "--vendored"]));
+  res.push_str(&run(&["run"]));
+  let res = res.replace(&format!("{}/", tmp.display()), "");
+  let _ = std::fs::remove_dir_all(&tmp);
+
+  let mut settings = insta::Settings::clone_current();
+  settings.set_prepend_module_to_snapshot(false);
+  settings.set_omit_expression(true);
+  settings.bind(|| {
+    assert_snapshot!("vendor_project", res);
+  });
+}
+
+/// Builds a library with `bend gen-lib` and calls it from C, with enough memory for the runtime
+/// and then with too little.
+#[test]
+fn gen_lib_heap_size() {
+  let _guard = RUN_MUTEX.lock().unwrap();
+  let tmp = std::env::temp_dir().join("bend_gen_lib_heap_size");
+  let _ = std::fs::remove_dir_all(&tmp);
+  std::fs::create_dir_all(&tmp).unwrap();
+  std::fs::write(
+    tmp.join("sum.bend"),
+    "#[export(List(u24)) -> u24]\ndef sum(xs):\n  fold xs:\n    case List/Cons:\n      return xs.head + xs.tail\n    case List/Nil:\n      return 0\n",
+  )
+  .unwrap();
+  // Limiting the address space makes the allocation of the runtime fail.
+  std::fs::write(
+    tmp.join("app.c"),
+    r#"#include <stdio.h>
+#include <sys/resource.h>
+#include "bend.h"
+
+int main(void) {
+  uint32_t items[] = {1, 2, 3};
+  uint32_t total = 0;
+  bend_status status = bend_sum((bend_list_u24){3, items}, &total);
+  printf("%d %u\n", status, total);
+  struct rlimit limit = {64 << 20, 64 << 20};
+  setrlimit(RLIMIT_AS, &limit);
+  total = 0;
+  status = bend_sum((bend_list_u24){3, items}, &total);
+  printf("%d %u\n", status, total);
+  return 0;
+}
+"#,
+  )
+  .unwrap();
+
+  let run = |cmd: &str, args: &[&str]| {
+    let output = std::process::Command::new(cmd).args(args).current_dir(&tmp).output().expect("Run command");
+    format!("{}{}", String::from_utf8_lossy(&output.stderr), String::from_utf8_lossy(&output.stdout))
+  };
+  let mut res = run(env!("CARGO_BIN_EXE_bend"), &["gen-lib", "sum.bend", "-o", "sum", "--heap-size=64M"]);
+  run("make", &["-s", "-C", "sum"]);
+  res.push_str(&run("cc", &["app.c", "-Isum", "-Lsum", "-lbend", "-lm", "-lpthread", "-o", "app"]));
+  res.push_str(&run(&tmp.join("app").display().to_string(), &[]));
+  let _ = std::fs::remove_dir_all(&tmp);
+
+  let mut settings = insta::Settings::clone_current();
+  settings.set_prepend_module_to_snapshot(false);
+  settings.set_omit_expression(true);
+  settings.bind(|| {
+    assert_snapshot!("gen_lib_heap_size", res);
+  });
+}
+
+/// Builds an executable with `bend build` and runs it, with enough memory for the runtime and then with too little.
+#[test]
+fn build_heap_size() {
+  let _guard = RUN_MUTEX.lock().unwrap();
+  let tmp = std::env::temp_dir().join("bend_build_heap_size");
+  let _ = std::fs::remove_dir_all(&tmp);
+  std::fs::create_dir_all(&tmp).unwrap();
+  std::fs::write(tmp.join("double.bend"), "def main(n):\n  return n * 2\n").unwrap();
+
+  let run = |cmd: &str, args: &[&str]| {
+    let output = std::process::Command::new(cmd).args(args).current_dir(&tmp).output().expect("Run command");
+    let code = output.status.code().unwrap_or(-1);
+    format!(
+      "{}{}exit {code}\n",
+      String::from_utf8_lossy(&output.stderr),
+      String::from_utf8_lossy(&output.stdout)
+    )
+  };
+  let mut res = run(env!("CARGO_BIN_EXE_bend"), &["build", "double.bend", "--heap-size=64M"]);
+  res.push_str(&run("./double", &["21"]));
+  // Limiting the address space makes the allocation of the runtime fail.
+  res.push_str(&run("sh", &["-c", "ulimit -v 65536; ./double 21"]));
+  let _ = std::fs::remove_dir_all(&tmp);
+
+  let mut settings = insta::Settings::clone_current();
+  settings.set_prepend_module_to_snapshot(false);
+  settings.set_omit_expression(true);
+  settings.bind(|| {
+    assert_snapshot!("build_heap_size", res);
+  });
+}
+
 #[test]
 fn mutual_recursion() {
   run_golden_test_dir(function_name!(), &|code, path| {
@@ -491,6 +709,7 @@ fn compile_long() {
     let diagnostics_cfg = DiagnosticsConfig {
       recursion_cycle: Severity::Warning,
       unused_definition: Severity::Allow,
+      unused_variable: Severity::Allow,
       ..Default::default()
     };
 
@@ -498,3 +717,129 @@ fn compile_long() {
     Ok("Compiled".to_string())
   })
 }
+
+#[test]
+fn numeric_semantics() {
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let _guard = RUN_MUTEX.lock().unwrap();
+    let book = do_parse_book(code, path, Book::builtins())?;
+    let diagnostics_cfg = DiagnosticsConfig::new(Severity::Allow, false);
+
+    // Both the Rust and the C runtimes must give the result of the in-process evaluator.
+    let c_run_opts = RunOpts { heap_size: Some(64 << 20), ..RunOpts::default() };
+    let mut res = String::new();
+    for (opts_name, compile_opts) in
+      [("default", CompileOpts::default()), ("-Oall", CompileOpts::default().set_all())]
+    {
+      let mut out = String::new();
+      for (cmd, run_opts) in [("run", RunOpts::default()), ("run-c", c_run_opts.clone())] {
+        out = match selftest_book(book.clone(), run_opts, compile_opts.clone(), diagnostics_cfg, cmd) {
+          SelftestResult::Pass(out) => out,
+          SelftestResult::Skip(diags) => format!("Error:\n{diags}"),
+          SelftestResult::Fail { expected, got } => {
+            let got = got.unwrap_or_else(|diags| format!("Error:\n{diags}"));
+            return Err(format!("{opts_name}: expected '{expected}' but '{cmd}' gave '{got}'").into());
+          }
+        };
+      }
+      res.push_str(&format!("{opts_name}:\n{out}\n\n"));
+    }
+    Ok(res)
+  })
+}
+
+#[test]
+fn run_def_stats() {
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let book = do_parse_book(code, path, Book::builtins())?;
+    let compile_opts = CompileOpts::default();
+    let diagnostics_cfg = DiagnosticsConfig::new(Severity::Allow, false);
+    let run_opts = RunOpts { def_stats: true, ..RunOpts::default() };
+
+    let (term, stats, diags) = run_book_simple(book, run_opts, compile_opts, diagnostics_cfg, None)?;
+    // Only the per-definition part of the stats is deterministic.
+    let stats = RunStats { summary: String::new(), ..stats };
+    Ok(format!("{diags}{term}\n{stats}"))
+  })
+}
+
+#[test]
+fn run_trace() {
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let book = do_parse_book(code, path, Book::builtins())?;
+    let compile_opts = CompileOpts::default();
+    let diagnostics_cfg = DiagnosticsConfig::new(Severity::Allow, false);
+    let trace_path = std::env::temp_dir().join(path.with_extension("trace").file_name().unwrap());
+    let trace = TraceOpts { path: trace_path.clone(), every: None, def: None };
+    let run_opts = RunOpts { trace: Some(trace), ..RunOpts::default() };
+
+    let (term, _, diags) = run_book_simple(book, run_opts, compile_opts, diagnostics_cfg, None)?;
+    let trace = std::fs::read_to_string(&trace_path).map_err(|e| e.to_string())?;
+    std::fs::remove_file(&trace_path).map_err(|e| e.to_string())?;
+    Ok(format!("{diags}{term}\n{trace}"))
+  })
+}
+
+/// Runs the debugger with the commands in the `.cmds` file next to each program.
+#[test]
+fn debug() {
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let book = do_parse_book(code, path, Book::builtins())?;
+    let compile_opts = CompileOpts::default();
+    let diagnostics_cfg = DiagnosticsConfig::new(Severity::Allow, false);
+    let cmds = std::fs::read_to_string(path.with_extension("cmds")).map_err(|e| e.to_string())?;
+
+    let mut out = vec![];
+    let mut debugger = Debugger::new(cmds.as_bytes(), &mut out, vec![], false);
+    let mut on_break = |bp: &Breakpoint| debugger.on_break(bp);
+    let res = debug_book(book, RunOpts::default(), compile_opts, diagnostics_cfg, None, &mut on_break)?;
+    let (term, _, diags) = res.unwrap();
+    Ok(format!("{}\n{diags}{term}", String::from_utf8_lossy(&out)))
+  })
+}
+
+#[test]
+fn run_profile() {
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let book = do_parse_book(code, path, Book::builtins())?;
+    let compile_opts = CompileOpts::default();
+    let diagnostics_cfg = DiagnosticsConfig::new(Severity::Allow, false);
+    let run_opts = RunOpts { profile: true, ..RunOpts::default() };
+
+    let (term, stats, diags) = run_book_simple(book, run_opts, compile_opts, diagnostics_cfg, None)?;
+    let folded = stats.profile.iter().map(|(stack, itrs)| format!("{stack} {itrs}\n")).collect::<String>();
+    Ok(format!("{diags}{term}\n{folded}"))
+  })
+}
+
+/// Shows the declarations of the exported functions in the header and Python package of `bend gen-py`.
+#[test]
+fn gen_py() {
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let book = do_parse_book(code, path, Book::builtins())?;
+    let exports = book_exports(&book, AdtEncoding::NumScott)?;
+    let dir = std::env::temp_dir().join(path.with_extension("py").file_name().unwrap());
+    // Only the generated parts are shown, so the code of the runtime doesn't matter.
+    write_py_project(&dir, "#define WITH_MAIN\n", &exports)?;
+
+    let header = std::fs::read_to_string(dir.join("bend.h")).map_err(|e| e.to_string())?;
+    let py = std::fs::read_to_string(dir.join("__init__.py")).map_err(|e| e.to_string())?;
+    std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    let header = header.split_once("returns BEND_OK.\n\n").unwrap().1;
+    let header = header.split_once("#ifdef __cplusplus").unwrap().0;
+    let py = &py[py.find("# Exported functions").unwrap()..];
+    Ok(format!("{header}{py}"))
+  })
+}
+
+#[test]
+fn gen_js() {
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let mut book = do_parse_book(code, path, Book::builtins())?;
+    let exports = book_exports(&book, AdtEncoding::NumScott)?;
+    let res = compile_book(&mut book, CompileOpts::default(), DiagnosticsConfig::default(), None)?;
+    let js = book_to_js(&res.hvm_book, &exports);
+    // Only the generated functions are shown, so that changes to the runtime don't affect the snapshot.
+    Ok(js[js.find("// Exported definitions").unwrap()..].to_string())
+  })
+}